@@ -0,0 +1,147 @@
+use std::cmp;
+use std::sync::atomic;
+use std::time::Duration;
+
+use clock;
+use {counters, JoinOutcome, Shared, ThreadHandle};
+
+
+/// How a cancellable wait for termination ended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Wait {
+    Terminated,
+    TimedOut,
+    Cancelled,
+}
+
+impl Shared {
+    /// Number of `abort_waits` calls so far, taken when a wait starts.
+    pub(crate) fn wait_generation(&self) -> u64 {
+        self.wait_cancels.load(atomic::Ordering::Acquire)
+    }
+
+    /// Like `wait_terminated`, also returning once `abort_waits` is called after the wait
+    /// started at `generation`, which a wait made of several calls takes once.
+    pub(crate) fn wait_cancellable(&self, generation: u64, timeout: Option<Duration>) -> Wait {
+        self.wait_for_termination(Some(generation), timeout)
+    }
+
+    /// Waits for the runnable to finish, for at most `timeout` on the clock of the handle, and
+    /// unless `generation` is given and `abort_waits` is called. With a timeout, the condition
+    /// variable is waited on in short slices of real time so that a mock clock can be advanced
+    /// from another thread.
+    pub(crate) fn wait_for_termination(&self, generation: Option<u64>, timeout: Option<Duration>) -> Wait {
+        let (lock, condvar) = (&self.termination.set, &self.termination.condvar);
+        let deadline = timeout.map(|timeout| self.clock.now() + timeout);
+        let mut terminated = lock.lock().unwrap();
+        loop {
+            if *terminated {
+                return Wait::Terminated;
+            }
+            if generation.is_some_and(|generation| self.wait_cancels.load(atomic::Ordering::Acquire) != generation) {
+                return Wait::Cancelled;
+            }
+            terminated = match deadline {
+                Some(deadline) => {
+                    let now = self.clock.now();
+                    if now >= deadline {
+                        return Wait::TimedOut;
+                    }
+                    condvar.wait_timeout(terminated, cmp::min(deadline - now, clock::WAIT_SLICE)).unwrap().0
+                }
+                None => condvar.wait(terminated).unwrap(),
+            };
+        }
+    }
+
+    fn abort_waits(&self) {
+        let (lock, condvar) = (&self.termination.set, &self.termination.condvar);
+        // Bumped under the lock so that a waiter cannot miss it between checking and waiting.
+        let _terminated = lock.lock().unwrap();
+        self.wait_cancels.fetch_add(1, atomic::Ordering::AcqRel);
+        condvar.notify_all();
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Like `join`, but gives up with `WaitCancelled` when another thread calls `abort_join`,
+    /// e.g. for an application that shuts down while a caller waits for a worker.
+    pub fn join_cancellable(&self) -> JoinOutcome<T> {
+        self.join_waiting(None)
+    }
+
+    pub(crate) fn join_waiting(&self, timeout: Option<Duration>) -> JoinOutcome<T> {
+        self.ensure_started().ok();
+        #[cfg(any(test, feature = "test-util"))]
+        self.shared.run_queued();
+        match self.shared.wait_cancellable(self.shared.wait_generation(), timeout) {
+            Wait::Terminated => (),
+            Wait::TimedOut => {
+                counters::AtomicCounters::increment(&self.shared.counters.joins);
+                return JoinOutcome::StillRunning;
+            }
+            Wait::Cancelled => return JoinOutcome::WaitCancelled,
+        }
+        match self.join() {
+            Some(Ok(value)) => JoinOutcome::Finished(value),
+            Some(Err(payload)) => JoinOutcome::Panicked(payload),
+            None => JoinOutcome::AlreadyJoined,
+        }
+    }
+
+    /// Makes the callers currently waiting for the thread give up: `join_cancellable` and
+    /// `join_timeout` return `WaitCancelled`, `join` returns `None` and `wait_finished` returns
+    /// `false`. The thread keeps running and can still be joined.
+    pub fn abort_join(&self) {
+        self.shared.abort_waits();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn test_abort_join() {
+        let handle = Arc::new(ThreadHandle::spawn("Test abort join".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            "stopped"
+        }).unwrap());
+        let waiters: Vec<_> = (0..2).map(|waiter| {
+            let handle = handle.clone();
+            thread::spawn(move || match waiter {
+                0 => handle.join_cancellable(),
+                _ => handle.join_timeout(Duration::from_secs(60)),
+            })
+        }).collect();
+        let joiner = {
+            let handle = handle.clone();
+            thread::spawn(move || handle.join().is_none())
+        };
+        let finisher = {
+            let handle = handle.clone();
+            thread::spawn(move || handle.wait_finished(None))
+        };
+        thread::sleep(Duration::from_millis(50));
+        handle.abort_join();
+        assert!(joiner.join().unwrap());
+        assert!(!finisher.join().unwrap());
+        for waiter in waiters {
+            match waiter.join().unwrap() {
+                JoinOutcome::WaitCancelled => (),
+                other => panic!("unexpected outcome {:?}", other),
+            }
+        }
+        assert!(handle.status() != ::ThreadStatus::Terminated);
+        handle.interrupt().unwrap();
+        match handle.join_cancellable() {
+            JoinOutcome::Finished("stopped") => (),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+}
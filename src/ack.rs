@@ -0,0 +1,95 @@
+use std::sync::atomic;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use current;
+use {Shared, Straggler, ThreadGroup, ThreadHandle};
+
+
+/// Time between two checks of `interrupt_all_and_wait_ack`.
+const POLL: Duration = Duration::from_millis(5);
+
+impl Shared {
+    /// Records that the runnable saw interruption, called whenever its token reports it. Only
+    /// checks made on the thread of the runnable count, not those of its owner or observers.
+    pub(crate) fn acknowledge_interrupt(&self) {
+        if !self.interrupt_acknowledged.load(atomic::Ordering::Relaxed) && self.is_interrupt_requested() && current::is_current(self) {
+            self.interrupt_acknowledged.store(true, atomic::Ordering::Release);
+        }
+    }
+
+    /// Whether the runnable saw the interruption requested, or terminated.
+    fn has_acknowledged_interrupt(&self) -> bool {
+        self.interrupt_acknowledged.load(atomic::Ordering::Acquire) || self.is_terminated()
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Whether the runnable observed interruption through its token, on its own thread, since it
+    /// was requested; `true` as well once it terminated.
+    pub fn interrupt_acknowledged(&self) -> bool {
+        self.shared.has_acknowledged_interrupt()
+    }
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// Interrupts every thread, then waits up to `timeout` until each one observed it or
+    /// terminated. Returns the threads that did not, those to escalate against, e.g. because
+    /// they are stuck in a blocking call.
+    pub fn interrupt_all_and_wait_ack(&self, timeout: Duration) -> Vec<Straggler> {
+        let deadline = Instant::now() + timeout;
+        for handle in self.handles() {
+            handle.shared.request_interrupt(Some(&format!("group {} interrupted", self.name())));
+        }
+        loop {
+            let unresponsive: Vec<_> = self.handles().iter().enumerate().filter(|&(_, handle)| !handle.interrupt_acknowledged()).map(|(index, handle)| Straggler {
+                index,
+                thread: handle.name().to_string(),
+                uptime: handle.uptime(),
+            }).collect();
+            let now = Instant::now();
+            if unresponsive.is_empty() || now >= deadline {
+                return unresponsive;
+            }
+            thread::sleep(POLL.min(deadline - now));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use InterruptToken;
+    use super::*;
+
+    #[test]
+    fn test_interrupt_all_and_wait_ack() {
+        let mut group = ThreadGroup::new("Test ack".to_string());
+        let (drain, drained) = mpsc::channel::<()>();
+        group.spawn("Test ack responsive".to_string(), move |interrupted: InterruptToken| {
+            while !interrupted.is_interrupted() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            // Draining after acknowledging still counts as responsive.
+            drained.recv().ok();
+        }).unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let (share, shared) = mpsc::channel();
+        group.spawn("Test ack stuck".to_string(), move |interrupted: InterruptToken| {
+            share.send(interrupted.clone()).unwrap();
+            released.recv().ok();
+        }).unwrap();
+        let stuck = shared.recv().unwrap();
+        group.spawn("Test ack finished".to_string(), |_| ()).unwrap();
+        let stragglers = group.interrupt_all_and_wait_ack(Duration::from_millis(100));
+        assert_eq!(vec![(1, "Test ack stuck".to_string())], stragglers.into_iter().map(|straggler| (straggler.index, straggler.thread)).collect::<Vec<_>>());
+        assert!(group.handles()[0].interrupt_acknowledged());
+        // Checks made by others do not acknowledge for the stuck runnable.
+        assert!(stuck.is_interrupted());
+        assert!(!group.handles()[1].interrupt_acknowledged());
+        drain.send(()).unwrap();
+        release.send(()).unwrap();
+        assert!(group.join_all().is_ok());
+    }
+}
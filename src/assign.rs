@@ -0,0 +1,121 @@
+use std::io;
+use std::mem;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use {Builder, InterruptToken, ThreadHandle};
+
+
+/// How often a thread waiting for its runnable looks for interruption.
+const INTERRUPT_CHECK: Duration = Duration::from_millis(10);
+
+type Runnable<T> = Box<dyn FnOnce(InterruptToken) -> T + Send>;
+
+enum Assignment<T> {
+    Waiting,
+    Assigned(Runnable<T>),
+    /// The runnable was taken, or the thread stopped waiting.
+    Closed,
+}
+
+/// Handle of a thread spawned before its work is known, see `Builder::spawn_unassigned`. It
+/// dereferences to the handle of the thread, whose value is `None` if the thread was
+/// interrupted before being assigned a runnable.
+pub struct AssignableHandle<T> {
+    handle: ThreadHandle<Option<T>>,
+    assignment: Arc<(Mutex<Assignment<T>>, Condvar)>,
+}
+
+impl<T> AssignableHandle<T> where T: Send + 'static {
+    /// Hands `runnable` to the waiting thread. Fails with the runnable if one was already
+    /// assigned or the thread stopped waiting.
+    pub fn assign<F>(&self, runnable: F) -> Result<(), F> where F: FnOnce(InterruptToken) -> T, F: Send + 'static {
+        let (ref lock, ref condvar) = *self.assignment;
+        let mut assignment = lock.lock().unwrap();
+        match *assignment {
+            Assignment::Waiting => {
+                *assignment = Assignment::Assigned(Box::new(runnable));
+                condvar.notify_all();
+                Ok(())
+            }
+            Assignment::Assigned(_) | Assignment::Closed => Err(runnable),
+        }
+    }
+
+    /// Whether a runnable was assigned, or the thread stopped waiting for one.
+    pub fn is_assigned(&self) -> bool {
+        !matches!(*self.assignment.0.lock().unwrap(), Assignment::Waiting)
+    }
+
+    pub fn into_handle(self) -> ThreadHandle<Option<T>> {
+        self.handle
+    }
+}
+
+impl<T> Deref for AssignableHandle<T> {
+    type Target = ThreadHandle<Option<T>>;
+
+    fn deref(&self) -> &ThreadHandle<Option<T>> {
+        &self.handle
+    }
+}
+
+impl Builder {
+    /// Spawns a thread that waits until a runnable is assigned with `AssignableHandle::assign`,
+    /// e.g. to create threads at startup while placement or limits can still be changed and
+    /// bind them to work later. Interrupting the thread before that ends it with `None`.
+    #[track_caller]
+    pub fn spawn_unassigned<T>(&self) -> io::Result<AssignableHandle<T>> where T: Send + 'static {
+        let assignment = Arc::new((Mutex::new(Assignment::Waiting), Condvar::new()));
+        let waiting = assignment.clone();
+        let handle = self.spawn(move |interrupted: InterruptToken| {
+            let (ref lock, ref condvar) = *waiting;
+            let mut assignment = lock.lock().unwrap();
+            loop {
+                match mem::replace(&mut *assignment, Assignment::Closed) {
+                    Assignment::Assigned(runnable) => {
+                        drop(assignment);
+                        return Some(runnable(interrupted));
+                    }
+                    Assignment::Closed => return None,
+                    Assignment::Waiting if interrupted.is_interrupted() => return None,
+                    Assignment::Waiting => *assignment = Assignment::Waiting,
+                }
+                assignment = condvar.wait_timeout(assignment, INTERRUPT_CHECK).unwrap().0;
+            }
+        })?;
+        Ok(AssignableHandle { handle, assignment })
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Like `Builder::spawn_unassigned`, with the default configuration.
+    #[track_caller]
+    pub fn spawn_unassigned(name: String) -> io::Result<AssignableHandle<T>> {
+        Builder::new(name).spawn_unassigned()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ThreadStatus;
+    use super::*;
+
+    #[test]
+    fn test_assign() {
+        let handle = ThreadHandle::spawn_unassigned("Test assign".to_string()).unwrap();
+        assert_eq!(ThreadStatus::Running, handle.status());
+        assert!(!handle.is_assigned());
+        assert!(handle.assign(|_| 42).is_ok());
+        assert!(handle.is_assigned());
+        assert!(handle.assign(|_| 0).is_err());
+        assert_eq!(Some(42), handle.join().unwrap().unwrap());
+
+        let interrupted = ThreadHandle::<u32>::spawn_unassigned("Test assign interrupted".to_string()).unwrap();
+        interrupted.interrupt().unwrap();
+        assert_eq!(None, interrupted.join().unwrap().unwrap());
+        assert!(interrupted.assign(|_| 1).is_err());
+    }
+}
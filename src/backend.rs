@@ -0,0 +1,355 @@
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+#[cfg(any(test, feature = "test-util"))]
+use std::cell::RefCell;
+#[cfg(any(test, feature = "test-util"))]
+use std::collections::VecDeque;
+
+use builder::SpawnSettings;
+use config;
+use recycle;
+use retry::{self, SpawnRetry};
+#[cfg(any(test, feature = "test-util"))]
+use Shared;
+
+
+/// Work of a managed thread, including reporting its outcome to the handle.
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Creates the threads managed handles run on.
+pub trait SpawnBackend: Send + Sync {
+    /// Runs `job` on a thread named `name`. Returns the thread to join once the job finished,
+    /// or `None` if the thread outlives the job (e.g. it is reused) or cannot be joined.
+    fn spawn(&self, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>>;
+
+    /// Like `spawn`, for a thread configured with a stack size of `bytes`; backends that do not
+    /// control the stack of their threads ignore it.
+    fn spawn_with_stack_size(&self, name: String, bytes: usize, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        let _ = bytes;
+        self.spawn(name, job)
+    }
+}
+
+/// Standard library threads, reused when recycling is enabled.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StdBackend;
+
+impl SpawnBackend for StdBackend {
+    fn spawn(&self, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        recycle::spawn(name, job)
+    }
+
+    /// Spawns a new thread, which is never recycled since cached threads have the default stack.
+    fn spawn_with_stack_size(&self, name: String, bytes: usize, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        thread::Builder::new().name(name).stack_size(bytes).spawn(job).map(Some)
+    }
+}
+
+/// Backend passing a stack size along with each thread it spawns.
+struct StackSized {
+    backend: Arc<dyn SpawnBackend>,
+    bytes: usize,
+}
+
+impl SpawnBackend for StackSized {
+    fn spawn(&self, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        self.backend.spawn_with_stack_size(name, self.bytes, job)
+    }
+}
+
+static DEFAULT_BACKEND: Mutex<Option<Arc<dyn SpawnBackend>>> = Mutex::new(None);
+
+/// Sets the backend used by handles not configured with one of their own;
+/// `None` restores standard threads.
+pub fn set_spawn_backend(backend: Option<Arc<dyn SpawnBackend>>) {
+    *DEFAULT_BACKEND.lock().unwrap() = backend;
+}
+
+/// Shared instance of the standard backend, so that spawning does not allocate one each time.
+fn std_backend() -> Arc<dyn SpawnBackend> {
+    static STD_BACKEND: OnceLock<Arc<dyn SpawnBackend>> = OnceLock::new();
+    STD_BACKEND.get_or_init(|| Arc::new(StdBackend)).clone()
+}
+
+/// The configured backend, or the process-wide default.
+pub(crate) fn resolve(backend: Option<&Arc<dyn SpawnBackend>>) -> Arc<dyn SpawnBackend> {
+    match backend {
+        Some(backend) => backend.clone(),
+        None => DEFAULT_BACKEND.lock().unwrap().clone().unwrap_or_else(std_backend),
+    }
+}
+
+/// How a handle gets its thread: the backend, retries and the fallback when spawning fails.
+pub(crate) struct Launcher {
+    backend: Arc<dyn SpawnBackend>,
+    retry: Option<SpawnRetry>,
+    inline_fallback: bool,
+    stack_size: Option<usize>,
+}
+
+impl Launcher {
+    pub(crate) fn new(settings: &SpawnSettings) -> Self {
+        let mut backend = resolve(settings.backend.as_ref());
+        let stack_size = settings.stack_size.or_else(|| config::installed().default_stack_size());
+        if let Some(bytes) = stack_size {
+            backend = Arc::new(StackSized { backend, bytes });
+        }
+        Launcher {
+            backend,
+            retry: settings.spawn_retry.clone(),
+            inline_fallback: settings.inline_fallback,
+            stack_size,
+        }
+    }
+
+    /// Whether jobs go to a new standard thread as they are, so that `launch_direct` may spawn
+    /// it without boxing the job.
+    pub(crate) fn is_direct(&self) -> bool {
+        self.retry.is_none() && !self.inline_fallback && self.stack_size.is_none()
+            && Arc::as_ptr(&self.backend) as *const () == Arc::as_ptr(&std_backend()) as *const ()
+            && !recycle::is_enabled()
+    }
+
+    /// Spawns a standard thread running `job`, for a launcher that `is_direct`.
+    pub(crate) fn launch_direct<F>(&self, name: String, job: F) -> io::Result<JoinHandle<()>> where F: FnOnce() + Send + 'static {
+        #[cfg(any(test, feature = "test-util"))]
+        take_queued();
+        thread::Builder::new().name(name).spawn(job)
+    }
+
+    pub(crate) fn launch(&self, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        #[cfg(any(test, feature = "test-util"))]
+        take_queued();
+        if !self.inline_fallback {
+            return retry::spawn(&*self.backend, name, job, self.retry.as_ref());
+        }
+        let job = Arc::new(Mutex::new(Some(job)));
+        let attempt = job.clone();
+        let spawned = retry::spawn(&*self.backend, name, Box::new(move || {
+            if let Some(job) = attempt.lock().unwrap().take() {
+                job();
+            }
+        }), self.retry.as_ref());
+        if spawned.is_err() {
+            // The failed spawn returned without running the job, which now runs on the caller.
+            if let Some(job) = job.lock().unwrap().take() {
+                job();
+                return Ok(None);
+            }
+        }
+        spawned
+    }
+}
+
+/// Job queued by a deferred `InlineBackend`, run by the test or by joining its handle,
+/// whichever comes first.
+#[cfg(any(test, feature = "test-util"))]
+pub(crate) type Queued = Arc<Mutex<Option<Job>>>;
+
+#[cfg(any(test, feature = "test-util"))]
+thread_local! {
+    /// Job the last `InlineBackend::spawn` on this thread queued, until its handle adopts it.
+    static QUEUED: RefCell<Option<Queued>> = const { RefCell::new(None) };
+}
+
+#[cfg(any(test, feature = "test-util"))]
+fn take_queued() -> Option<Queued> {
+    QUEUED.with(|queued| queued.borrow_mut().take())
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Shared {
+    /// Takes over the job that launching the thread queued in a deferred `InlineBackend`.
+    pub(crate) fn adopt_queued(&self) {
+        if let Some(queued) = take_queued() {
+            *self.queued.lock().unwrap() = Some(queued);
+        }
+    }
+
+    /// Runs the queued job of the thread on the caller if the backend did not run it yet, so
+    /// that joining does not wait for a job nobody is going to run.
+    pub(crate) fn run_queued(&self) {
+        let queued = self.queued.lock().unwrap().take();
+        let job = queued.and_then(|queued| queued.lock().unwrap().take());
+        if let Some(job) = job {
+            job();
+        }
+    }
+}
+
+/// Backend for tests running jobs on the calling thread: right away inside `spawn`, or when
+/// the test calls `run_next` or `run_pending`. Interrupting a handle before its job runs is
+/// seen by the runnable; joining a handle whose job has not run yet runs it on the joiner.
+#[cfg(any(test, feature = "test-util"))]
+pub struct InlineBackend {
+    deferred: bool,
+    queue: Mutex<VecDeque<(String, Queued)>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl InlineBackend {
+    /// Runs every job to completion before `spawn` returns.
+    pub fn immediate() -> Self {
+        InlineBackend {
+            deferred: false,
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Queues jobs until the test runs them.
+    pub fn deferred() -> Self {
+        InlineBackend {
+            deferred: true,
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.lock().unwrap().iter().filter(|&(_, queued)| queued.lock().unwrap().is_some()).count()
+    }
+
+    /// Runs the oldest queued job that was not run by a join, and returns the name of its thread.
+    pub fn run_next(&self) -> Option<String> {
+        loop {
+            let (name, queued) = self.queue.lock().unwrap().pop_front()?;
+            let job = queued.lock().unwrap().take();
+            if let Some(job) = job {
+                job();
+                return Some(name);
+            }
+        }
+    }
+
+    /// Runs queued jobs, including the ones they spawn, until none is left; returns how many ran.
+    pub fn run_pending(&self) -> usize {
+        let mut ran = 0;
+        while self.run_next().is_some() {
+            ran += 1;
+        }
+        ran
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl SpawnBackend for InlineBackend {
+    fn spawn(&self, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        if self.deferred {
+            let queued = Arc::new(Mutex::new(Some(job)));
+            self.queue.lock().unwrap().push_back((name, queued.clone()));
+            QUEUED.with(|slot| *slot.borrow_mut() = Some(queued));
+        } else {
+            job();
+        }
+        Ok(None)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::sync::atomic::{self, AtomicUsize};
+    use {Builder, ThreadStatus};
+    use super::*;
+
+    struct Counting {
+        spawned: AtomicUsize,
+    }
+
+    impl SpawnBackend for Counting {
+        fn spawn(&self, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+            if name.starts_with("Test backend") {
+                self.spawned.fetch_add(1, atomic::Ordering::SeqCst);
+            }
+            StdBackend.spawn(name, job)
+        }
+    }
+
+    struct Failing;
+
+    impl SpawnBackend for Failing {
+        fn spawn(&self, _: String, _: Job) -> io::Result<Option<JoinHandle<()>>> {
+            Err(io::Error::other("no threads here"))
+        }
+    }
+
+    #[test]
+    fn test_builder_backend() {
+        let backend = Arc::new(Counting { spawned: AtomicUsize::new(0) });
+        let handle = Builder::new("Test backend builder".to_string()).backend(backend.clone()).spawn(|_| 5).unwrap();
+        assert_eq!(5, handle.join().unwrap().unwrap());
+        let lazy = Builder::new("Test backend lazy".to_string()).backend(backend.clone()).lazy(|_| 6);
+        assert_eq!(1, backend.spawned.load(atomic::Ordering::SeqCst));
+        assert_eq!(6, lazy.join().unwrap().unwrap());
+        assert_eq!(2, backend.spawned.load(atomic::Ordering::SeqCst));
+        let failed = Builder::new("Test backend failing".to_string()).backend(Arc::new(Failing)).spawn(|_| ());
+        assert_eq!("no threads here", failed.err().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_std_backend_shared() {
+        assert!(Arc::ptr_eq(&std_backend(), &std_backend()));
+    }
+
+    #[test]
+    fn test_inline_immediate() {
+        let backend = Arc::new(InlineBackend::immediate());
+        let caller = ::std::thread::current().id();
+        let handle = Builder::new("Test inline immediate".to_string()).backend(backend).spawn(move |_| {
+            ::std::thread::current().id() == caller
+        }).unwrap();
+        assert_eq!(ThreadStatus::Terminated, handle.status());
+        assert!(handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_inline_deferred() {
+        let backend = Arc::new(InlineBackend::deferred());
+        let builder = Builder::new("Test inline deferred".to_string()).backend(backend.clone());
+        let interrupted = builder.spawn(|interrupted| interrupted.is_interrupted()).unwrap();
+        let panicking = builder.spawn(|_| -> bool { panic!("inline") }).unwrap();
+        assert_eq!(2, backend.pending());
+        assert_eq!(ThreadStatus::Running, interrupted.status());
+        interrupted.interrupt().unwrap();
+        assert_eq!(Some("Test inline deferred".to_string()), backend.run_next());
+        assert!(interrupted.join().unwrap().unwrap());
+        assert_eq!(1, backend.run_pending());
+        assert!(panicking.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_inline_deferred_join_runs_job() {
+        let backend = Arc::new(InlineBackend::deferred());
+        let builder = Builder::new("Test inline join".to_string()).backend(backend.clone());
+        let first = builder.spawn(|_| 1).unwrap();
+        let second = builder.spawn(|_| 2).unwrap();
+        let lazy = builder.lazy(|_| 3);
+        assert_eq!(2, second.join().unwrap().unwrap());
+        assert_eq!(1, backend.pending());
+        assert_eq!(3, lazy.join().unwrap().unwrap());
+        assert_eq!(1, backend.run_pending());
+        assert_eq!(None, backend.run_next());
+        assert_eq!(1, first.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_inline_fallback() {
+        struct Refusing;
+
+        impl SpawnBackend for Refusing {
+            fn spawn(&self, _name: String, _job: Job) -> io::Result<Option<JoinHandle<()>>> {
+                Err(io::Error::other("refused"))
+            }
+        }
+
+        let caller = thread::current().id();
+        let builder = Builder::new("Test inline fallback".to_string()).backend(Arc::new(Refusing));
+        assert!(builder.spawn(|_| ()).is_err());
+        let handle = builder.inline_fallback().spawn(move |interrupted| {
+            (thread::current().id() == caller, interrupted.is_interrupted())
+        }).unwrap();
+        assert_eq!(ThreadStatus::Terminated, handle.status());
+        assert_eq!((true, false), handle.join().unwrap().unwrap());
+    }
+}
@@ -0,0 +1,159 @@
+//! Captures a backtrace where a runnable panics, while its stack is still there.
+
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic;
+use std::sync::Once;
+
+use ThreadHandle;
+
+
+thread_local! {
+    static ARMED: Cell<bool> = const { Cell::new(false) };
+    static KEPT: Cell<bool> = const { Cell::new(false) };
+    static CAPTURED: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Chains a capturing hook in front of the current process-wide panic hook. It only captures
+/// on threads that armed it, so other panics cost nothing extra. It is installed once, when the
+/// first thread arms it: a hook set with `panic::set_hook` afterwards replaces it for good.
+fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if ARMED.with(Cell::get) {
+                // A cleanup panicking after the runnable must not hide where the runnable did.
+                if !KEPT.with(Cell::get) {
+                    CAPTURED.with(|captured| *captured.borrow_mut() = Some(Backtrace::force_capture()));
+                }
+            }
+            previous(info)
+        }));
+    });
+}
+
+/// Starts capturing backtraces of panics on the calling thread.
+pub(crate) fn arm() {
+    install();
+    CAPTURED.with(|captured| captured.borrow_mut().take());
+    KEPT.with(|kept| kept.set(false));
+    ARMED.with(|armed| armed.set(true));
+}
+
+/// Called once the runnable or a cleanup returned, or `panicked`: keeps the backtrace of the
+/// first of them that panicked, and drops those of the panics they recovered from.
+pub(crate) fn returned(panicked: bool) {
+    if !ARMED.with(Cell::get) || KEPT.with(Cell::get) {
+        return;
+    }
+    if panicked {
+        KEPT.with(|kept| kept.set(true));
+    } else {
+        CAPTURED.with(|captured| captured.borrow_mut().take());
+    }
+}
+
+/// Stops capturing and returns the backtrace of the panic that failed the thread, if any.
+pub(crate) fn disarm() -> Option<Backtrace> {
+    ARMED.with(|armed| armed.set(false));
+    CAPTURED.with(|captured| captured.borrow_mut().take())
+}
+
+/// Stops capturing on the calling thread without touching what it captured so far, and returns
+/// whether it was capturing.
+pub(crate) fn suspend() -> bool {
+    ARMED.with(|armed| armed.replace(false))
+}
+
+/// Undoes `suspend`.
+pub(crate) fn resume(armed: bool) {
+    ARMED.with(|cell| cell.set(armed));
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Backtrace captured where the runnable panicked, if it did. It is captured by a panic hook
+    /// the crate installs in front of the existing one when the first managed thread starts;
+    /// a program calling `panic::set_hook` later removes it, and this then returns `None`. Set
+    /// such hooks before spawning managed threads.
+    pub fn panic_backtrace(&self) -> Option<&Backtrace> {
+        self.shared.backtrace.get()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explode() -> u32 {
+        panic!("exploded")
+    }
+
+    #[test]
+    fn test_panic_backtrace() {
+        let handle = ThreadHandle::spawn("Test backtrace".to_string(), |_| explode()).unwrap();
+        assert!(handle.join().unwrap().is_err());
+        let backtrace = handle.panic_backtrace().unwrap().to_string();
+        assert!(backtrace.contains("explode"), "{}", backtrace);
+        let ok = ThreadHandle::spawn("Test backtrace ok".to_string(), |_| 1).unwrap();
+        assert_eq!(1, ok.join().unwrap().unwrap());
+        assert!(ok.panic_backtrace().is_none());
+    }
+
+    fn stumble() {
+        assert!(panic::catch_unwind(|| panic!("recovered")).is_err());
+    }
+
+    #[test]
+    fn test_recovered_panic_not_kept() {
+        let handle = ThreadHandle::spawn("Test backtrace recovered".to_string(), |_| {
+            stumble();
+            explode()
+        }).unwrap();
+        assert!(handle.join().unwrap().is_err());
+        let backtrace = handle.panic_backtrace().unwrap().to_string();
+        assert!(backtrace.contains("explode") && !backtrace.contains("stumble"), "{}", backtrace);
+
+        let handle = ThreadHandle::spawn("Test backtrace recovered ok".to_string(), |interrupted| {
+            stumble();
+            interrupted.defer(|| {
+                explode();
+            });
+            interrupted.defer(stumble);
+        }).unwrap();
+        assert!(handle.join().unwrap().is_err());
+        let backtrace = handle.panic_backtrace().unwrap().to_string();
+        assert!(backtrace.contains("explode") && !backtrace.contains("stumble"), "{}", backtrace);
+    }
+
+    #[test]
+    fn test_inline_job_keeps_caller_capture() {
+        use std::io;
+        use std::sync::Arc;
+        use std::thread::JoinHandle;
+        use {Builder, Job, SpawnBackend};
+
+        struct Refusing;
+
+        impl SpawnBackend for Refusing {
+            fn spawn(&self, _name: String, _job: Job) -> io::Result<Option<JoinHandle<()>>> {
+                Err(io::Error::other("refused"))
+            }
+        }
+
+        let handle = ThreadHandle::spawn("Test backtrace caller".to_string(), |_| {
+            let inline = Builder::new("Test backtrace inline".to_string())
+                .backend(Arc::new(Refusing))
+                .inline_fallback()
+                .spawn(|_| panic!("inline"))
+                .unwrap();
+            assert!(inline.join().unwrap().is_err());
+            assert!(inline.panic_backtrace().is_none());
+            explode()
+        }).unwrap();
+        assert!(handle.join().unwrap().is_err());
+        let backtrace = handle.panic_backtrace().unwrap().to_string();
+        assert!(backtrace.contains("explode"), "{}", backtrace);
+    }
+}
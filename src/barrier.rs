@@ -0,0 +1,179 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use {InterruptToken, Shared, ThreadGroup, ThreadHandle};
+
+
+/// Longest time a waiter sleeps before checking the participants again.
+const CHECK: Duration = Duration::from_millis(10);
+
+/// What happened to the participant that broke a `HandleBarrier`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakCause {
+    Interrupted,
+    Panicked,
+    /// Returned without arriving at the barrier.
+    Terminated,
+}
+
+/// Error of waiting at a broken `HandleBarrier`: a participant will not arrive.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BarrierBroken {
+    pub thread: String,
+    pub cause: BreakCause,
+}
+
+impl fmt::Display for BarrierBroken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cause = match self.cause {
+            BreakCause::Interrupted => "was interrupted",
+            BreakCause::Panicked => "panicked",
+            BreakCause::Terminated => "terminated",
+        };
+        write!(f, "barrier broken: thread {} {}", self.thread, cause)
+    }
+}
+
+impl Error for BarrierBroken {}
+
+struct State {
+    arrived: usize,
+    generation: u64,
+    broken: Option<BarrierBroken>,
+}
+
+/// Barrier for the phases of a fork-join computation that breaks, instead of waiting forever,
+/// once a participant is interrupted, panics or returns, see `watch`. A broken barrier stays
+/// broken.
+pub struct HandleBarrier {
+    parties: usize,
+    state: Mutex<State>,
+    condvar: Condvar,
+    participants: Mutex<Vec<Arc<Shared>>>,
+}
+
+impl HandleBarrier {
+    /// Barrier releasing its waiters each time `parties` threads arrived.
+    pub fn new(parties: usize) -> Self {
+        HandleBarrier {
+            parties,
+            state: Mutex::new(State {
+                arrived: 0,
+                generation: 0,
+                broken: None,
+            }),
+            condvar: Condvar::new(),
+            participants: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Tracks the thread of `handle` as a participant, whose failure breaks the barrier.
+    pub fn watch<T>(&self, handle: &ThreadHandle<T>) where T: Send + 'static {
+        self.participants.lock().unwrap().push(handle.shared.clone());
+    }
+
+    /// Tracks every thread of `group` as a participant.
+    pub fn watch_group<T>(&self, group: &ThreadGroup<T>) where T: Send + 'static {
+        self.participants.lock().unwrap().extend(group.handles().iter().map(|handle| handle.shared.clone()));
+    }
+
+    /// Waits until `parties` threads arrived, returning `true` on one of them, the leader. Fails
+    /// if the barrier is or gets broken, including when `token` is interrupted while waiting.
+    pub fn wait(&self, token: &InterruptToken) -> Result<bool, BarrierBroken> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(ref broken) = state.broken {
+            return Err(broken.clone());
+        }
+        let generation = state.generation;
+        state.arrived += 1;
+        if state.arrived >= self.parties {
+            state.arrived = 0;
+            state.generation += 1;
+            self.condvar.notify_all();
+            return Ok(true);
+        }
+        loop {
+            state = self.condvar.wait_timeout(state, CHECK).unwrap().0;
+            if state.generation != generation {
+                return Ok(false);
+            }
+            if let Some(ref broken) = state.broken {
+                return Err(broken.clone());
+            }
+            if let Some(broken) = self.find_failure(token) {
+                state.broken = Some(broken.clone());
+                self.condvar.notify_all();
+                return Err(broken);
+            }
+        }
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.state.lock().unwrap().broken.is_some()
+    }
+
+    /// First participant, starting with the waiting one, that will not arrive.
+    fn find_failure(&self, token: &InterruptToken) -> Option<BarrierBroken> {
+        let participants = self.participants.lock().unwrap();
+        Some(&token.shared).into_iter().chain(participants.iter()).find_map(|shared| {
+            let cause = if shared.is_terminated() {
+                if shared.has_panicked() { BreakCause::Panicked } else { BreakCause::Terminated }
+            } else if shared.is_interrupt_requested() {
+                BreakCause::Interrupted
+            } else {
+                return None;
+            };
+            Some(BarrierBroken {
+                thread: shared.name().to_string(),
+                cause,
+            })
+        })
+    }
+}
+
+impl fmt::Debug for HandleBarrier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("HandleBarrier")
+            .field("parties", &self.parties)
+            .field("arrived", &state.arrived)
+            .field("broken", &state.broken)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_barrier() {
+        let barrier = Arc::new(HandleBarrier::new(3));
+        let mut group = ThreadGroup::new("Test barrier".to_string());
+        for index in 0..3 {
+            let barrier = barrier.clone();
+            group.spawn(format!("Test barrier {}", index), move |interrupted: InterruptToken| {
+                let first = barrier.wait(&interrupted)?;
+                if index == 2 {
+                    panic!("phase two failed");
+                }
+                barrier.wait(&interrupted).map(|second| first || second)
+            }).unwrap();
+        }
+        barrier.watch_group(&group);
+        let outcomes = group.join_all_timeout(Duration::from_secs(5));
+        let broken: Vec<_> = outcomes.into_iter().take(2).map(|outcome| match outcome {
+            ::JoinOutcome::Finished(result) => result.unwrap_err(),
+            other => panic!("unexpected outcome {:?}", other),
+        }).collect();
+        let expected = BarrierBroken {
+            thread: "Test barrier 2".to_string(),
+            cause: BreakCause::Panicked,
+        };
+        assert_eq!(vec![expected.clone(), expected], broken);
+        assert!(barrier.is_broken());
+    }
+}
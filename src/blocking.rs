@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+use {InterruptToken, Shared, ThreadHandle};
+
+
+/// Region where the runnable is inside a blocking call it cannot interrupt, see
+/// `InterruptToken::blocking_section`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockingSection {
+    pub name: String,
+    /// Time spent in the section so far, according to the clock of the thread.
+    pub elapsed: Duration,
+}
+
+/// Restores the enclosing section when a section ends, including by panicking.
+struct Leave<'a> {
+    shared: &'a Shared,
+    enclosing: Option<(String, Instant)>,
+}
+
+impl<'a> Drop for Leave<'a> {
+    fn drop(&mut self) {
+        *self.shared.blocking.lock().unwrap() = self.enclosing.take();
+    }
+}
+
+impl Shared {
+    pub(crate) fn blocking_section(&self) -> Option<BlockingSection> {
+        self.blocking.lock().unwrap().as_ref().map(|&(ref name, entered)| BlockingSection {
+            name: name.clone(),
+            elapsed: self.clock.now().saturating_duration_since(entered),
+        })
+    }
+}
+
+impl InterruptToken {
+    /// Runs `call`, a blocking call that does not check for interruption such as a read from a
+    /// socket, marked as section `name` so that the handle and the watchdog can tell a thread busy
+    /// in a known call from a stuck one. Sections nest; the innermost one is reported.
+    pub fn blocking_section<F, R>(&self, name: &str, call: F) -> R where F: FnOnce() -> R {
+        let entered = (name.to_string(), self.shared.clock.now());
+        let _leave = Leave {
+            shared: &self.shared,
+            enclosing: self.shared.blocking.lock().unwrap().replace(entered),
+        };
+        call()
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Blocking section the runnable is in, with how long it has been inside.
+    pub fn blocking_section(&self) -> Option<BlockingSection> {
+        self.shared.blocking_section()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use std::thread::sleep;
+    use super::*;
+
+    #[test]
+    fn test_blocking_section() {
+        let (entered, has_entered) = channel();
+        let (release, released) = channel::<()>();
+        let handle = ThreadHandle::spawn("Test blocking".to_string(), move |interrupted| {
+            interrupted.blocking_section("read", || {
+                interrupted.blocking_section("connect", || ());
+                entered.send(()).unwrap();
+                released.recv().ok();
+            });
+        }).unwrap();
+        assert_eq!(None, handle.blocking_section());
+        has_entered.recv().unwrap();
+        sleep(Duration::from_millis(30));
+        let section = handle.blocking_section().unwrap();
+        assert_eq!("read", section.name);
+        assert!(section.elapsed >= Duration::from_millis(30));
+        release.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(None, handle.blocking_section());
+    }
+}
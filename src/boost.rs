@@ -0,0 +1,87 @@
+//! Raises the priority of a deprioritized thread once it is interrupted, so that it winds down
+//! promptly instead of being starved by the threads waiting for it.
+
+use sys;
+use Shared;
+
+
+impl Shared {
+    /// Gives the thread the nice value set with `Builder::boost_on_interrupt`, if any, keeping
+    /// the one it had to restore it later. Does nothing before the thread started, or if the
+    /// process may not raise priorities.
+    pub(crate) fn boost_priority(&self) {
+        self.apply_boost(false);
+    }
+
+    /// Boosts a thread that was interrupted before it could be, once it is placed. The placement
+    /// sets its own nice value, which overwrites a boost applied in between, so this one boosts
+    /// again even if the thread already was.
+    pub(crate) fn boost_priority_if_interrupted(&self) {
+        if self.is_interrupt_requested() {
+            self.apply_boost(true);
+        }
+    }
+
+    /// Lowers the nice value of the thread, keeping the one it replaces unless it is boosted
+    /// already and `again` is not set.
+    fn apply_boost(&self, again: bool) {
+        let (&nice, &tid) = match (self.interrupt_nice.get(), self.os_thread_id.get()) {
+            (Some(nice), Some(tid)) => (nice, tid),
+            _ => return,
+        };
+        let mut unboosted = self.unboosted_nice.lock().unwrap();
+        if unboosted.is_some() && !again {
+            return;
+        }
+        if let Ok(current) = sys::thread_nice(tid) {
+            if nice < current && sys::set_thread_nice(tid, nice).is_ok() {
+                *unboosted = Some(current);
+            }
+        }
+    }
+
+    /// Gives the thread back the nice value it had before being boosted, so that a recycled
+    /// thread does not keep it.
+    pub(crate) fn restore_priority(&self) {
+        if let (Some(nice), Some(&tid)) = (self.unboosted_nice.lock().unwrap().take(), self.os_thread_id.get()) {
+            sys::set_thread_nice(tid, nice).ok();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use {Builder, InterruptToken};
+    use super::*;
+
+    /// Whether the process has `CAP_SYS_NICE` and may thus lower nice values.
+    fn may_raise_priority() -> bool {
+        let status = ::std::fs::read_to_string("/proc/self/status").unwrap();
+        status.lines()
+            .find(|line| line.starts_with("CapEff:"))
+            .and_then(|line| u64::from_str_radix(line["CapEff:".len()..].trim(), 16).ok())
+            .is_some_and(|caps| caps & (1 << 23) != 0)
+    }
+
+    #[test]
+    fn test_boost_on_interrupt() {
+        if !may_raise_priority() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let handle = Builder::new("Test boost".to_string()).nice(10).boost_on_interrupt(5).spawn(move |interrupted: InterruptToken| {
+            let tid = sys::os_thread_id().unwrap();
+            sender.send(sys::thread_nice(tid).unwrap()).unwrap();
+            while !interrupted.is_interrupted() {
+                ::std::thread::sleep(Duration::from_millis(1));
+            }
+            sys::thread_nice(tid).unwrap()
+        }).unwrap();
+        assert_eq!(10, receiver.recv().unwrap());
+        handle.interrupt().unwrap();
+        assert_eq!(5, handle.join().unwrap().unwrap());
+    }
+}
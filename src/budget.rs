@@ -0,0 +1,107 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use {InterruptToken, Interrupted};
+
+
+const MAX_TICKS: u32 = 1 << 20;
+
+/// Token wrapper for tight loops that only looks at the interruption flag every so many ticks.
+pub struct Budget {
+    token: InterruptToken,
+    ticks: u32,
+    remaining: u32,
+    interval: Option<(Duration, Instant)>,
+}
+
+impl InterruptToken {
+    /// Returns a budget checking for interruption once every `ticks` calls to `tick`.
+    pub fn budget(&self, ticks: u32) -> Budget {
+        let ticks = cmp::max(ticks, 1);
+        Budget {
+            token: self.clone(),
+            ticks,
+            remaining: ticks,
+            interval: None,
+        }
+    }
+}
+
+impl Budget {
+    /// Adapts the number of ticks between checks so that they happen about once per `interval`,
+    /// starting from the configured count. The clock is only read when checking.
+    pub fn adapt_to(mut self, interval: Duration) -> Self {
+        self.interval = Some((interval, self.token.now()));
+        self
+    }
+
+    /// Current number of ticks between two checks.
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    /// Counts one iteration; fails once interruption was requested, noticed at the next check.
+    #[inline]
+    pub fn tick(&mut self) -> Result<(), Interrupted> {
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            return Ok(());
+        }
+        self.check()
+    }
+
+    #[cold]
+    fn check(&mut self) -> Result<(), Interrupted> {
+        if let Some((interval, ref mut last)) = self.interval {
+            let now = self.token.now();
+            let elapsed = now.duration_since(*last);
+            *last = now;
+            if elapsed > interval {
+                self.ticks = cmp::max(self.ticks / 2, 1);
+            } else if elapsed < interval / 2 {
+                self.ticks = cmp::min(self.ticks.saturating_mul(2), MAX_TICKS);
+            }
+        }
+        self.remaining = self.ticks;
+        if self.token.is_interrupted() {
+            Err(Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use super::*;
+
+    #[test]
+    fn test_budget_checks_every_ticks() {
+        let token = InterruptToken::new();
+        let mut budget = token.budget(4);
+        token.interrupt();
+        assert_eq!(Ok(()), budget.tick());
+        assert_eq!(Ok(()), budget.tick());
+        assert_eq!(Ok(()), budget.tick());
+        assert_eq!(Err(Interrupted), budget.tick());
+        assert_eq!(Ok(()), budget.tick());
+    }
+
+    #[test]
+    fn test_budget_adapts() {
+        let token = InterruptToken::new();
+        let mut fast = token.budget(1).adapt_to(Duration::from_secs(10));
+        for _ in 0..100 {
+            fast.tick().unwrap();
+        }
+        assert!(fast.ticks() > 1);
+        let mut slow = token.budget(8).adapt_to(Duration::from_millis(1));
+        for _ in 0..16 {
+            sleep(Duration::from_millis(1));
+            slow.tick().unwrap();
+        }
+        assert!(slow.ticks() < 8);
+    }
+}
@@ -0,0 +1,337 @@
+use std::any::Any;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicBool};
+use std::time::Instant;
+
+use backend::SpawnBackend;
+use clock::{self, Clock};
+use context::{ContextPropagator, ContextValues};
+use crash::CrashSink;
+use deadline;
+use leak::DropPolicy;
+use panics::{PanicHandler, PanicPolicy, PanicReport};
+use retry::{RetryPolicy, SpawnRetry};
+use token::Signal;
+#[cfg(all(feature = "linux", target_os = "linux"))]
+use sys;
+use {new_slot, InterruptToken, ThreadHandle};
+
+
+pub(crate) type Hook = Arc<dyn Fn() + Send + Sync>;
+
+/// Everything a `Builder` configures about a thread: closures run around its runnable, how it
+/// is spawned, and the state its token starts with. Groups, pools and combinators spawn through
+/// it as well.
+#[derive(Clone, Default)]
+pub(crate) struct SpawnSettings {
+    pub(crate) on_start: Option<Hook>,
+    pub(crate) on_stop: Option<Hook>,
+    pub(crate) on_panic: Option<PanicHandler>,
+    /// `None` inherits the policy of the installed `ThreadHandleConfig`.
+    pub(crate) panic_policy: Option<PanicPolicy>,
+    pub(crate) stack_size: Option<usize>,
+    pub(crate) crash_sink: Option<Arc<dyn CrashSink>>,
+    /// Thread group or pool reported in events.
+    pub(crate) group: Option<String>,
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    pub(crate) backend: Option<Arc<dyn SpawnBackend>>,
+    pub(crate) propagators: Vec<Arc<dyn ContextPropagator>>,
+    pub(crate) values: ContextValues,
+    pub(crate) detect_leaks: Option<bool>,
+    /// `None` inherits the policy of the installed `ThreadHandleConfig`.
+    pub(crate) drop_policy: Option<DropPolicy>,
+    pub(crate) spawn_retry: Option<SpawnRetry>,
+    pub(crate) inline_fallback: bool,
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) shutdown_class: i32,
+    pub(crate) throttle: Option<u8>,
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    pub(crate) placement: sys::Placement,
+}
+
+impl SpawnSettings {
+    /// Fresh token for a thread spawned with this configuration.
+    pub(crate) fn token(&self) -> InterruptToken {
+        self.token_with(())
+    }
+
+    /// Same as `token`, carrying `slot` in its shared state.
+    pub(crate) fn token_with<S>(&self, slot: S) -> InterruptToken where S: Any + Send + Sync {
+        let clock = self.clock.clone().unwrap_or_else(clock::system);
+        let token = InterruptToken::configured_with(Signal::Flag(AtomicBool::new(false)), clock, slot);
+        if !self.values.is_empty() {
+            token.shared.values.set(self.values.clone()).ok();
+        }
+        if let Some(deadline) = self.deadline {
+            token.shared.deadline.set(deadline).ok();
+            deadline::arm(&token.shared);
+        }
+        token.shared.shutdown_class.store(self.shutdown_class, atomic::Ordering::Relaxed);
+        if let Some(percent) = self.throttle {
+            token.throttle(percent);
+        }
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        {
+            if let Some(nice) = self.placement.interrupt_nice {
+                token.shared.interrupt_nice.set(nice).ok();
+            }
+        }
+        token
+    }
+}
+
+/// Configures a thread before spawning it.
+#[derive(Clone)]
+pub struct Builder {
+    name: String,
+    settings: SpawnSettings,
+}
+
+impl Builder {
+    pub fn new(name: String) -> Self {
+        Builder {
+            name,
+            settings: SpawnSettings::default(),
+        }
+    }
+
+    /// Closure run inside the new thread before the runnable, e.g. to set up thread-locals.
+    /// A panic in it counts as a panic of the runnable, which then never runs.
+    pub fn on_thread_start<F>(mut self, hook: F) -> Self where F: Fn() + Send + Sync + 'static {
+        self.settings.on_start = Some(Arc::new(hook));
+        self
+    }
+
+    /// Closure run inside the thread after the runnable returned or panicked, before the
+    /// handle reports it terminated.
+    pub fn on_thread_stop<F>(mut self, hook: F) -> Self where F: Fn() + Send + Sync + 'static {
+        self.settings.on_stop = Some(Arc::new(hook));
+        self
+    }
+
+    /// Handler invoked inside the thread when its runnable panics, instead of the one set
+    /// with `set_panic_handler`.
+    pub fn on_panic<F>(mut self, handler: F) -> Self where F: Fn(&PanicReport) + Send + Sync + 'static {
+        self.settings.on_panic = Some(Arc::new(handler));
+        self
+    }
+
+    /// Stack size of the thread, in bytes. Threads with a stack size of their own are never
+    /// recycled, and backends other than the standard one may ignore it.
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.settings.stack_size = Some(bytes);
+        self
+    }
+
+    /// What a panic of the runnable does beyond being reported; by default it is captured as
+    /// the outcome of the thread.
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.settings.panic_policy = Some(policy);
+        self
+    }
+
+    /// Sink receiving a crash report if the runnable panics, instead of the one set with
+    /// `set_crash_sink`.
+    pub fn crash_sink(mut self, sink: Arc<dyn CrashSink>) -> Self {
+        self.settings.crash_sink = Some(sink);
+        self
+    }
+
+    /// Clock the thread takes its timestamps, heartbeats and staleness from.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.settings.clock = Some(clock);
+        self
+    }
+
+    /// Backend creating the thread, instead of the one set with `set_spawn_backend`.
+    pub fn backend(mut self, backend: Arc<dyn SpawnBackend>) -> Self {
+        self.settings.backend = Some(backend);
+        self
+    }
+
+    /// Nice value of the thread, from -20 (highest priority) to 19 (lowest), set before the
+    /// runnable starts. Spawning waits for it and fails with the error of setting it, in which
+    /// case the runnable never runs. A thread placed this way is not recycled.
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.settings.placement.nice = Some(nice);
+        self
+    }
+
+    /// Nice value the thread takes once interrupted, until its runnable exits, e.g. for a
+    /// background thread started with `nice(19)` that must not hold up a shutdown. Lowering the
+    /// nice value needs `CAP_SYS_NICE` or a matching `RLIMIT_NICE`; without it the thread keeps
+    /// its priority.
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    pub fn boost_on_interrupt(mut self, nice: i32) -> Self {
+        self.settings.placement.interrupt_nice = Some(nice);
+        self
+    }
+
+    /// cgroup v2 directory, in threaded mode, the thread moves into when it starts. Failing to
+    /// move makes spawning fail, like `nice`.
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    pub fn cgroup<P>(mut self, cgroup: P) -> Self where P: Into<::std::path::PathBuf> {
+        self.settings.placement.cgroup = Some(cgroup.into());
+        self
+    }
+
+    /// Binds the thread to the CPUs of a NUMA node (see `numa_nodes`) and makes it allocate
+    /// memory from that node first. Failing to bind makes spawning fail, like `nice`, and the
+    /// bound thread is not recycled.
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    pub fn numa_node(mut self, node: usize) -> Self {
+        self.settings.placement.numa_node = Some(node);
+        self
+    }
+
+    /// Propagator capturing context when the handle is created and installing it in the
+    /// thread before the runnable, in addition to the ones added with `context::add_propagator`.
+    pub fn propagate_context(mut self, propagator: Arc<dyn ContextPropagator>) -> Self {
+        self.settings.propagators.push(propagator);
+        self
+    }
+
+    /// Value the runnable and its children can read with `token.context::<V>()`; a second
+    /// value of the same type replaces the first.
+    pub fn context_value<V>(mut self, value: V) -> Self where V: Any + Send + Sync {
+        self.settings.values.insert(value);
+        self
+    }
+
+    /// Whether dropping the handle while the thread runs is reported, see `detect_leaks`.
+    pub fn detect_leaks(mut self, enabled: bool) -> Self {
+        self.settings.detect_leaks = Some(enabled);
+        self
+    }
+
+    /// What dropping the handle does while the thread runs, see `DropPolicy`.
+    pub fn drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.settings.drop_policy = Some(policy);
+        self
+    }
+
+    /// Retries creating the thread while the OS is out of resources, as `policy` allows, calling
+    /// `on_failure` with the error and the number of the failed attempt before waiting, e.g. to
+    /// interrupt idle threads.
+    pub fn retry_spawn<F>(mut self, policy: RetryPolicy, on_failure: F) -> Self where
+        F: Fn(&io::Error, u32) + Send + Sync + 'static
+    {
+        self.settings.spawn_retry = Some(SpawnRetry {
+            policy,
+            on_failure: Arc::new(on_failure),
+        });
+        self
+    }
+
+    /// Runs the runnable on the calling thread, still with its own token, when the thread cannot
+    /// be created; the handle is then terminated by the time `spawn` returns.
+    pub fn inline_fallback(mut self) -> Self {
+        self.settings.inline_fallback = true;
+        self
+    }
+
+    /// Point in time by which the runnable should be done; the thread is interrupted once it
+    /// passes according to its clock, by a helper thread the crate starts for deadlines, so
+    /// that the interruption reaches observers and interruptible waits too.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.settings.deadline = Some(deadline);
+        self
+    }
+
+    /// Position of the thread in the teardown of its group or of the registry: lower classes are
+    /// interrupted and waited for first, so ingress threads can stop before the flushers they
+    /// feed without declaring a `ShutdownGraph`. The default class is 0.
+    pub fn shutdown_class(mut self, class: i32) -> Self {
+        self.settings.shutdown_class = class;
+        self
+    }
+
+    /// Caps the share of time the thread works from its start, see `InterruptToken::throttle`.
+    pub fn throttle(mut self, percent: u8) -> Self {
+        self.settings.throttle = Some(percent);
+        self
+    }
+
+    #[track_caller]
+    pub fn spawn<T, F>(&self, runnable: F) -> io::Result<ThreadHandle<T>> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        ThreadHandle::spawn_configured(self.name.clone(), self.settings.token_with(new_slot::<T>()), &self.settings, runnable)
+    }
+
+    /// Like `ThreadHandle::lazy`, with this configuration.
+    #[track_caller]
+    pub fn lazy<T, F>(&self, runnable: F) -> ThreadHandle<T> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        ThreadHandle::lazy_configured(self.name.clone(), &self.settings, runnable)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::Mutex;
+    use super::*;
+
+    thread_local! {
+        static REQUEST_ID: Cell<u32> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn test_builder_hooks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let on_start = events.clone();
+        let on_stop = events.clone();
+        let handle = Builder::new("Test hooks".to_string())
+            .on_thread_start(move || {
+                REQUEST_ID.with(|id| id.set(42));
+                on_start.lock().unwrap().push("start");
+            })
+            .on_thread_stop(move || on_stop.lock().unwrap().push("stop"))
+            .spawn(|_| REQUEST_ID.with(|id| id.get()))
+            .unwrap();
+        assert_eq!(42, handle.join().unwrap().unwrap());
+        assert_eq!(vec!["start", "stop"], *events.lock().unwrap());
+    }
+
+    #[test]
+    fn test_builder_stop_hook_after_panic() {
+        let stopped = Arc::new(Mutex::new(false));
+        let record = stopped.clone();
+        let handle = Builder::new("Test hooks panic".to_string())
+            .on_thread_stop(move || *record.lock().unwrap() = true)
+            .spawn(|_| -> u32 { panic!("") })
+            .unwrap();
+        assert!(handle.join().unwrap().is_err());
+        assert!(*stopped.lock().unwrap());
+    }
+
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    #[test]
+    fn test_builder_nice() {
+        extern "C" {
+            fn getpriority(which: i32, who: u32) -> i32;
+        }
+        let handle = Builder::new("Test nice".to_string())
+            .nice(19)
+            .spawn(|_| unsafe { getpriority(0, 0) })
+            .unwrap();
+        assert_eq!(19, handle.join().unwrap().unwrap());
+    }
+
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    #[test]
+    fn test_builder_missing_cgroup() {
+        let builder = Builder::new("Test cgroup".to_string()).cgroup("/nonexistent/thread-handle");
+        assert_eq!(io::ErrorKind::NotFound, builder.spawn(|_| ()).err().unwrap().kind());
+        let lazy = builder.lazy(|_| ());
+        assert_eq!(io::ErrorKind::NotFound, lazy.ensure_started().unwrap_err().kind());
+        assert!(lazy.join().is_none());
+    }
+}
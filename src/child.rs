@@ -0,0 +1,107 @@
+use std::io;
+use std::sync::{Arc, Weak};
+
+use {InterruptToken, Shared, ThreadHandle};
+
+
+impl Shared {
+    fn add_child(&self, child: &Arc<Shared>) {
+        let mut children = self.children.lock().unwrap();
+        match *children {
+            Some(ref mut children) => children.push(Arc::downgrade(child)),
+            None => {
+                drop(children);
+                child.request_interrupt(Some(&format!("parent {} exited", self.name())));
+            }
+        }
+    }
+
+    /// Interrupts every child, and every child added later, once the runnable has exited.
+    pub(crate) fn interrupt_children(&self) {
+        let children = self.children.lock().unwrap().take().unwrap_or_default();
+        if children.is_empty() {
+            return;
+        }
+        let reason = format!("parent {} exited", self.name());
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.request_interrupt(Some(&reason));
+        }
+    }
+}
+
+#[track_caller]
+fn spawn_child<T, F>(parent: &InterruptToken, name: String, runnable: F) -> io::Result<ThreadHandle<T>> where
+    T: Send + 'static,
+    F: FnOnce(InterruptToken) -> T, F: Send + 'static
+{
+    let child = ThreadHandle::spawn_with_token(name, parent.child(), runnable)?;
+    parent.shared.add_child(&child.shared);
+    Ok(child)
+}
+
+impl InterruptToken {
+    /// Spawns a thread that is interrupted when this token is, and when the runnable owning this
+    /// token returns or panics.
+    #[track_caller]
+    pub fn spawn_child<T, F>(&self, name: String, runnable: F) -> io::Result<ThreadHandle<T>> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        spawn_child(self, name, runnable)
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Spawns a thread that is interrupted when this one is interrupted or terminates.
+    #[track_caller]
+    pub fn spawn_child<U, F>(&self, name: String, runnable: F) -> io::Result<ThreadHandle<U>> where
+        U: Send + 'static,
+        F: FnOnce(InterruptToken) -> U, F: Send + 'static
+    {
+        spawn_child(&InterruptToken { shared: self.shared.clone() }, name, runnable)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use super::*;
+
+    fn wait_for_interrupt(interrupted: InterruptToken) {
+        while !interrupted.is_interrupted() {
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_children_interrupted_when_parent_returns() {
+        let (sender, receiver) = mpsc::channel();
+        let parent = ThreadHandle::spawn("Test child parent".to_string(), move |interrupted| {
+            let child = interrupted.spawn_child("Test child".to_string(), wait_for_interrupt).unwrap();
+            sender.send(child).unwrap();
+        }).unwrap();
+        parent.join().unwrap().unwrap();
+        let child = receiver.recv().unwrap();
+        assert!(child.join().unwrap().is_ok());
+        assert_eq!(Some("parent Test child parent exited"), child.interrupt_reason());
+    }
+
+    #[test]
+    fn test_children_interrupted_when_parent_panics() {
+        let parent = ThreadHandle::spawn("Test child panicking parent".to_string(), |interrupted| {
+            wait_for_interrupt(interrupted);
+            panic!("parent failed");
+        }).unwrap();
+        let child = parent.spawn_child("Test child of panicking".to_string(), wait_for_interrupt).unwrap();
+        sleep(Duration::from_millis(50));
+        assert!(!child.is_interrupt_requested());
+        ::test_util::assert_stops_after_interrupt(&parent, Duration::from_secs(5));
+        assert!(parent.join().unwrap().is_err());
+        assert!(child.join().unwrap().is_ok());
+        let late = parent.spawn_child("Test child late".to_string(), wait_for_interrupt).unwrap();
+        assert!(late.join().unwrap().is_ok());
+    }
+}
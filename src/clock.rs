@@ -0,0 +1,161 @@
+use std::cmp;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::Mutex;
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::atomic::{self, AtomicUsize};
+
+
+/// Real time between two looks at a clock while waiting for it to reach a deadline.
+pub(crate) const WAIT_SLICE: Duration = Duration::from_millis(10);
+
+/// Source of time for timestamps, heartbeats, staleness, watchdogs, retry backoff, rate
+/// limiting, and the timeouts of joins and termination waits.
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+pub(crate) fn system() -> Arc<dyn Clock> {
+    static SYSTEM: OnceLock<Arc<dyn Clock>> = OnceLock::new();
+    SYSTEM.get_or_init(|| Arc::new(SystemClock)).clone()
+}
+
+/// Waits until `clock` reaches `deadline` or `interrupted` returns `true`, polling in short
+/// slices of real time so that a mock clock can be advanced from another thread.
+pub(crate) fn sleep_until<F>(clock: &dyn Clock, deadline: Instant, interrupted: F) where F: Fn() -> bool {
+    while !interrupted() {
+        let now = clock.now();
+        if now >= deadline {
+            break;
+        }
+        sleep(cmp::min(deadline - now, WAIT_SLICE));
+    }
+}
+
+/// Timeout shared by waits for several threads one after the other, each wait counted on the
+/// clock of the thread it waits for.
+pub(crate) struct WaitBudget {
+    left: Option<Duration>,
+}
+
+impl WaitBudget {
+    pub(crate) fn new(timeout: Option<Duration>) -> Self {
+        WaitBudget { left: timeout }
+    }
+
+    /// Runs `wait` with what is left, then deducts the time it took on `clock`.
+    pub(crate) fn spend<R, F>(&mut self, clock: &dyn Clock, wait: F) -> R where F: FnOnce(Option<Duration>) -> R {
+        let started = clock.now();
+        let result = wait(self.left);
+        let spent = clock.now().saturating_duration_since(started);
+        self.left = self.left.map(|left| left.saturating_sub(spent));
+        result
+    }
+}
+
+/// Clock that only moves when told to, for tests of time-based behaviour without real sleeps.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<Instant>,
+    reads: AtomicUsize,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockClock {
+    /// Mock clock starting at the current real time.
+    pub fn new() -> Self {
+        MockClock { now: Mutex::new(Instant::now()), reads: AtomicUsize::new(0) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+
+    /// How often the clock was read so far. A thread waiting for the clock to reach a deadline
+    /// reads it every few milliseconds, so a count that keeps growing tells that it is waiting.
+    pub fn reads(&self) -> usize {
+        self.reads.load(atomic::Ordering::SeqCst)
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.reads.fetch_add(1, atomic::Ordering::SeqCst);
+        *self.now.lock().unwrap()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use test_util::assert_eventually;
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        assert_eq!(start, clock.now());
+        assert_eq!(2, clock.reads());
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(Duration::from_secs(60), clock.now() - start);
+    }
+
+    #[test]
+    fn test_wait_budget() {
+        let clock = MockClock::new();
+        let mut budget = WaitBudget::new(Some(Duration::from_secs(60)));
+        assert_eq!(Some(Duration::from_secs(60)), budget.spend(&clock, |left| {
+            clock.advance(Duration::from_secs(45));
+            left
+        }));
+        assert_eq!(Some(Duration::from_secs(15)), budget.spend(&clock, |left| {
+            clock.advance(Duration::from_secs(45));
+            left
+        }));
+        assert_eq!(Some(Duration::from_secs(0)), budget.spend(&clock, |left| left));
+        assert_eq!(None, WaitBudget::new(None).spend(&clock, |left| left));
+    }
+
+    #[test]
+    fn test_sleep_until_follows_mock_clock() {
+        let clock = Arc::new(MockClock::new());
+        let deadline = clock.now() + Duration::from_secs(3600);
+        let woken = Arc::new(AtomicBool::new(false));
+        let (sleeping, flag) = (clock.clone(), woken.clone());
+        let sleeper = thread::spawn(move || {
+            sleep_until(&*sleeping, deadline, || false);
+            flag.store(true, atomic::Ordering::SeqCst);
+        });
+        let reads = clock.reads();
+        assert_eventually(Duration::from_secs(5), "sleeper waiting", || clock.reads() >= reads + 2);
+        assert!(!woken.load(atomic::Ordering::SeqCst));
+        clock.advance(Duration::from_secs(3600));
+        sleeper.join().unwrap();
+        assert!(woken.load(atomic::Ordering::SeqCst));
+    }
+}
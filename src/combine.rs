@@ -0,0 +1,559 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "stream")]
+use std::pin::Pin;
+use std::sync::{atomic, Arc, Condvar, Mutex};
+#[cfg(feature = "stream")]
+use std::task::{Context, Poll};
+use std::task::Waker;
+use std::thread;
+
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+
+use backend;
+use builder::SpawnSettings;
+use group::{FailedThread, JoinFailure};
+use panics::PanicPayload;
+use {InterruptToken, ResultSlot, Shared, Slot, SlotRef, ThreadHandle};
+
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Calls `f` with the outcome of the runnable once it is available, or right away with `None`
+    /// if the result was already taken or handed to a receiver. Starts a lazy handle.
+    pub(crate) fn on_outcome<F>(&self, f: F) where F: FnOnce(Option<thread::Result<T>>) + Send + 'static {
+        {
+            let mut slot = self.result.lock().unwrap();
+            slot.resolve();
+            match slot.result.take() {
+                Some(outcome) => f(Some(outcome)),
+                // A forwarded result marks the thread joined before it terminates.
+                None if slot.forward.is_none() && !self.shared.joined.load(atomic::Ordering::Acquire) && !self.shared.is_terminated() => {
+                    slot.forward = Some(Box::new(move |outcome| f(Some(outcome))));
+                }
+                None => f(None),
+            }
+        }
+        self.ensure_started().ok();
+    }
+
+    /// Turns the handle into one of the same thread whose result is `f` applied to the value of
+    /// the runnable. `f` runs once, on the thread that first needs the mapped result: the one
+    /// joining, or the runnable's as soon as a receiver or a combinator waits for it. A panic in
+    /// `f` is reported as the outcome of the new handle.
+    pub fn map<U, F>(self, f: F) -> ThreadHandle<U> where
+        U: Send + 'static,
+        F: FnOnce(T) -> U + Send + 'static
+    {
+        let mapped = Arc::new(Mutex::new(Slot::new()));
+        let target = mapped.clone();
+        self.on_outcome(move |outcome| {
+            if let Some(outcome) = outcome {
+                let mut target = target.lock().unwrap();
+                target.deferred = Some(Box::new(move || {
+                    outcome.and_then(|value| panic::catch_unwind(AssertUnwindSafe(move || f(value))))
+                }));
+                if target.forward.is_some() || !target.subscribers.is_empty() {
+                    target.resolve();
+                }
+            }
+        });
+        ThreadHandle {
+            shared: self.shared.clone(),
+            result: SlotRef::Own(mapped),
+            join_handle: Mutex::new(self.join_handle.lock().unwrap().take()),
+            pending: Mutex::new(self.pending.lock().unwrap().take()),
+        }
+    }
+
+    /// Spawns `continuation` with the outcome of the runnable once it is available, returning the
+    /// handle of the continuation. If the result was already taken, the continuation never runs
+    /// and its handle terminates without a result.
+    pub fn then<U, F>(self, name: String, continuation: F) -> ThreadHandle<U> where
+        U: Send + 'static,
+        F: FnOnce(thread::Result<T>, InterruptToken) -> U + Send + 'static
+    {
+        let settings = SpawnSettings::default();
+        let input = Arc::new(Mutex::new(None));
+        let received = input.clone();
+        let (handle, job) = ThreadHandle::prepare(&name, true, settings.token(), &settings, move |token| {
+            let outcome = received.lock().unwrap().take().expect("continuation started without an outcome");
+            continuation(outcome, token)
+        });
+        let joined = self.shared.clone();
+        let continued = handle.shared.clone();
+        self.on_outcome(move |outcome| match outcome {
+            Some(outcome) => {
+                joined.joined.store(true, atomic::Ordering::Release);
+                *input.lock().unwrap() = Some(outcome);
+                if backend::resolve(None).spawn(name, Box::new(job)).is_err() {
+                    continued.finish(None);
+                }
+            }
+            None => continued.finish(None),
+        });
+        handle
+    }
+}
+
+/// Handle without a thread of its own, interrupting `targets` when interrupted; it terminates
+/// through `complete`.
+fn composite<U>(name: String, targets: &[&Arc<Shared>]) -> ThreadHandle<U> {
+    let token = InterruptToken::new();
+    let shared = token.shared.clone();
+    shared.name.set(name).ok();
+    shared.started.set(shared.created, shared.clock.now());
+    for target in targets {
+        shared.add_propagation_target(target);
+    }
+    ThreadHandle {
+        shared,
+        result: SlotRef::Own(Arc::new(Mutex::new(Slot::new()))),
+        join_handle: Mutex::new(None),
+        pending: Mutex::new(None),
+    }
+}
+
+fn complete<U>(shared: &Shared, result: &ResultSlot<U>, outcome: Option<thread::Result<U>>) {
+    let panic = match outcome {
+        Some(Err(ref payload)) => Some(payload.describe()),
+        _ => None,
+    };
+    if let Some(outcome) = outcome {
+        result.lock().unwrap().deliver(outcome);
+    }
+    shared.finish(panic.as_deref());
+}
+
+struct Race<T> {
+    winner: Option<thread::Result<T>>,
+    decided: bool,
+    remaining: usize,
+}
+
+/// Handle of the first of two threads to finish: the other is interrupted, and the handle
+/// terminates with the outcome of the winner once both did. Interrupting it interrupts both.
+pub fn race<T>(first: ThreadHandle<T>, second: ThreadHandle<T>) -> ThreadHandle<T> where T: Send + 'static {
+    let handle = composite(format!("race({}, {})", first.name(), second.name()), &[&first.shared, &second.shared]);
+    let state = Arc::new(Mutex::new(Race { winner: None, decided: false, remaining: 2 }));
+    for (branch, other) in [(&first, &second), (&second, &first)].iter() {
+        let (state, shared, result) = (state.clone(), handle.shared.clone(), handle.result.clone());
+        let (winner, loser) = (branch.shared.clone(), other.shared.clone());
+        branch.on_outcome(move |outcome| {
+            let mut state = state.lock().unwrap();
+            state.remaining -= 1;
+            if !state.decided && outcome.is_some() {
+                state.decided = true;
+                state.winner = outcome;
+                loser.request_interrupt(Some(&format!("lost race to {}", winner.name())));
+            }
+            if state.remaining == 0 {
+                complete(&shared, &result, state.winner.take());
+            }
+        });
+    }
+    handle
+}
+
+struct Both<T, U> {
+    first: Option<thread::Result<T>>,
+    second: Option<thread::Result<U>>,
+    remaining: usize,
+    branches: [Arc<Shared>; 2],
+    shared: Arc<Shared>,
+    result: SlotRef<(T, U)>,
+}
+
+impl<T, U> Both<T, U> {
+    /// Accounts for the outcome of `branch`, interrupting the other one if it failed.
+    fn settle(&mut self, branch: usize, failed: bool) {
+        self.remaining -= 1;
+        if failed {
+            let reason = format!("{} panicked", self.branches[branch].name());
+            self.branches[1 - branch].request_interrupt(Some(&reason));
+        }
+        if self.remaining > 0 {
+            return;
+        }
+        let outcome = match (self.first.take(), self.second.take()) {
+            (Some(Err(payload)), _) | (_, Some(Err(payload))) => Some(Err(payload)),
+            (Some(Ok(first)), Some(Ok(second))) => Some(Ok((first, second))),
+            _ => None,
+        };
+        complete(&self.shared, &self.result, outcome);
+    }
+}
+
+/// Handle pairing the results of two threads once both terminated. When either panics the
+/// other is interrupted, and the handle reports the panic. Interrupting it interrupts both.
+pub fn both<T, U>(first: ThreadHandle<T>, second: ThreadHandle<U>) -> ThreadHandle<(T, U)> where
+    T: Send + 'static,
+    U: Send + 'static
+{
+    let handle = composite(format!("both({}, {})", first.name(), second.name()), &[&first.shared, &second.shared]);
+    let state = Arc::new(Mutex::new(Both {
+        first: None,
+        second: None,
+        remaining: 2,
+        branches: [first.shared.clone(), second.shared.clone()],
+        shared: handle.shared.clone(),
+        result: handle.result.clone(),
+    }));
+    let settled = state.clone();
+    first.on_outcome(move |outcome| {
+        let failed = outcome.as_ref().is_some_and(Result::is_err);
+        let mut state = settled.lock().unwrap();
+        state.first = outcome;
+        state.settle(0, failed);
+    });
+    second.on_outcome(move |outcome| {
+        let failed = outcome.as_ref().is_some_and(Result::is_err);
+        let mut state = state.lock().unwrap();
+        state.second = outcome;
+        state.settle(1, failed);
+    });
+    handle
+}
+
+
+/// Error of `join2` and `join3`: the threads that failed, by their position in the arguments.
+#[derive(Debug)]
+pub struct JoinError {
+    pub failed: Vec<FailedThread<Infallible>>,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.failed.len();
+        write!(f, "{} {} failed:", count, if count == 1 { "thread" } else { "threads" })?;
+        for (i, failed) in self.failed.iter().enumerate() {
+            write!(f, "{} {} ", if i == 0 { "" } else { "," }, failed.thread)?;
+            match failed.failure {
+                JoinFailure::Panicked(ref payload) => write!(f, "(panicked: {})", payload.describe())?,
+                JoinFailure::Errored(never) => match never {},
+                JoinFailure::AlreadyJoined => write!(f, "(already joined)")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for JoinError {}
+
+fn join_recording<T>(index: usize, handle: &ThreadHandle<T>, failed: &mut Vec<FailedThread<Infallible>>) -> Option<T> where
+    T: Send + 'static
+{
+    let failure = match handle.join() {
+        Some(Ok(value)) => return Some(value),
+        Some(Err(payload)) => JoinFailure::Panicked(payload),
+        None => JoinFailure::AlreadyJoined,
+    };
+    failed.push(FailedThread {
+        index,
+        thread: handle.name().to_string(),
+        failure,
+    });
+    None
+}
+
+/// Joins two threads producing different types; all of them are joined even if one fails.
+pub fn join2<A, B>(a: &ThreadHandle<A>, b: &ThreadHandle<B>) -> Result<(A, B), JoinError> where
+    A: Send + 'static,
+    B: Send + 'static
+{
+    let mut failed = Vec::new();
+    match (join_recording(0, a, &mut failed), join_recording(1, b, &mut failed)) {
+        (Some(a), Some(b)) => Ok((a, b)),
+        _ => Err(JoinError { failed }),
+    }
+}
+
+/// Joins three threads producing different types, like `join2`.
+pub fn join3<A, B, C>(a: &ThreadHandle<A>, b: &ThreadHandle<B>, c: &ThreadHandle<C>) -> Result<(A, B, C), JoinError> where
+    A: Send + 'static,
+    B: Send + 'static,
+    C: Send + 'static
+{
+    let mut failed = Vec::new();
+    match (join_recording(0, a, &mut failed), join_recording(1, b, &mut failed), join_recording(2, c, &mut failed)) {
+        (Some(a), Some(b), Some(c)) => Ok((a, b, c)),
+        _ => Err(JoinError { failed }),
+    }
+}
+
+
+struct Completions<T> {
+    outcomes: VecDeque<(usize, thread::Result<T>)>,
+    /// Threads that have not reported their outcome yet.
+    remaining: usize,
+    /// Tasks polling for the next outcome.
+    wakers: Vec<Waker>,
+}
+
+struct CompletionQueue<T> {
+    completions: Mutex<Completions<T>>,
+    completed: Condvar,
+}
+
+/// Reports the outcome of one thread, or that it has none if it is dropped unused, e.g. along
+/// with a job its backend never ran.
+struct Reporter<T> {
+    queue: Option<Arc<CompletionQueue<T>>>,
+}
+
+impl<T> Reporter<T> {
+    fn report(mut self, outcome: Option<(usize, thread::Result<T>)>) {
+        if let Some(queue) = self.queue.take() {
+            queue.report(outcome);
+        }
+    }
+}
+
+impl<T> Drop for Reporter<T> {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.report(None);
+        }
+    }
+}
+
+impl<T> CompletionQueue<T> {
+    fn report(&self, outcome: Option<(usize, thread::Result<T>)>) {
+        let mut completions = self.completions.lock().unwrap();
+        completions.remaining -= 1;
+        completions.outcomes.extend(outcome);
+        for waker in completions.wakers.drain(..) {
+            waker.wake();
+        }
+        self.completed.notify_all();
+    }
+}
+
+/// Outcomes of a set of threads in the order they finished, see `as_completed`. With the
+/// `stream` feature it is also a `futures_core::Stream` of the same outcomes.
+pub struct Completed<T> {
+    queue: Arc<CompletionQueue<T>>,
+}
+
+impl<T> Iterator for Completed<T> {
+    type Item = (usize, thread::Result<T>);
+
+    /// Blocks until another thread finishes; ends once every thread with a result reported it.
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut completions = self.queue.completions.lock().unwrap();
+        loop {
+            if let Some(outcome) = completions.outcomes.pop_front() {
+                return Some(outcome);
+            }
+            if completions.remaining == 0 {
+                return None;
+            }
+            completions = self.queue.completed.wait(completions).unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<T> Stream for Completed<T> {
+    type Item = (usize, thread::Result<T>);
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut completions = self.queue.completions.lock().unwrap();
+        if let Some(outcome) = completions.outcomes.pop_front() {
+            return Poll::Ready(Some(outcome));
+        }
+        if completions.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        if !completions.wakers.iter().any(|waker| waker.will_wake(context.waker())) {
+            completions.wakers.push(context.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Iterates over the outcomes of `handles` as each of them finishes, along with the position of
+/// the handle; threads whose result was already taken are skipped.
+pub fn as_completed<T, I>(handles: I) -> Completed<T> where
+    T: Send + 'static,
+    I: IntoIterator<Item = ThreadHandle<T>>
+{
+    let handles: Vec<_> = handles.into_iter().collect();
+    let queue = Arc::new(CompletionQueue {
+        completions: Mutex::new(Completions {
+            outcomes: VecDeque::new(),
+            remaining: handles.len(),
+            wakers: Vec::new(),
+        }),
+        completed: Condvar::new(),
+    });
+    for (index, handle) in handles.into_iter().enumerate() {
+        let reporter = Reporter { queue: Some(queue.clone()) };
+        let joined = handle.shared.clone();
+        handle.on_outcome(move |outcome| {
+            if outcome.is_some() {
+                joined.joined.store(true, atomic::Ordering::Release);
+            }
+            reporter.report(outcome.map(|outcome| (index, outcome)));
+        });
+    }
+    Completed { queue }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let handle = ThreadHandle::spawn("Test map".to_string(), |_| 20).unwrap().map(|value| value + 1).map(|value| value * 2);
+        assert_eq!(42, handle.join().unwrap().unwrap());
+        assert!(handle.join().is_none());
+        let finished = ThreadHandle::spawn("Test map finished".to_string(), |_| "done").unwrap();
+        finished.wait_finished(None);
+        assert_eq!(4, finished.map(|value| value.len()).join().unwrap().unwrap());
+        let lazy = ThreadHandle::lazy("Test map lazy".to_string(), |_| 1).map(|value| value.to_string());
+        assert_eq!("1", lazy.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_map_applied_at_join() {
+        let handle = ThreadHandle::spawn("Test map at join".to_string(), |_| ()).unwrap()
+            .map(|_| thread::current().id());
+        handle.wait_finished(None);
+        assert_eq!(thread::current().id(), handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_then() {
+        let handle = ThreadHandle::spawn("Test then".to_string(), |_| 20).unwrap()
+            .then("Test then first".to_string(), |outcome, _| outcome.unwrap() + 1)
+            .then("Test then second".to_string(), |outcome, _| outcome.unwrap() * 2);
+        assert_eq!("Test then second", handle.name());
+        assert_eq!(42, handle.join().unwrap().unwrap());
+        let failed = ThreadHandle::spawn("Test then panic".to_string(), |_| -> i32 { panic!("first") }).unwrap()
+            .then("Test then recover".to_string(), |outcome, _| outcome.unwrap_err().describe());
+        assert_eq!("first", failed.join().unwrap().unwrap());
+        let taken = ThreadHandle::spawn("Test then taken".to_string(), |_| ()).unwrap();
+        let _receiver = taken.result_receiver().unwrap();
+        assert!(taken.then("Test then never".to_string(), |_, _| ()).join().is_none());
+    }
+
+    #[test]
+    fn test_map_panics() {
+        let handle = ThreadHandle::spawn("Test map panic".to_string(), |_| 0).unwrap().map(|value: i32| {
+            if value == 0 {
+                panic!("zero");
+            }
+            value
+        });
+        assert_eq!("zero", handle.join().unwrap().unwrap_err().describe());
+    }
+
+    #[test]
+    fn test_race() {
+        let slow = ThreadHandle::spawn("Test race slow".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+            "slow"
+        }).unwrap();
+        let fast = ThreadHandle::spawn("Test race fast".to_string(), |_| "fast").unwrap();
+        let raced = race(slow, fast);
+        assert_eq!("race(Test race slow, Test race fast)", raced.name());
+        assert_eq!("fast", raced.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_both() {
+        let first = ThreadHandle::spawn("Test both first".to_string(), |_| 1).unwrap();
+        let second = ThreadHandle::lazy("Test both second".to_string(), |_| "two");
+        assert_eq!((1, "two"), both(first, second).join().unwrap().unwrap());
+        let waiting = ThreadHandle::spawn("Test both waiting".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        let failing = ThreadHandle::spawn("Test both failing".to_string(), |_| panic!("failed")).unwrap();
+        let joined = both(waiting, failing);
+        assert_eq!("failed", joined.join().unwrap().unwrap_err().describe());
+        assert!(joined.status() == ::ThreadStatus::Terminated);
+        let interrupted = both(
+            ThreadHandle::spawn("Test both interrupted".to_string(), |interrupted| while !interrupted.is_interrupted() { sleep(Duration::from_millis(5)) }).unwrap(),
+            ThreadHandle::spawn("Test both interrupted too".to_string(), |interrupted| while !interrupted.is_interrupted() { sleep(Duration::from_millis(5)) }).unwrap(),
+        );
+        interrupted.interrupt().unwrap();
+        assert!(interrupted.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_join2_join3() {
+        let number = ThreadHandle::spawn("Test join2 number".to_string(), |_| 1).unwrap();
+        let text = ThreadHandle::spawn("Test join2 text".to_string(), |_| "two").unwrap();
+        assert_eq!((1, "two"), join2(&number, &text).unwrap());
+        let first = ThreadHandle::spawn("Test join3 first".to_string(), |_| 1).unwrap();
+        let failing = ThreadHandle::spawn("Test join3 failing".to_string(), |_| -> u8 { panic!("failed") }).unwrap();
+        let last = ThreadHandle::spawn("Test join3 last".to_string(), |_| ()).unwrap();
+        last.join().unwrap().unwrap();
+        let error = join3(&first, &failing, &last).unwrap_err();
+        assert_eq!(vec![1, 2], error.failed.iter().map(|failed| failed.index).collect::<Vec<_>>());
+        assert_eq!("2 threads failed: Test join3 failing (panicked: failed), Test join3 last (already joined)", error.to_string());
+        let fresh = ThreadHandle::spawn("Test join2 fresh".to_string(), |_| 3).unwrap();
+        let error = join2(&fresh, &text).unwrap_err();
+        assert_eq!("1 thread failed: Test join2 text (already joined)", error.to_string());
+    }
+
+    #[test]
+    fn test_as_completed() {
+        let (sender, receiver) = mpsc::channel::<()>();
+        let blocked = ThreadHandle::spawn("Test completed blocked".to_string(), move |_| receiver.recv().map(|_| "blocked").unwrap()).unwrap();
+        let quick = ThreadHandle::spawn("Test completed quick".to_string(), |_| "quick").unwrap();
+        let taken = ThreadHandle::spawn("Test completed taken".to_string(), |_| "taken").unwrap();
+        taken.join().unwrap().unwrap();
+        let mut completed = as_completed(vec![blocked, quick, taken]);
+        let (index, outcome) = completed.next().unwrap();
+        assert_eq!((1, "quick"), (index, outcome.unwrap()));
+        sender.send(()).unwrap();
+        let (index, outcome) = completed.next().unwrap();
+        assert_eq!((0, "blocked"), (index, outcome.unwrap()));
+        assert!(completed.next().is_none());
+    }
+
+    #[cfg(feature = "stream")]
+    struct Unpark(thread::Thread);
+
+    #[cfg(feature = "stream")]
+    impl ::std::task::Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_as_completed_stream() {
+        let (sender, receiver) = mpsc::channel::<()>();
+        let blocked = ThreadHandle::spawn("Test completed stream blocked".to_string(), move |_| receiver.recv().map(|_| 1).unwrap()).unwrap();
+        let quick = ThreadHandle::spawn("Test completed stream quick".to_string(), |_| 2).unwrap();
+        let mut completed = as_completed(vec![blocked, quick]);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut next = || loop {
+            match Pin::new(&mut completed).poll_next(&mut context) {
+                Poll::Ready(next) => break next.map(|(index, outcome)| (index, outcome.unwrap())),
+                Poll::Pending => thread::park(),
+            }
+        };
+        assert_eq!(Some((1, 2)), next());
+        sender.send(()).unwrap();
+        assert_eq!(Some((0, 1)), next());
+        assert_eq!(None, next());
+    }
+}
@@ -0,0 +1,266 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::time::Duration;
+
+use {InterruptToken, Shared, ThreadHandle};
+
+
+/// How often a worker waiting for a command looks for interruption.
+const INTERRUPT_CHECK: Duration = Duration::from_millis(10);
+
+/// Error sending a command; holds the command that was not delivered.
+#[derive(PartialEq, Eq)]
+pub enum CommandError<C> {
+    /// The command buffer is full; only returned by `try_send`.
+    Full(C),
+    /// The worker has terminated or stopped receiving commands.
+    Terminated(C),
+    /// The worker is quiescing: it runs the commands already sent but takes no more.
+    Quiescing(C),
+}
+
+impl<C> CommandError<C> {
+    pub fn into_inner(self) -> C {
+        match self {
+            CommandError::Full(command) | CommandError::Terminated(command) | CommandError::Quiescing(command) => command,
+        }
+    }
+}
+
+impl<C> fmt::Debug for CommandError<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::Full(_) => write!(f, "Full(..)"),
+            CommandError::Terminated(_) => write!(f, "Terminated(..)"),
+            CommandError::Quiescing(_) => write!(f, "Quiescing(..)"),
+        }
+    }
+}
+
+impl<C> fmt::Display for CommandError<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::Full(_) => write!(f, "command buffer is full"),
+            CommandError::Terminated(_) => write!(f, "worker has terminated"),
+            CommandError::Quiescing(_) => write!(f, "worker is quiescing"),
+        }
+    }
+}
+
+impl<C> Error for CommandError<C> {}
+
+/// Owner side of a command channel. Senders are counted from their look at quiescing until their
+/// command is in the channel, so that a quiescing worker does not stop while a command it
+/// accepted is still on its way.
+pub(crate) struct CommandSender<C> {
+    sender: SyncSender<C>,
+    sending: Arc<AtomicUsize>,
+}
+
+/// Worker side of a command channel, see `CommandSender`.
+pub(crate) struct CommandReceiver<C> {
+    receiver: Receiver<C>,
+    sending: Arc<AtomicUsize>,
+}
+
+pub(crate) fn channel<C>(capacity: usize) -> (CommandSender<C>, CommandReceiver<C>) {
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let sending = Arc::new(AtomicUsize::new(0));
+    (CommandSender { sender, sending: sending.clone() }, CommandReceiver { receiver, sending })
+}
+
+impl<C> CommandSender<C> {
+    /// Sends a command to the worker owning `shared`, blocking while the buffer is full.
+    pub(crate) fn send(&self, shared: &Shared, command: C) -> Result<(), CommandError<C>> {
+        self.deliver(shared, command, |sender, command| {
+            sender.send(command).map_err(|mpsc::SendError(command)| CommandError::Terminated(command))
+        })
+    }
+
+    pub(crate) fn try_send(&self, shared: &Shared, command: C) -> Result<(), CommandError<C>> {
+        self.deliver(shared, command, |sender, command| {
+            sender.try_send(command).map_err(|error| match error {
+                TrySendError::Full(command) => CommandError::Full(command),
+                TrySendError::Disconnected(command) => CommandError::Terminated(command),
+            })
+        })
+    }
+
+    fn deliver<F>(&self, shared: &Shared, command: C, send: F) -> Result<(), CommandError<C>> where
+        F: FnOnce(&SyncSender<C>, C) -> Result<(), CommandError<C>>
+    {
+        self.sending.fetch_add(1, atomic::Ordering::SeqCst);
+        let sent = if shared.is_quiescing() {
+            Err(CommandError::Quiescing(command))
+        } else {
+            send(&self.sender, command)
+        };
+        self.sending.fetch_sub(1, atomic::Ordering::SeqCst);
+        sent
+    }
+}
+
+/// Worker side of the command channel.
+pub struct Commands<C> {
+    receiver: CommandReceiver<C>,
+    token: InterruptToken,
+}
+
+impl<C> Commands<C> {
+    pub(crate) fn new(receiver: CommandReceiver<C>, token: InterruptToken) -> Self {
+        Commands { receiver, token }
+    }
+
+    /// Waits for the next command; returns `None` once interruption was requested, the owner
+    /// dropped its handle, or the worker is quiescing and every command sent was received.
+    pub fn recv(&self) -> Option<C> {
+        while !self.token.is_interrupted() {
+            // Once quiescing is observed, a sender either already saw it and gave up, or is
+            // counted until its command is in the channel.
+            let drained = self.token.is_quiescing() && self.receiver.sending.load(atomic::Ordering::SeqCst) == 0;
+            match self.receiver.receiver.recv_timeout(INTERRUPT_CHECK) {
+                Ok(command) => return Some(command),
+                Err(RecvTimeoutError::Timeout) if drained => return None,
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+        None
+    }
+
+    /// Returns a pending command without waiting.
+    pub fn try_recv(&self) -> Option<C> {
+        self.receiver.receiver.try_recv().ok()
+    }
+}
+
+/// Handle of a thread controlled through typed commands.
+pub struct CommandHandle<C, T> {
+    sender: CommandSender<C>,
+    handle: ThreadHandle<T>,
+}
+
+impl<C, T> CommandHandle<C, T> where T: Send + 'static {
+    pub fn handle(&self) -> &ThreadHandle<T> {
+        &self.handle
+    }
+
+    /// Sends a command, blocking while the buffer is full.
+    pub fn send(&self, command: C) -> Result<(), CommandError<C>> {
+        self.sender.send(&self.handle.shared, command)
+    }
+
+    pub fn try_send(&self, command: C) -> Result<(), CommandError<C>> {
+        self.sender.try_send(&self.handle.shared, command)
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Spawns a worker receiving commands from the returned handle, buffering up to `capacity`.
+    pub fn spawn_with_commands<C, F>(name: String, capacity: usize, runnable: F) -> io::Result<CommandHandle<C, T>> where
+        C: Send + 'static,
+        F: FnOnce(InterruptToken, Commands<C>) -> T, F: Send + 'static
+    {
+        let (sender, receiver) = channel(capacity);
+        let handle = ThreadHandle::spawn(name, move |interrupted: InterruptToken| {
+            let commands = Commands::new(receiver, interrupted.clone());
+            runnable(interrupted, commands)
+        })?;
+        Ok(CommandHandle { sender, handle })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Command {
+        Add(u32),
+        Stop,
+    }
+
+    #[test]
+    fn test_commands() {
+        let worker = ThreadHandle::spawn_with_commands("Test commands".to_string(), 4, |_, commands| {
+            let mut total = 0;
+            while let Some(command) = commands.recv() {
+                match command {
+                    Command::Add(amount) => total += amount,
+                    Command::Stop => break,
+                }
+            }
+            total
+        }).unwrap();
+        worker.send(Command::Add(2)).unwrap();
+        worker.send(Command::Add(3)).unwrap();
+        worker.send(Command::Stop).unwrap();
+        assert_eq!(5, worker.handle().join().unwrap().unwrap());
+        match worker.send(Command::Add(1)) {
+            Err(CommandError::Terminated(Command::Add(1))) => (),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_commands_quiesce() {
+        let worker = ThreadHandle::spawn_with_commands("Test commands quiesce".to_string(), 4, |_, commands| {
+            let mut received = Vec::new();
+            while let Some(command) = commands.recv() {
+                std::thread::sleep(INTERRUPT_CHECK);
+                received.push(command);
+            }
+            received
+        }).unwrap();
+        for command in 0..3 {
+            worker.send(command).unwrap();
+        }
+        worker.handle().quiesce();
+        assert_eq!(Err(CommandError::Quiescing(3)), worker.try_send(3));
+        assert_eq!(vec![0, 1, 2], worker.handle().join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_commands_accepted_while_quiescing_are_received() {
+        for _ in 0..20 {
+            let worker = ThreadHandle::spawn_with_commands("Test commands race".to_string(), 1024, |_, commands| {
+                let mut received = 0;
+                while commands.recv().is_some() {
+                    received += 1;
+                }
+                received
+            }).unwrap();
+            let worker = ::std::sync::Arc::new(worker);
+            let sending = worker.clone();
+            let sender = ::std::thread::spawn(move || {
+                let mut accepted = 0;
+                while sending.send(accepted).is_ok() {
+                    accepted += 1;
+                }
+                accepted
+            });
+            ::std::thread::sleep(Duration::from_millis(1));
+            worker.handle().quiesce();
+            let accepted = sender.join().unwrap();
+            assert_eq!(accepted, worker.handle().join().unwrap().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_commands_full_and_interrupt() {
+        let worker = ThreadHandle::spawn_with_commands("Test commands full".to_string(), 1, |interrupted, commands: Commands<u32>| {
+            while !interrupted.is_interrupted() {
+                std::thread::sleep(INTERRUPT_CHECK);
+            }
+            commands.recv()
+        }).unwrap();
+        worker.try_send(1).unwrap();
+        assert_eq!(Err(CommandError::Full(2)), worker.try_send(2));
+        worker.handle().interrupt().unwrap();
+        assert_eq!(None, worker.handle().join().unwrap().unwrap());
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use events::{self, Subscriber};
+use leak::{self, DropPolicy};
+use ordering::{self, InterruptOrdering};
+use panics::PanicPolicy;
+
+
+static INSTALLED: OnceLock<ThreadHandleConfig> = OnceLock::new();
+
+/// Defaults for every thread and pool spawned after it is installed, so that a large code base
+/// behaves consistently without configuring each spawn; builders still override them.
+#[derive(Clone, Default)]
+pub struct ThreadHandleConfig {
+    stack_size: Option<usize>,
+    detect_leaks: Option<bool>,
+    drop_policy: DropPolicy,
+    panic_policy: PanicPolicy,
+    subscribers: Vec<Arc<dyn Subscriber>>,
+    name_prefix: Option<String>,
+    interrupt_ordering: Option<InterruptOrdering>,
+}
+
+impl ThreadHandleConfig {
+    pub fn new() -> Self {
+        ThreadHandleConfig::default()
+    }
+
+    /// Stack size of new threads, in bytes; the platform default otherwise.
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Whether dropping the handle of a running thread is reported, see `detect_leaks`.
+    pub fn detect_leaks(mut self, enabled: bool) -> Self {
+        self.detect_leaks = Some(enabled);
+        self
+    }
+
+    /// What dropping the handle of a running thread does, see `DropPolicy`.
+    pub fn drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.drop_policy = policy;
+        self
+    }
+
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = policy;
+        self
+    }
+
+    /// Subscriber receiving the events of every thread, subscribed on installation.
+    pub fn subscriber(mut self, subscriber: Arc<dyn Subscriber>) -> Self {
+        self.subscribers.push(subscriber);
+        self
+    }
+
+    /// Prefix added to the name of every thread, e.g. the name of the service.
+    pub fn name_prefix(mut self, prefix: &str) -> Self {
+        self.name_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Memory ordering of the interrupt flag of every token, `AcquireRelease` by default.
+    pub fn interrupt_ordering(mut self, ordering: InterruptOrdering) -> Self {
+        self.interrupt_ordering = Some(ordering);
+        self
+    }
+
+    /// Makes this configuration the process-wide default. It can be installed once, before
+    /// spawning threads; later calls return the configuration they were given.
+    pub fn install(self) -> Result<(), ThreadHandleConfig> {
+        INSTALLED.set(self)?;
+        let installed = installed();
+        if let Some(enabled) = installed.detect_leaks {
+            leak::detect_leaks(enabled);
+        }
+        if let Some(interrupt_ordering) = installed.interrupt_ordering {
+            ordering::install(interrupt_ordering);
+        }
+        for subscriber in &installed.subscribers {
+            events::subscribe(subscriber.clone());
+        }
+        Ok(())
+    }
+
+    /// Name a thread named `name` gets under this configuration.
+    pub(crate) fn thread_name(&self, name: String) -> String {
+        match self.name_prefix {
+            Some(ref prefix) => format!("{}{}", prefix, name),
+            None => name,
+        }
+    }
+
+    pub(crate) fn default_stack_size(&self) -> Option<usize> {
+        self.stack_size
+    }
+
+    pub(crate) fn default_drop_policy(&self) -> DropPolicy {
+        self.drop_policy
+    }
+
+    pub(crate) fn default_panic_policy(&self) -> PanicPolicy {
+        self.panic_policy
+    }
+}
+
+impl fmt::Debug for ThreadHandleConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThreadHandleConfig")
+            .field("stack_size", &self.stack_size)
+            .field("detect_leaks", &self.detect_leaks)
+            .field("drop_policy", &self.drop_policy)
+            .field("panic_policy", &self.panic_policy)
+            .field("subscribers", &self.subscribers.len())
+            .field("name_prefix", &self.name_prefix)
+            .field("interrupt_ordering", &self.interrupt_ordering)
+            .finish()
+    }
+}
+
+/// The installed configuration, or the defaults.
+pub(crate) fn installed() -> &'static ThreadHandleConfig {
+    static DEFAULT: OnceLock<ThreadHandleConfig> = OnceLock::new();
+    INSTALLED.get().unwrap_or_else(|| DEFAULT.get_or_init(ThreadHandleConfig::default))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use Builder;
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config = ThreadHandleConfig::new().name_prefix("billing-").panic_policy(PanicPolicy::InterruptGroup);
+        assert_eq!("billing-worker", config.thread_name("worker".to_string()));
+        assert_eq!(PanicPolicy::InterruptGroup, config.default_panic_policy());
+        assert_eq!(None, config.default_stack_size());
+        assert_eq!(DropPolicy::Detach, config.default_drop_policy());
+    }
+
+    #[test]
+    fn test_stack_size() {
+        let handle = Builder::new("Test stack size".to_string()).stack_size(16 << 20).spawn(|_| {
+            let buffer = [1u8; 8 << 20];
+            buffer.iter().map(|&byte| byte as usize).sum::<usize>()
+        }).unwrap();
+        assert_eq!(8 << 20, handle.join().unwrap().unwrap());
+    }
+}
@@ -0,0 +1,203 @@
+//! Context carried from the spawning thread into managed threads: key/values for log records,
+//! user-defined propagators, e.g. for the current tracing span, and typed values the token
+//! carries.
+
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::{Arc, RwLock};
+
+use InterruptToken;
+
+
+/// Guard kept alive while the runnable runs, e.g. an entered span.
+pub type ContextGuard = Box<dyn Any>;
+
+/// Installs captured context on the new thread.
+pub type Install = Box<dyn FnOnce() -> ContextGuard + Send>;
+
+/// Captures context on the spawning thread, to install it in the spawned one.
+pub trait ContextPropagator: Send + Sync {
+    fn capture(&self) -> Install;
+}
+
+impl<F> ContextPropagator for F where F: Fn() -> Install + Send + Sync {
+    fn capture(&self) -> Install {
+        self()
+    }
+}
+
+thread_local! {
+    static VALUES: RefCell<BTreeMap<String, String>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+static PROPAGATORS: RwLock<Vec<Arc<dyn ContextPropagator>>> = RwLock::new(Vec::new());
+
+/// Sets a value of the current thread's context; threads it spawns inherit it.
+pub fn insert(key: &str, value: &str) -> Option<String> {
+    VALUES.with(|values| values.borrow_mut().insert(key.to_string(), value.to_string()))
+}
+
+pub fn remove(key: &str) -> Option<String> {
+    VALUES.with(|values| values.borrow_mut().remove(key))
+}
+
+pub fn get(key: &str) -> Option<String> {
+    VALUES.with(|values| values.borrow().get(key).cloned())
+}
+
+/// Empties the current thread's context.
+pub(crate) fn clear() {
+    VALUES.with(|values| values.borrow_mut().clear());
+}
+
+/// Every key/value of the current thread's context, ordered by key.
+pub fn entries() -> Vec<(String, String)> {
+    VALUES.with(|values| values.borrow().iter().map(|(key, value)| (key.clone(), value.clone())).collect())
+}
+
+/// Adds a propagator run for every managed thread spawned from now on.
+pub fn add_propagator(propagator: Arc<dyn ContextPropagator>) {
+    PROPAGATORS.write().unwrap().push(propagator);
+}
+
+/// Context captured when a handle is created.
+pub(crate) struct Captured {
+    values: BTreeMap<String, String>,
+    installs: Vec<Install>,
+}
+
+/// Context installed by a runnable; the guards of the propagators are dropped before the
+/// values are restored.
+pub(crate) struct Installed {
+    _guards: Vec<ContextGuard>,
+    _restore: Restore,
+}
+
+/// Restores the context a reused thread had before the runnable.
+struct Restore(BTreeMap<String, String>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = mem::take(&mut self.0);
+        VALUES.with(|values| *values.borrow_mut() = previous);
+    }
+}
+
+pub(crate) fn capture(propagators: &[Arc<dyn ContextPropagator>]) -> Captured {
+    let global = PROPAGATORS.read().unwrap();
+    Captured {
+        values: VALUES.with(|values| values.borrow().clone()),
+        installs: global.iter().chain(propagators).map(|propagator| propagator.capture()).collect(),
+    }
+}
+
+impl Captured {
+    /// Installs the context in the calling thread until the returned guard is dropped.
+    pub(crate) fn install(self) -> Installed {
+        let Captured { values: captured, installs } = self;
+        let previous = VALUES.with(|values| mem::replace(&mut *values.borrow_mut(), captured));
+        Installed {
+            _guards: installs.into_iter().map(|install| install()).collect(),
+            _restore: Restore(previous),
+        }
+    }
+}
+
+/// Typed values a thread is spawned with, at most one per type.
+#[derive(Clone, Default)]
+pub(crate) struct ContextValues {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl ContextValues {
+    pub(crate) fn insert<T>(&mut self, value: T) where T: Any + Send + Sync {
+        self.values.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn get<T>(&self) -> Option<&T> where T: Any + Send + Sync {
+        self.values.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref())
+    }
+
+    pub(crate) fn get_shared<T>(&self) -> Option<Arc<T>> where T: Any + Send + Sync {
+        self.values.get(&TypeId::of::<T>()).and_then(|value| value.clone().downcast().ok())
+    }
+}
+
+impl InterruptToken {
+    /// Value of type `T` the thread was spawned with, see `Builder::context_value`; children
+    /// spawned through the token inherit the values.
+    pub fn context<T>(&self) -> Option<&T> where T: Any + Send + Sync {
+        self.shared.values.get()?.get()
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::time::Duration;
+    use {Builder, ThreadHandle};
+    use super::*;
+
+    thread_local! {
+        static REQUEST: Cell<u32> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn test_values_propagate() {
+        insert("request_id", "42");
+        let handle = ThreadHandle::spawn("Test context values".to_string(), |_| {
+            let nested = ThreadHandle::spawn("Test context nested".to_string(), |_| get("request_id")).unwrap();
+            (entries(), nested.join().unwrap().unwrap())
+        }).unwrap();
+        remove("request_id");
+        let (entries, nested) = handle.join().unwrap().unwrap();
+        assert_eq!(vec![("request_id".to_string(), "42".to_string())], entries);
+        assert_eq!(Some("42".to_string()), nested);
+        assert_eq!(None, get("request_id"));
+    }
+
+    #[test]
+    fn test_builder_propagator() {
+        let propagator = || -> Install {
+            let request = REQUEST.with(|request| request.get());
+            Box::new(move || {
+                REQUEST.with(|current| current.set(request));
+                Box::new(())
+            })
+        };
+        REQUEST.with(|request| request.set(7));
+        let handle = Builder::new("Test context propagator".to_string())
+            .propagate_context(Arc::new(propagator))
+            .spawn(|_| REQUEST.with(|request| request.get()))
+            .unwrap();
+        assert_eq!(7, handle.join().unwrap().unwrap());
+    }
+
+    #[derive(Clone, PartialEq, Debug)]
+    struct Tenant(&'static str);
+
+    #[test]
+    fn test_context_values() {
+        let handle = Builder::new("Test context value".to_string())
+            .context_value(Tenant("acme"))
+            .context_value(Duration::from_secs(5))
+            .spawn(|interrupted| {
+                let child = interrupted.spawn_child("Test context value child".to_string(), |interrupted| {
+                    interrupted.context::<Tenant>().cloned()
+                }).unwrap();
+                let inherited = child.join().unwrap().unwrap();
+                (interrupted.context::<Tenant>().cloned(), interrupted.context::<Duration>().cloned(), interrupted.context::<u32>().cloned(), inherited)
+            })
+            .unwrap();
+        let expected = (Some(Tenant("acme")), Some(Duration::from_secs(5)), None, Some(Tenant("acme")));
+        assert_eq!(expected, handle.join().unwrap().unwrap());
+    }
+}
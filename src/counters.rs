@@ -0,0 +1,96 @@
+use std::sync::atomic::{self, AtomicU64};
+
+use ThreadHandle;
+
+
+/// How often a handle and its runnable were used, for debugging misbehaving callers and workers.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Counters {
+    /// Interruption requests, including repeated ones.
+    pub interrupts: u64,
+    /// Calls to `join` and `join_timeout`.
+    pub joins: u64,
+    pub heartbeats: u64,
+    /// Attempts after the first one of a retried runnable.
+    pub restarts: u64,
+    /// Progress reports of the runnable.
+    pub progress_updates: u64,
+    /// Recoverable errors reported by the runnable.
+    pub errors_reported: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct AtomicCounters {
+    pub(crate) interrupts: AtomicU64,
+    pub(crate) joins: AtomicU64,
+    pub(crate) heartbeats: AtomicU64,
+    pub(crate) restarts: AtomicU64,
+    pub(crate) progress_updates: AtomicU64,
+    pub(crate) errors_reported: AtomicU64,
+}
+
+impl AtomicCounters {
+    pub(crate) fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    pub(crate) fn load(&self) -> Counters {
+        let load = |counter: &AtomicU64| counter.load(atomic::Ordering::Relaxed);
+        Counters {
+            interrupts: load(&self.interrupts),
+            joins: load(&self.joins),
+            heartbeats: load(&self.heartbeats),
+            restarts: load(&self.restarts),
+            progress_updates: load(&self.progress_updates),
+            errors_reported: load(&self.errors_reported),
+        }
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    pub fn counters(&self) -> Counters {
+        self.shared.counters.load()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use {RetryPolicy, ThreadHandle};
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let handle = ThreadHandle::spawn("Test counters".to_string(), |interrupted| {
+            interrupted.heartbeat();
+            interrupted.heartbeat();
+            interrupted.report_progress(1, 2);
+            while !interrupted.is_interrupted() {
+                ::std::thread::sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        handle.join_timeout(Duration::from_millis(50));
+        handle.interrupt().unwrap();
+        handle.interrupt().unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(Counters {
+            interrupts: 2,
+            joins: 2,
+            heartbeats: 2,
+            restarts: 0,
+            progress_updates: 1,
+            errors_reported: 0,
+        }, handle.counters());
+    }
+
+    #[test]
+    fn test_restart_counter() {
+        let policy = RetryPolicy::new(3).backoff(Duration::from_millis(1));
+        let handle = ThreadHandle::spawn_with_retry("Test counters retry".to_string(), policy, || {
+            |_| Err::<(), _>(())
+        }).unwrap();
+        handle.join().unwrap().unwrap().unwrap_err();
+        assert_eq!(2, handle.counters().restarts);
+    }
+}
@@ -0,0 +1,195 @@
+use std::any::Any;
+use std::convert::TryFrom;
+use std::fmt::Write as FmtWrite;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use panics::PanicPayload;
+use Shared;
+
+
+/// Details of a panicked thread, handed to the crash sink.
+#[derive(Clone, Debug)]
+pub struct CrashReport {
+    pub thread: String,
+    pub message: String,
+    pub started: Option<SystemTime>,
+    pub panicked: SystemTime,
+    /// Captured with the `backtrace` feature, `None` otherwise.
+    pub backtrace: Option<String>,
+    /// Restarts before the run that panicked, as `restart_count` reports for supervised threads
+    /// and those spawned with a retry policy.
+    pub restarts: u32,
+}
+
+impl CrashReport {
+    /// Single-line JSON rendering of the report.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+        write!(json, "\"thread\":{},\"message\":{}", quote(&self.thread), quote(&self.message)).unwrap();
+        match self.started {
+            Some(started) => write!(json, ",\"started\":{}", unix_seconds(started)).unwrap(),
+            None => json.push_str(",\"started\":null"),
+        }
+        write!(json, ",\"panicked\":{}", unix_seconds(self.panicked)).unwrap();
+        match self.backtrace {
+            Some(ref backtrace) => write!(json, ",\"backtrace\":{}", quote(backtrace)).unwrap(),
+            None => json.push_str(",\"backtrace\":null"),
+        }
+        write!(json, ",\"restarts\":{}}}", self.restarts).unwrap();
+        json
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).map(|since| since.as_secs_f64()).unwrap_or(0.0)
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Destination for crash reports of panicked threads.
+pub trait CrashSink: Send + Sync {
+    fn record(&self, report: &CrashReport) -> io::Result<()>;
+}
+
+/// Appends each report as a line of JSON to a file.
+pub struct JsonFileSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonFileSink {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        JsonFileSink {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl CrashSink for JsonFileSink {
+    fn record(&self, report: &CrashReport) -> io::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", report.to_json())
+    }
+}
+
+static DEFAULT_SINK: Mutex<Option<Arc<dyn CrashSink>>> = Mutex::new(None);
+
+/// Sets the sink receiving crash reports of threads whose builder has no sink of its own.
+pub fn set_crash_sink(sink: Option<Arc<dyn CrashSink>>) {
+    *DEFAULT_SINK.lock().unwrap() = sink;
+}
+
+pub(crate) fn record(sink: Option<&Arc<dyn CrashSink>>, shared: &Shared, payload: &(dyn Any + Send)) {
+    let sink = match sink {
+        Some(sink) => Some(sink.clone()),
+        None => DEFAULT_SINK.lock().unwrap().clone(),
+    };
+    let sink = match sink {
+        Some(sink) => sink,
+        None => return,
+    };
+    let now = shared.clock.now();
+    let panicked = SystemTime::now();
+    #[cfg(feature = "backtrace")]
+    let backtrace = shared.backtrace.get().map(|backtrace| backtrace.to_string());
+    #[cfg(not(feature = "backtrace"))]
+    let backtrace = None;
+    let report = CrashReport {
+        thread: shared.name().to_string(),
+        message: payload.describe(),
+        started: shared.started_at().map(|started| panicked - now.saturating_duration_since(started)),
+        panicked,
+        backtrace,
+        restarts: u32::try_from(shared.counters.restarts.load(atomic::Ordering::Relaxed)).unwrap_or(u32::MAX),
+    };
+    // Reporting is best effort and must not keep the handle from learning the thread terminated.
+    panic::catch_unwind(AssertUnwindSafe(|| sink.record(&report))).ok();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use {Builder, InterruptToken};
+    use super::*;
+
+    struct Collect(Mutex<Vec<CrashReport>>);
+
+    impl CrashSink for Collect {
+        fn record(&self, report: &CrashReport) -> io::Result<()> {
+            self.0.lock().unwrap().push(report.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_crash_sink() {
+        let sink = Arc::new(Collect(Mutex::new(Vec::new())));
+        let handle = Builder::new("Test crash sink".to_string())
+            .crash_sink(sink.clone())
+            .spawn(|_| -> u32 { panic!("crashed") })
+            .unwrap();
+        assert!(handle.join().unwrap().is_err());
+        let reports = sink.0.lock().unwrap();
+        assert_eq!(1, reports.len());
+        assert_eq!("Test crash sink", reports[0].thread);
+        assert_eq!("crashed", reports[0].message);
+        assert!(reports[0].started.unwrap() <= reports[0].panicked);
+        assert_eq!(0, reports[0].restarts);
+    }
+
+    #[test]
+    fn test_crash_restarts() {
+        let sink = Arc::new(Collect(Mutex::new(Vec::new())));
+        let token = InterruptToken::new();
+        // More restarts than the attempt history keeps.
+        token.shared.counters.restarts.store(100, atomic::Ordering::Relaxed);
+        record(Some(&(sink.clone() as Arc<dyn CrashSink>)), &token.shared, &"crashed");
+        assert_eq!(100, sink.0.lock().unwrap()[0].restarts);
+    }
+
+    #[test]
+    fn test_json_file_sink() {
+        let path = env::temp_dir().join(format!("thread-handle-crash-{}.jsonl", std::process::id()));
+        fs::remove_file(&path).ok();
+        let sink = Arc::new(JsonFileSink::new(path.clone()));
+        for _ in 0..2 {
+            let handle = Builder::new("Test \"json\" sink".to_string())
+                .crash_sink(sink.clone())
+                .spawn(|_| -> u32 { panic!("line\nbreak") })
+                .unwrap();
+            assert!(handle.join().unwrap().is_err());
+        }
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        let lines: Vec<_> = written.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("{\"thread\":\"Test \\\"json\\\" sink\",\"message\":\"line\\nbreak\",\"started\":"), "{}", lines[0]);
+        assert!(lines[0].ends_with(",\"restarts\":0}"), "{}", lines[0]);
+    }
+}
@@ -0,0 +1,113 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use {InterruptToken, Shared};
+
+
+thread_local! {
+    static CURRENT: RefCell<Option<InterruptToken>> = const { RefCell::new(None) };
+}
+
+/// Makes a token the one of the calling thread until dropped, restoring the previous one, e.g.
+/// on a recycled thread or for a runnable run inline.
+pub(crate) struct Entered(Option<InterruptToken>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+pub(crate) fn enter(token: &InterruptToken) -> Entered {
+    Entered(CURRENT.with(|current| current.borrow_mut().replace(token.clone())))
+}
+
+/// Token of the managed thread the caller runs on, `None` on other threads, so that code deep
+/// in a call stack can check for interruption or send heartbeats without the token being passed
+/// down to it.
+pub fn current_token() -> Option<InterruptToken> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Whether the caller runs on the thread of the runnable owning `shared`.
+pub(crate) fn is_current(shared: &Shared) -> bool {
+    CURRENT.with(|current| current.borrow().as_ref().is_some_and(|token| token.shared.id == shared.id))
+}
+
+/// Value of type `V` the managed thread the caller runs on was spawned with, see
+/// `InterruptToken::context`.
+pub fn current_context<V>() -> Option<Arc<V>> where V: Any + Send + Sync {
+    CURRENT.with(|current| current.borrow().as_ref()?.shared.values.get()?.get_shared())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use {Builder, ThreadHandle, ThreadPool};
+    use super::*;
+
+    fn deep_in_a_library() -> Option<bool> {
+        current_token().map(|token| token.is_interrupted())
+    }
+
+    #[test]
+    fn test_current_token() {
+        assert!(current_token().is_none());
+        let handle = Builder::new("Test current token".to_string()).context_value(7u32).spawn(|interrupted: InterruptToken| {
+            let current = current_token().unwrap();
+            assert_eq!(interrupted.id(), current.id());
+            assert_eq!(Some(false), deep_in_a_library());
+            interrupted.interrupt();
+            (deep_in_a_library(), current_context::<u32>().map(|value| *value), current_context::<u64>().is_none())
+        }).unwrap();
+        assert_eq!((Some(true), Some(7), true), handle.join().unwrap().unwrap());
+
+        // Plain threads have no token.
+        assert!(thread::spawn(current_token).join().unwrap().is_none());
+        let handle = ThreadHandle::spawn("Test current token nested".to_string(), |interrupted: InterruptToken| {
+            let token = InterruptToken::new();
+            {
+                let _entered = enter(&token);
+                assert_eq!(token.id(), current_token().unwrap().id());
+            }
+            current_token().unwrap().id() == interrupted.id()
+        }).unwrap();
+        assert!(handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_current_token_in_cleanups() {
+        let (sender, receiver) = mpsc::channel();
+        let stopped = sender.clone();
+        let handle = Builder::new("Test current token cleanups".to_string()).on_thread_stop(move || {
+            stopped.send(current_token().map(|token| token.id())).unwrap();
+        }).spawn(move |interrupted: InterruptToken| {
+            interrupted.defer(move || sender.send(current_token().map(|token| token.id())).unwrap());
+            interrupted.id()
+        }).unwrap();
+        let id = handle.join().unwrap().unwrap();
+        assert_eq!(vec![Some(id), Some(id)], receiver.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_current_token_in_pool_task() {
+        let pool = ThreadPool::builder("Test current token pool".to_string()).workers(1).build().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let task = pool.submit(move |_| {
+            sender.send(()).unwrap();
+            while deep_in_a_library() == Some(false) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            deep_in_a_library()
+        });
+        receiver.recv().unwrap();
+        task.interrupt();
+        assert_eq!(Some(true), task.join().unwrap().unwrap());
+        assert!(pool.workers().iter().all(|worker| !worker.is_interrupt_requested()));
+    }
+}
@@ -0,0 +1,114 @@
+use std::any;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use {InterruptToken, RetryPolicy, ThreadHandle};
+
+
+/// Long-lived service object running on its own thread until interrupted.
+pub trait Daemon: Send + 'static {
+    type Error: Clone + fmt::Debug + Send + Sync + 'static;
+
+    /// Name of the thread; by default the name of the type, without its path.
+    fn name(&self) -> String {
+        let name = any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name).to_string()
+    }
+
+    /// Serves until `token` is interrupted, calling `token.set_ready()` once it serves.
+    /// Returning `Err` or panicking fails the thread.
+    fn run(&mut self, token: &InterruptToken) -> Result<(), Self::Error>;
+
+    /// Called on the thread of the daemon once `run` returned or panicked, to release resources.
+    fn shutdown(&mut self) {}
+}
+
+fn run<D>(daemon: &mut D, token: &InterruptToken) -> Result<(), D::Error> where D: Daemon {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| daemon.run(token)));
+    token.set_stopping();
+    daemon.shutdown();
+    match outcome {
+        Ok(result) => result,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// Spawns a thread running `daemon`, named after it. An error it returns is reported like a
+/// panic, as with `ThreadHandle::spawn_fallible`.
+pub fn spawn_daemon<D>(mut daemon: D) -> io::Result<ThreadHandle<Result<(), D::Error>>> where D: Daemon {
+    ThreadHandle::spawn_fallible(daemon.name(), move |interrupted| run(&mut daemon, &interrupted))
+}
+
+/// Like `spawn_daemon`, running the same daemon again after it failed, as `policy` allows;
+/// see `ThreadHandle::spawn_with_retry`.
+pub fn spawn_daemon_with_retry<D>(daemon: D, policy: RetryPolicy) -> io::Result<ThreadHandle<Result<(), D::Error>>> where D: Daemon {
+    let name = daemon.name();
+    let daemon = Arc::new(Mutex::new(daemon));
+    ThreadHandle::spawn_with_retry(name, policy, move || {
+        let daemon = daemon.clone();
+        // A panicking attempt poisons the lock; the next attempt starts over with the same daemon.
+        move |interrupted: InterruptToken| run(&mut *daemon.lock().unwrap_or_else(PoisonError::into_inner), &interrupted)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Sender};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use super::*;
+
+    struct Ticker {
+        ticks: u32,
+        fail_first: u32,
+        stopped: Sender<u32>,
+    }
+
+    impl Daemon for Ticker {
+        type Error = String;
+
+        fn run(&mut self, token: &InterruptToken) -> Result<(), String> {
+            if self.fail_first > 0 {
+                self.fail_first -= 1;
+                panic!("not ready");
+            }
+            while !token.is_interrupted() {
+                self.ticks += 1;
+                sleep(Duration::from_millis(5));
+            }
+            Ok(())
+        }
+
+        fn shutdown(&mut self) {
+            self.stopped.send(self.ticks).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_spawn_daemon() {
+        let (stopped, shutdowns) = mpsc::channel();
+        let handle = spawn_daemon(Ticker { ticks: 0, fail_first: 0, stopped }).unwrap();
+        assert_eq!("Ticker", handle.name());
+        sleep(Duration::from_millis(50));
+        handle.interrupt().unwrap();
+        assert_eq!(Ok(()), handle.join().unwrap().unwrap());
+        assert!(shutdowns.recv().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_spawn_daemon_with_retry() {
+        let (stopped, shutdowns) = mpsc::channel();
+        let policy = RetryPolicy::new(3).backoff(Duration::from_millis(1));
+        let handle = spawn_daemon_with_retry(Ticker { ticks: 0, fail_first: 2, stopped }, policy).unwrap();
+        assert_eq!(0, shutdowns.recv().unwrap());
+        assert_eq!(0, shutdowns.recv().unwrap());
+        sleep(Duration::from_millis(50));
+        handle.interrupt().unwrap();
+        assert_eq!(Ok(()), handle.join().unwrap().unwrap());
+        assert_eq!(3, handle.attempts().len());
+        shutdowns.recv().unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+use std::cmp;
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clock;
+use {CancelSignal, InterruptToken, Shared};
+
+
+/// Threads with a deadline, interrupted by a helper thread once it passes so that a runnable
+/// blocked somewhere other than `is_interrupted` sees it too; also tokens with an external
+/// signal and listeners, told once the signal is raised.
+struct Timer {
+    armed: Mutex<Vec<Weak<Shared>>>,
+    changed: Condvar,
+}
+
+impl Timer {
+    fn run(&self) {
+        let mut armed = self.armed.lock().unwrap();
+        loop {
+            armed.retain(|shared| match shared.upgrade() {
+                Some(shared) => !shared.is_terminated() && !shared.check_deadline() && !shared.check_external_signal(),
+                None => false,
+            });
+            // Deadlines are measured with the clock of each thread, which may be a mock one
+            // advanced from elsewhere: look again after a slice of real time at most. External
+            // signals are checked every slice.
+            let wait = armed.iter()
+                .filter_map(Weak::upgrade)
+                .filter_map(|shared| match shared.deadline.get() {
+                    _ if shared.signal.is_external() => Some(clock::WAIT_SLICE),
+                    Some(&deadline) => Some(deadline.saturating_duration_since(shared.clock.now())),
+                    None => None,
+                })
+                .min()
+                .map(|remaining| cmp::min(remaining, clock::WAIT_SLICE));
+            armed = match wait {
+                Some(wait) => self.changed.wait_timeout(armed, wait).unwrap().0,
+                None => self.changed.wait(armed).unwrap(),
+            };
+        }
+    }
+}
+
+/// Has `shared` interrupted once its deadline passes, and its listeners told once its external
+/// signal is raised. Without the helper thread, which is only started when first needed, both
+/// are still noticed by `is_interrupted`.
+pub(crate) fn arm(shared: &Arc<Shared>) {
+    static TIMER: OnceLock<Option<Arc<Timer>>> = OnceLock::new();
+    let timer = TIMER.get_or_init(|| {
+        let timer = Arc::new(Timer { armed: Mutex::new(Vec::new()), changed: Condvar::new() });
+        let running = timer.clone();
+        thread::Builder::new()
+            .name("thread-handle deadlines".to_string())
+            .spawn(move || running.run())
+            .ok()
+            .map(|_| timer)
+    });
+    if let Some(timer) = timer {
+        timer.armed.lock().unwrap().push(Arc::downgrade(shared));
+        timer.changed.notify_all();
+    }
+}
+
+
+impl Shared {
+    /// Requests interruption once the deadline has passed; returns whether it was requested.
+    pub(crate) fn check_deadline(&self) -> bool {
+        match self.deadline.get() {
+            Some(&deadline) if self.clock.now() >= deadline => {
+                if !self.signal.is_cancelled() {
+                    self.request_interrupt(Some("deadline passed"));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl InterruptToken {
+    /// Point in time by which the runnable should be done, set with `Builder::deadline`. Once it
+    /// passes, the thread counts as interrupted.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.shared.deadline.get().copied()
+    }
+
+    /// Time left until the deadline according to the clock of the thread, zero once it passed.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline().map(|deadline| deadline.saturating_duration_since(self.now()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use {Builder, Clock, MockClock};
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        let clock = Arc::new(MockClock::new());
+        let deadline = clock.now() + Duration::from_secs(10);
+        let handle = Builder::new("Test deadline".to_string())
+            .clock(clock.clone())
+            .deadline(deadline)
+            .spawn(|interrupted| {
+                let mut batches = 0;
+                while !interrupted.is_interrupted() {
+                    batches += 1;
+                    ::std::thread::sleep(Duration::from_millis(5));
+                }
+                (batches, interrupted.deadline(), interrupted.child().remaining())
+            })
+            .unwrap();
+        ::std::thread::sleep(Duration::from_millis(30));
+        assert!(!handle.is_interrupt_requested());
+        clock.advance(Duration::from_secs(10));
+        let (batches, seen, remaining) = handle.join().unwrap().unwrap();
+        assert!(batches > 0);
+        assert_eq!(Some(deadline), seen);
+        assert_eq!(Some(Duration::from_secs(0)), remaining);
+        assert_eq!(Some("deadline passed"), handle.interrupt_reason());
+        assert_eq!(None, InterruptToken::new().remaining());
+    }
+
+    #[test]
+    fn test_deadline_interrupts_blocked_runnable() {
+        let (release, blocked) = mpsc::channel::<()>();
+        let handle = Builder::new("Test deadline blocked".to_string())
+            .deadline(Instant::now() + Duration::from_millis(20))
+            .spawn(move |_| blocked.recv().ok())
+            .unwrap();
+        // The runnable never looks at its token: only the timer can interrupt it.
+        let give_up = Instant::now() + Duration::from_secs(10);
+        while handle.interrupt_reason().is_none() && Instant::now() < give_up {
+            ::std::thread::sleep(Duration::from_millis(5));
+        }
+        release.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(Some("deadline passed"), handle.interrupt_reason());
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use {BlockingSection, InterruptRequest, JoinOutcome, Snapshot, ThreadHandle};
+use sys;
+
+
+/// What a thread that did not finish in time was doing, see `ThreadHandle::join_or_dump`.
+#[derive(Clone, Debug)]
+pub struct HangReport {
+    /// State of the handle, including the age of its last heartbeat.
+    pub snapshot: Snapshot,
+    pub blocking_section: Option<BlockingSection>,
+    pub interrupt_history: Vec<InterruptRequest>,
+    /// Stack of the thread as the OS reports it. Normally `None`: it is only read on Linux, from
+    /// `/proc/self/task/<tid>/stack`, which only root can read, falling back to the kernel
+    /// function the thread waits in where that is exposed.
+    pub os_stack: Option<String>,
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Like `join_timeout`, also describing what the thread was doing if it is still running
+    /// once `timeout` elapsed, so that a hanging join comes with something to act on.
+    pub fn join_or_dump(&self, timeout: Duration) -> (JoinOutcome<T>, Option<HangReport>) {
+        match self.join_timeout(timeout) {
+            JoinOutcome::StillRunning => (JoinOutcome::StillRunning, Some(self.hang_report())),
+            outcome => (outcome, None),
+        }
+    }
+
+    fn hang_report(&self) -> HangReport {
+        HangReport {
+            snapshot: self.snapshot(),
+            blocking_section: self.blocking_section(),
+            interrupt_history: self.interrupt_history(),
+            os_stack: self.shared.os_thread_id.get().and_then(|&tid| sys::os_thread_stack(tid)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use super::*;
+
+    #[test]
+    fn test_join_or_dump() {
+        let (release, released) = mpsc::channel::<()>();
+        let (enter, entered) = mpsc::channel();
+        let handle = ThreadHandle::spawn("Test join or dump".to_string(), move |interrupted| {
+            interrupted.heartbeat();
+            interrupted.blocking_section("recv", || {
+                enter.send(()).unwrap();
+                released.recv().ok()
+            });
+        }).unwrap();
+        entered.recv().unwrap();
+        handle.interrupt().unwrap();
+        let (outcome, report) = handle.join_or_dump(Duration::from_millis(50));
+        match outcome {
+            JoinOutcome::StillRunning => (),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        let report = report.unwrap();
+        assert_eq!("Test join or dump", report.snapshot.name);
+        assert!(report.snapshot.heartbeat_age.is_some());
+        assert_eq!("recv", report.blocking_section.unwrap().name);
+        assert_eq!(1, report.interrupt_history.len());
+        release.send(()).unwrap();
+        match handle.join_or_dump(Duration::from_secs(5)) {
+            (JoinOutcome::Finished(()), None) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
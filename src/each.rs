@@ -0,0 +1,141 @@
+use std::cmp;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use {InterruptToken, ThreadHandle};
+
+
+/// How often the coordinator waiting for a free slot looks for interruption.
+const INTERRUPT_CHECK: Duration = Duration::from_millis(10);
+
+/// Outcomes of the jobs of `spawn_each`, in the order they finished, along with the position of
+/// the job in the list.
+pub struct SpawnEach<T> {
+    coordinator: ThreadHandle<io::Result<()>>,
+    outcomes: Receiver<(usize, thread::Result<T>)>,
+}
+
+impl<T> SpawnEach<T> where T: Send + 'static {
+    /// Handle of the thread walking the job list, which terminates once every job it started
+    /// finished; its result is the error that kept it from starting a thread, if any.
+    pub fn coordinator(&self) -> &ThreadHandle<io::Result<()>> {
+        &self.coordinator
+    }
+
+    /// Stops starting jobs and interrupts the running ones.
+    pub fn interrupt(&self) {
+        self.coordinator.interrupt().ok();
+    }
+}
+
+impl<T> Iterator for SpawnEach<T> {
+    type Item = (usize, thread::Result<T>);
+
+    /// Blocks until another job finishes; ends once every started job reported.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.outcomes.recv().ok()
+    }
+}
+
+/// Runs `runnable` on each job of `jobs` in its own thread, named after `name` and the position
+/// of the job, with at most `max_parallel` of them running at once. The jobs are taken from the
+/// iterator on a coordinator thread, whose interruption also interrupts the running jobs.
+pub fn spawn_each<I, T, F>(name: String, jobs: I, max_parallel: usize, runnable: F) -> io::Result<SpawnEach<T>> where
+    I: IntoIterator, I::IntoIter: Send + 'static, I::Item: Send + 'static,
+    T: Send + 'static,
+    F: Fn(I::Item, InterruptToken) -> T + Send + Sync + 'static
+{
+    let max_parallel = cmp::max(max_parallel, 1);
+    let jobs = jobs.into_iter();
+    let runnable = Arc::new(runnable);
+    let (outcomes, receiver) = mpsc::channel();
+    let coordinator = ThreadHandle::spawn(name.clone(), move |interrupted: InterruptToken| {
+        let (freed, slot_freed) = mpsc::channel();
+        let mut in_flight = 0;
+        let mut spawned = Ok(());
+        for (index, job) in jobs.enumerate() {
+            while in_flight >= max_parallel && !interrupted.is_interrupted() {
+                if slot_freed.recv_timeout(INTERRUPT_CHECK).is_ok() {
+                    in_flight -= 1;
+                }
+            }
+            if interrupted.is_interrupted() {
+                break;
+            }
+            let runnable = runnable.clone();
+            let child = match interrupted.spawn_child(format!("{}-{}", name, index), move |token| runnable(job, token)) {
+                Ok(child) => child,
+                Err(error) => {
+                    spawned = Err(error);
+                    break;
+                }
+            };
+            let (outcomes, freed, joined) = (outcomes.clone(), freed.clone(), child.shared.clone());
+            child.on_outcome(move |outcome| {
+                if let Some(outcome) = outcome {
+                    joined.joined.store(true, atomic::Ordering::Release);
+                    outcomes.send((index, outcome)).ok();
+                }
+                freed.send(()).ok();
+            });
+            in_flight += 1;
+        }
+        // Children are interrupted once this runnable returns, so wait for them first.
+        for _ in 0..in_flight {
+            slot_freed.recv().ok();
+        }
+        spawned
+    })?;
+    Ok(SpawnEach {
+        coordinator,
+        outcomes: receiver,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread::sleep;
+    use super::*;
+
+    #[test]
+    fn test_spawn_each() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (counter, highest) = (running.clone(), peak.clone());
+        let each = spawn_each("Test each".to_string(), 0..10u64, 3, move |job, _| {
+            let now = counter.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+            highest.fetch_max(now, atomic::Ordering::SeqCst);
+            sleep(Duration::from_millis(20));
+            counter.fetch_sub(1, atomic::Ordering::SeqCst);
+            if job == 4 {
+                panic!("bad job");
+            }
+            job * 2
+        }).unwrap();
+        let mut outcomes: Vec<_> = each.map(|(index, outcome)| (index, outcome.ok())).collect();
+        outcomes.sort();
+        assert_eq!(10, outcomes.len());
+        assert_eq!((4, None), outcomes[4]);
+        assert_eq!((9, Some(18)), outcomes[9]);
+        assert!(peak.load(atomic::Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn test_spawn_each_interrupted() {
+        let each = spawn_each("Test each interrupted".to_string(), 0.., 2, |_: u32, token: InterruptToken| {
+            while !token.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        sleep(Duration::from_millis(30));
+        each.interrupt();
+        each.coordinator().join().unwrap().unwrap().unwrap();
+        assert_eq!(2, each.count());
+    }
+}
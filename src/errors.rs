@@ -0,0 +1,102 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::time::Instant;
+
+use counters::AtomicCounters;
+use {InterruptToken, ThreadHandle};
+
+
+/// Errors kept for the owner until it takes the stream; later ones are dropped while it is full.
+const BACKLOG: usize = 64;
+
+/// Recoverable error reported by a runnable that keeps running.
+#[derive(Debug)]
+pub struct WorkerError {
+    pub at: Instant,
+    /// `Debug` form of the error.
+    pub message: String,
+    pub error: Box<dyn Any + Send>,
+}
+
+/// Channel carrying reported errors, created on first use since most threads report none; the
+/// receiver is handed out once.
+pub(crate) struct ErrorStream {
+    channel: OnceLock<Channel>,
+}
+
+struct Channel {
+    sender: SyncSender<WorkerError>,
+    receiver: Mutex<Option<Receiver<WorkerError>>>,
+}
+
+impl ErrorStream {
+    pub(crate) fn new() -> Self {
+        ErrorStream {
+            channel: OnceLock::new(),
+        }
+    }
+
+    fn channel(&self) -> &Channel {
+        self.channel.get_or_init(|| {
+            let (sender, receiver) = mpsc::sync_channel(BACKLOG);
+            Channel {
+                sender,
+                receiver: Mutex::new(Some(receiver)),
+            }
+        })
+    }
+}
+
+impl InterruptToken {
+    /// Reports a problem the runnable recovered from, e.g. one bad record, to the owner of the
+    /// handle. Never blocks: the error is dropped if the stream is full or was dropped.
+    pub fn report_error<E>(&self, error: E) where E: fmt::Debug + Send + 'static {
+        AtomicCounters::increment(&self.shared.counters.errors_reported);
+        self.shared.errors.channel().sender.try_send(WorkerError {
+            at: self.shared.clock.now(),
+            message: format!("{:?}", error),
+            error: Box::new(error),
+        }).ok();
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Returns the stream of errors reported with `report_error`, starting with up to 64 reported
+    /// before it was taken; `None` if it was already taken. It disconnects once the handle, the
+    /// token and its clones are dropped.
+    pub fn errors(&self) -> Option<Receiver<WorkerError>> {
+        self.shared.errors.channel().receiver.lock().unwrap().take()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use super::*;
+
+    #[test]
+    fn test_report_error() {
+        let (proceed, wait) = channel();
+        let handle = ThreadHandle::spawn("Test report error".to_string(), move |interrupted| {
+            for record in 0..3 {
+                if record != 1 {
+                    interrupted.report_error(format!("bad record {}", record));
+                }
+            }
+            wait.recv().unwrap();
+            interrupted.report_error(42u32);
+            3
+        }).unwrap();
+        let errors = handle.errors().unwrap();
+        assert!(handle.errors().is_none());
+        assert_eq!("\"bad record 0\"", errors.recv().unwrap().message);
+        assert_eq!("\"bad record 2\"", errors.recv().unwrap().message);
+        proceed.send(()).unwrap();
+        assert_eq!(Some(&42u32), errors.recv().unwrap().error.downcast_ref::<u32>());
+        assert_eq!(3, handle.join().unwrap().unwrap());
+        assert_eq!(3, handle.counters().errors_reported);
+    }
+}
@@ -0,0 +1,184 @@
+//! Structured lifecycle events published by every thread spawned through this crate.
+//!
+//! The `minimal` feature turns publishing off, for processes spawning many short-lived threads;
+//! subscribers, hooks and the OpenTelemetry integration then receive nothing.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{self, AtomicBool};
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "otel")]
+use otel;
+use {HandleId, ServiceState, Shared};
+
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ThreadEvent {
+    /// The runnable is about to run on its thread.
+    Spawned,
+    /// Interruption was requested for the first time.
+    InterruptRequested { reason: Option<String> },
+    Heartbeat,
+    /// The worker reported a new lifecycle stage.
+    StateChanged { state: ServiceState },
+    Panicked { message: String },
+    /// The runnable returned or panicked after running for `duration`.
+    Terminated { duration: Duration },
+    /// `join` or a shutdown has been waiting for the thread for `waited`, see
+    /// `set_slow_join_threshold`.
+    SlowJoin { waited: Duration },
+    /// The handle of the running thread was dropped, with leak detection enabled.
+    Leaked { spawned_at: String },
+    /// A pool worker ran a task that had been queued for `waited`.
+    TaskRan { waited: Duration, ran: Duration, panicked: bool },
+    /// A supervised thread started the runnables passed to `reload`, for the `reloads`-th time.
+    Reloaded { reloads: u64 },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Event {
+    pub at: SystemTime,
+    pub id: HandleId,
+    pub thread: String,
+    /// Thread group or pool the thread belongs to.
+    pub group: Option<String>,
+    pub event: ThreadEvent,
+}
+
+/// Receives every event, on the thread where it happens.
+pub trait Subscriber: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+impl<F> Subscriber for F where F: Fn(&Event) + Send + Sync {
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Identifies a subscription, to cancel it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SubscriptionId(u64);
+
+struct Subscribers {
+    next_id: u64,
+    subscribers: Vec<(SubscriptionId, Arc<dyn Subscriber>)>,
+}
+
+static SUBSCRIBERS: RwLock<Subscribers> = RwLock::new(Subscribers { next_id: 0, subscribers: Vec::new() });
+/// Lets events be skipped without locking while nobody is subscribed.
+static ANY: AtomicBool = AtomicBool::new(false);
+
+pub fn subscribe(subscriber: Arc<dyn Subscriber>) -> SubscriptionId {
+    let mut subscribers = SUBSCRIBERS.write().unwrap();
+    subscribers.next_id += 1;
+    let id = SubscriptionId(subscribers.next_id);
+    subscribers.subscribers.push((id, subscriber));
+    ANY.store(true, atomic::Ordering::Release);
+    id
+}
+
+/// Cancels a subscription; returns `false` if it was not active.
+pub fn unsubscribe(id: SubscriptionId) -> bool {
+    let mut subscribers = SUBSCRIBERS.write().unwrap();
+    let before = subscribers.subscribers.len();
+    subscribers.subscribers.retain(|&(subscribed, _)| subscribed != id);
+    ANY.store(!subscribers.subscribers.is_empty(), atomic::Ordering::Release);
+    subscribers.subscribers.len() != before
+}
+
+/// Publishes an event of the thread owning `shared`; tokens not attached to a thread stay silent.
+pub(crate) fn publish<F>(shared: &Shared, event: F) where F: FnOnce() -> ThreadEvent {
+    if cfg!(feature = "minimal") {
+        return;
+    }
+    let subscribed = ANY.load(atomic::Ordering::Acquire);
+    if !subscribed && !cfg!(feature = "otel") {
+        return;
+    }
+    let thread = match shared.thread_name() {
+        Some(name) => name.to_string(),
+        None => return,
+    };
+    let event = event();
+    #[cfg(feature = "otel")]
+    otel::record(shared, &event);
+    if !subscribed {
+        return;
+    }
+    let subscribers: Vec<_> = SUBSCRIBERS.read().unwrap().subscribers.iter().map(|(_, subscriber)| subscriber.clone()).collect();
+    let event = Event {
+        at: SystemTime::now(),
+        id: shared.id,
+        thread,
+        group: shared.group.get().cloned(),
+        event,
+    };
+    for subscriber in subscribers {
+        // One failing subscriber must neither silence the others nor break the thread observed.
+        panic::catch_unwind(AssertUnwindSafe(|| subscriber.on_event(&event))).ok();
+    }
+}
+
+
+#[cfg(all(test, not(feature = "minimal")))]
+mod tests {
+    use std::sync::{mpsc, Mutex};
+    use std::thread::sleep;
+    use {ThreadGroup, ThreadHandle};
+    use super::*;
+
+    struct Recorder {
+        prefix: &'static str,
+        events: Mutex<Vec<Event>>,
+    }
+
+    impl Subscriber for Recorder {
+        fn on_event(&self, event: &Event) {
+            if event.thread.starts_with(self.prefix) {
+                self.events.lock().unwrap().push(event.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn test_subscriber() {
+        let recorder = Arc::new(Recorder {
+            prefix: "Test events",
+            events: Mutex::new(Vec::new()),
+        });
+        let id = subscribe(recorder.clone());
+        let (running, is_running) = mpsc::channel();
+        let handle = ThreadHandle::spawn("Test events worker".to_string(), move |interrupted| {
+            interrupted.heartbeat();
+            running.send(()).unwrap();
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+            panic!("stopped");
+        }).unwrap();
+        is_running.recv().unwrap();
+        handle.interrupt_with_reason("shutting down").unwrap();
+        assert!(handle.join().unwrap().is_err());
+        let mut group = ThreadGroup::new("Test events group".to_string());
+        group.spawn("Test events member".to_string(), |_| ()).unwrap();
+        group.handles()[0].join().unwrap().unwrap();
+        assert!(unsubscribe(id));
+
+        let events = recorder.events.lock().unwrap();
+        let worker: Vec<_> = events.iter().filter(|event| event.thread == "Test events worker").map(|event| event.event.clone()).collect();
+        assert_eq!(5, worker.len());
+        assert_eq!(ThreadEvent::Spawned, worker[0]);
+        assert_eq!(ThreadEvent::Heartbeat, worker[1]);
+        assert_eq!(ThreadEvent::InterruptRequested { reason: Some("shutting down".to_string()) }, worker[2]);
+        assert_eq!(ThreadEvent::Panicked { message: "stopped".to_string() }, worker[3]);
+        match worker[4] {
+            ThreadEvent::Terminated { duration } => assert!(duration <= events.last().unwrap().at.duration_since(events[0].at).unwrap()),
+            ref other => panic!("unexpected event {:?}", other),
+        }
+        let member: Vec<_> = events.iter().filter(|event| event.thread == "Test events member").collect();
+        assert_eq!(2, member.len());
+        assert!(member.iter().all(|event| event.group == Some("Test events group".to_string())));
+    }
+}
@@ -0,0 +1,63 @@
+use std::any::Any;
+use std::fmt;
+
+use {InterruptToken, Shared, ThreadHandle};
+
+
+/// Reason recorded with `InterruptToken::exit_with`.
+pub(crate) struct ExitReason {
+    value: Box<dyn Any + Send>,
+    /// `Debug` form of the value, for monitors.
+    description: String,
+}
+
+impl Shared {
+    pub(crate) fn exit_description(&self) -> Option<String> {
+        self.exit_reason.lock().unwrap().as_ref().map(|reason| reason.description.clone())
+    }
+}
+
+impl InterruptToken {
+    /// Records why the runnable is about to exit, e.g. a deliberate restart, so the owner can tell
+    /// it from a failure whatever the runnable returns. A later call replaces the reason.
+    pub fn exit_with<R>(&self, reason: R) where R: fmt::Debug + Send + 'static {
+        *self.shared.exit_reason.lock().unwrap() = Some(ExitReason {
+            description: format!("{:?}", reason),
+            value: Box::new(reason),
+        });
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Reason the runnable recorded with `exit_with`, if it recorded one of type `R`.
+    pub fn exit_reason<R>(&self) -> Option<R> where R: Clone + 'static {
+        let reason = self.shared.exit_reason.lock().unwrap();
+        reason.as_ref().and_then(|reason| reason.value.downcast_ref::<R>()).cloned()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, PartialEq, Debug)]
+    enum Reason {
+        ConfigReload,
+    }
+
+    #[test]
+    fn test_exit_reason() {
+        let handle = ThreadHandle::spawn("Test exit reason".to_string(), |interrupted| {
+            interrupted.exit_with(Reason::ConfigReload);
+        }).unwrap();
+        let monitor = handle.monitor();
+        handle.join().unwrap().unwrap();
+        assert_eq!(Some(Reason::ConfigReload), handle.exit_reason());
+        assert_eq!(None, handle.exit_reason::<String>());
+        assert_eq!(Some("ConfigReload".to_string()), monitor.recv().unwrap().exit_reason);
+        let silent = ThreadHandle::spawn("Test no exit reason".to_string(), |_| ()).unwrap();
+        silent.join().unwrap().unwrap();
+        assert_eq!(None, silent.exit_reason::<Reason>());
+    }
+}
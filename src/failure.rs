@@ -0,0 +1,63 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use {Shared, ThreadHandle, ThreadStatus};
+
+
+impl Shared {
+    /// Records the error returned by a fallible runnable: its `Debug` form for reports, and a
+    /// copy for `fallible_status`.
+    pub(crate) fn record_error<E>(&self, error: &E) where E: Clone + fmt::Debug + Send + Sync + 'static {
+        self.returned_error.set(format!("{:?}", error)).ok();
+        self.failure.set(Arc::new(error.clone())).ok();
+    }
+}
+
+impl<T, E> ThreadHandle<Result<T, E>> where T: Send + 'static, E: Clone + Send + Sync + 'static {
+    /// Like `status`, reporting `Failed` with the error the runnable of `spawn_fallible` or
+    /// `spawn_supervised` returned once the thread failed, so that health endpoints can tell
+    /// why it stopped while another component joins it.
+    pub fn fallible_status(&self) -> ThreadStatus<E> {
+        let failure = self.shared.failure.get().and_then(|failure| {
+            let failure: &(dyn Any + Send + Sync) = &**failure;
+            failure.downcast_ref::<E>()
+        });
+        match (self.status(), failure) {
+            (ThreadStatus::Terminated, Some(error)) => ThreadStatus::Failed(error.clone()),
+            (ThreadStatus::Terminated, None) => ThreadStatus::Terminated,
+            (ThreadStatus::Running, _) => ThreadStatus::Running,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use super::*;
+
+    #[derive(Clone, PartialEq, Debug)]
+    enum SyncError {
+        Unreachable(String),
+    }
+
+    #[test]
+    fn test_fallible_status() {
+        let (start, started) = mpsc::channel::<()>();
+        let handle = ThreadHandle::spawn_fallible("Test fallible status".to_string(), move |_| {
+            started.recv().ok();
+            Err::<(), _>(SyncError::Unreachable("replica-2".to_string()))
+        }).unwrap();
+        assert_eq!(ThreadStatus::Running, handle.fallible_status());
+        start.send(()).unwrap();
+        let snapshot = handle.wait_until(None, |snapshot| snapshot.status == ThreadStatus::Terminated).unwrap();
+        assert_eq!(Some("Unreachable(\"replica-2\")".to_string()), snapshot.failure);
+        assert_eq!(ThreadStatus::Failed(SyncError::Unreachable("replica-2".to_string())), handle.fallible_status());
+        assert!(handle.join().unwrap().unwrap().is_err());
+
+        let handle = ThreadHandle::spawn_fallible("Test fallible status ok".to_string(), |_| Ok::<_, SyncError>(1)).unwrap();
+        handle.wait_until(None, |snapshot| snapshot.status == ThreadStatus::Terminated).unwrap();
+        assert_eq!(ThreadStatus::Terminated, handle.fallible_status());
+    }
+}
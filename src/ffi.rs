@@ -0,0 +1,98 @@
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::sync::Arc;
+
+use {InterruptToken, Shared};
+
+
+/// Interruption state of a token in a form C code can use, for native libraries taking an
+/// "abort" callback with a context pointer, see `InterruptToken::as_raw`.
+///
+/// The context pointer stays valid as long as this value is alive; pass it to the callbacks
+/// only while the native call using them is running.
+pub struct RawInterrupt {
+    /// Boxed, as the context is a thin pointer to the `Arc`.
+    shared: Box<Arc<Shared>>,
+}
+
+impl RawInterrupt {
+    /// Context pointer to pass along with `should_abort` and `interrupt`.
+    pub fn context(&self) -> *mut c_void {
+        &*self.shared as *const Arc<Shared> as *mut c_void
+    }
+
+    /// Callback returning 1 once interruption was requested, including through a deadline or
+    /// a custom signal, and 0 otherwise or inside `InterruptToken::shield`.
+    ///
+    /// # Safety
+    ///
+    /// The callback must be given the `context` of a `RawInterrupt` that is still alive.
+    pub fn should_abort(&self) -> unsafe extern "C" fn(*mut c_void) -> c_int {
+        should_abort
+    }
+
+    /// Callback requesting interruption, returning 1 if it had already been requested.
+    ///
+    /// # Safety
+    ///
+    /// The callback must be given the `context` of a `RawInterrupt` that is still alive.
+    pub fn interrupt(&self) -> unsafe extern "C" fn(*mut c_void) -> c_int {
+        interrupt
+    }
+}
+
+impl fmt::Debug for RawInterrupt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RawInterrupt").field("context", &self.context()).finish()
+    }
+}
+
+/// # Safety
+///
+/// `context` must come from a `RawInterrupt` that is still alive, which keeps the state alive.
+unsafe extern "C" fn should_abort(context: *mut c_void) -> c_int {
+    let shared = &*(context as *const Arc<Shared>);
+    shared.is_interrupted() as c_int
+}
+
+/// # Safety
+///
+/// `context` must come from a `RawInterrupt` that is still alive.
+unsafe extern "C" fn interrupt(context: *mut c_void) -> c_int {
+    let shared = &*(context as *const Arc<Shared>);
+    shared.request_interrupt(None) as c_int
+}
+
+impl InterruptToken {
+    /// Exports the interruption state of this token to C code, so that native libraries with
+    /// their own abort hooks honor the same cancellation as the Rust code driving them.
+    pub fn as_raw(&self) -> RawInterrupt {
+        RawInterrupt {
+            shared: Box::new(self.shared.clone()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_interrupt() {
+        let token = InterruptToken::new();
+        let raw = token.as_raw();
+        let (should_abort, interrupt, context) = (raw.should_abort(), raw.interrupt(), raw.context());
+        unsafe {
+            assert_eq!(0, should_abort(context));
+            token.interrupt();
+            assert_eq!(1, should_abort(context));
+            assert_eq!(1, interrupt(context));
+        }
+
+        let token = InterruptToken::new();
+        let raw = token.as_raw();
+        assert_eq!(0, unsafe { raw.interrupt()(raw.context()) });
+        assert!(token.is_interrupted());
+    }
+}
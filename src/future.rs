@@ -0,0 +1,135 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use shutdown::{self, ShutdownGraph};
+use ThreadGroup;
+
+
+#[derive(Default)]
+struct State {
+    stragglers: Option<Vec<String>>,
+    waker: Option<Waker>,
+}
+
+/// Future of a shutdown run by a helper thread, resolving to the names of the threads still
+/// running when their class or layer timed out. It works with any executor, and awaiting it never
+/// blocks the thread polling it.
+pub struct ShutdownFuture {
+    state: Arc<Mutex<State>>,
+}
+
+impl ShutdownFuture {
+    /// Runs `shutdown` on a helper thread, outside the registry so that it is not asked to stop.
+    pub(crate) fn spawn<F>(shutdown: F) -> io::Result<Self> where F: FnOnce() -> Vec<String> + Send + 'static {
+        let state = Arc::new(Mutex::new(State::default()));
+        let done = state.clone();
+        thread::Builder::new().name("thread-handle shutdown".to_string()).spawn(move || {
+            let stragglers = shutdown();
+            let mut state = done.lock().unwrap();
+            state.stragglers = Some(stragglers);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        })?;
+        Ok(ShutdownFuture { state })
+    }
+}
+
+impl Future for ShutdownFuture {
+    type Output = Vec<String>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Vec<String>> {
+        let mut state = self.state.lock().unwrap();
+        match state.stragglers.take() {
+            Some(stragglers) => Poll::Ready(stragglers),
+            None => {
+                state.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// Like `shutdown`, for async code: the threads are interrupted and waited for by a helper
+    /// thread rather than by a worker of the runtime.
+    pub fn shutdown_async(&self, grace: Option<Duration>) -> io::Result<ShutdownFuture> {
+        let members: Vec<_> = self.handles().iter().map(|handle| handle.shared.clone()).collect();
+        ShutdownFuture::spawn(move || shutdown::shutdown_by_class(members.iter().collect(), grace))
+    }
+}
+
+impl ShutdownGraph {
+    /// Like `shutdown`, for async code.
+    pub fn shutdown_async(self, grace: Option<Duration>) -> io::Result<ShutdownFuture> {
+        ShutdownFuture::spawn(move || self.shutdown(grace))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::task::Wake;
+    use std::time::Instant;
+    use {InterruptToken, ThreadHandle};
+    use super::*;
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Polls `future` on the current thread, parking in between, and counts the polls.
+    fn block_on<F>(mut future: F) -> (F::Output, usize) where F: Future + Unpin {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut polls = 0;
+        loop {
+            polls += 1;
+            if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut context) {
+                return (output, polls);
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn test_shutdown_async() {
+        let mut group = ThreadGroup::new("Test shutdown async".to_string());
+        group.spawn("Test shutdown async stopping".to_string(), |interrupted: InterruptToken| {
+            while !interrupted.is_interrupted() {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        group.spawn("Test shutdown async stuck".to_string(), move |_| {
+            released.recv().ok();
+        }).unwrap();
+        let start = Instant::now();
+        let (stragglers, polls) = block_on(group.shutdown_async(Some(Duration::from_millis(100))).unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(polls >= 2);
+        assert_eq!(vec!["Test shutdown async stuck".to_string()], stragglers);
+        release.send(()).unwrap();
+        assert!(group.join_all().is_ok());
+
+        let handle = ThreadHandle::spawn("Test shutdown async graph".to_string(), |interrupted: InterruptToken| {
+            while !interrupted.is_interrupted() {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        let mut graph = ShutdownGraph::new();
+        graph.add(&handle);
+        assert!(block_on(graph.shutdown_async(None).unwrap()).0.is_empty());
+        assert!(handle.join().unwrap().is_ok());
+    }
+}
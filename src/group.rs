@@ -0,0 +1,797 @@
+use std::any::Any;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::thread;
+use std::sync::atomic::{self, AtomicU64, AtomicUsize};
+use std::time::Duration;
+
+use builder::SpawnSettings;
+use clock::WaitBudget;
+use config;
+use namespace;
+use panics::{PanicPayload, PanicPolicy};
+use sys;
+use {InterruptToken, JoinOutcome, PanicReport, Shared, ThreadHandle};
+
+
+/// Limits enforced by a `ThreadGroup`; unset limits are unbounded.
+#[derive(Clone, Default, Debug)]
+pub struct Quota {
+    max_live: Option<usize>,
+    max_spawns: Option<usize>,
+    max_cpu_time: Option<Duration>,
+}
+
+impl Quota {
+    pub fn new() -> Self {
+        Quota::default()
+    }
+
+    pub fn max_live(mut self, threads: usize) -> Self {
+        self.max_live = Some(threads);
+        self
+    }
+
+    pub fn max_spawns(mut self, spawns: usize) -> Self {
+        self.max_spawns = Some(spawns);
+        self
+    }
+
+    /// Cumulative CPU time of finished threads; only measured on Linux.
+    pub fn max_cpu_time(mut self, time: Duration) -> Self {
+        self.max_cpu_time = Some(time);
+        self
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QuotaKind {
+    LiveThreads,
+    TotalSpawns,
+    CpuTime,
+}
+
+#[derive(Clone, Debug)]
+pub struct QuotaExceeded {
+    pub group: String,
+    pub kind: QuotaKind,
+    pub usage: GroupUsage,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GroupUsage {
+    pub live: usize,
+    pub spawned: usize,
+    pub cpu_time: Duration,
+}
+
+pub type QuotaCallback = Arc<dyn Fn(&QuotaExceeded) + Send + Sync>;
+
+/// Usage of a group or pool, checked against its quota before each spawn.
+pub(crate) struct Accounting {
+    group: String,
+    quota: Quota,
+    live: AtomicUsize,
+    spawned: AtomicUsize,
+    cpu_nanos: AtomicU64,
+    on_exceeded: Option<QuotaCallback>,
+}
+
+impl Accounting {
+    pub(crate) fn new(group: String, quota: Quota, on_exceeded: Option<QuotaCallback>) -> Arc<Self> {
+        Arc::new(Accounting {
+            group,
+            quota,
+            live: AtomicUsize::new(0),
+            spawned: AtomicUsize::new(0),
+            cpu_nanos: AtomicU64::new(0),
+            on_exceeded,
+        })
+    }
+
+    /// Counts a thread about to be spawned, unless the quota is exceeded and no callback lets
+    /// the spawn go ahead. The thread stays counted until the returned member is dropped.
+    pub(crate) fn admit(self: &Arc<Self>) -> io::Result<Member> {
+        if let Some(kind) = self.exceeded() {
+            if !self.notify(kind) {
+                return Err(io::Error::other(format!("quota of thread group {} exceeded: {:?}", self.group, kind)));
+            }
+        }
+        self.live.fetch_add(1, atomic::Ordering::SeqCst);
+        self.spawned.fetch_add(1, atomic::Ordering::SeqCst);
+        Ok(Member {
+            accounting: self.clone(),
+            cpu_at_start: None,
+        })
+    }
+
+    pub(crate) fn usage(&self) -> GroupUsage {
+        GroupUsage {
+            live: self.live.load(atomic::Ordering::SeqCst),
+            spawned: self.spawned.load(atomic::Ordering::SeqCst),
+            cpu_time: Duration::from_nanos(self.cpu_nanos.load(atomic::Ordering::SeqCst)),
+        }
+    }
+
+    fn exceeded(&self) -> Option<QuotaKind> {
+        let usage = self.usage();
+        if self.quota.max_live.is_some_and(|max| usage.live >= max) {
+            Some(QuotaKind::LiveThreads)
+        } else if self.quota.max_spawns.is_some_and(|max| usage.spawned >= max) {
+            Some(QuotaKind::TotalSpawns)
+        } else if self.quota.max_cpu_time.is_some_and(|max| usage.cpu_time >= max) {
+            Some(QuotaKind::CpuTime)
+        } else {
+            None
+        }
+    }
+
+    fn notify(&self, kind: QuotaKind) -> bool {
+        match self.on_exceeded {
+            Some(ref callback) => {
+                callback(&QuotaExceeded {
+                    group: self.group.clone(),
+                    kind,
+                    usage: self.usage(),
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn add_cpu_time(&self, time: Duration) {
+        let nanos = time.as_nanos() as u64;
+        let before = Duration::from_nanos(self.cpu_nanos.fetch_add(nanos, atomic::Ordering::SeqCst));
+        if let Some(max) = self.quota.max_cpu_time {
+            if before < max && before + time >= max {
+                self.notify(QuotaKind::CpuTime);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum JoinFailure<E> {
+    Panicked(Box<dyn Any + Send>),
+    /// The thread returned an error, for `try_join_all`.
+    Errored(E),
+    AlreadyJoined,
+}
+
+/// Failed thread of a group join.
+#[derive(Debug)]
+pub struct FailedThread<E> {
+    pub index: usize,
+    pub thread: String,
+    pub failure: JoinFailure<E>,
+}
+
+/// Error of `ThreadGroup::join_all` and `try_join_all`: the threads that failed, along with the
+/// results of those that succeeded.
+#[derive(Debug)]
+pub struct GroupJoinError<T, E = Infallible> {
+    pub group: String,
+    pub failed: Vec<FailedThread<E>>,
+    /// Results of the threads that succeeded, with their index.
+    pub succeeded: Vec<(usize, T)>,
+    /// In fail-fast mode, index of the thread whose failure interrupted the others.
+    pub cause: Option<usize>,
+    /// Indices of the threads interrupted because of that failure.
+    pub cancelled: Vec<usize>,
+}
+
+impl<T, E> fmt::Display for GroupJoinError<T, E> where E: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} threads of group {} failed:", self.failed.len(), self.failed.len() + self.succeeded.len(), self.group)?;
+        for (i, failed) in self.failed.iter().enumerate() {
+            write!(f, "{} {} ", if i == 0 { "" } else { "," }, failed.thread)?;
+            match failed.failure {
+                JoinFailure::Panicked(ref payload) => write!(f, "(panicked: {})", payload.describe())?,
+                JoinFailure::Errored(ref error) => write!(f, "(error: {:?})", error)?,
+                JoinFailure::AlreadyJoined => write!(f, "(already joined)")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T, E> Error for GroupJoinError<T, E> where T: fmt::Debug, E: fmt::Debug {}
+
+/// Thread still running when `ThreadGroup::cancel_with_deadline` gave up waiting.
+#[derive(Clone, Debug)]
+pub struct Straggler {
+    pub index: usize,
+    pub thread: String,
+    pub uptime: Option<Duration>,
+}
+
+/// Outcome of `ThreadGroup::cancel_with_deadline`, listing the threads by name.
+#[derive(Clone, Debug)]
+pub struct CancelReport {
+    pub group: String,
+    pub exited: Vec<String>,
+    pub panicked: Vec<PanicReport>,
+    pub running: Vec<Straggler>,
+}
+
+impl fmt::Display for CancelReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "group {} cancelled: {} exited, {} panicked, {} still running", self.group, self.exited.len(), self.panicked.len(), self.running.len())?;
+        for (i, straggler) in self.running.iter().enumerate() {
+            write!(f, "{} {}", if i == 0 { ":" } else { "," }, straggler.thread)?;
+            if let Some(uptime) = straggler.uptime {
+                write!(f, " (up {:.3}s)", uptime.as_secs_f64())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps a group's live count and CPU time up to date from inside its threads.
+pub(crate) struct Member {
+    accounting: Arc<Accounting>,
+    /// CPU time of the thread when the runnable started, or `None` if it never did.
+    cpu_at_start: Option<Duration>,
+}
+
+impl Member {
+    /// Called first thing in the runnable: only the CPU time spent from now on is charged to the
+    /// group, as the thread may have run other work before.
+    pub(crate) fn start(&mut self) {
+        self.cpu_at_start = Some(sys::thread_cpu_time().unwrap_or_default());
+    }
+}
+
+/// A member dropped without having started, because spawning its thread failed or a lazy
+/// thread was discarded, is not counted as spawned and charges no CPU time.
+impl Drop for Member {
+    fn drop(&mut self) {
+        match self.cpu_at_start {
+            Some(start) => {
+                if let Some(now) = sys::thread_cpu_time() {
+                    self.accounting.add_cpu_time(now.saturating_sub(start));
+                }
+            }
+            None => {
+                self.accounting.spawned.fetch_sub(1, atomic::Ordering::SeqCst);
+            }
+        }
+        self.accounting.live.fetch_sub(1, atomic::Ordering::SeqCst);
+    }
+}
+
+/// Cancellation of the whole group on the first failure, see `ThreadGroup::fail_fast`.
+struct FailFast {
+    group: String,
+    members: Mutex<Vec<(usize, Weak<Shared>)>>,
+    cause: OnceLock<usize>,
+    cancelled: Mutex<Vec<usize>>,
+}
+
+impl FailFast {
+    /// Adds a member, interrupting it right away if the group already failed, unless the member
+    /// itself failed before it could be added.
+    fn join(&self, index: usize, shared: &Arc<Shared>) {
+        let mut members = self.members.lock().unwrap();
+        if let Some(&cause) = self.cause.get() {
+            if cause != index {
+                self.cancel(index, shared, cause);
+            }
+        }
+        members.push((index, Arc::downgrade(shared)));
+    }
+
+    /// Interrupts every other running member, the first time a member fails.
+    fn fail(&self, index: usize) {
+        let members = self.members.lock().unwrap();
+        if self.cause.set(index).is_err() {
+            return;
+        }
+        for &(member, ref shared) in members.iter().filter(|&&(member, _)| member != index) {
+            if let Some(shared) = shared.upgrade() {
+                if !shared.is_terminated() {
+                    self.cancel(member, &shared, index);
+                }
+            }
+        }
+    }
+
+    fn cancel(&self, index: usize, shared: &Shared, cause: usize) {
+        self.cancelled.lock().unwrap().push(index);
+        shared.request_interrupt(Some(&format!("thread {} of group {} failed", cause, self.group)));
+    }
+}
+
+/// Reports the failure of a member to its fail-fast group, including by panicking.
+struct FailGuard {
+    fail_fast: Arc<FailFast>,
+    index: usize,
+    failed: bool,
+}
+
+impl Drop for FailGuard {
+    fn drop(&mut self) {
+        if self.failed || thread::panicking() {
+            self.fail_fast.fail(self.index);
+        }
+    }
+}
+
+/// A named set of threads producing the same result type.
+pub struct ThreadGroup<T> {
+    handles: Vec<ThreadHandle<T>>,
+    accounting: Arc<Accounting>,
+    fail_fast: Option<Arc<FailFast>>,
+    /// Whether a result counts as a failure in fail-fast mode.
+    failed: fn(&T) -> bool,
+    panic_policy: Option<PanicPolicy>,
+    /// Whether thread names are prefixed with the name of the group.
+    namespaced: bool,
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    pub fn new(name: String) -> Self {
+        ThreadGroup::with_quota(name, Quota::new(), None)
+    }
+
+    /// Creates a group enforcing `quota`. Without a callback, spawns exceeding the quota are
+    /// rejected; with one, the callback is invoked and the spawn goes ahead.
+    pub fn with_quota(name: String, quota: Quota, on_exceeded: Option<QuotaCallback>) -> Self {
+        let group = ThreadGroup {
+            handles: Vec::new(),
+            fail_fast: None,
+            failed: |_| false,
+            panic_policy: None,
+            namespaced: false,
+            accounting: Accounting::new(name, quota, on_exceeded),
+        };
+        match config::installed().default_panic_policy() {
+            PanicPolicy::InterruptGroup => group.fail_fast(),
+            _ => group,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.accounting.group
+    }
+
+    /// Interrupts every other thread of the group as soon as one panics; joining then reports
+    /// which thread failed first and which were cancelled because of it.
+    pub fn fail_fast(self) -> Self {
+        self.fail_fast_when(|_| false)
+    }
+
+    /// Panic policy of the threads spawned into the group from now on; `InterruptGroup` turns
+    /// on `fail_fast` unless the group already is fail-fast.
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = Some(policy);
+        if policy == PanicPolicy::InterruptGroup && self.fail_fast.is_none() {
+            self = self.fail_fast();
+        }
+        self
+    }
+
+    /// Makes the group a namespace: the threads spawned into it from now on are named
+    /// "group/thread", so that the registry can target them with `registry::names_in`.
+    pub fn namespaced(mut self) -> Self {
+        self.namespaced = true;
+        self
+    }
+
+    fn fail_fast_when(mut self, failed: fn(&T) -> bool) -> Self {
+        self.failed = failed;
+        let fail_fast = FailFast {
+            group: self.name().to_string(),
+            members: Mutex::new(Vec::new()),
+            cause: OnceLock::new(),
+            cancelled: Mutex::new(Vec::new()),
+        };
+        self.fail_fast = Some(Arc::new(fail_fast));
+        self
+    }
+
+    /// Spawns a thread into the group and returns its index.
+    pub fn spawn<F>(&mut self, name: String, runnable: F) -> io::Result<usize> where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let mut member = self.accounting.admit()?;
+        let settings = SpawnSettings {
+            group: Some(self.accounting.group.clone()),
+            panic_policy: self.panic_policy,
+            ..SpawnSettings::default()
+        };
+        let index = self.handles.len();
+        let guard = self.fail_fast.clone().map(|fail_fast| FailGuard {
+            fail_fast,
+            index,
+            failed: false,
+        });
+        let failed = self.failed;
+        let name = if self.namespaced {
+            format!("{}{}{}", self.name(), namespace::SEPARATOR, name)
+        } else {
+            name
+        };
+        let handle = ThreadHandle::spawn_configured(name, settings.token(), &settings, move |interrupted| {
+            member.start();
+            let mut guard = guard;
+            let value = runnable(interrupted);
+            if let Some(ref mut guard) = guard {
+                guard.failed = failed(&value);
+            }
+            value
+        })?;
+        if let Some(ref fail_fast) = self.fail_fast {
+            fail_fast.join(index, &handle.shared);
+        }
+        self.handles.push(handle);
+        Ok(self.handles.len() - 1)
+    }
+
+    /// Spawns one thread per available core, each pinned to its core where the platform
+    /// supports it. The thread at index `i` runs on the `i`-th core of `available_cores()`.
+    pub fn spawn_per_core<F, R>(name: String, mut factory: F) -> io::Result<Self> where
+        F: FnMut(usize) -> R,
+        R: FnOnce(InterruptToken) -> T, R: Send + 'static
+    {
+        let mut group = ThreadGroup::new(name);
+        for (index, cpu) in sys::available_cpus().into_iter().enumerate() {
+            let runnable = factory(index);
+            let name = format!("{}-{}", group.name(), index);
+            group.spawn(name, move |interrupted| {
+                sys::pin_to_cpu(cpu);
+                runnable(interrupted)
+            })?;
+        }
+        Ok(group)
+    }
+
+    pub fn handles(&self) -> &[ThreadHandle<T>] {
+        &self.handles
+    }
+
+    pub(crate) fn into_handles(self) -> Vec<ThreadHandle<T>> {
+        self.handles
+    }
+
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    pub fn usage(&self) -> GroupUsage {
+        self.accounting.usage()
+    }
+
+    /// Joins every thread and returns their results in index order, or, if any of them panicked
+    /// or had already been joined, which ones. All threads are joined either way.
+    pub fn join_all(&self) -> Result<Vec<T>, GroupJoinError<T>> {
+        self.collect(|result| result.map_err(JoinFailure::Panicked))
+    }
+
+    fn collect<U, E, F>(&self, mut classify: F) -> Result<Vec<U>, GroupJoinError<U, E>> where
+        F: FnMut(thread::Result<T>) -> Result<U, JoinFailure<E>>
+    {
+        let mut succeeded = Vec::new();
+        let mut failed = Vec::new();
+        for (index, handle) in self.handles.iter().enumerate() {
+            let outcome = match handle.join() {
+                Some(result) => classify(result),
+                None => Err(JoinFailure::AlreadyJoined),
+            };
+            match outcome {
+                Ok(value) => succeeded.push((index, value)),
+                Err(failure) => failed.push(FailedThread {
+                    index,
+                    thread: handle.name().to_string(),
+                    failure,
+                }),
+            }
+        }
+        if failed.is_empty() {
+            Ok(succeeded.into_iter().map(|(_, value)| value).collect())
+        } else {
+            let fail_fast = self.fail_fast.as_ref();
+            Err(GroupJoinError {
+                group: self.name().to_string(),
+                failed,
+                succeeded,
+                cause: fail_fast.and_then(|fail_fast| fail_fast.cause.get().copied()),
+                cancelled: fail_fast.map_or_else(Vec::new, |fail_fast| fail_fast.cancelled.lock().unwrap().clone()),
+            })
+        }
+    }
+
+    /// Interrupts every thread, then waits up to `timeout` in total for them to terminate and
+    /// reports how each one ended. No result is taken, so the threads can still be joined.
+    pub fn cancel_with_deadline(&self, timeout: Duration) -> CancelReport {
+        let mut budget = WaitBudget::new(Some(timeout));
+        for handle in &self.handles {
+            handle.shared.request_interrupt(Some(&format!("group {} cancelled", self.name())));
+        }
+        let mut report = CancelReport {
+            group: self.name().to_string(),
+            exited: Vec::new(),
+            panicked: Vec::new(),
+            running: Vec::new(),
+        };
+        for (index, handle) in self.handles.iter().enumerate() {
+            if !budget.spend(&*handle.shared.clock, |remaining| handle.wait_finished(remaining)) {
+                report.running.push(Straggler {
+                    index,
+                    thread: handle.name().to_string(),
+                    uptime: handle.uptime(),
+                });
+            } else if let Some(message) = handle.shared.panic_message.get() {
+                report.panicked.push(PanicReport {
+                    thread: handle.name().to_string(),
+                    message: message.clone(),
+                });
+            } else {
+                report.exited.push(handle.name().to_string());
+            }
+        }
+        report
+    }
+
+    /// Joins every thread, in index order, waiting no longer than `timeout` in total;
+    /// threads still running at the deadline are reported as such and can be joined later.
+    pub fn join_all_timeout(&self, timeout: Duration) -> Vec<JoinOutcome<T>> {
+        let mut budget = WaitBudget::new(Some(timeout));
+        self.handles.iter().map(|handle| {
+            budget.spend(&*handle.shared.clock, |remaining| handle.join_waiting(remaining))
+        }).collect()
+    }
+}
+
+impl<T, E> ThreadGroup<Result<T, E>> where T: Send + 'static, E: Send + 'static {
+    /// Like `fail_fast`, also counting threads that return an error as failed.
+    pub fn fail_fast_on_error(self) -> Self {
+        self.fail_fast_when(Result::is_err)
+    }
+
+    /// Like `join_all`, also counting threads that returned an error as failed.
+    pub fn try_join_all(&self) -> Result<Vec<T>, GroupJoinError<T, E>> {
+        self.collect(|result| match result {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(error)) => Err(JoinFailure::Errored(error)),
+            Err(payload) => Err(JoinFailure::Panicked(payload)),
+        })
+    }
+}
+
+
+/// Runs `f` on every item, each on its own thread of a fail-fast group named `name`: the first
+/// item failing, by returning an error or panicking, interrupts the others. The results are in
+/// item order. If a thread cannot be spawned, the ones already running are interrupted and
+/// joined before the error is returned.
+pub fn par_map<I, T, E, F>(name: String, items: I, f: F) -> io::Result<Result<Vec<T>, GroupJoinError<T, E>>> where
+    I: IntoIterator, I::Item: Send + 'static,
+    T: Send + 'static, E: Send + 'static,
+    F: Fn(I::Item, InterruptToken) -> Result<T, E> + Send + Sync + 'static
+{
+    let f = Arc::new(f);
+    let mut group = ThreadGroup::new(name).fail_fast_on_error();
+    for (index, item) in items.into_iter().enumerate() {
+        let f = f.clone();
+        let name = format!("{}-{}", group.name(), index);
+        if let Err(error) = group.spawn(name, move |interrupted| f(item, interrupted)) {
+            for handle in group.handles() {
+                handle.interrupt().ok();
+            }
+            group.try_join_all().ok();
+            return Err(error);
+        }
+    }
+    Ok(group.try_join_all())
+}
+
+/// Identifiers of the cores `ThreadGroup::spawn_per_core` would use, honouring the
+/// process affinity mask and cgroup CPU quota.
+pub fn available_cores() -> Vec<usize> {
+    sys::available_cpus()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::thread::sleep;
+    use std::time::Instant;
+    use super::*;
+
+    #[test]
+    fn test_group_max_live() {
+        let mut group = ThreadGroup::with_quota("Test live".to_string(), Quota::new().max_live(1), None);
+        group.spawn("Test live first".to_string(), |_| sleep(Duration::from_millis(300))).unwrap();
+        assert!(group.spawn("Test live second".to_string(), |_| ()).is_err());
+        assert!(group.handles()[0].join().unwrap().is_ok());
+        assert_eq!(1, group.spawn("Test live third".to_string(), |_| ()).unwrap());
+    }
+
+    #[test]
+    fn test_group_max_spawns_callback() {
+        let exceeded = Arc::new(Mutex::new(Vec::new()));
+        let record = exceeded.clone();
+        let callback: QuotaCallback = Arc::new(move |event: &QuotaExceeded| {
+            record.lock().unwrap().push(event.kind);
+        });
+        let mut group = ThreadGroup::with_quota("Test spawns".to_string(), Quota::new().max_spawns(2), Some(callback));
+        for i in 0..3 {
+            group.spawn(format!("Test spawns {}", i), |_| ()).unwrap();
+        }
+        assert_eq!(vec![QuotaKind::TotalSpawns], *exceeded.lock().unwrap());
+        for handle in group.handles() {
+            assert!(handle.join().unwrap().is_ok());
+        }
+        assert_eq!(3, group.usage().spawned);
+        assert_eq!(0, group.usage().live);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_group_cpu_time() {
+        let mut group = ThreadGroup::with_quota("Test cpu".to_string(), Quota::new().max_cpu_time(Duration::from_millis(20)), None);
+        group.spawn("Test cpu busy".to_string(), |_| {
+            let started = Instant::now();
+            while started.elapsed() < Duration::from_millis(50) {}
+        }).unwrap();
+        assert!(group.handles()[0].join().unwrap().is_ok());
+        assert!(group.usage().cpu_time >= Duration::from_millis(20));
+        assert!(group.spawn("Test cpu rejected".to_string(), |_| ()).is_err());
+    }
+
+    #[test]
+    fn test_join_all_timeout() {
+        let mut group = ThreadGroup::new("Test join all timeout".to_string());
+        group.spawn("Test join all quick".to_string(), |_| 1).unwrap();
+        group.spawn("Test join all stuck".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+            2
+        }).unwrap();
+        group.spawn("Test join all panic".to_string(), |_| panic!("")).unwrap();
+        let started = Instant::now();
+        let outcomes = group.join_all_timeout(Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        match outcomes[..] {
+            [JoinOutcome::Finished(1), JoinOutcome::StillRunning, JoinOutcome::Panicked(_)] => (),
+            ref other => panic!("unexpected outcomes {:?}", other),
+        }
+        group.handles()[1].interrupt().unwrap();
+        assert_eq!(2, group.handles()[1].join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_join_all() {
+        let mut group = ThreadGroup::new("Test join all".to_string());
+        for i in 0..3 {
+            group.spawn(format!("Test join all {}", i), move |_| i * 10).unwrap();
+        }
+        assert_eq!(vec![0, 10, 20], group.join_all().unwrap());
+        let error = group.join_all().unwrap_err();
+        assert_eq!(3, error.failed.len());
+        assert!(error.failed.iter().all(|failed| matches!(failed.failure, JoinFailure::AlreadyJoined)));
+    }
+
+    #[test]
+    fn test_try_join_all() {
+        let mut group = ThreadGroup::new("Test try join all".to_string());
+        group.spawn("Test try join ok".to_string(), |_| Ok(1)).unwrap();
+        group.spawn("Test try join error".to_string(), |_| Err("refused")).unwrap();
+        group.spawn("Test try join panic".to_string(), |_| panic!("crashed")).unwrap();
+        let error = group.try_join_all().unwrap_err();
+        assert_eq!(vec![(0, 1)], error.succeeded);
+        assert_eq!(vec![1, 2], error.failed.iter().map(|failed| failed.index).collect::<Vec<_>>());
+        assert_eq!("2 of 3 threads of group Test try join all failed: Test try join error (error: \"refused\"), Test try join panic (panicked: crashed)", error.to_string());
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let mut group = ThreadGroup::new("Test fail fast".to_string()).fail_fast_on_error();
+        for i in 0..2 {
+            group.spawn(format!("Test fail fast waiting {}", i), |interrupted| {
+                while !interrupted.is_interrupted() {
+                    sleep(Duration::from_millis(5));
+                }
+                Ok(())
+            }).unwrap();
+        }
+        group.spawn("Test fail fast quick".to_string(), |_| Ok(())).unwrap();
+        group.handles()[2].wait_finished(None);
+        group.spawn("Test fail fast failing".to_string(), |_| {
+            sleep(Duration::from_millis(20));
+            Err("invalid input")
+        }).unwrap();
+        let error = group.try_join_all().unwrap_err();
+        assert_eq!(Some(3), error.cause);
+        assert_eq!(vec![0, 1], error.cancelled);
+        assert_eq!(vec![3], error.failed.iter().map(|failed| failed.index).collect::<Vec<_>>());
+        assert_eq!(Some("thread 3 of group Test fail fast failed"), group.handles()[0].interrupt_reason());
+
+        let mut panicking = ThreadGroup::new("Test fail fast panic".to_string()).fail_fast();
+        panicking.spawn("Test fail fast panicking".to_string(), |_| panic!("crashed")).unwrap();
+        panicking.handles()[0].wait_finished(None);
+        panicking.spawn("Test fail fast late".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        let error = panicking.join_all().unwrap_err();
+        assert_eq!((Some(0), vec![1]), (error.cause, error.cancelled));
+        assert_eq!(1, error.failed.len());
+
+        // However quickly it fails, the cause is never counted as cancelled by its own failure.
+        for _ in 0..20 {
+            let mut group = ThreadGroup::new("Test fail fast instant".to_string()).fail_fast_on_error();
+            group.spawn("Test fail fast instant failing".to_string(), |_| Err::<(), _>("invalid")).unwrap();
+            let error = group.try_join_all().unwrap_err();
+            assert_eq!((Some(0), vec![]), (error.cause, error.cancelled));
+        }
+    }
+
+    #[test]
+    fn test_par_map() {
+        let squares = par_map("Test par map".to_string(), 1..5, |item: u32, _| Ok::<_, ()>(item * item)).unwrap();
+        assert_eq!(vec![1, 4, 9, 16], squares.unwrap());
+
+        let error = par_map("Test par map failing".to_string(), 0..3, |item, interrupted| {
+            if item == 2 {
+                return Err("invalid item");
+            }
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+            Ok(item)
+        }).unwrap().unwrap_err();
+        assert_eq!(Some(2), error.cause);
+        assert_eq!(vec![2], error.failed.iter().map(|failed| failed.index).collect::<Vec<_>>());
+        let mut cancelled = error.cancelled.clone();
+        cancelled.sort();
+        assert_eq!(vec![0, 1], cancelled);
+    }
+
+    #[test]
+    fn test_spawn_per_core() {
+        let cores = available_cores();
+        let group = ThreadGroup::spawn_per_core("Test core".to_string(), |index| {
+            move |_| index
+        }).unwrap();
+        assert_eq!(cores.len(), group.len());
+        for (index, handle) in group.handles().iter().enumerate() {
+            assert_eq!(index, handle.join().unwrap().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_cancel_with_deadline() {
+        let mut group = ThreadGroup::new("Test cancel".to_string());
+        group.spawn("Test cancel cooperative".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        group.spawn("Test cancel panicking".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+            panic!("cancelled");
+        }).unwrap();
+        group.spawn("Test cancel stubborn".to_string(), |_| sleep(Duration::from_millis(500))).unwrap();
+        let report = group.cancel_with_deadline(Duration::from_millis(100));
+        assert_eq!(vec!["Test cancel cooperative".to_string()], report.exited);
+        assert_eq!("cancelled", report.panicked[0].message);
+        assert_eq!(2, report.running[0].index);
+        assert!(report.to_string().contains("1 still running: Test cancel stubborn (up "), "{}", report);
+        assert!(group.handles()[0].join().unwrap().is_ok());
+        group.handles()[2].join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::atomic::{self, AtomicU64};
+use std::time::{Duration, Instant};
+
+use counters::AtomicCounters;
+use events;
+use {InterruptToken, Shared, ThreadHandle};
+
+
+/// Point in time stored as nanoseconds since the creation of the shared state, so it can be
+/// updated with a single atomic store; zero means "never".
+pub(crate) struct Timestamp(AtomicU64);
+
+impl Timestamp {
+    pub(crate) fn new() -> Self {
+        Timestamp(AtomicU64::new(0))
+    }
+
+    pub(crate) fn set(&self, origin: Instant, at: Instant) {
+        let nanos = at.saturating_duration_since(origin).as_nanos() as u64;
+        self.0.store(nanos + 1, atomic::Ordering::Relaxed);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.store(0, atomic::Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self, origin: Instant) -> Option<Instant> {
+        match self.0.load(atomic::Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(origin + Duration::from_nanos(nanos - 1)),
+        }
+    }
+}
+
+impl Shared {
+    pub(crate) fn last_heartbeat(&self) -> Option<Instant> {
+        self.last_heartbeat.get(self.created)
+    }
+
+    pub(crate) fn started_at(&self) -> Option<Instant> {
+        self.started.get(self.created)
+    }
+
+    /// Time since the last sign of life: the last heartbeat, or the start of the thread.
+    pub(crate) fn silence(&self, now: Instant) -> Option<Duration> {
+        self.last_heartbeat().or_else(|| self.started_at()).map(|since| now.saturating_duration_since(since))
+    }
+}
+
+impl InterruptToken {
+    /// Signals that the worker is alive and making progress.
+    pub fn heartbeat(&self) {
+        self.shared.last_heartbeat.set(self.shared.created, self.shared.clock.now());
+        AtomicCounters::increment(&self.shared.counters.heartbeats);
+        events::publish(&self.shared, || events::ThreadEvent::Heartbeat);
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    pub fn last_heartbeat(&self) -> Option<Instant> {
+        self.shared.last_heartbeat()
+    }
+
+    /// Whether the thread is running but has not sent a heartbeat (or, if it never sent one,
+    /// has not started) within `threshold`. Terminated threads are never stale, nor are lazy
+    /// ones that did not start, which this does not start.
+    pub fn is_stale(&self, threshold: Duration) -> bool {
+        !self.shared.is_terminated()
+            && self.shared.silence(self.shared.clock.now()).is_some_and(|silence| silence > threshold)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+    use {Builder, MockClock};
+    use super::*;
+
+    #[test]
+    fn test_heartbeat() {
+        let clock = Arc::new(MockClock::new());
+        let (beat, beaten) = mpsc::channel();
+        let handle = Builder::new("Test heartbeat".to_string()).clock(clock.clone()).spawn(move |interrupted| {
+            interrupted.heartbeat();
+            beat.send(()).unwrap();
+            while !interrupted.is_interrupted() {
+                ::std::thread::sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        beaten.recv().unwrap();
+        assert!(handle.last_heartbeat().is_some());
+        assert!(!handle.is_stale(Duration::from_millis(500)));
+        clock.advance(Duration::from_millis(400));
+        assert!(handle.is_stale(Duration::from_millis(200)));
+        handle.interrupt().unwrap();
+        handle.join().unwrap().unwrap();
+        assert!(!handle.is_stale(Duration::from_millis(0)));
+    }
+
+    #[test]
+    fn test_stale_without_heartbeat() {
+        let clock = Arc::new(MockClock::new());
+        let (started, starting) = mpsc::channel();
+        let (finish, finishing) = mpsc::channel::<()>();
+        let handle = Builder::new("Test silent".to_string()).clock(clock.clone()).spawn(move |_| {
+            started.send(()).unwrap();
+            finishing.recv().ok();
+        }).unwrap();
+        starting.recv().unwrap();
+        assert_eq!(None, handle.last_heartbeat());
+        assert!(!handle.is_stale(Duration::from_millis(100)));
+        clock.advance(Duration::from_millis(150));
+        assert!(handle.is_stale(Duration::from_millis(100)));
+        finish.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_stale_leaves_lazy_unstarted() {
+        let clock = Arc::new(MockClock::new());
+        let lazy = Builder::new("Test stale lazy".to_string()).clock(clock.clone()).lazy(|_| ());
+        clock.advance(Duration::from_secs(60));
+        assert!(!lazy.is_stale(Duration::from_millis(0)));
+        assert_eq!(None, lazy.uptime());
+    }
+}
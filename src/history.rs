@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::thread;
+use std::time::SystemTime;
+
+use {Shared, ThreadHandle};
+
+
+/// Number of interruption requests kept per handle; older ones are dropped.
+const HISTORY: usize = 8;
+
+/// Interruption request, as kept for diagnostics by `ThreadHandle::interrupt_history`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InterruptRequest {
+    pub at: SystemTime,
+    pub reason: Option<String>,
+    /// Name of the thread that asked, `None` for unnamed threads.
+    pub requester: Option<String>,
+}
+
+pub(crate) type History = VecDeque<InterruptRequest>;
+
+impl Shared {
+    pub(crate) fn record_interrupt(&self, reason: Option<&str>) {
+        let mut history = self.interrupt_history.lock().unwrap();
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back(InterruptRequest {
+            at: SystemTime::now(),
+            reason: reason.map(str::to_string),
+            requester: thread::current().name().map(str::to_string),
+        });
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// The latest interruption requests, repeated ones included, oldest first, to find out who
+    /// asked the thread to stop and why; see `interrupt_reason` for the first reason given.
+    pub fn interrupt_history(&self) -> Vec<InterruptRequest> {
+        self.shared.interrupt_history.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_interrupt_history() {
+        let handle = ThreadHandle::spawn("Test history".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        let before = SystemTime::now();
+        thread::Builder::new().name("Test history requester".to_string()).spawn({
+            let trigger = handle.trigger();
+            move || trigger.interrupt_with_reason("reload").unwrap()
+        }).unwrap().join().unwrap();
+        handle.interrupt_with_reason("shutdown").unwrap();
+        let history = handle.interrupt_history();
+        assert_eq!(vec![Some("reload"), Some("shutdown")], history.iter().map(|request| request.reason.as_deref()).collect::<Vec<_>>());
+        assert_eq!(Some("Test history requester"), history[0].requester.as_deref());
+        assert_eq!(thread::current().name(), history[1].requester.as_deref());
+        assert!(before <= history[0].at && history[0].at <= history[1].at);
+        for _ in 0..HISTORY {
+            handle.interrupt().unwrap();
+        }
+        let history = handle.interrupt_history();
+        assert_eq!(HISTORY, history.len());
+        assert!(history.iter().all(|request| request.reason.is_none()));
+        assert_eq!(Some("reload"), handle.interrupt_reason());
+        handle.join().unwrap().unwrap();
+
+        let handle = ThreadHandle::spawn("Test history empty".to_string(), |_| ()).unwrap();
+        handle.wait_finished(None);
+        assert!(handle.interrupt_history().is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+//! Process-wide lifecycle hooks: closures notified about every thread spawned by this crate,
+//! and spawn hooks that can change or reject every spawn. Hooks see a summary of the events
+//! published to `events` subscribers.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use events::{self, SubscriptionId, Subscriber, ThreadEvent};
+use HandleId;
+
+pub use intercept::{register as register_spawn_hook, unregister as unregister_spawn_hook, SpawnHookId, SpawnRequest};
+
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    /// The runnable is about to run on its thread.
+    Started,
+    /// Interruption was requested for the first time.
+    InterruptRequested,
+    Completed { runtime: Duration },
+    Panicked { runtime: Duration, message: String },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Event {
+    pub thread: String,
+    pub kind: EventKind,
+}
+
+/// Identifies a registered hook, to unregister it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct HookId(SubscriptionId);
+
+/// Subscriber turning the events of a thread into the hook events; a panic is reported once the
+/// thread terminated, with its runtime.
+struct Hook<F> {
+    hook: F,
+    /// Message of threads that panicked and have not terminated yet.
+    panicked: Mutex<HashMap<HandleId, String>>,
+}
+
+impl<F> Subscriber for Hook<F> where F: Fn(&Event) + Send + Sync {
+    fn on_event(&self, event: &events::Event) {
+        let kind = match event.event {
+            ThreadEvent::Spawned => EventKind::Started,
+            ThreadEvent::InterruptRequested { .. } => EventKind::InterruptRequested,
+            ThreadEvent::Panicked { ref message } => {
+                self.panicked.lock().unwrap().insert(event.id, message.clone());
+                return;
+            }
+            ThreadEvent::Terminated { duration: runtime } => match self.panicked.lock().unwrap().remove(&event.id) {
+                Some(message) => EventKind::Panicked { runtime, message },
+                None => EventKind::Completed { runtime },
+            },
+            _ => return,
+        };
+        (self.hook)(&Event {
+            thread: event.thread.clone(),
+            kind,
+        });
+    }
+}
+
+/// Registers a hook invoked, on the thread where the event happens, for every lifecycle event.
+pub fn register<F>(hook: F) -> HookId where F: Fn(&Event) + Send + Sync + 'static {
+    HookId(events::subscribe(Arc::new(Hook {
+        hook,
+        panicked: Mutex::new(HashMap::new()),
+    })))
+}
+
+/// Removes a hook; returns `false` if it was not registered.
+pub fn unregister(id: HookId) -> bool {
+    events::unsubscribe(id.0)
+}
+
+
+#[cfg(all(test, not(feature = "minimal")))]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread::sleep;
+    use ThreadHandle;
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_hooks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let record = events.clone();
+        let id = register(move |event| {
+            if event.thread.starts_with("Test hooks lifecycle") {
+                record.lock().unwrap().push(event.clone());
+            }
+        });
+        let (running, is_running) = mpsc::channel();
+        let interrupted = ThreadHandle::spawn("Test hooks lifecycle interrupted".to_string(), move |interrupted| {
+            running.send(()).unwrap();
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+        }).unwrap();
+        is_running.recv().unwrap();
+        interrupted.interrupt().unwrap();
+        interrupted.interrupt().unwrap();
+        interrupted.join().unwrap().unwrap();
+        let panicked = ThreadHandle::spawn("Test hooks lifecycle panicked".to_string(), |_| -> () { panic!("boom") }).unwrap();
+        assert!(panicked.join().unwrap().is_err());
+        assert!(unregister(id));
+        assert!(!unregister(id));
+
+        let events = events.lock().unwrap();
+        let kinds: Vec<_> = events.iter().map(|event| match event.kind {
+            EventKind::Started => "started".to_string(),
+            EventKind::InterruptRequested => "interrupt".to_string(),
+            EventKind::Completed { .. } => "completed".to_string(),
+            EventKind::Panicked { ref message, .. } => format!("panicked: {}", message),
+        }).collect();
+        assert_eq!(vec!["started", "interrupt", "completed", "started", "panicked: boom"], kinds);
+    }
+}
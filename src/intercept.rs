@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{self, AtomicBool};
+use std::thread::JoinHandle;
+
+use backend::{Job, SpawnBackend};
+use builder::SpawnSettings;
+use leak::DropPolicy;
+use panics::PanicPolicy;
+
+
+type SpawnHook = Arc<dyn Fn(&mut SpawnRequest) -> io::Result<()> + Send + Sync>;
+
+/// Identifies a spawn hook, to unregister it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SpawnHookId(u64);
+
+struct SpawnHooks {
+    next_id: u64,
+    hooks: Vec<(SpawnHookId, SpawnHook)>,
+}
+
+static SPAWN_HOOKS: RwLock<SpawnHooks> = RwLock::new(SpawnHooks { next_id: 0, hooks: Vec::new() });
+/// Lets spawns skip the hooks without locking while none is registered.
+static ANY: AtomicBool = AtomicBool::new(false);
+
+/// Thread about to be spawned, as seen by spawn hooks, which may change its configuration.
+pub struct SpawnRequest {
+    name: String,
+    settings: SpawnSettings,
+}
+
+impl SpawnRequest {
+    /// Name of the thread, including the prefix of the installed configuration.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    /// Thread group or pool the thread belongs to.
+    pub fn group(&self) -> Option<&str> {
+        self.settings.group.as_deref()
+    }
+
+    /// Stack size chosen with the builder, in bytes.
+    pub fn stack_size(&self) -> Option<usize> {
+        self.settings.stack_size
+    }
+
+    pub fn set_stack_size(&mut self, bytes: usize) {
+        self.settings.stack_size = Some(bytes);
+    }
+
+    pub fn set_detect_leaks(&mut self, enabled: bool) {
+        self.settings.detect_leaks = Some(enabled);
+    }
+
+    pub fn set_drop_policy(&mut self, policy: DropPolicy) {
+        self.settings.drop_policy = Some(policy);
+    }
+
+    pub fn set_panic_policy(&mut self, policy: PanicPolicy) {
+        self.settings.panic_policy = Some(policy);
+    }
+
+    /// Runs the thread on `backend`, e.g. to reroute it to threads the framework manages.
+    pub fn set_backend(&mut self, backend: Arc<dyn SpawnBackend>) {
+        self.settings.backend = Some(backend);
+    }
+}
+
+/// Registers a hook invoked, on the spawning thread, before every thread is created by this
+/// crate. It may change the request, or reject it with an error that `spawn` returns; hooks run
+/// in the order they were registered and the first error stops the spawn.
+pub fn register<F>(hook: F) -> SpawnHookId where F: Fn(&mut SpawnRequest) -> io::Result<()> + Send + Sync + 'static {
+    let mut hooks = SPAWN_HOOKS.write().unwrap();
+    hooks.next_id += 1;
+    let id = SpawnHookId(hooks.next_id);
+    hooks.hooks.push((id, Arc::new(hook)));
+    ANY.store(true, atomic::Ordering::Release);
+    id
+}
+
+/// Removes a spawn hook; returns `false` if it was not registered.
+pub fn unregister(id: SpawnHookId) -> bool {
+    let mut hooks = SPAWN_HOOKS.write().unwrap();
+    let before = hooks.hooks.len();
+    hooks.hooks.retain(|&(registered, _)| registered != id);
+    ANY.store(!hooks.hooks.is_empty(), atomic::Ordering::Release);
+    hooks.hooks.len() != before
+}
+
+/// Runs the spawn hooks on the thread about to be spawned as `name` with `settings`, updating the
+/// name; returns the configuration to spawn it with.
+pub(crate) fn apply<'a>(name: &mut String, settings: &'a SpawnSettings) -> io::Result<Cow<'a, SpawnSettings>> {
+    if !ANY.load(atomic::Ordering::Acquire) {
+        return Ok(Cow::Borrowed(settings));
+    }
+    // Cloned so that a hook can spawn threads, or register hooks, without deadlocking.
+    let registered: Vec<SpawnHook> = SPAWN_HOOKS.read().unwrap().hooks.iter().map(|(_, hook)| hook.clone()).collect();
+    let mut request = SpawnRequest {
+        name: name.clone(),
+        settings: settings.clone(),
+    };
+    for hook in registered {
+        hook(&mut request)?;
+    }
+    *name = request.name;
+    Ok(Cow::Owned(request.settings))
+}
+
+/// Configuration of a lazy thread rejected by a spawn hook: starting it fails with `error`.
+pub(crate) fn rejected(settings: &SpawnSettings, error: io::Error) -> SpawnSettings {
+    let mut settings = settings.clone();
+    settings.backend = Some(Arc::new(Rejected {
+        kind: error.kind(),
+        message: error.to_string(),
+    }));
+    settings.spawn_retry = None;
+    settings.inline_fallback = false;
+    settings.stack_size = None;
+    settings
+}
+
+struct Rejected {
+    kind: io::ErrorKind,
+    message: String,
+}
+
+impl SpawnBackend for Rejected {
+    fn spawn(&self, _name: String, _job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        Err(io::Error::new(self.kind, self.message.clone()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {InlineBackend, ThreadHandle, ThreadStatus};
+    use super::*;
+
+    #[test]
+    fn test_spawn_hooks() {
+        let inline = Arc::new(InlineBackend::immediate());
+        let backend = inline.clone();
+        let id = register(move |request| {
+            if request.name().starts_with("Test spawn hook rejected") {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "threads of this kind are not allowed"));
+            }
+            if request.name() == "Test spawn hook rerouted" {
+                request.set_name("Test spawn hook inline".to_string());
+                request.set_backend(backend.clone());
+            }
+            Ok(())
+        });
+        let error = ThreadHandle::spawn("Test spawn hook rejected".to_string(), |_| ()).unwrap_err();
+        assert_eq!(io::ErrorKind::PermissionDenied, error.kind());
+        let lazy = ThreadHandle::lazy("Test spawn hook rejected lazy".to_string(), |_| ());
+        assert_eq!(io::ErrorKind::PermissionDenied, lazy.ensure_started().unwrap_err().kind());
+        assert_eq!(io::ErrorKind::PermissionDenied, lazy.ensure_started().unwrap_err().kind());
+        assert_eq!(ThreadStatus::Terminated, lazy.status());
+        assert!(lazy.join().is_none());
+        assert_eq!("threads of this kind are not allowed", lazy.start_error().unwrap().to_string());
+        let rerouted = ThreadHandle::spawn("Test spawn hook rerouted".to_string(), |_| 3).unwrap();
+        assert_eq!("Test spawn hook inline", rerouted.name());
+        assert_eq!(3, rerouted.join().unwrap().unwrap());
+        assert!(unregister(id));
+        assert!(!unregister(id));
+        assert!(ThreadHandle::spawn("Test spawn hook rejected again".to_string(), |_| ()).unwrap().join().unwrap().is_ok());
+    }
+}
@@ -0,0 +1,364 @@
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{self, AtomicBool};
+use std::task::{Context, Poll, Waker};
+
+use deadline;
+use {CancelSignal, InterruptToken, Shared};
+
+
+/// Whoever to tell once interruption is requested.
+#[derive(Default)]
+pub(crate) struct Listeners {
+    next_key: u64,
+    /// Wakers of the pending `Cancelled` futures, by key.
+    wakers: Vec<(u64, Waker)>,
+    flags: Vec<Weak<AtomicBool>>,
+    /// Child tokens, whose own listeners are told in turn.
+    children: Vec<Weak<Shared>>,
+    /// Source of the stop tokens handed out, dropped to stop them.
+    #[cfg(feature = "stop-token")]
+    stop_source: Option<stop_token::StopSource>,
+    /// Whether the helper thread of deadlines checks the external signal of the token.
+    watched: bool,
+}
+
+impl Listeners {
+    /// Sets the waker of the future registered under `key`, registering it first if needed.
+    fn set_waker(&mut self, key: Option<u64>, waker: &Waker) -> u64 {
+        if let Some(key) = key {
+            if let Some(&mut (_, ref mut registered)) = self.wakers.iter_mut().find(|&&mut (registered, _)| registered == key) {
+                if !registered.will_wake(waker) {
+                    *registered = waker.clone();
+                }
+                return key;
+            }
+        }
+        let key = key.unwrap_or_else(|| {
+            self.next_key += 1;
+            self.next_key
+        });
+        self.wakers.push((key, waker.clone()));
+        key
+    }
+
+    /// Takes out everyone to tell. The key counter stays, so that a future registering later
+    /// cannot reuse the key of one still to be dropped, and so does the watch, which the helper
+    /// thread of deadlines keeps.
+    fn take_pending(&mut self) -> Listeners {
+        Listeners {
+            next_key: self.next_key,
+            wakers: mem::take(&mut self.wakers),
+            flags: mem::take(&mut self.flags),
+            children: mem::take(&mut self.children),
+            #[cfg(feature = "stop-token")]
+            stop_source: self.stop_source.take(),
+            watched: self.watched,
+        }
+    }
+}
+
+impl Shared {
+    /// Wakes the `Cancelled` futures, sets the flags and stops the stop tokens exported from
+    /// this token, then tells its children.
+    pub(crate) fn notify_interrupt_listeners(&self) {
+        let listeners = self.interrupt_listeners.lock().unwrap().take_pending();
+        for flag in listeners.flags.iter().filter_map(Weak::upgrade) {
+            flag.store(true, atomic::Ordering::Release);
+        }
+        for (_, waker) in listeners.wakers {
+            waker.wake();
+        }
+        for child in listeners.children.iter().filter_map(Weak::upgrade) {
+            child.notify_interrupt_listeners();
+        }
+    }
+
+    /// Tells the listeners once an external signal is raised; returns whether it was.
+    pub(crate) fn check_external_signal(&self) -> bool {
+        let raised = self.signal.is_external() && self.signal.is_cancelled();
+        if raised {
+            self.notify_interrupt_listeners();
+        }
+        raised
+    }
+
+    pub(crate) fn add_child_listener(&self, child: &Arc<Shared>) {
+        let mut listeners = self.interrupt_listeners.lock().unwrap();
+        listeners.children.retain(|child| child.strong_count() > 0);
+        listeners.children.push(Arc::downgrade(child));
+    }
+}
+
+/// Registers a listener of `shared` with `register`. A token whose signal can be raised from
+/// elsewhere is then checked by the helper thread of deadlines, so that its listeners are told.
+fn listen<R, F>(shared: &Arc<Shared>, register: F) -> R where F: FnOnce(&mut Listeners) -> R {
+    let (registered, watch) = {
+        let mut listeners = shared.interrupt_listeners.lock().unwrap();
+        let registered = register(&mut listeners);
+        let watch = !listeners.watched && shared.signal.is_external();
+        listeners.watched |= watch;
+        (registered, watch)
+    };
+    if watch {
+        deadline::arm(shared);
+    }
+    registered
+}
+
+/// Future resolving once interruption of a token is requested, see `InterruptToken::cancelled`.
+pub struct Cancelled {
+    token: InterruptToken,
+    /// Key of its waker among the listeners, once it was polled.
+    key: Option<u64>,
+}
+
+impl Clone for Cancelled {
+    fn clone(&self) -> Self {
+        self.token.cancelled()
+    }
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        if this.token.is_interrupted() {
+            return Poll::Ready(());
+        }
+        let key = this.key;
+        this.key = Some(listen(&this.token.shared, |listeners| listeners.set_waker(key, context.waker())));
+        // Requested between the first check and the registration, which it then missed.
+        match this.token.is_interrupted() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Cancelled {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.token.shared.interrupt_listeners.lock().unwrap().wakers.retain(|&(registered, _)| registered != key);
+        }
+    }
+}
+
+impl InterruptToken {
+    /// Future resolving once interruption is requested, for async code or libraries taking a
+    /// cancellation future. It wakes when interruption is requested on this token, a parent
+    /// token or a handle of its thread, or once the deadline of the thread passes; a custom
+    /// signal raised from elsewhere is noticed within 10 ms by a helper thread.
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled { token: self.clone(), key: None }
+    }
+
+    /// Flag set once interruption is requested, under the same conditions as `cancelled` wakes,
+    /// for libraries that poll an `AtomicBool`. Storing to it does not interrupt the token.
+    pub fn as_atomic_bool(&self) -> Arc<AtomicBool> {
+        let flag = Arc::new(AtomicBool::new(false));
+        listen(&self.shared, |listeners| {
+            listeners.flags.retain(|flag| flag.strong_count() > 0);
+            listeners.flags.push(Arc::downgrade(&flag));
+        });
+        if self.shared.is_interrupt_requested() {
+            flag.store(true, atomic::Ordering::Release);
+        }
+        flag
+    }
+
+    /// `StopToken` of the `stop-token` crate, stopped once interruption is requested under the
+    /// same conditions as `cancelled` wakes, for libraries of the async-std ecosystem. Only
+    /// this direction is provided: a `StopToken` is a future, which nothing would poll to
+    /// interrupt a token.
+    #[cfg(feature = "stop-token")]
+    pub fn as_stop_token(&self) -> stop_token::StopToken {
+        let token = listen(&self.shared, |listeners| listeners.stop_source.get_or_insert_with(stop_token::StopSource::new).token());
+        // Requested before the source was registered, in which case it was not dropped.
+        if self.shared.is_interrupt_requested() {
+            self.shared.notify_interrupt_listeners();
+        }
+        token
+    }
+}
+
+/// Token interrupted when the flag is set, for code holding an `Arc<AtomicBool>` as its stop
+/// flag; interrupting the token sets the flag.
+impl From<Arc<AtomicBool>> for InterruptToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        InterruptToken::with_signal(flag)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Mutex};
+    use std::task::Wake;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use ThreadHandle;
+    use super::*;
+
+    struct Unpark(Mutex<Option<thread::Thread>>);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            if let Some(thread) = self.0.lock().unwrap().take() {
+                thread.unpark();
+            }
+        }
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let (sender, pending) = mpsc::channel();
+        let handle = ThreadHandle::spawn("Test cancelled".to_string(), move |interrupted: InterruptToken| {
+            let unpark = Arc::new(Unpark(Mutex::new(Some(thread::current()))));
+            let waker = Waker::from(unpark.clone());
+            let mut context = Context::from_waker(&waker);
+            let mut cancelled = interrupted.cancelled();
+            let mut polls = 0;
+            while Pin::new(&mut cancelled).poll(&mut context).is_pending() {
+                polls += 1;
+                sender.send(()).ok();
+                thread::park();
+            }
+            polls
+        }).unwrap();
+        pending.recv().unwrap();
+        handle.interrupt().unwrap();
+        assert!(handle.join().unwrap().unwrap() >= 1);
+    }
+
+    /// Polls `future` on the calling thread until it is ready; returns `false` if it stayed
+    /// pending for a second without waking the thread.
+    fn wait_woken<F>(mut future: F) -> bool where F: Future<Output = ()> + Unpin {
+        let unpark = Arc::new(Unpark(Mutex::new(None)));
+        let waker = Waker::from(unpark.clone());
+        let mut context = Context::from_waker(&waker);
+        loop {
+            *unpark.0.lock().unwrap() = Some(thread::current());
+            if Pin::new(&mut future).poll(&mut context).is_ready() {
+                return true;
+            }
+            let parked = Instant::now();
+            thread::park_timeout(Duration::from_secs(1));
+            if parked.elapsed() >= Duration::from_secs(1) {
+                return false;
+            }
+        }
+    }
+
+    #[test]
+    fn test_cancelled_wakes() {
+        let parent = InterruptToken::new();
+        let child = parent.child();
+        let interrupting = parent.clone();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            interrupting.interrupt();
+        });
+        assert!(wait_woken(child.cancelled()));
+        interrupter.join().unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let token = InterruptToken::from(stop.clone());
+        let child = token.child();
+        let raiser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            stop.store(true, atomic::Ordering::Release);
+        });
+        let flag = child.as_atomic_bool();
+        assert!(wait_woken(child.cancelled()));
+        assert!(flag.load(atomic::Ordering::Acquire));
+        raiser.join().unwrap();
+    }
+
+    #[test]
+    fn test_cancelled_after_shield() {
+        let handle = ThreadHandle::spawn("Test cancelled shield".to_string(), |interrupted: InterruptToken| {
+            let unpark = Arc::new(Unpark(Mutex::new(Some(thread::current()))));
+            let waker = Waker::from(unpark.clone());
+            let mut cancelled = interrupted.cancelled();
+            interrupted.shield(|| {
+                while !interrupted.shared.is_interrupt_requested() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                assert!(Pin::new(&mut cancelled).poll(&mut Context::from_waker(&waker)).is_pending());
+            });
+            // Woken once the section ended, without being polled again.
+            let woken = unpark.0.lock().unwrap().is_none();
+            woken
+        }).unwrap();
+        handle.interrupt().unwrap();
+        assert!(handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_listeners_pruned() {
+        let token = InterruptToken::new();
+        let waker = Waker::from(Arc::new(Unpark(Mutex::new(None))));
+        for _ in 0..3 {
+            let mut cancelled = token.cancelled();
+            assert!(Pin::new(&mut cancelled).poll(&mut Context::from_waker(&waker)).is_pending());
+            drop(token.as_atomic_bool());
+        }
+        let listeners = token.shared.interrupt_listeners.lock().unwrap();
+        assert_eq!(0, listeners.wakers.len());
+        assert_eq!(1, listeners.flags.len());
+    }
+
+    #[test]
+    fn test_notify_keeps_keys_and_watch() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let token = InterruptToken::from(stop.clone());
+        let waker = Waker::from(Arc::new(Unpark(Mutex::new(None))));
+        let mut cancelled = token.cancelled();
+        assert!(Pin::new(&mut cancelled).poll(&mut Context::from_waker(&waker)).is_pending());
+        stop.store(true, atomic::Ordering::Release);
+        assert!(token.shared.check_external_signal());
+        let listeners = token.shared.interrupt_listeners.lock().unwrap();
+        assert!(listeners.wakers.is_empty());
+        assert_eq!(1, listeners.next_key);
+        assert!(listeners.watched);
+    }
+
+    #[cfg(feature = "stop-token")]
+    #[test]
+    fn test_stop_token() {
+        let token = InterruptToken::new();
+        let stop = token.as_stop_token();
+        let interrupting = token.clone();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            interrupting.interrupt();
+        });
+        assert!(wait_woken(stop));
+        assert!(wait_woken(token.as_stop_token()));
+        interrupter.join().unwrap();
+    }
+
+    #[test]
+    fn test_atomic_bool_view() {
+        let token = InterruptToken::new();
+        let flag = token.as_atomic_bool();
+        assert!(!flag.load(atomic::Ordering::Acquire));
+        token.interrupt();
+        assert!(flag.load(atomic::Ordering::Acquire));
+        assert!(token.as_atomic_bool().load(atomic::Ordering::Acquire));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let token = InterruptToken::from(stop.clone());
+        stop.store(true, atomic::Ordering::Release);
+        assert!(token.is_interrupted());
+
+        let parent = InterruptToken::new();
+        let driven = InterruptToken::with_signal(parent.clone());
+        parent.interrupt();
+        assert!(driven.is_interrupted());
+    }
+}
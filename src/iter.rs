@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::fmt;
+
+use InterruptToken;
+
+
+/// Marker yielded by `Interruptible::with_marker` when iteration stopped early, and error of
+/// `Budget::tick`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+impl Error for Interrupted {}
+
+/// Adds `interruptible` to every iterator.
+pub trait InterruptibleExt: Iterator + Sized {
+    /// Yields items until interruption of `token` is requested, checked before each item.
+    fn interruptible(self, token: &InterruptToken) -> Interruptible<Self> {
+        Interruptible {
+            iter: self,
+            token: token.clone(),
+            interrupted: false,
+        }
+    }
+}
+
+impl<I: Iterator> InterruptibleExt for I {}
+
+pub struct Interruptible<I> {
+    iter: I,
+    token: InterruptToken,
+    interrupted: bool,
+}
+
+impl<I> Interruptible<I> {
+    /// Whether iteration stopped because of interruption rather than exhaustion.
+    pub fn was_interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    /// Yields `Ok` items, then a single `Err(Interrupted)` if iteration was cut short.
+    pub fn with_marker(self) -> Marked<I> {
+        Marked {
+            inner: self,
+            done: false,
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for Interruptible<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.interrupted || self.token.is_interrupted() {
+            self.interrupted = true;
+            return None;
+        }
+        self.iter.next()
+    }
+}
+
+impl InterruptToken {
+    /// Calls `process` on consecutive chunks of `items`, checking for interruption before each
+    /// chunk, and returns how many items were processed.
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn run_chunked<T, F>(&self, items: &[T], chunk_size: usize, mut process: F) -> usize where
+        F: FnMut(&[T])
+    {
+        let mut completed = 0;
+        for chunk in items.chunks(chunk_size).interruptible(self) {
+            process(chunk);
+            completed += chunk.len();
+        }
+        completed
+    }
+}
+
+pub struct Marked<I> {
+    inner: Interruptible<I>,
+    done: bool,
+}
+
+impl<I: Iterator> Iterator for Marked<I> {
+    type Item = Result<I::Item, Interrupted>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.inner.next() {
+            Some(item) => Some(Ok(item)),
+            None => {
+                self.done = true;
+                if self.inner.was_interrupted() {
+                    Some(Err(Interrupted))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interruptible() {
+        let token = InterruptToken::new();
+        let mut seen = Vec::new();
+        let mut items = (0..10).interruptible(&token);
+        for item in &mut items {
+            seen.push(item);
+            if item == 3 {
+                token.interrupt();
+            }
+        }
+        assert_eq!(vec![0, 1, 2, 3], seen);
+        assert!(items.was_interrupted());
+        let mut exhausted = (0..2).interruptible(&InterruptToken::new());
+        assert_eq!(2, exhausted.by_ref().count());
+        assert!(!exhausted.was_interrupted());
+    }
+
+    #[test]
+    fn test_with_marker() {
+        let token = InterruptToken::new();
+        let marked: Vec<_> = (0..5).interruptible(&token).with_marker().inspect(|item| {
+            if *item == Ok(1) {
+                token.interrupt();
+            }
+        }).collect();
+        assert_eq!(vec![Ok(0), Ok(1), Err(Interrupted)], marked);
+        let complete: Vec<_> = (0..2).interruptible(&InterruptToken::new()).with_marker().collect();
+        assert_eq!(vec![Ok(0), Ok(1)], complete);
+    }
+
+    #[test]
+    fn test_run_chunked() {
+        let token = InterruptToken::new();
+        let items: Vec<u32> = (0..10).collect();
+        let mut sums = Vec::new();
+        assert_eq!(10, token.run_chunked(&items, 4, |chunk| sums.push(chunk.iter().sum::<u32>())));
+        assert_eq!(vec![6, 22, 17], sums);
+        let completed = token.run_chunked(&items, 3, |chunk| {
+            if chunk[0] == 3 {
+                token.interrupt();
+            }
+        });
+        assert_eq!(6, completed);
+    }
+}
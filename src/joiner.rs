@@ -0,0 +1,161 @@
+use std::io;
+use std::sync::atomic;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use {HandleId, InterruptToken, ThreadGroup, ThreadHandle};
+
+
+/// Joins a large number of threads in the order they finish, keeping nothing of a thread once
+/// its outcome was taken: the handle is released when it is added, and its state as soon as
+/// the thread terminates. Memory grows with the threads running or not yet collected, not with
+/// the total spawned.
+pub struct Joiner<T> {
+    sender: Sender<(usize, Option<thread::Result<T>>)>,
+    receiver: Receiver<(usize, Option<thread::Result<T>>)>,
+    added: usize,
+    pending: usize,
+}
+
+impl<T> Joiner<T> where T: Send + 'static {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Joiner { sender, receiver, added: 0, pending: 0 }
+    }
+
+    /// Hands a thread over to the joiner and returns its index; a thread whose result was
+    /// already taken is skipped when collecting.
+    pub fn add(&mut self, handle: ThreadHandle<T>) -> usize {
+        let index = self.added;
+        let sender = self.sender.clone();
+        let joined = handle.shared.clone();
+        handle.on_outcome(move |outcome| {
+            if outcome.is_some() {
+                joined.joined.store(true, atomic::Ordering::Release);
+            }
+            sender.send((index, outcome)).ok();
+        });
+        self.added += 1;
+        self.pending += 1;
+        index
+    }
+
+    /// Spawns a thread collected by the joiner and returns its index.
+    #[track_caller]
+    pub fn spawn<F>(&mut self, name: String, runnable: F) -> io::Result<usize> where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let handle = ThreadHandle::spawn(name, runnable)?;
+        Ok(self.add(handle))
+    }
+
+    /// Number of threads whose outcome was not collected yet.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+}
+
+impl<T> Default for Joiner<T> where T: Send + 'static {
+    fn default() -> Self {
+        Joiner::new()
+    }
+}
+
+impl<T> Iterator for Joiner<T> {
+    type Item = (usize, thread::Result<T>);
+
+    /// Blocks until another thread finishes; ends once every thread added was collected.
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending > 0 {
+            self.pending -= 1;
+            if let Ok((index, Some(outcome))) = self.receiver.recv() {
+                return Some((index, outcome));
+            }
+        }
+        None
+    }
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// Releases the handles of the group into a `Joiner`, yielding each outcome with the index
+    /// of its thread as soon as the thread finishes.
+    pub fn join_all_any_order(self) -> Joiner<T> {
+        let mut joiner = Joiner::new();
+        for handle in self.into_handles() {
+            joiner.add(handle);
+        }
+        joiner
+    }
+
+    /// Channel receiving the outcome of each thread of the group, with the id of its handle, as
+    /// soon as the thread terminates, so that a coordinator can aggregate and react while others
+    /// still run. Results are taken from the handles; threads whose result was already taken,
+    /// and threads spawned later, are left out. The channel disconnects after the last outcome.
+    pub fn results_channel(&self) -> Receiver<(HandleId, thread::Result<T>)> {
+        let (sender, receiver) = mpsc::channel();
+        for handle in self.handles() {
+            let sender = sender.clone();
+            let joined = handle.shared.clone();
+            handle.on_outcome(move |outcome| {
+                if let Some(outcome) = outcome {
+                    joined.joined.store(true, atomic::Ordering::Release);
+                    sender.send((joined.id, outcome)).ok();
+                }
+            });
+        }
+        receiver
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use super::*;
+
+    #[test]
+    fn test_joiner() {
+        let mut joiner = Joiner::new();
+        for i in 0..100 {
+            joiner.spawn(format!("Test joiner {}", i), move |_| i * 2).unwrap();
+        }
+        let taken = ThreadHandle::spawn("Test joiner taken".to_string(), |_| 0).unwrap();
+        taken.join().unwrap().unwrap();
+        assert_eq!(100, joiner.add(taken));
+        assert_eq!(101, joiner.pending());
+        let mut values: Vec<_> = joiner.by_ref().map(|(index, outcome)| (index, outcome.unwrap())).collect();
+        values.sort();
+        assert_eq!((0..100).map(|i| (i, i * 2)).collect::<Vec<_>>(), values);
+        assert_eq!(0, joiner.pending());
+    }
+
+    #[test]
+    fn test_group_join_all_any_order() {
+        let (sender, receiver) = channel::<()>();
+        let mut group = ThreadGroup::new("Test any order".to_string());
+        group.spawn("Test any order blocked".to_string(), move |_| receiver.recv().map(|_| "blocked").unwrap()).unwrap();
+        group.spawn("Test any order quick".to_string(), |_| "quick").unwrap();
+        let mut joiner = group.join_all_any_order();
+        assert_eq!((1, "quick"), joiner.next().map(|(index, outcome)| (index, outcome.unwrap())).unwrap());
+        sender.send(()).unwrap();
+        assert_eq!((0, "blocked"), joiner.next().map(|(index, outcome)| (index, outcome.unwrap())).unwrap());
+        assert!(joiner.next().is_none());
+    }
+
+    #[test]
+    fn test_group_results_channel() {
+        let (sender, receiver) = channel::<()>();
+        let mut group = ThreadGroup::new("Test results channel".to_string());
+        group.spawn("Test results channel blocked".to_string(), move |_| receiver.recv().map(|_| 1).unwrap()).unwrap();
+        group.spawn("Test results channel quick".to_string(), |_| 2).unwrap();
+        let ids: Vec<_> = group.handles().iter().map(ThreadHandle::id).collect();
+        let results = group.results_channel();
+        let (id, outcome) = results.recv().unwrap();
+        assert_eq!((ids[1], 2), (id, outcome.unwrap()));
+        sender.send(()).unwrap();
+        let (id, outcome) = results.recv().unwrap();
+        assert_eq!((ids[0], 1), (id, outcome.unwrap()));
+        assert!(results.recv().is_err());
+        assert!(group.handles()[0].join().is_none());
+    }
+}
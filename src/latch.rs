@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::fmt;
+#[cfg(not(all(test, loom)))]
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(all(test, loom))]
+use loom::sync::{Condvar, Mutex};
+
+use InterruptToken;
+
+
+/// Longest time a waiter sleeps before checking for interruption again.
+const INTERRUPT_CHECK: Duration = Duration::from_millis(10);
+
+/// Why `Latch::wait` returned before the latch was set.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LatchError {
+    /// Interruption was requested while waiting.
+    Interrupted,
+    TimedOut,
+}
+
+impl fmt::Display for LatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LatchError::Interrupted => write!(f, "interrupted while waiting for a latch"),
+            LatchError::TimedOut => write!(f, "timed out waiting for a latch"),
+        }
+    }
+}
+
+impl Error for LatchError {}
+
+/// Signal set once and never reset, waking everyone waiting for it, e.g. for a worker to tell
+/// its owner that its index is loaded. Handles use one for the termination of their thread.
+#[derive(Default)]
+pub struct Latch {
+    pub(crate) set: Mutex<bool>,
+    pub(crate) condvar: Condvar,
+}
+
+impl Latch {
+    pub fn new() -> Self {
+        Latch::default()
+    }
+
+    /// Sets the latch and wakes the waiters; returns whether it was already set.
+    pub fn set(&self) -> bool {
+        let mut set = self.set.lock().unwrap();
+        let previous = *set;
+        *set = true;
+        self.condvar.notify_all();
+        previous
+    }
+
+    pub fn is_set(&self) -> bool {
+        *self.set.lock().unwrap()
+    }
+
+    /// Waits until the latch is set, giving up once interruption is requested through `token`
+    /// or `timeout` elapsed.
+    pub fn wait(&self, token: &InterruptToken, timeout: Option<Duration>) -> Result<(), LatchError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut set = self.set.lock().unwrap();
+        loop {
+            if *set {
+                return Ok(());
+            }
+            if token.is_interrupted() {
+                return Err(LatchError::Interrupted);
+            }
+            let wait = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(LatchError::TimedOut);
+                    }
+                    INTERRUPT_CHECK.min(deadline - now)
+                }
+                None => INTERRUPT_CHECK,
+            };
+            set = self.condvar.wait_timeout(set, wait).unwrap().0;
+        }
+    }
+
+    /// Waits until the latch is set, for a thread without a token of its own such as the owner
+    /// of a handle; returns `false` if `timeout` elapsed first.
+    pub fn wait_timeout(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut set = self.set.lock().unwrap();
+        while !*set {
+            set = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.condvar.wait_timeout(set, deadline - now).unwrap().0
+                }
+                None => self.condvar.wait(set).unwrap(),
+            };
+        }
+        true
+    }
+}
+
+impl fmt::Debug for Latch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Latch")
+            .field("set", &self.is_set())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use ThreadHandle;
+    use super::*;
+
+    #[test]
+    fn test_latch() {
+        let loaded = Arc::new(Latch::new());
+        let (signal, waiter) = (loaded.clone(), loaded.clone());
+        let handle = ThreadHandle::spawn("Test latch".to_string(), move |interrupted: InterruptToken| {
+            waiter.wait(&interrupted, None)
+        }).unwrap();
+        assert!(!loaded.wait_timeout(Some(Duration::from_millis(20))));
+        assert!(!signal.set());
+        assert!(signal.set());
+        assert!(loaded.is_set());
+        assert!(loaded.wait_timeout(None));
+        assert_eq!(Ok(()), handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_latch_interrupted() {
+        let latch = Arc::new(Latch::new());
+        let waiter = latch.clone();
+        let handle = ThreadHandle::spawn("Test latch interrupted".to_string(), move |interrupted: InterruptToken| {
+            waiter.wait(&interrupted, None)
+        }).unwrap();
+        handle.interrupt().unwrap();
+        assert_eq!(Err(LatchError::Interrupted), handle.join().unwrap().unwrap());
+        assert_eq!(Err(LatchError::TimedOut), latch.wait(&InterruptToken::new(), Some(Duration::from_millis(10))));
+    }
+
+    /// Model-checks the termination handshake that joins rely on; run with
+    /// `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+    #[cfg(loom)]
+    #[test]
+    fn test_latch_loom() {
+        loom::model(|| {
+            let latch = loom::sync::Arc::new(Latch::new());
+            let setters: Vec<_> = (0..2).map(|_| {
+                let latch = latch.clone();
+                loom::thread::spawn(move || latch.set())
+            }).collect();
+            assert!(latch.wait_timeout(None));
+            let previous: Vec<_> = setters.into_iter().map(|setter| setter.join().unwrap()).collect();
+            assert_eq!(1, previous.iter().filter(|previous| !**previous).count());
+        });
+    }
+}
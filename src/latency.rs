@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+
+/// Number of most recent samples percentiles are estimated from.
+const WINDOW: usize = 1024;
+
+/// Percentile estimates over the most recent samples of a duration.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Ring of the last `WINDOW` samples.
+#[derive(Default)]
+pub(crate) struct LatencyWindow {
+    samples: Vec<Duration>,
+    next: usize,
+}
+
+impl LatencyWindow {
+    pub(crate) fn record(&mut self, sample: Duration) {
+        if self.samples.len() < WINDOW {
+            self.samples.push(sample);
+        } else {
+            self.samples[self.next] = sample;
+        }
+        self.next = (self.next + 1) % WINDOW;
+    }
+
+    /// `None` until a sample was recorded.
+    pub(crate) fn percentiles(&self) -> Option<Percentiles> {
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        let at = |fraction: f64| sorted[((sorted.len() - 1) as f64 * fraction).round() as usize];
+        sorted.last().map(|&max| Percentiles {
+            p50: at(0.5),
+            p90: at(0.9),
+            p99: at(0.99),
+            max,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut window = LatencyWindow::default();
+        assert_eq!(None, window.percentiles());
+        for millis in (1..=100).rev() {
+            window.record(Duration::from_millis(millis));
+        }
+        let percentiles = window.percentiles().unwrap();
+        assert_eq!(Duration::from_millis(51), percentiles.p50);
+        assert_eq!(Duration::from_millis(90), percentiles.p90);
+        assert_eq!(Duration::from_millis(99), percentiles.p99);
+        assert_eq!(Duration::from_millis(100), percentiles.max);
+        for _ in 0..WINDOW {
+            window.record(Duration::from_millis(1));
+        }
+        assert_eq!(Duration::from_millis(1), window.percentiles().unwrap().max);
+    }
+}
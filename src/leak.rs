@@ -0,0 +1,125 @@
+use std::sync::atomic::{self, AtomicBool};
+
+use config;
+use current;
+use events::{self, ThreadEvent};
+use ThreadHandle;
+
+
+static DETECT_LEAKS: AtomicBool = AtomicBool::new(false);
+
+/// What dropping the handle of a running thread does, unless something else waits for its
+/// result, such as a receiver from `result_receiver`.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum DropPolicy {
+    /// The thread keeps running on its own.
+    #[default]
+    Detach,
+    /// The thread is interrupted and left to finish on its own.
+    Interrupt,
+    /// The thread is interrupted and the drop waits for it to terminate, except on the thread
+    /// itself.
+    InterruptAndJoin,
+}
+
+/// Enables or disables leak detection for every handle spawned without `Builder::detect_leaks`:
+/// dropping the handle of a running thread that nobody waits for publishes `ThreadEvent::Leaked`.
+pub fn detect_leaks(enabled: bool) {
+    DETECT_LEAKS.store(enabled, atomic::Ordering::Release);
+}
+
+impl<T> Drop for ThreadHandle<T> {
+    fn drop(&mut self) {
+        let shared = &self.shared;
+        // A lazy handle that never started takes its runnable with it.
+        if shared.is_terminated() || self.pending.get_mut().unwrap().is_some() {
+            return;
+        }
+        // Receivers and combinators waiting for the result own the thread from now on.
+        if self.result.lock().unwrap().forward.is_some() {
+            return;
+        }
+        let policy = shared.drop_policy.get().copied().unwrap_or_else(|| config::installed().default_drop_policy());
+        if policy != DropPolicy::Detach {
+            shared.request_interrupt(Some("handle dropped"));
+        }
+        let own_thread = current::current_token().is_some_and(|token| token.shared.id == shared.id);
+        if policy == DropPolicy::InterruptAndJoin && !own_thread {
+            shared.wait_terminated(None);
+            return;
+        }
+        if !shared.detect_leaks.get().copied().unwrap_or_else(|| DETECT_LEAKS.load(atomic::Ordering::Acquire)) {
+            return;
+        }
+        let spawned_at = shared.spawn_origin().map_or_else(|| "<unknown>".to_string(), |origin| origin.to_string());
+        events::publish(shared, || ThreadEvent::Leaked { spawned_at });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    #[cfg(not(feature = "minimal"))]
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::Duration;
+    #[cfg(not(feature = "minimal"))]
+    use events::{self, Event, ThreadEvent};
+    use {Builder, ThreadStatus};
+    use super::DropPolicy;
+
+    #[cfg(not(feature = "minimal"))]
+    #[test]
+    fn test_leak_detected() {
+        let leaked = Arc::new(Mutex::new(Vec::new()));
+        let record = leaked.clone();
+        let id = events::subscribe(Arc::new(move |event: &Event| {
+            if let ThreadEvent::Leaked { ref spawned_at } = event.event {
+                if event.thread.starts_with("Test leak") {
+                    record.lock().unwrap().push((event.thread.clone(), spawned_at.clone()));
+                }
+            }
+        }));
+        let (sender, receiver) = mpsc::channel::<()>();
+        let builder = Builder::new("Test leak running".to_string()).detect_leaks(true);
+        drop(builder.spawn(move |_| receiver.recv_timeout(Duration::from_secs(5))).unwrap());
+        let finished = Builder::new("Test leak finished".to_string()).detect_leaks(true).spawn(|_| ()).unwrap();
+        finished.wait_finished(None);
+        drop(finished);
+        drop(Builder::new("Test leak lazy".to_string()).detect_leaks(true).lazy(|_| ()));
+        let received = Builder::new("Test leak received".to_string()).detect_leaks(true).spawn(|_| sleep(Duration::from_millis(50))).unwrap();
+        let receiver_of_result = received.result_receiver().unwrap();
+        drop(received);
+        receiver_of_result.recv().unwrap().unwrap();
+        sender.send(()).unwrap();
+        events::unsubscribe(id);
+        let leaked = leaked.lock().unwrap();
+        assert_eq!(1, leaked.len());
+        assert_eq!("Test leak running", leaked[0].0);
+        assert!(leaked[0].1.starts_with("src/leak.rs:"), "{}", leaked[0].1);
+    }
+
+    #[test]
+    fn test_drop_policy() {
+        let (stopped, on_stopped) = mpsc::channel();
+        let interrupted = Builder::new("Test drop interrupt".to_string()).drop_policy(DropPolicy::Interrupt).spawn(move |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+            stopped.send(()).unwrap();
+        }).unwrap();
+        drop(interrupted);
+        assert_eq!(Ok(()), on_stopped.recv_timeout(Duration::from_secs(5)));
+
+        let (finished, on_finished) = mpsc::channel::<ThreadStatus>();
+        let joined = Builder::new("Test drop join".to_string()).drop_policy(DropPolicy::InterruptAndJoin).spawn(move |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+            finished.send(ThreadStatus::Running).unwrap();
+        }).unwrap();
+        drop(joined);
+        assert_eq!(Ok(ThreadStatus::Running), on_finished.try_recv());
+    }
+}
@@ -1,63 +1,1109 @@
+#[cfg(feature = "stream")]
+extern crate futures_core;
+#[cfg(all(test, loom))]
+extern crate loom;
+#[cfg(feature = "otel")]
+extern crate opentelemetry;
+#[cfg(feature = "parking_lot")]
+extern crate parking_lot;
+#[cfg(feature = "stop-token")]
+extern crate stop_token;
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::fmt;
+use std::convert::Infallible;
+use std::hash::{Hash, Hasher};
 use std::io;
-use std::sync::{Arc, Weak, RwLock};
-use std::sync::atomic::{self, AtomicBool};
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe, Location};
+use std::sync::{mpsc, Arc, Condvar, Mutex, OnceLock, Weak};
+use std::sync::atomic::{self, AtomicBool, AtomicI32, AtomicU64, AtomicUsize};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+mod abort;
+mod ack;
+mod assign;
+mod backend;
+#[cfg(feature = "backtrace")]
+mod backtrace;
+mod barrier;
+mod blocking;
+#[cfg(all(feature = "linux", target_os = "linux"))]
+mod boost;
+mod budget;
+mod builder;
+mod child;
+mod clock;
+mod combine;
+mod command;
+mod config;
+pub mod context;
+mod counters;
+mod crash;
+mod current;
+mod daemon;
+mod deadline;
+mod dump;
+mod each;
+mod errors;
+pub mod events;
+mod exit;
+mod failure;
+mod ffi;
+mod future;
+mod group;
+mod heartbeat;
+mod history;
+pub mod hooks;
+mod intercept;
+mod interop;
+mod iter;
+mod joiner;
+mod latch;
+mod latency;
+mod leak;
+mod lifecycle;
+mod limit;
+mod link;
+mod lock;
+mod monitor;
+pub mod namespace;
+#[cfg(all(feature = "linux", target_os = "linux"))]
+mod numa;
+mod observer;
+mod ordering;
+mod origin;
+#[cfg(feature = "otel")]
+mod otel;
+mod panics;
+mod pause;
+#[cfg(all(feature = "linux", target_os = "linux"))]
+mod place;
+mod pool;
+mod prepare;
+mod process;
+mod progress;
+mod quiesce;
+mod recycle;
+pub mod registry;
+mod reload;
+mod replay;
+mod retry;
+mod scale;
+mod schedule;
+mod scope;
+mod semaphore;
+mod shard;
+mod shield;
+mod shutdown;
+mod singleflight;
+mod sink;
+mod slow;
+mod snapshot;
+mod stream;
+mod supervise;
+mod sys;
+mod temp;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod throttle;
+mod token;
+mod trigger;
+mod watchdog;
+
+pub use assign::AssignableHandle;
+pub use backend::{set_spawn_backend, Job, SpawnBackend, StdBackend};
+#[cfg(any(test, feature = "test-util"))]
+pub use backend::InlineBackend;
+pub use barrier::{BarrierBroken, BreakCause, HandleBarrier};
+pub use blocking::BlockingSection;
+pub use budget::Budget;
+pub use builder::Builder;
+pub use clock::{Clock, SystemClock};
+#[cfg(any(test, feature = "test-util"))]
+pub use clock::MockClock;
+pub use combine::{as_completed, both, join2, join3, race, Completed, JoinError};
+pub use command::{CommandError, CommandHandle, Commands};
+pub use config::ThreadHandleConfig;
+pub use counters::Counters;
+pub use crash::{set_crash_sink, CrashReport, CrashSink, JsonFileSink};
+pub use current::{current_context, current_token};
+pub use daemon::{spawn_daemon, spawn_daemon_with_retry, Daemon};
+pub use dump::HangReport;
+pub use each::{spawn_each, SpawnEach};
+pub use errors::WorkerError;
+pub use ffi::RawInterrupt;
+pub use future::ShutdownFuture;
+pub use group::{available_cores, par_map, CancelReport, FailedThread, GroupJoinError, GroupUsage, JoinFailure, Quota, QuotaCallback, QuotaExceeded, QuotaKind, Straggler, ThreadGroup};
+pub use history::InterruptRequest;
+pub use interop::Cancelled;
+pub use iter::{Interrupted, Interruptible, InterruptibleExt, Marked};
+pub use joiner::Joiner;
+pub use latch::{Latch, LatchError};
+pub use latency::Percentiles;
+pub use leak::{detect_leaks, DropPolicy};
+pub use lifecycle::{NotReady, ServiceState};
+pub use limit::{Limiter, RateLimiter};
+pub use link::LinkedSet;
+pub use lock::LockError;
+pub use monitor::{DownNotification, DownReason};
+#[cfg(all(feature = "linux", target_os = "linux"))]
+pub use numa::{numa_nodes, NumaNode};
+pub use observer::InterruptObserver;
+pub use ordering::InterruptOrdering;
+pub use origin::SpawnOrigin;
+pub use panics::{set_panic_handler, unwinding_supported, CombinedPanic, PanicHandler, PanicPayload, PanicPolicy, PanicReport};
+pub use pool::{scoped_pool, PoolBuilder, PoolScope, PoolStats, ReadyFuture, SubmitError, TaskHandle, TaskMeta, ThreadPool};
+pub use prepare::PrepareError;
+pub use process::ProcessHandle;
+pub use progress::{GroupProgress, Progress};
+pub use recycle::{disable_recycling, enable_recycling, idle_threads};
+pub use replay::{EventRecorder, RecordedEvent, Timeline};
+pub use retry::{Attempt, AttemptOutcome, RetryPolicy};
+pub use scale::{ScalableGroup, ScaleEvent};
+pub use schedule::{JobId, Scheduler};
+#[cfg(any(test, feature = "test-util"))]
+pub use schedule::VirtualTime;
+pub use scope::InterruptScope;
+pub use semaphore::{AcquireError, Semaphore, SemaphorePermit};
+pub use shard::ShardedGroup;
+pub use shutdown::{NodeId, ShutdownGraph};
+pub use singleflight::{Flight, Singleflight};
+pub use sink::{sink_fn, Delivery, FnSink, ResultSink};
+pub use slow::set_slow_join_threshold;
+pub use snapshot::{Change, Snapshot};
+pub use stream::{Iter, Sink, StreamHandle};
+pub use supervise::{ChildFailure, FailureAction, RestartPolicy};
+pub use temp::TempPath;
+pub use token::{CancelSignal, InterruptToken};
+pub use trigger::InterruptTrigger;
+pub use watchdog::{Stall, StallAction, Watchdog};
 
+use builder::SpawnSettings;
 
-#[derive(PartialEq, Eq, Debug)]
-pub enum ThreadStatus {
+/// Status of a thread. `E` is the error type of a thread spawned with `spawn_fallible`, which
+/// `fallible_status` reports as `Failed`; `status` never does.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ThreadStatus<E = Infallible> {
     Running,
     Terminated,
+    /// Returned this error.
+    Failed(E),
+}
+
+/// Result of requesting interruption of a running thread.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InterruptOutcome {
+    /// This was the first request.
+    Requested,
+    AlreadyRequested,
+}
+
+/// Error of requesting interruption of a thread that has terminated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AlreadyTerminated;
+
+impl InterruptOutcome {
+    /// Outcome of a request, given whether interruption had already been requested.
+    pub(crate) fn after(previous: bool) -> Self {
+        if previous {
+            InterruptOutcome::AlreadyRequested
+        } else {
+            InterruptOutcome::Requested
+        }
+    }
+}
+
+impl fmt::Display for AlreadyTerminated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "thread already terminated")
+    }
+}
+
+impl ::std::error::Error for AlreadyTerminated {}
+
+/// Result of joining with a timeout. More ways for a wait to end may be added, so matches need
+/// a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum JoinOutcome<T> {
+    Finished(T),
+    Panicked(Box<dyn Any + Send>),
+    StillRunning,
+    AlreadyJoined,
+    /// The wait was given up with `ThreadHandle::abort_join`; the thread can still be joined.
+    WaitCancelled,
+}
+
+/// Identifies a handle for the life of the process; the token of its runnable, its events,
+/// diagnostics and down notifications carry the same id.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct HandleId(u64);
+
+impl HandleId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        HandleId(NEXT.fetch_add(1, atomic::Ordering::Relaxed))
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for HandleId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// State shared between a handle and the token given to its runnable.
+pub(crate) struct Shared<S: ?Sized = dyn Any + Send + Sync> {
+    id: HandleId,
+    signal: token::Signal,
+    terminated: AtomicBool,
+    termination: Latch,
+    /// Bumped by `ThreadHandle::abort_join` to release the callers waiting for termination.
+    wait_cancels: AtomicU64,
+    attempts: Mutex<Vec<Attempt>>,
+    propagate_to: Mutex<Vec<Weak<Shared>>>,
+    /// Threads spawned as children; `None` once the runnable exited and they were interrupted.
+    children: Mutex<Option<Vec<Weak<Shared>>>>,
+    progress: Mutex<Option<Progress>>,
+    state: Mutex<progress::PublishedState>,
+    name: OnceLock<String>,
+    /// Standard thread the runnable runs on, whose name stands in for `name` when the handle
+    /// spawned it directly instead of keeping a copy.
+    thread: OnceLock<thread::Thread>,
+    group: OnceLock<String>,
+    /// Why the thread of a lazy handle could not be spawned.
+    start_error: OnceLock<io::Error>,
+    interrupt_reason: OnceLock<String>,
+    interrupt_history: Mutex<history::History>,
+    /// Name the OS reported for the thread when the runnable started.
+    os_name: OnceLock<String>,
+    os_thread_id: OnceLock<u64>,
+    clock: Arc<dyn Clock>,
+    /// Set with `Builder::deadline`; interruption is requested once it passes.
+    deadline: OnceLock<Instant>,
+    /// Threads of a lower class stop first when a group or the registry shuts down.
+    shutdown_class: AtomicI32,
+    created: Instant,
+    started: heartbeat::Timestamp,
+    last_heartbeat: heartbeat::Timestamp,
+    interrupt_requested: heartbeat::Timestamp,
+    finished: heartbeat::Timestamp,
+    joined: AtomicBool,
+    panicked: AtomicBool,
+    linked_failure: OnceLock<PanicReport>,
+    panic_message: OnceLock<String>,
+    /// Error returned by a runnable spawned with `spawn_fallible`.
+    returned_error: OnceLock<String>,
+    /// Copy of that error, for `fallible_status`.
+    failure: OnceLock<Arc<dyn Any + Send + Sync>>,
+    counters: counters::AtomicCounters,
+    values: OnceLock<context::ContextValues>,
+    /// Cleanups registered with `InterruptToken::defer`, run when the runnable exits.
+    deferred: Mutex<Vec<Job>>,
+    /// Where the thread was spawned from, for leak reports and diagnostics.
+    origin: OnceLock<origin::SpawnOrigin>,
+    /// Leak detection chosen with the builder, overriding the process-wide setting.
+    detect_leaks: OnceLock<bool>,
+    /// Drop policy chosen with the builder, overriding the installed configuration.
+    drop_policy: OnceLock<DropPolicy>,
+    exit_reason: Mutex<Option<exit::ExitReason>>,
+    /// Innermost `InterruptToken::blocking_section` the runnable is in, and when it entered it.
+    blocking: Mutex<Option<(String, Instant)>>,
+    /// Recoverable errors reported with `InterruptToken::report_error`.
+    errors: errors::ErrorStream,
+    /// Set with `ThreadHandle::quiesce`; the runnable finishes its queued work and exits.
+    quiescing: AtomicBool,
+    /// Depth of nested `InterruptToken::shield` sections the runnable is in.
+    shielded: AtomicUsize,
+    /// Set with `ThreadHandle::pause`; the runnable holds still in `InterruptToken::yield_now`.
+    paused: (Mutex<bool>, Condvar),
+    throttle: Mutex<Option<throttle::Throttle>>,
+    reloads: (Mutex<reload::Reloads>, Condvar),
+    /// Set by `ThreadHandle::reload` until the current runnable of a supervised thread exits.
+    reloading: AtomicBool,
+    /// Whether the runnable saw interruption once it was requested.
+    interrupt_acknowledged: AtomicBool,
+    interrupt_listeners: Mutex<interop::Listeners>,
+    /// Stage reported with `InterruptToken::set_ready` and the like.
+    lifecycle: (Mutex<ServiceState>, Condvar),
+    /// Monitors to notify; `None` once they were notified.
+    monitors: Mutex<Option<Vec<mpsc::Sender<DownNotification>>>>,
+    /// Job of the thread while a deferred `InlineBackend` holds it.
+    #[cfg(any(test, feature = "test-util"))]
+    queued: Mutex<Option<backend::Queued>>,
+    #[cfg(feature = "backtrace")]
+    backtrace: OnceLock<std::backtrace::Backtrace>,
+    /// Nice value the thread takes once interrupted, see `Builder::boost_on_interrupt`.
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    interrupt_nice: OnceLock<i32>,
+    /// Nice value the thread had before it was boosted, restored when the runnable exits.
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    unboosted_nice: Mutex<Option<i32>>,
+    /// Reply of the thread once it applied `Builder::nice` and the like, until launching it
+    /// waited for it.
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    placing: Mutex<Option<place::Placing>>,
+    /// Span covering the runnable, while it runs.
+    #[cfg(feature = "otel")]
+    span: Mutex<Option<opentelemetry::global::BoxedSpan>>,
+    /// Context of the code that created the handle, parent of the span.
+    #[cfg(feature = "otel")]
+    span_parent: OnceLock<opentelemetry::Context>,
+    /// Result slot of the handle when the token was created along with it, which saves
+    /// allocating it separately; `()` otherwise.
+    slot: S,
+}
+
+impl<S> Shared<S> {
+    pub(crate) fn new(signal: token::Signal, clock: Arc<dyn Clock>, slot: S) -> Self {
+        Shared {
+            id: HandleId::next(),
+            signal,
+            terminated: AtomicBool::new(false),
+            termination: Latch::new(),
+            wait_cancels: AtomicU64::new(0),
+            attempts: Mutex::new(Vec::new()),
+            propagate_to: Mutex::new(Vec::new()),
+            children: Mutex::new(Some(Vec::new())),
+            progress: Mutex::new(None),
+            state: Mutex::new(None),
+            name: OnceLock::new(),
+            thread: OnceLock::new(),
+            group: OnceLock::new(),
+            start_error: OnceLock::new(),
+            interrupt_reason: OnceLock::new(),
+            interrupt_history: Mutex::new(history::History::new()),
+            os_name: OnceLock::new(),
+            os_thread_id: OnceLock::new(),
+            deadline: OnceLock::new(),
+            shutdown_class: AtomicI32::new(0),
+            created: clock.now(),
+            clock,
+            started: heartbeat::Timestamp::new(),
+            last_heartbeat: heartbeat::Timestamp::new(),
+            interrupt_requested: heartbeat::Timestamp::new(),
+            finished: heartbeat::Timestamp::new(),
+            joined: AtomicBool::new(false),
+            panicked: AtomicBool::new(false),
+            linked_failure: OnceLock::new(),
+            panic_message: OnceLock::new(),
+            returned_error: OnceLock::new(),
+            failure: OnceLock::new(),
+            counters: counters::AtomicCounters::default(),
+            values: OnceLock::new(),
+            deferred: Mutex::new(Vec::new()),
+            origin: OnceLock::new(),
+            detect_leaks: OnceLock::new(),
+            drop_policy: OnceLock::new(),
+            exit_reason: Mutex::new(None),
+            blocking: Mutex::new(None),
+            errors: errors::ErrorStream::new(),
+            quiescing: AtomicBool::new(false),
+            shielded: AtomicUsize::new(0),
+            paused: (Mutex::new(false), Condvar::new()),
+            throttle: Mutex::new(None),
+            reloads: (Mutex::new(reload::Reloads::default()), Condvar::new()),
+            reloading: AtomicBool::new(false),
+            interrupt_acknowledged: AtomicBool::new(false),
+            interrupt_listeners: Mutex::new(interop::Listeners::default()),
+            lifecycle: (Mutex::new(ServiceState::Starting), Condvar::new()),
+            monitors: Mutex::new(Some(Vec::new())),
+            #[cfg(any(test, feature = "test-util"))]
+            queued: Mutex::new(None),
+            #[cfg(feature = "backtrace")]
+            backtrace: OnceLock::new(),
+            #[cfg(all(feature = "linux", target_os = "linux"))]
+            interrupt_nice: OnceLock::new(),
+            #[cfg(all(feature = "linux", target_os = "linux"))]
+            unboosted_nice: Mutex::new(None),
+            #[cfg(all(feature = "linux", target_os = "linux"))]
+            placing: Mutex::new(None),
+            #[cfg(feature = "otel")]
+            span: Mutex::new(None),
+            #[cfg(feature = "otel")]
+            span_parent: OnceLock::new(),
+            slot,
+        }
+    }
+}
+
+impl Shared {
+    /// Requests interruption of a thread that has not terminated yet.
+    pub(crate) fn interrupt_running(&self, reason: Option<&str>) -> Result<InterruptOutcome, AlreadyTerminated> {
+        if self.is_terminated() {
+            Err(AlreadyTerminated)
+        } else {
+            Ok(InterruptOutcome::after(self.request_interrupt(reason)))
+        }
+    }
+
+    /// Requests interruption and forwards it to linked threads the first time it is requested;
+    /// only the reason given with the first request is kept.
+    pub(crate) fn request_interrupt(&self, reason: Option<&str>) -> bool {
+        counters::AtomicCounters::increment(&self.counters.interrupts);
+        self.record_interrupt(reason);
+        // Set first, so that whoever sees the interruption also sees its reason.
+        if let (Some(reason), false) = (reason, self.signal.is_cancelled()) {
+            self.interrupt_reason.set(reason.to_string()).ok();
+        }
+        // Pairs with `InterruptToken::acquire_fence`, for a flag raised with relaxed ordering or
+        // a custom signal.
+        atomic::fence(atomic::Ordering::Release);
+        let previous = self.signal.cancel();
+        if !previous {
+            self.interrupt_requested.set(self.created, self.clock.now());
+            events::publish(self, || events::ThreadEvent::InterruptRequested {
+                reason: reason.map(str::to_string),
+            });
+            self.notify_interrupt_listeners();
+            #[cfg(all(feature = "linux", target_os = "linux"))]
+            self.boost_priority();
+            self.propagate_interrupt();
+        }
+        previous
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        self.thread_name().unwrap_or("<unnamed>")
+    }
+
+    /// Name of the thread, `None` for a token not attached to one.
+    pub(crate) fn thread_name(&self) -> Option<&str> {
+        self.name.get().map(String::as_str).or_else(|| self.thread.get().and_then(thread::Thread::name))
+    }
+
+    fn has_panicked(&self) -> bool {
+        self.panicked.load(atomic::Ordering::Acquire)
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.terminated.load(atomic::Ordering::Acquire)
+    }
+
+    /// Marks the thread as terminated, with the message of its panic if it panicked; a panicked
+    /// thread interrupts the threads linked to it.
+    fn finish(&self, panic: Option<&str>) {
+        self.interrupt_children();
+        self.finished.set(self.created, self.clock.now());
+        // Whoever sees the thread terminated also sees whether it panicked.
+        if let Some(message) = panic {
+            self.panicked.store(true, atomic::Ordering::Release);
+            self.panic_message.set(message.to_string()).ok();
+        }
+        self.terminated.store(true, atomic::Ordering::Release);
+        self.termination.set();
+        self.set_service_state(ServiceState::Stopped);
+        if let Some(message) = panic {
+            self.propagate_failure(message);
+        }
+        self.notify_monitors(panic);
+    }
+
+    fn status(&self) -> ThreadStatus {
+        if self.is_terminated() {
+            ThreadStatus::Terminated
+        } else {
+            ThreadStatus::Running
+        }
+    }
+
+    pub(crate) fn is_interrupt_requested(&self) -> bool {
+        self.signal.is_cancelled() || self.check_deadline()
+    }
+
+    /// Whether the runnable should stop, as its token reports it.
+    pub(crate) fn is_interrupted(&self) -> bool {
+        let interrupted = !self.is_shielded_here() && (self.is_interrupt_requested() || self.is_reloading());
+        if interrupted {
+            self.acknowledge_interrupt();
+        }
+        interrupted
+    }
+
+    /// Time the runnable has been running, up to its termination; `None` until it starts.
+    fn uptime(&self) -> Option<Duration> {
+        let started = self.started_at()?;
+        let until = self.finished.get(self.created).unwrap_or_else(|| self.clock.now());
+        Some(until.saturating_duration_since(started))
+    }
+
+    /// Blocks until the runnable has finished; returns `false` if `timeout` elapsed first on
+    /// the clock of the handle.
+    pub(crate) fn wait_terminated(&self, timeout: Option<Duration>) -> bool {
+        self.wait_for_termination(None, timeout) == abort::Wait::Terminated
+    }
+}
+
+type ResultSubscriber<T> = Box<dyn FnOnce(&thread::Result<T>) + Send>;
+type ResultForward<T> = Box<dyn FnOnce(thread::Result<T>) + Send>;
+type DeferredResult<T> = Box<dyn FnOnce() -> thread::Result<T> + Send>;
+
+/// Outcome of the runnable until it is joined, or where to send it once it is available.
+struct Slot<T> {
+    result: Option<thread::Result<T>>,
+    /// Computes the outcome when it is first needed, see `ThreadHandle::map`.
+    deferred: Option<DeferredResult<T>>,
+    forward: Option<ResultForward<T>>,
+    /// Called with the outcome as soon as it is available.
+    subscribers: Vec<ResultSubscriber<T>>,
+}
+
+impl<T> Slot<T> {
+    fn new() -> Self {
+        Slot {
+            result: None,
+            deferred: None,
+            forward: None,
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Hands the outcome to the subscribers, then to the forward or else keeps it for `join`.
+    fn deliver(&mut self, outcome: thread::Result<T>) {
+        for subscriber in self.subscribers.drain(..) {
+            subscriber(&outcome);
+        }
+        match self.forward.take() {
+            Some(forward) => forward(outcome),
+            None => self.result = Some(outcome),
+        }
+    }
+
+    /// Computes a deferred outcome and delivers it.
+    fn resolve(&mut self) {
+        if let Some(deferred) = self.deferred.take() {
+            self.deliver(deferred());
+        }
+    }
+}
+
+type ResultSlot<T> = Mutex<Slot<T>>;
+
+/// Empty slot, for a token created along with the handle, see `SlotRef`.
+fn new_slot<T>() -> ResultSlot<T> {
+    Mutex::new(Slot::new())
+}
+
+/// Result slot of a handle: the one in the shared state of its thread if the token was created
+/// with it, or one of its own, e.g. for a mapped handle.
+enum SlotRef<T> {
+    Shared(Arc<Shared>),
+    Own(Arc<ResultSlot<T>>),
+}
+
+impl<T> SlotRef<T> where T: Send + 'static {
+    /// The slot of `shared` if it has one for `T`, otherwise a new one.
+    fn new(shared: &Arc<Shared>) -> Self {
+        if shared.slot.is::<ResultSlot<T>>() {
+            SlotRef::Shared(shared.clone())
+        } else {
+            SlotRef::Own(Arc::new(Mutex::new(Slot::new())))
+        }
+    }
+}
+
+impl<T> Clone for SlotRef<T> {
+    fn clone(&self) -> Self {
+        match *self {
+            SlotRef::Shared(ref shared) => SlotRef::Shared(shared.clone()),
+            SlotRef::Own(ref slot) => SlotRef::Own(slot.clone()),
+        }
+    }
+}
+
+impl<T> Deref for SlotRef<T> {
+    type Target = ResultSlot<T>;
+
+    fn deref(&self) -> &ResultSlot<T> {
+        match *self {
+            // `new` checked that the slot has this type.
+            SlotRef::Shared(ref shared) => unsafe { &*(&shared.slot as *const (dyn Any + Send + Sync) as *const ResultSlot<T>) },
+            SlotRef::Own(ref slot) => slot,
+        }
+    }
 }
 
+/// Job of a lazy handle waiting to be spawned.
+struct Pending {
+    name: String,
+    job: Job,
+    launcher: backend::Launcher,
+}
+
+/// Handle of a managed thread.
+///
+/// The handle is `Send + Sync` whenever `T: Send`, so it can be shared through an `Arc`: any
+/// number of threads may interrupt, inspect and join it at the same time. Exactly one of
+/// concurrent joiners receives the outcome; the others get `None` once the thread terminated.
 pub struct ThreadHandle<T> {
-    interrupted: Weak<AtomicBool>,
-    join_handle: RwLock<Option<JoinHandle<T>>>,
+    shared: Arc<Shared>,
+    result: SlotRef<T>,
+    join_handle: Mutex<Option<JoinHandle<()>>>,
+    pending: Mutex<Option<Pending>>,
 }
 
 impl<T> ThreadHandle<T> where T: Send + 'static {
+    #[track_caller]
     pub fn spawn<F>(name: String, runnable: F) -> io::Result<Self> where
-        F: FnOnce(Arc<AtomicBool>) -> T, F: Send + 'static
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
     {
-        let interrupted_flag = Arc::new(AtomicBool::new(false));
-        let interrupted = Arc::downgrade(&interrupted_flag);
-        let join_handle = thread::Builder::new()
-            .name(name)
-            .spawn(move || {
-                runnable(interrupted_flag)
-            })?;
-        Ok(ThreadHandle {
-            interrupted: interrupted,
-            join_handle: RwLock::new(Some(join_handle)),
-        })
+        ThreadHandle::spawn_with_token(name, InterruptToken::with_slot(new_slot::<T>()), runnable)
     }
 
-    pub fn status(&self) -> ThreadStatus {
-        if self.interrupted.upgrade().is_some() {
-            ThreadStatus::Running
+    /// Spawns a thread whose interruption is backed by a user-provided signal.
+    #[track_caller]
+    pub fn spawn_with_signal<S, F>(name: String, signal: S, runnable: F) -> io::Result<Self> where
+        S: CancelSignal + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        ThreadHandle::spawn_with_token(name, InterruptToken::with_signal(signal), runnable)
+    }
+
+    #[track_caller]
+    pub(crate) fn spawn_with_token<F>(name: String, token: InterruptToken, runnable: F) -> io::Result<Self> where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        ThreadHandle::spawn_configured(name, token, &SpawnSettings::default(), runnable)
+    }
+
+    #[track_caller]
+    pub(crate) fn spawn_configured<F>(name: String, token: InterruptToken, settings: &SpawnSettings, runnable: F) -> io::Result<Self> where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let mut name = config::installed().thread_name(name);
+        let settings = intercept::apply(&mut name, settings)?;
+        let launcher = backend::Launcher::new(&settings);
+        let direct = launcher.is_direct();
+        let (handle, job) = ThreadHandle::prepare(&name, !direct, token, &settings, runnable);
+        let launched = if direct {
+            launcher.launch_direct(name, job).map(|join_handle| {
+                handle.shared.thread.set(join_handle.thread().clone()).ok();
+                Some(join_handle)
+            })
         } else {
-            ThreadStatus::Terminated
+            launcher.launch(name, Box::new(job))
+        };
+        match launched {
+            Ok(join_handle) => handle.launched(join_handle)?,
+            Err(error) => {
+                handle.shared.finish(None);
+                return Err(error);
+            }
         }
+        Ok(handle)
     }
 
-    pub fn interrupt(&self) -> Result<bool, ()> {
-        if let Some(interrupted_flag) = self.interrupted.upgrade() {
-            let previous = interrupted_flag.compare_and_swap(false, true, atomic::Ordering::Relaxed);
-            Ok(previous)
-        } else {
-            Err(())
+    /// Creates a handle whose thread is only spawned on first use: querying its status,
+    /// joining it or calling `ensure_started`.
+    #[track_caller]
+    pub fn lazy<F>(name: String, runnable: F) -> Self where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        ThreadHandle::lazy_configured(name, &SpawnSettings::default(), runnable)
+    }
+
+    #[track_caller]
+    pub(crate) fn lazy_configured<F>(name: String, settings: &SpawnSettings, runnable: F) -> Self where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let mut name = config::installed().thread_name(name);
+        let settings = intercept::apply(&mut name, settings).unwrap_or_else(|error| Cow::Owned(intercept::rejected(settings, error)));
+        let (handle, job) = ThreadHandle::prepare(&name, true, settings.token_with(new_slot::<T>()), &settings, runnable);
+        *handle.pending.lock().unwrap() = Some(Pending {
+            name,
+            job: Box::new(job),
+            launcher: backend::Launcher::new(&settings),
+        });
+        handle
+    }
+
+    /// Builds the handle and the job that runs `runnable` and reports its outcome to it. Unless
+    /// `keep_name` is set, the job must run on a standard thread spawned under `name`, which
+    /// then keeps the name for the handle.
+    #[track_caller]
+    fn prepare<F>(name: &str, keep_name: bool, token: InterruptToken, settings: &SpawnSettings, runnable: F) -> (Self, impl FnOnce() + Send + 'static) where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let shared = token.shared.clone();
+        if keep_name {
+            shared.name.set(name.to_string()).ok();
+        }
+        shared.origin.set(origin::SpawnOrigin::capture(Location::caller())).ok();
+        if let Some(detect_leaks) = settings.detect_leaks {
+            shared.detect_leaks.set(detect_leaks).ok();
+        }
+        if let Some(drop_policy) = settings.drop_policy {
+            shared.drop_policy.set(drop_policy).ok();
+        }
+        if let Some(ref group) = settings.group {
+            shared.group.set(group.clone()).ok();
+        }
+        let result = SlotRef::new(&shared);
+        let finished = shared.clone();
+        let slot = result.clone();
+        let settings = settings.clone();
+        let context = context::capture(&settings.propagators);
+        #[cfg(feature = "otel")]
+        shared.span_parent.set(opentelemetry::Context::current()).ok();
+        #[cfg(feature = "backtrace")]
+        let spawner = thread::current().id();
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        let place = place::channel(&settings.placement).map(|(place, placing)| {
+            *shared.placing.lock().unwrap() = Some(placing);
+            place
+        });
+        let job = move || {
+            if !keep_name {
+                token.shared.thread.set(thread::current()).ok();
+            }
+            if !cfg!(feature = "minimal") {
+                if let Some(os_name) = sys::os_thread_name() {
+                    token.shared.os_name.set(os_name).ok();
+                }
+            }
+            if let Some(tid) = sys::os_thread_id() {
+                token.shared.os_thread_id.set(tid).ok();
+            }
+            #[cfg(all(feature = "linux", target_os = "linux"))]
+            {
+                if place.map_or(Ok(()), place::Place::apply).is_err() {
+                    finished.finish(None);
+                    return;
+                }
+            }
+            token.shared.started.set(token.shared.created, token.shared.clock.now());
+            events::publish(&finished, || events::ThreadEvent::Spawned);
+            let on_start = settings.on_start;
+            // A job running inline on the thread that created the handle, e.g. with the inline
+            // fallback, leaves the thread-wide state of its caller alone.
+            #[cfg(feature = "backtrace")]
+            let inline = thread::current().id() == spawner;
+            #[cfg(feature = "backtrace")]
+            let caller_armed = if inline { backtrace::suspend() } else { backtrace::arm(); false };
+            // The deferred cleanups and the stop hook still see the token as the current one.
+            let current = current::enter(&token);
+            let mut outcome = panic::catch_unwind(AssertUnwindSafe(move || {
+                let _context = context.install();
+                #[cfg(all(feature = "linux", target_os = "linux"))]
+                token.shared.boost_priority_if_interrupted();
+                if let Some(on_start) = on_start {
+                    on_start();
+                }
+                runnable(token)
+            }));
+            #[cfg(feature = "backtrace")]
+            backtrace::returned(outcome.is_err());
+            outcome = panics::combine(outcome, finished.run_deferred());
+            #[cfg(all(feature = "linux", target_os = "linux"))]
+            finished.restore_priority();
+            #[cfg(feature = "backtrace")]
+            {
+                if inline {
+                    backtrace::resume(caller_armed);
+                } else if let (Err(_), Some(captured)) = (&outcome, backtrace::disarm()) {
+                    finished.backtrace.set(captured).ok();
+                }
+            }
+            if let Some(on_stop) = settings.on_stop {
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| on_stop())) {
+                    outcome = panics::combine(outcome, vec![payload]);
+                }
+            }
+            drop(current);
+            // A panic, or an error returned by a fallible runnable, fails the thread.
+            let panic = match outcome {
+                Err(ref payload) => Some(payload.describe()),
+                Ok(_) => finished.returned_error.get().cloned(),
+            };
+            if let Some(ref message) = panic {
+                let payload: &(dyn Any + Send) = match outcome {
+                    Err(ref payload) => &**payload,
+                    Ok(_) => message,
+                };
+                panics::report(settings.on_panic.as_ref(), finished.name(), payload);
+                crash::record(settings.crash_sink.as_ref(), &finished, payload);
+                events::publish(&finished, || events::ThreadEvent::Panicked { message: message.clone() });
+                let policy = settings.panic_policy.unwrap_or_else(|| config::installed().default_panic_policy());
+                if policy == panics::PanicPolicy::AbortProcess && outcome.is_err() {
+                    panics::abort(finished.name(), message);
+                }
+            }
+            events::publish(&finished, || events::ThreadEvent::Terminated {
+                duration: finished.uptime().unwrap_or_default(),
+            });
+            slot.lock().unwrap().deliver(outcome);
+            finished.finish(panic.as_deref());
+        };
+        let handle = ThreadHandle {
+            shared,
+            result,
+            join_handle: Mutex::new(None),
+            pending: Mutex::new(None),
+        };
+        (handle, job)
+    }
+
+    /// Spawns the thread of a lazy handle if that has not happened yet. If spawning fails the
+    /// handle is left terminated without a result, and this keeps returning the error.
+    pub fn ensure_started(&self) -> io::Result<()> {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(Pending { name, job, launcher }) = pending.take() {
+            match launcher.launch(name, job) {
+                Ok(join_handle) => {
+                    if let Err(error) = self.launched(join_handle) {
+                        self.shared.start_error.set(error).ok();
+                    }
+                }
+                Err(error) => {
+                    self.shared.start_error.set(error).ok();
+                    self.shared.finish(None);
+                }
+            }
+        }
+        match self.shared.start_error.get() {
+            Some(error) => Err(io::Error::new(error.kind(), error.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes over the thread `launch` started, waiting for it to be placed. If it could not be,
+    /// the thread terminates without running the runnable.
+    fn launched(&self, join_handle: Option<JoinHandle<()>>) -> io::Result<()> {
+        *self.join_handle.lock().unwrap() = join_handle;
+        #[cfg(any(test, feature = "test-util"))]
+        self.shared.adopt_queued();
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        self.shared.wait_placed()?;
+        Ok(())
+    }
+
+    /// Why the thread of a lazy handle could not be spawned, in which case `status` reports it
+    /// `Terminated` and `join` returns `None`.
+    pub fn start_error(&self) -> Option<&io::Error> {
+        self.shared.start_error.get()
+    }
+
+    /// Drops the runnable of a lazy handle that was never started, leaving it terminated.
+    pub(crate) fn discard_pending(&self) -> bool {
+        let discarded = self.pending.lock().unwrap().take().is_some();
+        if discarded {
+            self.shared.finish(None);
+        }
+        discarded
+    }
+
+    /// Starts a lazy thread; if that fails, see `start_error`.
+    pub fn status(&self) -> ThreadStatus {
+        self.ensure_started().ok();
+        self.shared.status()
+    }
+
+    /// Requests interruption. Repeated requests are harmless and report `AlreadyRequested`;
+    /// once the thread terminated they fail with `AlreadyTerminated`.
+    pub fn interrupt(&self) -> Result<InterruptOutcome, AlreadyTerminated> {
+        self.request_interrupt(None)
+    }
+
+    /// Like `interrupt`, recording why; the reason is published to event subscribers.
+    pub fn interrupt_with_reason(&self, reason: &str) -> Result<InterruptOutcome, AlreadyTerminated> {
+        self.request_interrupt(Some(reason))
+    }
+
+    fn request_interrupt(&self, reason: Option<&str>) -> Result<InterruptOutcome, AlreadyTerminated> {
+        self.shared.interrupt_running(reason)
+    }
+
+    /// Reason given with the first interruption request, if any.
+    pub fn interrupt_reason(&self) -> Option<&str> {
+        self.shared.interrupt_reason.get().map(String::as_str)
+    }
+
+    pub fn id(&self) -> HandleId {
+        self.shared.id
+    }
+
+    pub fn name(&self) -> &str {
+        self.shared.name()
+    }
+
+    /// Name the OS stored for the thread, possibly truncated; `None` until it started or
+    /// where the platform does not expose thread names, or with the `minimal` feature.
+    pub fn os_name(&self) -> Option<&str> {
+        self.shared.os_name.get().map(|name| name.as_str())
+    }
+
+    pub fn is_interrupt_requested(&self) -> bool {
+        self.shared.is_interrupt_requested()
+    }
+
+    /// Time the thread has been running, or ran for if it terminated; `None` until it starts.
+    pub fn uptime(&self) -> Option<Duration> {
+        self.shared.uptime()
+    }
+
+    /// History of the latest 64 attempts, oldest first; empty unless spawned with
+    /// `spawn_with_retry` or `spawn_supervised`.
+    pub fn attempts(&self) -> Vec<Attempt> {
+        self.shared.attempts.lock().unwrap().clone()
+    }
+
+    /// Waits up to `timeout` for the thread to terminate and takes its result if it did.
+    pub fn join_timeout(&self, timeout: Duration) -> JoinOutcome<T> {
+        self.join_waiting(Some(timeout))
+    }
+
+    /// Returns a receiver getting the outcome of the runnable once it finishes, instead of `join`,
+    /// which then returns `None`. Returns `None` if a receiver was already handed out or the
+    /// result was already taken.
+    pub fn result_receiver(&self) -> Option<mpsc::Receiver<thread::Result<T>>> {
+        self.ensure_started().ok();
+        let mut slot = self.result.lock().unwrap();
+        slot.resolve();
+        if slot.forward.is_some() || (self.shared.is_terminated() && slot.result.is_none()) {
+            return None;
         }
+        let (sender, receiver) = mpsc::channel();
+        match slot.result.take() {
+            Some(result) => {
+                self.shared.joined.store(true, atomic::Ordering::Release);
+                sender.send(result).ok();
+            }
+            None => {
+                let shared = self.shared.clone();
+                slot.forward = Some(Box::new(move |outcome| {
+                    shared.joined.store(true, atomic::Ordering::Release);
+                    sender.send(outcome).ok();
+                }));
+            }
+        }
+        Some(receiver)
+    }
+
+    /// Blocks until the thread has terminated without taking its result, so any number of
+    /// threads sharing the handle can wait. Returns `false` if `timeout` elapsed first or the
+    /// wait was given up with `abort_join`.
+    pub fn wait_finished(&self, timeout: Option<Duration>) -> bool {
+        self.ensure_started().ok();
+        self.shared.wait_cancellable(self.shared.wait_generation(), timeout) == abort::Wait::Terminated
     }
 
+    /// Waits for the thread to terminate and takes its result. Returns `None` if the result was
+    /// already taken, if the wait was given up with `abort_join`, or if a lazy thread could not
+    /// be spawned, see `start_error`.
+    ///
+    /// If cleanups panicked after the runnable, or after one another, the payload is a
+    /// `CombinedPanic` wrapping the first panic rather than that panic's own payload: downcast
+    /// through `PanicPayload::payload`, or take `CombinedPanic::original` before passing it on
+    /// to `resume_unwind`.
     pub fn join(&self) -> Option<thread::Result<T>> {
-        if self.join_handle.read().unwrap().is_some() {
-            if let Some(join_handle) = self.join_handle.write().unwrap().take() {
-                Some(join_handle.join())
-            } else {
-                None
+        counters::AtomicCounters::increment(&self.shared.counters.joins);
+        self.ensure_started().ok();
+        #[cfg(any(test, feature = "test-util"))]
+        self.shared.run_queued();
+        if self.shared.wait_terminated_reporting(None) == abort::Wait::Cancelled {
+            return None;
+        }
+        let join_handle = self.join_handle.lock().unwrap().take();
+        if let Some(join_handle) = join_handle {
+            join_handle.join().ok();
+        }
+        let result = {
+            let mut slot = self.result.lock().unwrap();
+            slot.resolve();
+            slot.result.take()
+        };
+        if result.is_some() {
+            self.shared.joined.store(true, atomic::Ordering::Release);
+        }
+        result
+    }
+
+    /// Like `join`, resuming the panic of the runnable in the calling thread with its
+    /// original payload; `None` if the result was already taken.
+    pub fn join_propagate(&self) -> Option<T> {
+        match self.join()? {
+            Ok(value) => Some(value),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Clone + Send + 'static {
+    /// Returns a receiver getting a copy of the outcome, with panics reduced to their message;
+    /// there can be any number of them. Once the result has been joined, the receiver is
+    /// disconnected instead.
+    pub fn subscribe_result(&self) -> mpsc::Receiver<Result<T, String>> {
+        self.ensure_started().ok();
+        let (sender, receiver) = mpsc::channel();
+        let mut slot = self.result.lock().unwrap();
+        slot.resolve();
+        match slot.result {
+            Some(ref result) => {
+                sender.send(copy_outcome(result)).ok();
             }
-        } else {
-            None
+            None if !self.shared.is_terminated() => {
+                slot.subscribers.push(Box::new(move |result: &thread::Result<T>| {
+                    sender.send(copy_outcome(result)).ok();
+                }));
+            }
+            None => (),
+        }
+        receiver
+    }
+}
+
+fn copy_outcome<T: Clone>(result: &thread::Result<T>) -> Result<T, String> {
+    match *result {
+        Ok(ref value) => Ok(value.clone()),
+        Err(ref payload) => Err(payload.describe()),
+    }
+}
+
+/// Handles are equal when they are the same handle, i.e. have the same `HandleId`.
+impl<T> PartialEq for ThreadHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.shared.id == other.shared.id
+    }
+}
+
+impl<T> Eq for ThreadHandle<T> {}
+
+impl<T> Hash for ThreadHandle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.shared.id.hash(state);
+    }
+}
+
+/// Formatting never starts a lazy handle, which shows as running with no uptime until it does.
+impl<T> fmt::Debug for ThreadHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThreadHandle")
+            .field("id", &self.shared.id)
+            .field("name", &self.shared.name())
+            .field("status", &self.shared.status())
+            .field("interrupt_requested", &self.shared.is_interrupt_requested())
+            .field("uptime", &self.shared.uptime())
+            .field("counters", &self.shared.counters.load())
+            .finish()
+    }
+}
+
+impl<T> fmt::Display for ThreadHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({:?}", self.shared.name(), self.shared.status())?;
+        if self.shared.is_interrupt_requested() {
+            write!(f, ", interrupted")?;
+        }
+        match self.shared.uptime() {
+            Some(uptime) => write!(f, ", up {:.3}s)", uptime.as_secs_f64()),
+            None => write!(f, ", not started)"),
         }
     }
 }
@@ -71,50 +1117,278 @@ mod tests {
 
     #[test]
     fn test_status() {
+        let (finish, finishing) = mpsc::channel::<()>();
         let handle = ThreadHandle::spawn("Test status".to_string(), move |_| {
-            sleep(Duration::from_millis(1000));
+            finishing.recv().ok();
         }).unwrap();
-        sleep(Duration::from_millis(700));
         assert_eq!(ThreadStatus::Running, handle.status());
-        sleep(Duration::from_millis(700));
+        finish.send(()).unwrap();
+        test_util::assert_terminates_within(&handle, Duration::from_secs(5));
         assert_eq!(ThreadStatus::Terminated, handle.status());
     }
 
     #[test]
     fn test_join_ok() {
+        let (finish, finishing) = mpsc::channel::<()>();
         let handle = ThreadHandle::spawn("Test join ok".to_string(), move |_| {
-            sleep(Duration::from_millis(1000));
+            finishing.recv().ok();
             17
         }).unwrap();
+        finish.send(()).unwrap();
         assert_eq!(17, handle.join().unwrap().unwrap());
         assert!(handle.join().is_none());
     }
 
     #[test]
     fn test_join_error() {
+        let (finish, finishing) = mpsc::channel::<()>();
         let handle = ThreadHandle::spawn("Test join error".to_string(), move |_| {
-            sleep(Duration::from_millis(1000));
+            finishing.recv().ok();
             panic!("");
         }).unwrap();
+        finish.send(()).unwrap();
         assert!(handle.join().unwrap().is_err());
         assert!(handle.join().is_none());
     }
 
     #[test]
+    #[allow(clippy::assign_op_pattern)]
     fn test_interrupt() {
+        let (looped, looping) = mpsc::channel();
         let handle = ThreadHandle::spawn("Test interrupt".to_string(), move |interrupted| {
             let mut i = 0;
-            while !interrupted.load(atomic::Ordering::Relaxed) {
-                sleep(Duration::from_millis(200));
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
                 i = i + 1;
+                looped.send(i).ok();
             }
             i
         }).unwrap();
-        sleep(Duration::from_millis(1000));
-        assert_eq!(false, handle.interrupt().unwrap());
-        assert_eq!(true, handle.interrupt().unwrap());
+        assert_eq!(1, looping.recv().unwrap());
+        assert_eq!(Ok(InterruptOutcome::Requested), handle.interrupt());
+        assert_eq!(Ok(InterruptOutcome::AlreadyRequested), handle.interrupt());
         let result = handle.join().unwrap().unwrap();
-        assert!(result > 0 && result < 10);
+        assert!(result > 0);
+        assert_eq!(Err(AlreadyTerminated), handle.interrupt());
+    }
+
+    #[test]
+    fn test_interrupt_with_signal() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let handle = ThreadHandle::spawn_with_signal("Test signal".to_string(), flag.clone(), move |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(50));
+            }
+        }).unwrap();
+        flag.store(true, atomic::Ordering::Relaxed);
+        assert!(handle.join().unwrap().is_ok());
+        assert_eq!(ThreadStatus::Terminated, handle.status());
         assert!(handle.interrupt().is_err());
     }
+
+    #[test]
+    fn test_lazy() {
+        let started = Arc::new(AtomicBool::new(false));
+        let flag = started.clone();
+        let handle = ThreadHandle::lazy("Test lazy".to_string(), move |_| {
+            flag.store(true, atomic::Ordering::SeqCst);
+            5
+        });
+        assert!(!started.load(atomic::Ordering::SeqCst));
+        handle.ensure_started().unwrap();
+        assert_eq!(5, handle.join().unwrap().unwrap());
+        assert!(started.load(atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_lazy_interrupted_before_start() {
+        let handle = ThreadHandle::lazy("Test lazy interrupt".to_string(), |interrupted| interrupted.is_interrupted());
+        assert_eq!(Ok(InterruptOutcome::Requested), handle.interrupt());
+        assert!(handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_format() {
+        let handle = ThreadHandle::lazy("Test format".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+        });
+        assert_eq!("Test format (Running, not started)", handle.to_string());
+        handle.ensure_started().unwrap();
+        handle.interrupt().unwrap();
+        handle.join().unwrap().unwrap();
+        let display = handle.to_string();
+        assert!(display.starts_with("Test format (Terminated, interrupted, up "), "{}", display);
+        let debug = format!("{:?}", handle);
+        let prefix = format!("ThreadHandle {{ id: {:?}, name: \"Test format\", status: Terminated, interrupt_requested: true, uptime: Some(", handle.id());
+        assert!(debug.starts_with(&prefix), "{}", debug);
+    }
+
+    #[test]
+    fn test_join_timeout() {
+        let handle = ThreadHandle::spawn("Test join timeout".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+            5
+        }).unwrap();
+        match handle.join_timeout(Duration::from_millis(50)) {
+            JoinOutcome::StillRunning => (),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        handle.interrupt().unwrap();
+        match handle.join_timeout(Duration::from_secs(5)) {
+            JoinOutcome::Finished(5) => (),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        match handle.join_timeout(Duration::from_millis(10)) {
+            JoinOutcome::AlreadyJoined => (),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_join_timeout_follows_clock() {
+        let clock = Arc::new(MockClock::new());
+        let (release, released) = mpsc::channel::<()>();
+        let handle = Arc::new(Builder::new("Test join timeout clock".to_string()).clock(clock.clone()).spawn(move |_| {
+            released.recv().ok();
+            5
+        }).unwrap());
+        let (sender, waited) = mpsc::channel();
+        let waiter = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                sender.send(!handle.wait_finished(Some(Duration::from_secs(3600)))).unwrap();
+                sender.send(!handle.shared.wait_terminated(Some(Duration::from_secs(3600)))).unwrap();
+                sender.send(matches!(handle.join_timeout(Duration::from_secs(3600)), JoinOutcome::StillRunning)).unwrap();
+            })
+        };
+        for _ in 0..3 {
+            let reads = clock.reads();
+            test_util::assert_eventually(Duration::from_secs(5), "waiter following the clock", || clock.reads() >= reads + 3);
+            assert!(waited.try_recv().is_err());
+            clock.advance(Duration::from_secs(3600));
+            assert!(waited.recv().unwrap());
+        }
+        waiter.join().unwrap();
+        drop(release);
+        match handle.join_timeout(Duration::from_secs(3600)) {
+            JoinOutcome::Finished(5) => (),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_result_receiver() {
+        let handle = ThreadHandle::spawn("Test result receiver".to_string(), |_| {
+            sleep(Duration::from_millis(100));
+            3
+        }).unwrap();
+        let receiver = handle.result_receiver().unwrap();
+        assert!(handle.result_receiver().is_none());
+        assert_eq!(3, receiver.recv().unwrap().unwrap());
+        assert!(handle.join().is_none());
+
+        let finished = ThreadHandle::spawn("Test result receiver finished".to_string(), |_| -> u32 { panic!("") }).unwrap();
+        finished.shared.wait_terminated(None);
+        assert!(finished.result_receiver().unwrap().recv().unwrap().is_err());
+        assert!(finished.result_receiver().is_none());
+    }
+
+    #[test]
+    fn test_broadcast_completion() {
+        let handle = Arc::new(ThreadHandle::spawn("Test broadcast".to_string(), |_| {
+            sleep(Duration::from_millis(100));
+            "done".to_string()
+        }).unwrap());
+        let subscribers: Vec<_> = (0..3).map(|_| handle.subscribe_result()).collect();
+        let waiters: Vec<_> = (0..3).map(|_| {
+            let handle = handle.clone();
+            thread::spawn(move || handle.wait_finished(None))
+        }).collect();
+        for waiter in waiters {
+            assert!(waiter.join().unwrap());
+        }
+        for subscriber in subscribers.iter() {
+            assert_eq!(Ok("done".to_string()), subscriber.recv().unwrap());
+        }
+        assert_eq!(Ok("done".to_string()), handle.subscribe_result().recv().unwrap());
+        assert_eq!("done", handle.join().unwrap().unwrap());
+        assert!(handle.subscribe_result().recv().is_err());
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_handle_identity() {
+        let (sender, receiver) = mpsc::channel();
+        let first = ThreadHandle::spawn("Test identity".to_string(), move |interrupted| sender.send(interrupted.id()).unwrap()).unwrap();
+        let second = ThreadHandle::lazy("Test identity".to_string(), |_| ());
+        assert_eq!(first.id(), receiver.recv().unwrap());
+        assert_ne!(first.id(), second.id());
+        assert!(first != second);
+        let handles: std::collections::HashSet<_> = vec![first, second].into_iter().collect();
+        assert_eq!(2, handles.len());
+    }
+
+    fn assert_send_sync<S: Send + Sync>() {}
+
+    #[test]
+    fn test_handle_is_send_sync() {
+        assert_send_sync::<ThreadHandle<()>>();
+        assert_send_sync::<ThreadHandle<std::cell::Cell<u32>>>();
+        assert_send_sync::<InterruptToken>();
+    }
+
+    #[test]
+    fn test_concurrent_join_and_interrupt() {
+        for round in 0..20 {
+            let handle = Arc::new(ThreadHandle::spawn(format!("Test concurrent {}", round), |interrupted| {
+                while !interrupted.is_interrupted() {
+                    thread::yield_now();
+                }
+                7
+            }).unwrap());
+            let joiners: Vec<_> = (0..4).map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || handle.join().map(|result| result.unwrap()))
+            }).collect();
+            let interrupters: Vec<_> = (0..4).map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    handle.status();
+                    handle.interrupt()
+                })
+            }).collect();
+            // Interrupting fails once the thread terminated; only one request can be the first.
+            let outcomes: Vec<_> = interrupters.into_iter().map(|interrupter| interrupter.join().unwrap()).collect();
+            assert_eq!(1, outcomes.iter().filter(|outcome| **outcome == Ok(InterruptOutcome::Requested)).count());
+            let results: Vec<_> = joiners.into_iter().filter_map(|joiner| joiner.join().unwrap()).collect();
+            assert_eq!(vec![7], results);
+            assert_eq!(ThreadStatus::Terminated, handle.status());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[cfg(not(feature = "minimal"))]
+    #[test]
+    fn test_os_name_truncated() {
+        let handle = ThreadHandle::spawn("Test a rather long thread name".to_string(), |_| ()).unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!("Test a rather long thread name", handle.name());
+        assert_eq!(Some("Test a rather l"), handle.os_name());
+    }
+
+    #[test]
+    fn test_join_propagate() {
+        let handle = ThreadHandle::spawn("Test propagate value".to_string(), |_| 3).unwrap();
+        assert_eq!(Some(3), handle.join_propagate());
+        assert_eq!(None, handle.join_propagate());
+        let failing = ThreadHandle::spawn("Test propagate panic".to_string(), |_| -> u32 {
+            panic::panic_any(17u8)
+        }).unwrap();
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| failing.join_propagate())).unwrap_err();
+        assert_eq!(Some(&17u8), payload.downcast_ref::<u8>());
+    }
 }
@@ -0,0 +1,186 @@
+use std::time::{Duration, Instant};
+
+use events;
+use {InterruptToken, Shared, ThreadGroup, ThreadHandle};
+
+
+/// Stage of a service in its lifecycle, as reported by its worker through the token.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ServiceState {
+    /// Until the worker reports it is ready.
+    Starting,
+    Ready,
+    Stopping,
+    /// The runnable has terminated; set by the crate.
+    Stopped,
+}
+
+/// Member of a group that did not report it is ready, see `ThreadGroup::wait_all_ready`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NotReady {
+    pub index: usize,
+    pub thread: String,
+    pub state: ServiceState,
+}
+
+impl Shared {
+    pub(crate) fn service_state(&self) -> ServiceState {
+        *self.lifecycle.0.lock().unwrap()
+    }
+
+    /// Moves to `state` and wakes up whoever waits for readiness; nothing moves out of `Stopped`.
+    pub(crate) fn set_service_state(&self, state: ServiceState) {
+        {
+            let (ref lock, ref condvar) = self.lifecycle;
+            let mut current = lock.lock().unwrap();
+            if *current == state || *current == ServiceState::Stopped {
+                return;
+            }
+            *current = state;
+            condvar.notify_all();
+        }
+        // Termination is already published as `Terminated`.
+        if state != ServiceState::Stopped {
+            events::publish(self, || events::ThreadEvent::StateChanged { state });
+        }
+    }
+}
+
+impl InterruptToken {
+    /// Reports that the worker is serving.
+    pub fn set_ready(&self) {
+        self.shared.set_service_state(ServiceState::Ready);
+    }
+
+    /// Reports that the worker is winding down and no longer serving.
+    pub fn set_stopping(&self) {
+        self.shared.set_service_state(ServiceState::Stopping);
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Lifecycle stage last reported by the worker, `Stopped` once it terminated.
+    pub fn service_state(&self) -> ServiceState {
+        self.shared.service_state()
+    }
+
+    /// Blocks until the worker reports it is ready. Returns `false` if it is stopping or stopped
+    /// instead, or if `timeout` elapsed first.
+    pub fn wait_ready(&self, timeout: Option<Duration>) -> bool {
+        self.ensure_started().ok();
+        let (ref lock, ref condvar) = self.shared.lifecycle;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = lock.lock().unwrap();
+        loop {
+            match *state {
+                ServiceState::Ready => return true,
+                ServiceState::Stopping | ServiceState::Stopped => return false,
+                ServiceState::Starting => (),
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    condvar.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => condvar.wait(state).unwrap(),
+            };
+        }
+    }
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// Blocks until every thread of the group reports it is ready, waiting no longer than
+    /// `timeout` in total, e.g. to open a listener only once all background workers are
+    /// prepared. Otherwise returns the threads that are not ready, stopping or stopped ones
+    /// included, in index order.
+    pub fn wait_all_ready(&self, timeout: Option<Duration>) -> Result<(), Vec<NotReady>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let not_ready: Vec<_> = self.handles().iter().enumerate().filter(|&(_, handle)| {
+            !handle.wait_ready(deadline.map(|deadline| deadline.saturating_duration_since(Instant::now())))
+        }).map(|(index, handle)| NotReady {
+            index,
+            thread: handle.name().to_string(),
+            state: handle.service_state(),
+        }).collect();
+        if not_ready.is_empty() {
+            Ok(())
+        } else {
+            Err(not_ready)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread::sleep;
+    use super::*;
+
+    #[test]
+    fn test_wait_ready() {
+        let (proceed, wait) = mpsc::channel();
+        let handle = ThreadHandle::spawn("Test ready".to_string(), move |interrupted| {
+            sleep(Duration::from_millis(50));
+            interrupted.set_ready();
+            wait.recv().unwrap();
+            interrupted.set_stopping();
+            wait.recv().unwrap();
+        }).unwrap();
+        assert_eq!(ServiceState::Starting, handle.service_state());
+        assert!(handle.wait_ready(Some(Duration::from_secs(5))));
+        assert_eq!(ServiceState::Ready, handle.service_state());
+        proceed.send(()).unwrap();
+        while handle.service_state() == ServiceState::Ready {
+            sleep(Duration::from_millis(1));
+        }
+        assert_eq!(ServiceState::Stopping, handle.service_state());
+        assert!(!handle.wait_ready(None));
+        proceed.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(ServiceState::Stopped, handle.service_state());
+    }
+
+    #[test]
+    fn test_wait_ready_never_ready() {
+        let handle = ThreadHandle::spawn("Test never ready".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+        }).unwrap();
+        assert!(!handle.wait_ready(Some(Duration::from_millis(50))));
+        handle.interrupt().unwrap();
+        assert!(!handle.wait_ready(None));
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_wait_all_ready() {
+        let mut group = ThreadGroup::new("Test all ready".to_string());
+        for delay in [0, 30] {
+            group.spawn(format!("Test all ready {}", delay), move |interrupted| {
+                sleep(Duration::from_millis(delay));
+                interrupted.set_ready();
+                while !interrupted.is_interrupted() {
+                    sleep(Duration::from_millis(5));
+                }
+            }).unwrap();
+        }
+        group.spawn("Test all ready never".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        let not_ready = group.wait_all_ready(Some(Duration::from_millis(100))).unwrap_err();
+        assert_eq!(vec![NotReady {
+            index: 2,
+            thread: "Test all ready never".to_string(),
+            state: ServiceState::Starting,
+        }], not_ready);
+        group.cancel_with_deadline(Duration::from_secs(5));
+        assert_eq!(3, group.wait_all_ready(None).unwrap_err().len());
+    }
+}
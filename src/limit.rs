@@ -0,0 +1,279 @@
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use clock::{self, Clock};
+use {InterruptToken, ThreadHandle};
+
+
+struct Slots {
+    running: Mutex<usize>,
+    released: Condvar,
+    capacity: usize,
+}
+
+/// Caps how many threads spawned through it may run at the same time.
+#[derive(Clone)]
+pub struct Limiter {
+    slots: Arc<Slots>,
+}
+
+/// Slot held by a running thread; released when the runnable finishes.
+struct Permit {
+    slots: Arc<Slots>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        *self.slots.running.lock().unwrap() -= 1;
+        self.slots.released.notify_one();
+    }
+}
+
+impl Limiter {
+    /// Limiter letting `capacity` threads run at once. Panics if `capacity` is zero, with
+    /// which no thread could ever start.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "limiter needs at least one slot");
+        Limiter {
+            slots: Arc::new(Slots {
+                running: Mutex::new(0),
+                released: Condvar::new(),
+                capacity,
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity
+    }
+
+    pub fn running(&self) -> usize {
+        *self.slots.running.lock().unwrap()
+    }
+
+    fn acquire(&self) -> Permit {
+        let mut running = self.slots.running.lock().unwrap();
+        while *running >= self.slots.capacity {
+            running = self.slots.released.wait(running).unwrap();
+        }
+        *running += 1;
+        Permit { slots: self.slots.clone() }
+    }
+
+    fn try_acquire(&self) -> Option<Permit> {
+        let mut running = self.slots.running.lock().unwrap();
+        if *running >= self.slots.capacity {
+            return None;
+        }
+        *running += 1;
+        Some(Permit { slots: self.slots.clone() })
+    }
+
+    /// Spawns a thread, blocking the caller until a slot is free.
+    pub fn spawn<T, F>(&self, name: String, runnable: F) -> io::Result<ThreadHandle<T>> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let permit = self.acquire();
+        spawn_with_permit(name, permit, runnable)
+    }
+
+    /// Spawns a thread if a slot is free, failing with `WouldBlock` otherwise.
+    pub fn try_spawn<T, F>(&self, name: String, runnable: F) -> io::Result<ThreadHandle<T>> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        match self.try_acquire() {
+            Some(permit) => spawn_with_permit(name, permit, runnable),
+            None => Err(io::Error::new(io::ErrorKind::WouldBlock, "concurrency limit reached")),
+        }
+    }
+}
+
+fn spawn_with_permit<T, F>(name: String, permit: Permit, runnable: F) -> io::Result<ThreadHandle<T>> where
+    T: Send + 'static,
+    F: FnOnce(InterruptToken) -> T, F: Send + 'static
+{
+    ThreadHandle::spawn(name, move |interrupted| {
+        let _permit = permit;
+        runnable(interrupted)
+    })
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Token bucket limiting how many threads may be spawned per period;
+/// up to `permits` spawns can happen in a burst.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    permits: u32,
+    period: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl RateLimiter {
+    pub fn new(permits: u32, period: Duration) -> Self {
+        RateLimiter::with_clock(permits, period, clock::system())
+    }
+
+    /// Like `new`, refilling the bucket according to `clock`.
+    pub fn with_clock(permits: u32, period: Duration, clock: Arc<dyn Clock>) -> Self {
+        assert!(permits > 0, "rate limiter needs at least one permit per period");
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: f64::from(permits),
+                refilled: clock.now(),
+            })),
+            permits,
+            period,
+            clock,
+        }
+    }
+
+    /// Takes a token if one is available, otherwise returns how long until the next one.
+    fn take(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = self.clock.now();
+        let per_token = self.period.as_secs_f64() / f64::from(self.permits);
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() / per_token;
+        bucket.tokens = (bucket.tokens + refill).min(f64::from(self.permits));
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * per_token))
+        }
+    }
+
+    /// Spawns a thread, blocking the caller until the rate allows it.
+    pub fn spawn<T, F>(&self, name: String, runnable: F) -> io::Result<ThreadHandle<T>> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        while let Err(wait) = self.take() {
+            clock::sleep_until(&*self.clock, self.clock.now() + wait, || false);
+        }
+        ThreadHandle::spawn(name, runnable)
+    }
+
+    /// Spawns a thread if the rate allows it, failing with `WouldBlock` otherwise.
+    pub fn try_spawn<T, F>(&self, name: String, runnable: F) -> io::Result<ThreadHandle<T>> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        match self.take() {
+            Ok(()) => ThreadHandle::spawn(name, runnable),
+            Err(_) => Err(io::Error::new(io::ErrorKind::WouldBlock, "spawn rate limit reached")),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{self, AtomicUsize};
+    use std::sync::mpsc;
+    use std::thread::{self, sleep};
+    use MockClock;
+    use test_util::assert_eventually;
+    use super::*;
+
+    #[test]
+    fn test_limiter_caps_running() {
+        let limiter = Limiter::new(2);
+        let peak = Arc::new(AtomicUsize::new(0));
+        let current = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..5).map(|i| {
+            let peak = peak.clone();
+            let current = current.clone();
+            limiter.spawn(format!("Test limiter {}", i), move |_| {
+                let now = current.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+                peak.fetch_max(now, atomic::Ordering::SeqCst);
+                sleep(Duration::from_millis(100));
+                current.fetch_sub(1, atomic::Ordering::SeqCst);
+            }).unwrap()
+        }).collect();
+        for handle in handles.iter() {
+            assert!(handle.join().unwrap().is_ok());
+        }
+        assert_eq!(2, peak.load(atomic::Ordering::SeqCst));
+        assert_eq!(0, limiter.running());
+    }
+
+    #[test]
+    fn test_limiter_try_spawn_rejects() {
+        let limiter = Limiter::new(1);
+        let (release, released) = mpsc::channel::<()>();
+        let first = limiter.try_spawn("Test limiter first".to_string(), move |_| {
+            released.recv().ok();
+        }).unwrap();
+        let rejected = limiter.try_spawn("Test limiter rejected".to_string(), |_| ());
+        assert_eq!(io::ErrorKind::WouldBlock, rejected.err().unwrap().kind());
+        drop(release);
+        assert!(first.join().unwrap().is_ok());
+        let second = limiter.try_spawn("Test limiter second".to_string(), |_| 3).unwrap();
+        assert_eq!(3, second.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_limiter_releases_on_panic() {
+        let limiter = Limiter::new(1);
+        let failing = limiter.spawn("Test limiter panic".to_string(), |_| panic!("")).unwrap();
+        assert!(failing.join().unwrap().is_err());
+        assert_eq!(0, limiter.running());
+    }
+
+    #[test]
+    #[should_panic(expected = "limiter needs at least one slot")]
+    fn test_limiter_rejects_zero_capacity() {
+        Limiter::new(0);
+    }
+
+    #[test]
+    fn test_rate_limiter_try_spawn() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(10));
+        assert!(limiter.try_spawn("Test rate first".to_string(), |_| ()).is_ok());
+        assert!(limiter.try_spawn("Test rate second".to_string(), |_| ()).is_ok());
+        let rejected = limiter.try_spawn("Test rate rejected".to_string(), |_| ());
+        assert_eq!(io::ErrorKind::WouldBlock, rejected.err().unwrap().kind());
+    }
+
+    #[test]
+    fn test_rate_limiter_spawn_waits() {
+        let clock = Arc::new(MockClock::new());
+        let limiter = RateLimiter::with_clock(5, Duration::from_millis(500), clock.clone());
+        let mut handles: Vec<_> = (0..5).map(|i| {
+            limiter.spawn(format!("Test rate {}", i), |_| ()).unwrap()
+        }).collect();
+        let (sender, spawned) = mpsc::channel();
+        let waiting = limiter.clone();
+        let spawner = thread::spawn(move || {
+            sender.send(waiting.spawn("Test rate 5".to_string(), |_| ()).unwrap()).unwrap();
+        });
+        let reads = clock.reads();
+        assert_eventually(Duration::from_secs(5), "spawner waiting", || clock.reads() >= reads + 3);
+        assert!(spawned.try_recv().is_err());
+        clock.advance(Duration::from_millis(100));
+        handles.push(spawned.recv().unwrap());
+        spawner.join().unwrap();
+        for handle in handles.iter() {
+            assert!(handle.join().unwrap().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter_with_mock_clock() {
+        let clock = Arc::new(MockClock::new());
+        let limiter = RateLimiter::with_clock(1, Duration::from_secs(60), clock.clone());
+        assert!(limiter.try_spawn("Test rate mock first".to_string(), |_| ()).is_ok());
+        assert!(limiter.try_spawn("Test rate mock rejected".to_string(), |_| ()).is_err());
+        clock.advance(Duration::from_secs(60));
+        assert!(limiter.try_spawn("Test rate mock second".to_string(), |_| ()).is_ok());
+    }
+}
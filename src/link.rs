@@ -0,0 +1,237 @@
+use std::io;
+use std::sync::{Arc, Weak};
+use std::thread;
+
+use {CancelSignal, InterruptToken, PanicReport, Shared, ThreadHandle};
+
+
+impl Shared {
+    pub(crate) fn add_propagation_target(&self, target: &Arc<Shared>) {
+        self.propagate_to.lock().unwrap().push(Arc::downgrade(target));
+        if self.signal.is_cancelled() {
+            target.request_interrupt(Some(&format!("linked to {}", self.name())));
+        }
+    }
+
+    pub(crate) fn propagate_interrupt(&self) {
+        // Copy the targets out so that cyclic links never hold two locks at once.
+        let targets = self.propagate_to.lock().unwrap().clone();
+        if targets.is_empty() {
+            return;
+        }
+        let reason = format!("linked to {}", self.name());
+        for target in targets.iter().filter_map(Weak::upgrade) {
+            target.request_interrupt(Some(&reason));
+        }
+    }
+
+    /// Marks the linked threads as failed because this one panicked, then interrupts them.
+    pub(crate) fn propagate_failure(&self, message: &str) {
+        let targets = self.propagate_to.lock().unwrap().clone();
+        if targets.is_empty() {
+            return;
+        }
+        let reason = format!("linked thread {} panicked", self.name());
+        for target in targets.iter().filter_map(Weak::upgrade) {
+            target.linked_failure.set(PanicReport {
+                thread: self.name().to_string(),
+                message: message.to_string(),
+            }).ok();
+            target.request_interrupt(Some(&reason));
+        }
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Links two threads: interrupting either of them, or either of them panicking,
+    /// requests interruption of the other.
+    pub fn link<U>(&self, other: &ThreadHandle<U>) where U: Send + 'static {
+        self.propagate_to(other);
+        other.propagate_to(self);
+    }
+
+    /// One-directional link: interruption or panic of this thread requests interruption of `other`.
+    pub fn propagate_to<U>(&self, other: &ThreadHandle<U>) where U: Send + 'static {
+        self.shared.add_propagation_target(&other.shared);
+    }
+
+    /// First panic of a linked thread that caused this one to be interrupted.
+    pub fn linked_failure(&self) -> Option<&PanicReport> {
+        self.shared.linked_failure.get()
+    }
+}
+
+/// Threads linked with each other, restarted all together when any of them fails.
+pub struct LinkedSet<T> {
+    members: Vec<Member<T>>,
+}
+
+type Runnable<T> = Box<dyn FnOnce(InterruptToken) -> T + Send>;
+type Factory<T> = Box<dyn FnMut() -> Runnable<T> + Send>;
+
+struct Member<T> {
+    name: String,
+    factory: Factory<T>,
+    handle: ThreadHandle<T>,
+}
+
+impl<T> LinkedSet<T> where T: Send + 'static {
+    pub fn new() -> Self {
+        LinkedSet { members: Vec::new() }
+    }
+
+    /// Spawns a thread running closures produced by `factory`, linked to every other member.
+    pub fn spawn<F, R>(&mut self, name: String, mut factory: F) -> io::Result<usize> where
+        F: FnMut() -> R, F: Send + 'static,
+        R: FnOnce(InterruptToken) -> T, R: Send + 'static
+    {
+        let mut factory: Factory<T> = Box::new(move || Box::new(factory()));
+        let handle = ThreadHandle::spawn(name.clone(), factory())?;
+        for member in self.members.iter() {
+            member.handle.link(&handle);
+        }
+        self.members.push(Member { name, factory, handle });
+        Ok(self.members.len() - 1)
+    }
+
+    pub fn handles(&self) -> Vec<&ThreadHandle<T>> {
+        self.members.iter().map(|member| &member.handle).collect()
+    }
+
+    /// Whether a member panicked, or was interrupted because a linked member did.
+    pub fn has_failed(&self) -> bool {
+        self.members.iter().any(|member| member.handle.linked_failure().is_some() || member.handle.shared.has_panicked())
+    }
+
+    /// Interrupts and joins every member, then spawns all of them again from their factories.
+    /// Returns the results of the previous generation, in spawn order. If a member cannot be
+    /// spawned, those already restarted are interrupted and joined before the error is returned.
+    pub fn restart(&mut self) -> io::Result<Vec<Option<thread::Result<T>>>> {
+        for member in self.members.iter() {
+            member.handle.interrupt().ok();
+        }
+        let results = self.members.iter().map(|member| member.handle.join()).collect();
+        let mut handles = Vec::with_capacity(self.members.len());
+        for member in self.members.iter_mut() {
+            handles.push(ThreadHandle::lazy(member.name.clone(), (member.factory)()));
+        }
+        for (i, handle) in handles.iter().enumerate() {
+            for other in handles[..i].iter() {
+                other.link(handle);
+            }
+        }
+        for (member, handle) in self.members.iter_mut().zip(handles) {
+            member.handle = handle;
+        }
+        for (i, member) in self.members.iter().enumerate() {
+            if let Err(error) = member.handle.ensure_started() {
+                for started in self.members[..i].iter() {
+                    started.handle.interrupt().ok();
+                    started.handle.join();
+                }
+                for pending in self.members[i + 1..].iter() {
+                    pending.handle.discard_pending();
+                }
+                return Err(error);
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl<T> Default for LinkedSet<T> where T: Send + 'static {
+    fn default() -> Self {
+        LinkedSet::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{self, AtomicUsize};
+    use std::sync::mpsc;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use test_util::{assert_stops_after_interrupt, assert_terminates_within};
+    use {InterruptToken, ThreadStatus};
+    use super::*;
+
+    fn wait_for_interrupt(interrupted: InterruptToken) {
+        while !interrupted.is_interrupted() {
+            sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_link_interrupt() {
+        let producer = ThreadHandle::spawn("Test link producer".to_string(), wait_for_interrupt).unwrap();
+        let consumer = ThreadHandle::spawn("Test link consumer".to_string(), wait_for_interrupt).unwrap();
+        producer.link(&consumer);
+        assert_eq!(Ok(::InterruptOutcome::Requested), consumer.interrupt());
+        assert!(producer.join().unwrap().is_ok());
+        assert!(consumer.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_propagate_to_is_one_directional() {
+        let first = ThreadHandle::spawn("Test propagate first".to_string(), wait_for_interrupt).unwrap();
+        let second = ThreadHandle::spawn("Test propagate second".to_string(), wait_for_interrupt).unwrap();
+        first.propagate_to(&second);
+        assert_stops_after_interrupt(&second, Duration::from_secs(5));
+        assert!(second.join().unwrap().is_ok());
+        // Propagation happens within `interrupt`, so it would have reached `first` by now.
+        assert!(!first.is_interrupt_requested());
+        assert_eq!(ThreadStatus::Running, first.status());
+        assert_stops_after_interrupt(&first, Duration::from_secs(5));
+        assert!(first.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_link_panic() {
+        let failing = ThreadHandle::spawn("Test link panic".to_string(), |_| {
+            sleep(Duration::from_millis(200));
+            panic!("producer died");
+        }).unwrap();
+        let consumer = ThreadHandle::spawn("Test link survivor".to_string(), wait_for_interrupt).unwrap();
+        failing.link(&consumer);
+        assert!(consumer.join().unwrap().is_ok());
+        assert!(failing.join().unwrap().is_err());
+        let failure = consumer.linked_failure().unwrap();
+        assert_eq!("Test link panic", failure.thread);
+        assert_eq!("producer died", failure.message);
+        assert_eq!(Some("linked thread Test link panic panicked"), consumer.interrupt_reason());
+    }
+
+    #[test]
+    fn test_linked_set_restart() {
+        let generation = Arc::new(AtomicUsize::new(0));
+        let (sender, started) = mpsc::channel();
+        let mut set = LinkedSet::new();
+        let current = generation.clone();
+        set.spawn("Test linked set flaky".to_string(), move || {
+            let (current, sender) = (current.clone(), sender.clone());
+            move |interrupted: InterruptToken| {
+                let number = current.fetch_add(1, atomic::Ordering::SeqCst);
+                sender.send(()).unwrap();
+                if number == 0 {
+                    panic!("first generation fails");
+                }
+                wait_for_interrupt(interrupted);
+            }
+        }).unwrap();
+        set.spawn("Test linked set steady".to_string(), || wait_for_interrupt).unwrap();
+        started.recv().unwrap();
+        assert_terminates_within(set.handles()[0], Duration::from_secs(5));
+        assert!(set.has_failed());
+        let previous = set.restart().unwrap();
+        assert!(previous[0].as_ref().unwrap().is_err());
+        assert!(previous[1].as_ref().unwrap().is_ok());
+        started.recv().unwrap();
+        assert!(!set.has_failed());
+        assert_eq!(2, generation.load(atomic::Ordering::SeqCst));
+        set.handles()[1].interrupt().unwrap();
+        for handle in set.handles() {
+            assert!(handle.join().unwrap().is_ok());
+        }
+    }
+}
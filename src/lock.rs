@@ -0,0 +1,154 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError, TryLockError};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use InterruptToken;
+
+
+/// Longest real time between two attempts at a contended lock.
+const MAX_BACKOFF: Duration = Duration::from_millis(5);
+
+/// Why `InterruptToken::lock` did not acquire the lock.
+pub enum LockError<'a, T: ?Sized + 'a> {
+    /// Interruption was requested while waiting.
+    Interrupted,
+    TimedOut,
+    /// The lock was acquired, but a thread panicked while holding it.
+    Poisoned(PoisonError<MutexGuard<'a, T>>),
+}
+
+impl<'a, T: ?Sized> fmt::Debug for LockError<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockError::Interrupted => write!(f, "Interrupted"),
+            LockError::TimedOut => write!(f, "TimedOut"),
+            LockError::Poisoned(_) => write!(f, "Poisoned(..)"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> fmt::Display for LockError<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockError::Interrupted => write!(f, "interrupted while waiting for the lock"),
+            LockError::TimedOut => write!(f, "timed out waiting for the lock"),
+            LockError::Poisoned(_) => write!(f, "lock poisoned by a panicking thread"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Error for LockError<'a, T> {}
+
+impl InterruptToken {
+    /// Acquires `mutex`, giving up once interruption is requested or `timeout` elapsed, so that a
+    /// winding-down thread is not stuck behind a lock another one holds. The lock is retried with
+    /// a growing pause of up to a few milliseconds, so this suits locks held briefly.
+    pub fn lock<'a, T: ?Sized>(&self, mutex: &'a Mutex<T>, timeout: Option<Duration>) -> Result<MutexGuard<'a, T>, LockError<'a, T>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut backoff = Duration::from_micros(50);
+        loop {
+            match mutex.try_lock() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::Poisoned(poisoned)) => return Err(LockError::Poisoned(poisoned)),
+                Err(TryLockError::WouldBlock) => (),
+            }
+            if self.is_interrupted() {
+                return Err(LockError::Interrupted);
+            }
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => return Err(LockError::TimedOut),
+                Some(deadline) => sleep(backoff.min(deadline - now)),
+                None => sleep(backoff),
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Like `lock`, for a `parking_lot` mutex, which is never poisoned. The thread waits parked
+    /// on the mutex, a few milliseconds at a time between two looks at interruption.
+    #[cfg(feature = "parking_lot")]
+    pub fn lock_parking_lot<'a, T: ?Sized>(&self, mutex: &'a parking_lot::Mutex<T>, timeout: Option<Duration>) -> Result<parking_lot::MutexGuard<'a, T>, LockError<'a, T>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(guard) = mutex.try_lock() {
+                return Ok(guard);
+            }
+            if self.is_interrupted() {
+                return Err(LockError::Interrupted);
+            }
+            let now = Instant::now();
+            let wait = match deadline {
+                Some(deadline) if now >= deadline => return Err(LockError::TimedOut),
+                Some(deadline) => MAX_BACKOFF.min(deadline - now),
+                None => MAX_BACKOFF,
+            };
+            if let Some(guard) = mutex.try_lock_for(wait) {
+                return Ok(guard);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use ThreadHandle;
+    use super::*;
+
+    #[test]
+    fn test_lock() {
+        let mutex = Arc::new(Mutex::new(0));
+        let token = InterruptToken::new();
+        *token.lock(&mutex, None).unwrap() += 1;
+        let held = mutex.lock().unwrap();
+        let started = Instant::now();
+        assert!(matches!(token.lock(&mutex, Some(Duration::from_millis(30))), Err(LockError::TimedOut)));
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        let contended = mutex.clone();
+        let handle = ThreadHandle::spawn("Test lock".to_string(), move |interrupted| {
+            interrupted.lock(&contended, None).map(|_| ()).map_err(|error| error.to_string())
+        }).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        handle.interrupt().unwrap();
+        assert_eq!(Err("interrupted while waiting for the lock".to_string()), handle.join().unwrap().unwrap());
+        drop(held);
+    }
+
+    #[cfg(feature = "parking_lot")]
+    #[test]
+    fn test_lock_parking_lot() {
+        let mutex = Arc::new(parking_lot::Mutex::new(0));
+        let token = InterruptToken::new();
+        *token.lock_parking_lot(&mutex, None).unwrap() += 1;
+        let held = mutex.lock();
+        assert!(matches!(token.lock_parking_lot(&mutex, Some(Duration::from_millis(30))), Err(LockError::TimedOut)));
+        let contended = mutex.clone();
+        let handle = ThreadHandle::spawn("Test lock parking_lot".to_string(), move |interrupted| {
+            interrupted.lock_parking_lot(&contended, None).map(|_| ()).map_err(|error| error.to_string())
+        }).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        handle.interrupt().unwrap();
+        assert_eq!(Err("interrupted while waiting for the lock".to_string()), handle.join().unwrap().unwrap());
+        drop(held);
+    }
+
+    #[test]
+    fn test_lock_poisoned() {
+        let mutex = Arc::new(Mutex::new(0));
+        let poisoner = mutex.clone();
+        thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("poison");
+        }).join().unwrap_err();
+        let token = InterruptToken::new();
+        match token.lock(&mutex, None) {
+            Err(LockError::Poisoned(poisoned)) => assert_eq!(0, *poisoned.into_inner()),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        };
+    }
+}
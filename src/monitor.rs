@@ -0,0 +1,115 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::SystemTime;
+
+use {HandleId, Shared, ThreadHandle};
+
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DownReason {
+    Completed,
+    Panicked(String),
+    /// The runnable of `spawn_fallible` returned an error, in its `Debug` form.
+    Failed(String),
+    /// The runnable never ran: the handle was lazy and discarded, or spawning failed.
+    NeverStarted,
+}
+
+/// Sent to monitors when the watched thread terminates.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DownNotification {
+    pub id: HandleId,
+    pub thread: String,
+    pub reason: DownReason,
+    /// `Debug` form of the reason the runnable recorded with `InterruptToken::exit_with`.
+    pub exit_reason: Option<String>,
+    pub at: SystemTime,
+}
+
+impl Shared {
+    fn down_notification(&self, panic: Option<&str>) -> DownNotification {
+        let reason = match panic {
+            Some(message) if self.returned_error.get().map(String::as_str) == Some(message) => {
+                DownReason::Failed(message.to_string())
+            }
+            Some(message) => DownReason::Panicked(message.to_string()),
+            None if self.started_at().is_none() => DownReason::NeverStarted,
+            None => DownReason::Completed,
+        };
+        DownNotification {
+            id: self.id,
+            thread: self.name().to_string(),
+            reason,
+            exit_reason: self.exit_description(),
+            at: SystemTime::now(),
+        }
+    }
+
+    fn add_monitor(&self, monitor: Sender<DownNotification>) {
+        let mut monitors = self.monitors.lock().unwrap();
+        match *monitors {
+            Some(ref mut monitors) => monitors.push(monitor),
+            None => {
+                drop(monitors);
+                let panic = self.panic_message.get().map(String::as_str);
+                monitor.send(self.down_notification(panic)).ok();
+            }
+        }
+    }
+
+    /// Notifies the monitors once the runnable has terminated.
+    pub(crate) fn notify_monitors(&self, panic: Option<&str>) {
+        let monitors = self.monitors.lock().unwrap().take().unwrap_or_default();
+        if monitors.is_empty() {
+            return;
+        }
+        let notification = self.down_notification(panic);
+        for monitor in monitors {
+            monitor.send(notification.clone()).ok();
+        }
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Returns a receiver getting a single notification when the thread terminates, right away
+    /// if it already has. Monitoring neither joins the thread nor keeps it from being joined.
+    pub fn monitor(&self) -> Receiver<DownNotification> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.add_monitor(sender);
+        receiver
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_monitor() {
+        let handle = ThreadHandle::spawn("Test monitor".to_string(), |_| {
+            sleep(Duration::from_millis(100));
+            panic!("went down");
+        }).unwrap();
+        let monitor = handle.monitor();
+        let other = handle.monitor();
+        let down = monitor.recv().unwrap();
+        assert_eq!("Test monitor", down.thread);
+        assert_eq!(DownReason::Panicked("went down".to_string()), down.reason);
+        assert_eq!(down, other.recv().unwrap());
+        assert_eq!(DownReason::Panicked("went down".to_string()), handle.monitor().recv().unwrap().reason);
+        assert!(handle.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_monitor_never_started() {
+        let handle = ThreadHandle::lazy("Test monitor lazy".to_string(), |_| ());
+        let monitor = handle.monitor();
+        assert!(handle.discard_pending());
+        assert_eq!(DownReason::NeverStarted, monitor.recv().unwrap().reason);
+        let completed = ThreadHandle::spawn("Test monitor completed".to_string(), |_| ()).unwrap();
+        completed.join().unwrap().unwrap();
+        assert_eq!(DownReason::Completed, completed.monitor().recv().unwrap().reason);
+    }
+}
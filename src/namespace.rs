@@ -0,0 +1,102 @@
+//! Hierarchical thread names, such as "ingest/parser-3", with groups acting as namespaces.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use {Snapshot, ThreadGroup, ThreadStatus};
+
+
+/// Separates the levels of a hierarchical name, as in "ingest/parser-3".
+pub const SEPARATOR: char = '/';
+
+/// Whether `name` is `namespace` itself or lies below it.
+pub(crate) fn contains(namespace: &str, name: &str) -> bool {
+    let namespace = namespace.trim_end_matches(SEPARATOR);
+    match name.strip_prefix(namespace) {
+        Some(rest) => rest.is_empty() || rest.starts_with(SEPARATOR),
+        None => false,
+    }
+}
+
+#[derive(Default)]
+struct Node<'a> {
+    threads: Vec<&'a Snapshot>,
+    children: BTreeMap<&'a str, Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn render(&self, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        for (namespace, child) in self.children.iter() {
+            writeln!(out, "{}{}{}", indent, namespace, SEPARATOR).unwrap();
+            child.render(depth + 1, out);
+        }
+        for snapshot in self.threads.iter() {
+            let leaf = snapshot.name.rsplit(SEPARATOR).next().unwrap_or("");
+            let status = match snapshot.status {
+                ThreadStatus::Terminated if snapshot.panicked => "Panicked".to_string(),
+                status => format!("{:?}", status),
+            };
+            writeln!(out, "{}{} ({})", indent, leaf, status).unwrap();
+        }
+    }
+}
+
+/// Renders `snapshots` as a tree of their namespaces, one line per namespace or thread;
+/// namespaces are sorted by name and come before the threads directly in them.
+pub fn render_tree(snapshots: &[Snapshot]) -> String {
+    let mut root = Node::default();
+    for snapshot in snapshots {
+        let mut levels: Vec<&str> = snapshot.name.split(SEPARATOR).collect();
+        levels.pop();
+        let node = levels.into_iter().fold(&mut root, |node, level| node.children.entry(level).or_default());
+        node.threads.push(snapshot);
+    }
+    let mut out = String::new();
+    root.render(0, &mut out);
+    out
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// New namespaced group nested in this one's namespace, e.g. "ingest/parsers".
+    pub fn subgroup<U>(&self, name: &str) -> ThreadGroup<U> where U: Send + 'static {
+        ThreadGroup::new(format!("{}{}{}", self.name(), SEPARATOR, name)).namespaced()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use InterruptToken;
+    use super::*;
+
+    #[test]
+    fn test_namespace_contains() {
+        assert!(contains("ingest", "ingest/parser-3"));
+        assert!(contains("ingest/", "ingest/parser-3"));
+        assert!(contains("ingest", "ingest"));
+        assert!(!contains("ingest", "ingester/parser-3"));
+        assert!(!contains("ingest/parser", "ingest"));
+    }
+
+    #[test]
+    fn test_namespaced_group() {
+        let mut ingest = ThreadGroup::new("Test namespace ingest".to_string()).namespaced();
+        let mut parsers = ingest.subgroup("parsers");
+        ingest.spawn("fetcher".to_string(), |_| ()).unwrap();
+        parsers.spawn("parser-1".to_string(), |_| ()).unwrap();
+        parsers.spawn("parser-2".to_string(), |_: InterruptToken| panic!("bad input")).unwrap();
+        assert_eq!("Test namespace ingest/parsers/parser-1", parsers.handles()[0].name());
+        assert!(ingest.join_all().is_ok());
+        assert!(parsers.join_all().is_err());
+        let mut snapshots = ingest.snapshot();
+        snapshots.extend(parsers.snapshot());
+        assert_eq!(concat!(
+            "Test namespace ingest/\n",
+            "  parsers/\n",
+            "    parser-1 (Terminated)\n",
+            "    parser-2 (Panicked)\n",
+            "  fetcher (Terminated)\n",
+        ), render_tree(&snapshots));
+    }
+}
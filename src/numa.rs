@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+
+use sys;
+
+
+const NODE_ROOT: &str = "/sys/devices/system/node";
+
+/// NUMA node of the machine and the CPUs attached to it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NumaNode {
+    pub id: usize,
+    pub cpus: Vec<usize>,
+}
+
+/// Nodes with CPUs, in order of their id; empty where the kernel does not expose NUMA topology.
+pub fn numa_nodes() -> Vec<NumaNode> {
+    let entries = match fs::read_dir(NODE_ROOT) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut nodes: Vec<NumaNode> = entries.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let id = entry.file_name().to_str()?.strip_prefix("node")?.parse().ok()?;
+        let cpus = parse_cpu_list(fs::read_to_string(entry.path().join("cpulist")).ok()?.trim())?;
+        Some(NumaNode { id, cpus })
+    }).filter(|node| !node.cpus.is_empty()).collect();
+    nodes.sort_by_key(|node| node.id);
+    nodes
+}
+
+/// Parses the kernel list format, e.g. `0-3,8,10-11`.
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Restricts the calling thread to the CPUs of `node` and makes it allocate memory there first.
+pub(crate) fn bind_to_node(node: usize) -> io::Result<()> {
+    let cpus = numa_nodes().into_iter().find(|candidate| candidate.id == node)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no NUMA node {}", node)))?
+        .cpus;
+    if !sys::pin_to_cpus(&cpus) {
+        return Err(io::Error::last_os_error());
+    }
+    memory::prefer_node(node)
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+mod memory {
+    use std::io;
+    use std::os::raw::{c_int, c_long, c_ulong};
+
+    const MASK_WORDS: usize = 16;
+    const MPOL_PREFERRED: c_int = 1;
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_SET_MEMPOLICY: c_long = 238;
+    #[cfg(target_arch = "aarch64")]
+    const SYS_SET_MEMPOLICY: c_long = 237;
+
+    extern "C" {
+        fn syscall(number: c_long, ...) -> c_long;
+    }
+
+    pub fn prefer_node(node: usize) -> io::Result<()> {
+        if node >= MASK_WORDS * 64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("NUMA node {} out of range", node)));
+        }
+        let mut mask: [c_ulong; MASK_WORDS] = [0; MASK_WORDS];
+        mask[node / 64] |= 1 << (node % 64);
+        // The kernel reads one bit less than `maxnode`.
+        let maxnode = (MASK_WORDS * 64 + 1) as c_ulong;
+        if unsafe { syscall(SYS_SET_MEMPOLICY, MPOL_PREFERRED, mask.as_ptr(), maxnode) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod memory {
+    use std::io;
+
+    pub fn prefer_node(_node: usize) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "memory policies are not supported on this architecture"))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use Builder;
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(Some(vec![0, 1, 2, 3, 8, 10, 11]), parse_cpu_list("0-3,8,10-11"));
+        assert_eq!(Some(vec![]), parse_cpu_list(""));
+        assert_eq!(None, parse_cpu_list("0-a"));
+    }
+
+    #[test]
+    fn test_spawn_on_numa_node() {
+        let node = match numa_nodes().into_iter().next() {
+            Some(node) => node,
+            None => return,
+        };
+        let handle = Builder::new("Test numa".to_string()).numa_node(node.id).spawn(|_| ()).unwrap();
+        assert!(handle.join().unwrap().is_ok());
+        let missing = Builder::new("Test numa missing".to_string()).numa_node(4096).spawn(|_| ());
+        assert_eq!(io::ErrorKind::NotFound, missing.err().unwrap().kind());
+    }
+}
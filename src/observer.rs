@@ -0,0 +1,94 @@
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use {HandleId, InterruptToken, Shared};
+
+
+/// Time between two checks of `wait_interrupted`.
+const POLL: Duration = Duration::from_millis(10);
+
+/// Read-only view of a token, see `InterruptToken::clone_observer`: it sees interruption as the
+/// token does but cannot request it.
+#[derive(Clone)]
+pub struct InterruptObserver {
+    shared: Arc<Shared>,
+}
+
+impl InterruptObserver {
+    /// Id of the handle whose token this observes.
+    pub fn id(&self) -> HandleId {
+        self.shared.id
+    }
+
+    /// Whether interruption was requested, as `InterruptToken::is_interrupted` reports it.
+    pub fn is_interrupted(&self) -> bool {
+        self.shared.is_interrupted()
+    }
+
+    pub fn interrupt_reason(&self) -> Option<&str> {
+        self.shared.interrupt_reason.get().map(String::as_str)
+    }
+
+    /// Blocks until interruption is requested; returns `false` if `timeout` elapsed first.
+    pub fn wait_interrupted(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if self.is_interrupted() {
+                return true;
+            }
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => return false,
+                Some(deadline) => thread::sleep(POLL.min(deadline - now)),
+                None => thread::sleep(POLL),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for InterruptObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InterruptObserver")
+            .field("id", &self.shared.id)
+            .field("interrupted", &self.is_interrupted())
+            .finish()
+    }
+}
+
+impl InterruptToken {
+    /// Observer of this token for threads not spawned through this crate, such as plain
+    /// `std::thread::spawn` threads, so that they stop on the same signal without being able to
+    /// trigger it.
+    pub fn clone_observer(&self) -> InterruptObserver {
+        InterruptObserver {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ThreadHandle;
+    use super::*;
+
+    #[test]
+    fn test_clone_observer() {
+        let handle = ThreadHandle::spawn("Test observer".to_string(), |interrupted: InterruptToken| {
+            let observer = interrupted.clone_observer();
+            let helper = thread::spawn(move || {
+                assert!(observer.wait_interrupted(None));
+                observer.interrupt_reason().map(str::to_string)
+            });
+            helper.join().unwrap()
+        }).unwrap();
+        let token = InterruptToken::new();
+        let observer = token.clone_observer();
+        assert!(!observer.wait_interrupted(Some(Duration::from_millis(20))));
+        assert_eq!(token.id(), observer.id());
+        handle.interrupt_with_reason("reload").unwrap();
+        assert_eq!(Some("reload".to_string()), handle.join().unwrap().unwrap());
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::atomic::{self, AtomicU8};
+
+use InterruptToken;
+
+
+static INSTALLED: AtomicU8 = AtomicU8::new(InterruptOrdering::AcquireRelease as u8);
+
+/// Memory ordering of the built-in interrupt flag, see `ThreadHandleConfig::interrupt_ordering`.
+///
+/// With `AcquireRelease`, writes made before requesting interruption are visible to a runnable
+/// once its token reports it. Termination always synchronizes: whatever the runnable wrote
+/// before exiting is visible to a thread that observed it terminated, e.g. through `join`,
+/// `is_terminated` or a `wait_*` method.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InterruptOrdering {
+    /// The flag only tells whether to stop; a runnable reading data published along with the
+    /// request calls `InterruptToken::acquire_fence` after seeing it.
+    Relaxed,
+    #[default]
+    AcquireRelease,
+    SeqCst,
+}
+
+impl InterruptOrdering {
+    fn from_u8(ordering: u8) -> Self {
+        match ordering {
+            0 => InterruptOrdering::Relaxed,
+            1 => InterruptOrdering::AcquireRelease,
+            _ => InterruptOrdering::SeqCst,
+        }
+    }
+}
+
+pub(crate) fn install(ordering: InterruptOrdering) {
+    INSTALLED.store(ordering as u8, atomic::Ordering::Relaxed);
+}
+
+fn installed() -> InterruptOrdering {
+    InterruptOrdering::from_u8(INSTALLED.load(atomic::Ordering::Relaxed))
+}
+
+/// Ordering of a load of the interrupt flag.
+pub(crate) fn load() -> atomic::Ordering {
+    match installed() {
+        InterruptOrdering::Relaxed => atomic::Ordering::Relaxed,
+        InterruptOrdering::AcquireRelease => atomic::Ordering::Acquire,
+        InterruptOrdering::SeqCst => atomic::Ordering::SeqCst,
+    }
+}
+
+/// Ordering of the swap raising the interrupt flag.
+pub(crate) fn swap() -> atomic::Ordering {
+    match installed() {
+        InterruptOrdering::Relaxed => atomic::Ordering::Relaxed,
+        InterruptOrdering::AcquireRelease => atomic::Ordering::AcqRel,
+        InterruptOrdering::SeqCst => atomic::Ordering::SeqCst,
+    }
+}
+
+impl InterruptToken {
+    /// Acquire fence: after `is_interrupted` returned `true`, makes the writes the interrupting
+    /// thread made before requesting interruption visible, whatever `InterruptOrdering` is
+    /// installed, as requesting interruption issues a release fence before raising the flag.
+    /// Only needed under `InterruptOrdering::Relaxed` or with a custom signal.
+    pub fn acquire_fence(&self) {
+        atomic::fence(atomic::Ordering::Acquire);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_ordering() {
+        assert_eq!(InterruptOrdering::AcquireRelease, installed());
+        assert_eq!(atomic::Ordering::Acquire, load());
+        assert_eq!(atomic::Ordering::AcqRel, swap());
+        for ordering in [InterruptOrdering::Relaxed, InterruptOrdering::AcquireRelease, InterruptOrdering::SeqCst] {
+            assert_eq!(ordering, InterruptOrdering::from_u8(ordering as u8));
+        }
+    }
+
+    /// Model-checks the handshake of `Shared::request_interrupt` and `acquire_fence` under
+    /// `InterruptOrdering::Relaxed`; run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+    #[cfg(loom)]
+    #[test]
+    fn test_acquire_fence_loom() {
+        use loom::sync::Arc;
+        use loom::sync::atomic::{fence, AtomicBool, AtomicUsize};
+
+        loom::model(|| {
+            let (published, flag) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicBool::new(false)));
+            let (read, interrupted) = (published.clone(), flag.clone());
+            let runnable = loom::thread::spawn(move || {
+                if interrupted.load(atomic::Ordering::Relaxed) {
+                    fence(atomic::Ordering::Acquire);
+                    assert_eq!(42, read.load(atomic::Ordering::Relaxed));
+                }
+            });
+            published.store(42, atomic::Ordering::Relaxed);
+            fence(atomic::Ordering::Release);
+            flag.swap(true, atomic::Ordering::Relaxed);
+            runnable.join().unwrap();
+        });
+    }
+}
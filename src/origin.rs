@@ -0,0 +1,76 @@
+use std::fmt;
+use std::panic::Location;
+use std::thread;
+
+use {Shared, ThreadHandle};
+
+
+/// Where a thread was spawned from: the call site, captured through `#[track_caller]`, and the
+/// thread that made the call.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SpawnOrigin {
+    pub file: &'static str,
+    pub line: u32,
+    pub column: u32,
+    /// Name of the spawning thread, if it had one; not recorded with the `minimal` feature.
+    pub thread: Option<String>,
+}
+
+impl SpawnOrigin {
+    pub(crate) fn capture(location: &'static Location<'static>) -> Self {
+        SpawnOrigin {
+            file: location.file(),
+            line: location.line(),
+            column: location.column(),
+            thread: if cfg!(feature = "minimal") {
+                None
+            } else {
+                thread::current().name().map(str::to_string)
+            },
+        }
+    }
+}
+
+impl fmt::Display for SpawnOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)?;
+        match self.thread {
+            Some(ref thread) => write!(f, " (from thread {})", thread),
+            None => write!(f, " (from an unnamed thread)"),
+        }
+    }
+}
+
+impl Shared {
+    pub(crate) fn spawn_origin(&self) -> Option<SpawnOrigin> {
+        self.origin.get().cloned()
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Call site that spawned the thread and the thread it ran on; `None` for handles made from
+    /// a bare token.
+    pub fn spawn_origin(&self) -> Option<SpawnOrigin> {
+        self.shared.spawn_origin()
+    }
+}
+
+
+#[cfg(all(test, not(feature = "minimal")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_origin() {
+        let line = line!() + 2;
+        let handle = thread::Builder::new().name("Test origin spawner".to_string()).spawn(|| {
+            ThreadHandle::spawn("Test origin".to_string(), |_| ()).unwrap()
+        }).unwrap().join().unwrap();
+        let origin = handle.spawn_origin().unwrap();
+        assert_eq!(("src/origin.rs", line), (origin.file, origin.line));
+        assert_eq!(Some("Test origin spawner".to_string()), origin.thread);
+        assert!(origin.to_string().ends_with("(from thread Test origin spawner)"));
+        assert_eq!(Some(origin), handle.snapshot().origin);
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::OnceLock;
+
+use opentelemetry::{global, KeyValue};
+use opentelemetry::metrics::{Counter, Histogram, UpDownCounter};
+use opentelemetry::trace::{Span, Status, Tracer};
+
+use events::ThreadEvent;
+use Shared;
+
+
+const SCOPE: &str = "thread-handle";
+
+struct Instruments {
+    active: UpDownCounter<i64>,
+    panics: Counter<u64>,
+    task_wait: Histogram<f64>,
+    task_duration: Histogram<f64>,
+}
+
+/// Instruments of the global meter provider, which has to be installed before the first
+/// managed thread starts.
+fn instruments() -> &'static Instruments {
+    static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+    INSTRUMENTS.get_or_init(|| {
+        let meter = global::meter(SCOPE);
+        Instruments {
+            active: meter.i64_up_down_counter("thread_handle.threads.active")
+                .with_description("Managed threads currently running")
+                .build(),
+            panics: meter.u64_counter("thread_handle.threads.panics")
+                .with_description("Managed threads that panicked")
+                .build(),
+            task_wait: meter.f64_histogram("thread_handle.pool.task_wait")
+                .with_description("Time pool tasks spent queued")
+                .with_unit("s")
+                .build(),
+            task_duration: meter.f64_histogram("thread_handle.pool.task_duration")
+                .with_description("Time pool tasks took to run")
+                .with_unit("s")
+                .build(),
+        }
+    })
+}
+
+fn attributes(shared: &Shared) -> Vec<KeyValue> {
+    let mut attributes = vec![KeyValue::new("thread.name", shared.name().to_string())];
+    if let Some(group) = shared.group.get() {
+        attributes.push(KeyValue::new("thread.group", group.clone()));
+    }
+    attributes
+}
+
+/// Mirrors a lifecycle event of a managed thread in its span, which lasts from `Spawned` to
+/// `Terminated`, and in the thread metrics.
+pub(crate) fn record(shared: &Shared, event: &ThreadEvent) {
+    let mut span = shared.span.lock().unwrap();
+    match *event {
+        ThreadEvent::Spawned => {
+            let tracer = global::tracer(SCOPE);
+            let mut started = match shared.span_parent.get() {
+                Some(parent) => tracer.start_with_context(shared.name().to_string(), parent),
+                None => tracer.start(shared.name().to_string()),
+            };
+            for attribute in attributes(shared) {
+                started.set_attribute(attribute);
+            }
+            *span = Some(started);
+            instruments().active.add(1, &attributes(shared));
+        }
+        ThreadEvent::InterruptRequested { ref reason } => {
+            if let Some(ref mut span) = *span {
+                let reason = reason.iter().map(|reason| KeyValue::new("reason", reason.clone())).collect();
+                span.add_event("interrupt requested", reason);
+            }
+        }
+        ThreadEvent::Heartbeat => {
+            if let Some(ref mut span) = *span {
+                span.add_event("heartbeat", Vec::new());
+            }
+        }
+        ThreadEvent::StateChanged { state } => {
+            if let Some(ref mut span) = *span {
+                span.add_event("state changed", vec![KeyValue::new("state", format!("{:?}", state))]);
+            }
+        }
+        ThreadEvent::Panicked { ref message } => {
+            if let Some(ref mut span) = *span {
+                span.add_event("panicked", vec![KeyValue::new("message", message.clone())]);
+                span.set_status(Status::error(message.clone()));
+            }
+            instruments().panics.add(1, &attributes(shared));
+        }
+        ThreadEvent::Terminated { .. } => {
+            if let Some(mut ended) = span.take() {
+                ended.end();
+                instruments().active.add(-1, &attributes(shared));
+            }
+        }
+        ThreadEvent::SlowJoin { waited } => {
+            if let Some(ref mut span) = *span {
+                span.add_event("slow join", vec![KeyValue::new("waited_ms", waited.as_millis() as i64)]);
+            }
+        }
+        ThreadEvent::Leaked { ref spawned_at } => {
+            if let Some(ref mut span) = *span {
+                span.add_event("leaked", vec![KeyValue::new("spawned_at", spawned_at.clone())]);
+            }
+        }
+        ThreadEvent::TaskRan { waited, ran, panicked } => {
+            let mut attributes = attributes(shared);
+            attributes.push(KeyValue::new("task.panicked", panicked));
+            instruments().task_wait.record(waited.as_secs_f64(), &attributes);
+            instruments().task_duration.record(ran.as_secs_f64(), &attributes);
+        }
+        ThreadEvent::Reloaded { reloads } => {
+            if let Some(ref mut span) = *span {
+                span.add_event("reloaded", vec![KeyValue::new("reloads", reloads as i64)]);
+            }
+        }
+    }
+}
+
+
+#[cfg(all(test, not(feature = "minimal")))]
+mod tests {
+    use std::sync::mpsc::channel;
+    use ThreadHandle;
+
+    #[test]
+    fn test_span_covers_runnable() {
+        let (sender, receiver) = channel();
+        let handle = ThreadHandle::spawn("Test otel span".to_string(), move |interrupted| {
+            interrupted.heartbeat();
+            sender.send(interrupted.shared.span.lock().unwrap().is_some()).unwrap();
+            panic!("traced");
+        }).unwrap();
+        assert!(receiver.recv().unwrap());
+        assert!(handle.join().unwrap().is_err());
+        assert!(handle.shared.span.lock().unwrap().is_none());
+    }
+}
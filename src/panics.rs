@@ -0,0 +1,288 @@
+use std::any::{Any, TypeId};
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use {InterruptToken, ThreadHandle};
+
+
+/// Helpers for the payload of a panic, as found in the error of a joined thread.
+pub trait PanicPayload {
+    /// The message of a panic raised with a string, as `panic!` does.
+    fn message(&self) -> Option<&str>;
+
+    /// The message, or a placeholder for payloads that are not strings.
+    fn describe(&self) -> String {
+        self.message().unwrap_or("Box<Any>").to_string()
+    }
+
+    /// The payload of a panic raised with `std::panic::panic_any`, if it has type `E`.
+    fn payload<E: Any>(&self) -> Option<&E>;
+}
+
+impl PanicPayload for dyn Any + Send {
+    fn message(&self) -> Option<&str> {
+        if let Some(message) = self.downcast_ref::<&'static str>() {
+            Some(message)
+        } else if let Some(combined) = self.downcast_ref::<CombinedPanic>() {
+            Some(&combined.message)
+        } else {
+            self.downcast_ref::<String>().map(String::as_str)
+        }
+    }
+
+    fn payload<E: Any>(&self) -> Option<&E> {
+        match self.downcast_ref::<CombinedPanic>() {
+            Some(combined) if TypeId::of::<E>() != TypeId::of::<CombinedPanic>() => combined.original.payload(),
+            _ => self.downcast_ref::<E>(),
+        }
+    }
+}
+
+/// Payload of a thread whose cleanups, registered with `InterruptToken::defer` or run as its
+/// stop hook, panicked after the runnable already had: the first panic stays the outcome, and
+/// the message lists every one of them. `PanicPayload::payload` looks into the first panic.
+#[derive(Debug)]
+pub struct CombinedPanic {
+    pub original: Box<dyn Any + Send>,
+    /// Messages of the later panics, in the order they happened.
+    pub cleanups: Vec<String>,
+    message: String,
+}
+
+/// Adds the panics of the cleanups run after the runnable to its outcome, keeping the first
+/// panic as the cause.
+pub(crate) fn combine<T>(outcome: thread::Result<T>, mut cleanups: Vec<Box<dyn Any + Send>>) -> thread::Result<T> {
+    if cleanups.is_empty() {
+        return outcome;
+    }
+    let original = match outcome {
+        Err(payload) => payload,
+        Ok(_) => cleanups.remove(0),
+    };
+    if cleanups.is_empty() {
+        return Err(original);
+    }
+    let cleanups: Vec<_> = cleanups.iter().map(|payload| payload.describe()).collect();
+    let mut message = original.describe();
+    for cleanup in &cleanups {
+        message.push_str("; then cleanup panicked: ");
+        message.push_str(cleanup);
+    }
+    Err(Box::new(CombinedPanic { original, cleanups, message }))
+}
+
+/// Panic of a runnable spawned by this crate, as passed to panic handlers.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PanicReport {
+    pub thread: String,
+    pub message: String,
+}
+
+/// What happens to the process when a runnable panics, see `Builder::panic_policy`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PanicPolicy {
+    /// The panic becomes the outcome of the thread, for whoever joins it.
+    #[default]
+    Capture,
+    /// The process is aborted once the panic was reported to panic handlers, crash sinks and
+    /// event subscribers, for workers the process cannot do without.
+    AbortProcess,
+    /// The other threads of the group are interrupted, as with `ThreadGroup::fail_fast`; same
+    /// as `Capture` for a thread outside a group.
+    InterruptGroup,
+}
+
+pub type PanicHandler = Arc<dyn Fn(&PanicReport) + Send + Sync>;
+
+static DEFAULT_HANDLER: Mutex<Option<PanicHandler>> = Mutex::new(None);
+
+/// Sets the handler invoked when a runnable spawned by this crate panics and its builder has
+/// no handler of its own. Unlike `std::panic::set_hook`, threads spawned by other means are
+/// unaffected; the process-wide hook still runs first, as for any panic.
+pub fn set_panic_handler(handler: Option<PanicHandler>) {
+    *DEFAULT_HANDLER.lock().unwrap() = handler;
+}
+
+pub(crate) fn report(handler: Option<&PanicHandler>, thread: &str, payload: &(dyn Any + Send)) {
+    let handler = match handler {
+        Some(handler) => Some(handler.clone()),
+        None => DEFAULT_HANDLER.lock().unwrap().clone(),
+    };
+    if let Some(handler) = handler {
+        let report = PanicReport {
+            thread: thread.to_string(),
+            message: payload.describe(),
+        };
+        // A failing handler must not keep the handle from learning that its thread terminated.
+        panic::catch_unwind(AssertUnwindSafe(|| handler(&report))).ok();
+    }
+}
+
+/// Ends the process after the panic of a thread whose policy is `PanicPolicy::AbortProcess`.
+pub(crate) fn abort(thread: &str, message: &str) -> ! {
+    eprintln!("thread {} panicked with abort policy: {}; aborting", thread, message);
+    process::abort()
+}
+
+/// Whether panics unwind. Built with `panic = "abort"`, a panic ends the process before this
+/// crate sees it, so panic handlers, crash sinks, `Panicked` events, failure propagation to
+/// linked threads, monitors and groups, and retries of panicking attempts never run; use
+/// `spawn_fallible` to get them from returned errors instead.
+pub fn unwinding_supported() -> bool {
+    !cfg!(panic = "abort")
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Spawns a thread running `cleanup` on the worker with the outcome of the runnable once it
+    /// returned or panicked; the panic is then resumed, so it is reported as usual. A panic of
+    /// `cleanup` is combined with the outcome, as for `InterruptToken::defer`.
+    #[track_caller]
+    pub fn spawn_with_cleanup<F, C>(name: String, runnable: F, cleanup: C) -> io::Result<Self> where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static,
+        C: FnOnce(&thread::Result<T>) + Send + 'static
+    {
+        ThreadHandle::spawn(name, move |interrupted| {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(move || runnable(interrupted)));
+            let cleaned = panic::catch_unwind(AssertUnwindSafe(|| cleanup(&outcome)));
+            match combine(outcome, cleaned.err().into_iter().collect()) {
+                Ok(value) => value,
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        })
+    }
+}
+
+impl<T, E> ThreadHandle<Result<T, E>> where T: Send + 'static, E: Clone + fmt::Debug + Send + Sync + 'static {
+    /// Spawns a thread for which returning `Err` counts as a failure, as a panic would: it is
+    /// reported to panic handlers and crash sinks, and propagated to linked threads and
+    /// monitors with the `Debug` form of the error as message. Joining still yields the error,
+    /// and `fallible_status` reports a copy of it; an error which is not `Clone` can be wrapped
+    /// in an `Arc`.
+    pub fn spawn_fallible<F>(name: String, runnable: F) -> io::Result<Self> where
+        F: FnOnce(InterruptToken) -> Result<T, E>, F: Send + 'static
+    {
+        ThreadHandle::spawn(name, move |interrupted: InterruptToken| {
+            let shared = interrupted.shared.clone();
+            let result = runnable(interrupted);
+            if let Err(ref error) = result {
+                shared.record_error(error);
+            }
+            result
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::panic::panic_any;
+    use std::process::Command;
+    use std::time::Duration;
+    use {Builder, ThreadGroup, ThreadHandle};
+    use super::*;
+
+    #[test]
+    fn test_builder_panic_handler() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let record = reports.clone();
+        let handle = Builder::new("Test panic handler".to_string())
+            .on_panic(move |report| record.lock().unwrap().push(report.clone()))
+            .spawn(|_| -> u32 { panic!("scoped") })
+            .unwrap();
+        assert!(handle.join().unwrap().is_err());
+        let ok = Builder::new("Test panic handler ok".to_string())
+            .on_panic(|_| panic!("must not be called"))
+            .spawn(|_| 1)
+            .unwrap();
+        assert_eq!(1, ok.join().unwrap().unwrap());
+        assert_eq!(vec![PanicReport {
+            thread: "Test panic handler".to_string(),
+            message: "scoped".to_string(),
+        }], *reports.lock().unwrap());
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Fatal(u32);
+
+    #[test]
+    fn test_panic_payload() {
+        let formatted = ThreadHandle::spawn("Test payload formatted".to_string(), |_| -> () { panic!("code {}", 7) }).unwrap();
+        let error = formatted.join().unwrap().unwrap_err();
+        assert_eq!(Some("code 7"), error.message());
+        let literal = ThreadHandle::spawn("Test payload literal".to_string(), |_| -> () { panic!("literal") }).unwrap();
+        assert_eq!("literal", literal.join().unwrap().unwrap_err().describe());
+        let custom = ThreadHandle::spawn("Test payload custom".to_string(), |_| -> () { panic::panic_any(Fatal(3)) }).unwrap();
+        let error = custom.join().unwrap().unwrap_err();
+        assert_eq!(None, error.message());
+        assert_eq!("Box<Any>", error.describe());
+        assert_eq!(Some(&Fatal(3)), error.payload::<Fatal>());
+        assert_eq!(None, error.payload::<u32>());
+    }
+
+    #[test]
+    fn test_spawn_with_cleanup() {
+        let cleaned = Arc::new(Mutex::new(Vec::new()));
+        let record = cleaned.clone();
+        let handle = ThreadHandle::spawn_with_cleanup("Test cleanup".to_string(), |_| 1, move |outcome: &thread::Result<u32>| {
+            record.lock().unwrap().push(format!("{:?}", outcome.as_ref().ok()));
+        }).unwrap();
+        assert_eq!(1, handle.join().unwrap().unwrap());
+        let record = cleaned.clone();
+        let handle = ThreadHandle::spawn_with_cleanup("Test cleanup panic".to_string(), |_| -> u32 { panic!("mid-job") }, move |outcome: &thread::Result<u32>| {
+            record.lock().unwrap().push(outcome.as_ref().unwrap_err().describe());
+        }).unwrap();
+        assert_eq!("mid-job", handle.join().unwrap().unwrap_err().describe());
+        assert_eq!(vec!["Some(1)".to_string(), "mid-job".to_string()], *cleaned.lock().unwrap());
+
+        let handle = ThreadHandle::spawn_with_cleanup("Test cleanup panics".to_string(), |_| -> u32 { panic_any(7u8) }, |_: &thread::Result<u32>| {
+            panic!("cleanup");
+        }).unwrap();
+        let payload = handle.join().unwrap().unwrap_err();
+        assert_eq!(Some(&7), payload.payload::<u8>());
+        assert_eq!(vec!["cleanup".to_string()], payload.downcast_ref::<CombinedPanic>().unwrap().cleanups);
+        let handle = ThreadHandle::spawn_with_cleanup("Test cleanup panics alone".to_string(), |_| 1, |_: &thread::Result<u32>| {
+            panic!("cleanup");
+        }).unwrap();
+        assert_eq!("cleanup", handle.join().unwrap().unwrap_err().describe());
+    }
+
+    #[test]
+    fn test_panic_policy_abort() {
+        if env::var_os("THREAD_HANDLE_TEST_ABORT").is_some() {
+            let handle = Builder::new("Test abort policy".to_string())
+                .panic_policy(PanicPolicy::AbortProcess)
+                .on_panic(|report| println!("reported {}", report.message))
+                .spawn(|_| -> u32 { panic!("fatal") })
+                .unwrap();
+            handle.join();
+            unreachable!("the process must have aborted");
+        }
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "panics::tests::test_panic_policy_abort", "--nocapture"])
+            .env("THREAD_HANDLE_TEST_ABORT", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("reported fatal"));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("thread Test abort policy panicked with abort policy: fatal; aborting"));
+    }
+
+    #[test]
+    fn test_panic_policy_interrupt_group() {
+        let mut group = ThreadGroup::new("Test policy group".to_string()).panic_policy(PanicPolicy::InterruptGroup);
+        group.spawn("Test policy worker".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        group.spawn("Test policy failing".to_string(), |_| panic!("failed")).unwrap();
+        let error = group.join_all().unwrap_err();
+        assert_eq!(Some(1), error.cause);
+        assert_eq!(vec![0], error.cancelled);
+    }
+}
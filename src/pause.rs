@@ -0,0 +1,97 @@
+use std::thread;
+use std::time::Duration;
+
+use {InterruptToken, Interrupted, ThreadHandle};
+
+
+/// Real time a paused runnable waits between two looks at its interruption flag, which custom
+/// signals may set without waking it up.
+const PAUSE_SLICE: Duration = Duration::from_millis(10);
+
+impl InterruptToken {
+    /// Cooperative yield point for compute-heavy loops: blocks while the handle is paused, sleeps
+    /// as required by `throttle`, gives other threads a chance to run, and fails once
+    /// interruption was requested.
+    pub fn yield_now(&self) -> Result<(), Interrupted> {
+        self.wait_while_paused();
+        self.shared.throttle_pause();
+        thread::yield_now();
+        if self.is_interrupted() {
+            Err(Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.shared.paused.0.lock().unwrap()
+    }
+
+    /// Blocks while the handle is paused, returning early if interruption is requested.
+    fn wait_while_paused(&self) {
+        let (ref lock, ref condvar) = self.shared.paused;
+        let mut paused = lock.lock().unwrap();
+        while *paused && !self.is_interrupted() {
+            paused = condvar.wait_timeout(paused, PAUSE_SLICE).unwrap().0;
+        }
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Asks the runnable to hold still at its next `yield_now` until `resume` is called;
+    /// interrupting it releases it too. Returns whether it was already paused.
+    pub fn pause(&self) -> bool {
+        let mut paused = self.shared.paused.0.lock().unwrap();
+        let previous = *paused;
+        *paused = true;
+        previous
+    }
+
+    /// Lets a paused runnable continue; returns whether it was paused.
+    pub fn resume(&self) -> bool {
+        let (ref lock, ref condvar) = self.shared.paused;
+        let mut paused = lock.lock().unwrap();
+        let previous = *paused;
+        *paused = false;
+        condvar.notify_all();
+        previous
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.shared.paused.0.lock().unwrap()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{self, AtomicU64};
+    use std::thread::sleep;
+    use super::*;
+
+    #[test]
+    fn test_yield_now() {
+        let iterations = Arc::new(AtomicU64::new(0));
+        let counter = iterations.clone();
+        let handle = ThreadHandle::spawn("Test yield".to_string(), move |interrupted| {
+            while interrupted.yield_now().is_ok() {
+                counter.fetch_add(1, atomic::Ordering::SeqCst);
+            }
+        }).unwrap();
+        sleep(Duration::from_millis(20));
+        assert!(!handle.pause());
+        assert!(handle.is_paused());
+        sleep(Duration::from_millis(20));
+        let paused_at = iterations.load(atomic::Ordering::SeqCst);
+        sleep(Duration::from_millis(50));
+        assert_eq!(paused_at, iterations.load(atomic::Ordering::SeqCst));
+        assert!(handle.resume());
+        sleep(Duration::from_millis(20));
+        assert!(iterations.load(atomic::Ordering::SeqCst) > paused_at);
+        handle.pause();
+        sleep(Duration::from_millis(20));
+        handle.interrupt().unwrap();
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+//! Moves a thread to the priority, cgroup and NUMA node set with the builder before its
+//! runnable starts, reporting a failure back to the spawner.
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, ThreadId};
+
+use sys::Placement;
+use {recycle, Shared};
+
+
+/// Thread side of the placement, moved into the job.
+pub(crate) struct Place {
+    placement: Placement,
+    /// Thread that created the handle; a job running on it runs inline and is not placed.
+    spawner: ThreadId,
+    reply: Sender<io::Result<()>>,
+}
+
+/// Spawner side of the placement, waiting for the thread to report it.
+pub(crate) struct Placing {
+    reply: Receiver<io::Result<()>>,
+}
+
+/// Pairs up both sides if `placement` changes anything about the thread.
+pub(crate) fn channel(placement: &Placement) -> Option<(Place, Placing)> {
+    if placement.nice.is_none() && placement.cgroup.is_none() && placement.numa_node.is_none() {
+        return None;
+    }
+    let (reply, waiting) = mpsc::channel();
+    let place = Place {
+        placement: placement.clone(),
+        spawner: thread::current().id(),
+        reply,
+    };
+    Some((place, Placing { reply: waiting }))
+}
+
+impl Place {
+    /// Applies the placement to the calling thread and reports the outcome to the spawner. The
+    /// thread is not recycled afterwards, so that the next runnable does not inherit the
+    /// placement; a job running inline on the spawner leaves its thread alone.
+    pub(crate) fn apply(self) -> io::Result<()> {
+        let placed = if thread::current().id() == self.spawner {
+            Ok(())
+        } else {
+            recycle::retire_current();
+            self.placement.apply()
+        };
+        let reported = match placed {
+            Ok(()) => Ok(()),
+            Err(ref error) => Err(io::Error::new(error.kind(), error.to_string())),
+        };
+        self.reply.send(reported).ok();
+        placed
+    }
+}
+
+impl Shared {
+    /// Waits until the thread was placed, returning why it could not be. Does not wait for a
+    /// job queued by a deferred `InlineBackend`, which runs inline.
+    pub(crate) fn wait_placed(&self) -> io::Result<()> {
+        let placing = match self.placing.lock().unwrap().take() {
+            Some(placing) => placing,
+            None => return Ok(()),
+        };
+        #[cfg(any(test, feature = "test-util"))]
+        {
+            if self.queued.lock().unwrap().is_some() {
+                return Ok(());
+            }
+        }
+        // A backend dropping the job without running it drops the sender too.
+        placing.reply.recv().unwrap_or(Ok(()))
+    }
+}
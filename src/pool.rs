@@ -0,0 +1,1381 @@
+use std::any::{self, Any};
+use std::cmp;
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use builder::SpawnSettings;
+use current;
+use events::{self, ThreadEvent};
+use group::{Accounting, GroupUsage, Quota, QuotaCallback};
+use latency::{LatencyWindow, Percentiles};
+use {InterruptOutcome, InterruptToken, ThreadHandle, ThreadStatus};
+
+
+/// Task as queued, run with the state of the worker and returning whether it panicked; tasks of
+/// a scoped pool may borrow data living for `'a`.
+type Job<'a> = Box<dyn FnOnce(&InterruptToken, &mut WorkerState) -> bool + Send + 'a>;
+
+/// State a worker keeps across tasks, see `PoolBuilder::worker_state`.
+type WorkerState = Option<Box<dyn Any>>;
+
+struct QueuedTask<'a> {
+    priority: i32,
+    /// Priority it is dispatched by, its own raised by aging as of the last `age`.
+    rank: i64,
+    sequence: u64,
+    enqueued: Instant,
+    tag: Option<String>,
+    job: Job<'a>,
+}
+
+impl<'a> QueuedTask<'a> {
+    fn meta(&self, now: Instant) -> TaskMeta {
+        TaskMeta {
+            id: self.sequence,
+            priority: self.priority,
+            tag: self.tag.clone(),
+            queued_for: now.saturating_duration_since(self.enqueued),
+        }
+    }
+}
+
+/// Orders the queue: the highest rank first, and the earliest submitted among equal ranks.
+impl<'a> Ord for QueuedTask<'a> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.rank.cmp(&other.rank).then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl<'a> PartialOrd for QueuedTask<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> PartialEq for QueuedTask<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl<'a> Eq for QueuedTask<'a> {}
+
+/// What `ThreadPool::purge` knows about a queued task.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TaskMeta {
+    /// Same as `TaskHandle::id`.
+    pub id: u64,
+    pub priority: i32,
+    /// Set with `ThreadPool::submit_tagged`.
+    pub tag: Option<String>,
+    pub queued_for: Duration,
+}
+
+struct Queue<'a> {
+    tasks: BinaryHeap<QueuedTask<'a>>,
+    /// Last time the ranks of the queued tasks were recomputed for aging.
+    aged_at: Instant,
+    next_sequence: u64,
+    closed: bool,
+    /// Set with `ThreadPool::quiesce`, which also closes the queue.
+    quiescing: bool,
+    /// Worker threads that started, or were spawned on demand and are about to, and have not
+    /// exited.
+    workers: usize,
+    /// Workers set with `PoolBuilder::workers`, or of a scoped pool, whose thread has not
+    /// started yet; `needs_worker` counts on them.
+    unstarted: usize,
+    /// Workers running a task.
+    busy: usize,
+}
+
+type WorkerInit = Arc<dyn Fn(usize) + Send + Sync>;
+type StateFactory = Arc<dyn Fn(usize) -> Box<dyn Any> + Send + Sync>;
+
+/// What a worker runs before taking its first task.
+#[derive(Clone, Default)]
+struct WorkerSetup {
+    init: Option<WorkerInit>,
+    state: Option<StateFactory>,
+}
+
+/// Load and latency figures of a pool, see `ThreadPool::stats`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PoolStats {
+    pub queued: usize,
+    /// Workers running a task.
+    pub busy: usize,
+    /// Workers waiting for a task.
+    pub idle: usize,
+    /// Tasks that ran to completion or panicked.
+    pub completed: u64,
+    /// Tasks that panicked, included in `completed`.
+    pub failed: u64,
+    /// Time recent tasks spent queued before a worker took them; `None` before the first one.
+    pub queue_wait: Option<Percentiles>,
+    /// Time recent tasks took to run.
+    pub execution: Option<Percentiles>,
+}
+
+#[derive(Default)]
+struct TaskTimes {
+    completed: u64,
+    failed: u64,
+    queue_wait: LatencyWindow,
+    execution: LatencyWindow,
+}
+
+/// How far the workers set with `PoolBuilder::workers` got with their initialization.
+#[derive(Default)]
+struct Readiness {
+    ready: usize,
+    /// Workers whose thread could not be spawned or whose `init` panicked.
+    failed: usize,
+    /// Polled `ReadyFuture`s.
+    wakers: Vec<Waker>,
+}
+
+struct PoolShared<'a> {
+    queue: Mutex<Queue<'a>>,
+    times: Mutex<TaskTimes>,
+    available: Condvar,
+    aging: Option<Duration>,
+    readiness: Mutex<Readiness>,
+    became_ready: Condvar,
+}
+
+impl<'a> PoolShared<'a> {
+    fn new(aging: Option<Duration>, workers: usize) -> Self {
+        PoolShared {
+            queue: Mutex::new(Queue {
+                tasks: BinaryHeap::new(),
+                aged_at: Instant::now(),
+                next_sequence: 0,
+                closed: false,
+                quiescing: false,
+                workers: 0,
+                unstarted: workers,
+                busy: 0,
+            }),
+            times: Mutex::new(TaskTimes::default()),
+            available: Condvar::new(),
+            aging,
+            readiness: Mutex::new(Readiness::default()),
+            became_ready: Condvar::new(),
+        }
+    }
+
+    /// Records that a worker finished its initialization, or failed to.
+    fn report_ready(&self, ready: bool) {
+        let wakers = {
+            let mut readiness = self.readiness.lock().unwrap();
+            match ready {
+                true => readiness.ready += 1,
+                false => readiness.failed += 1,
+            }
+            mem::take(&mut readiness.wakers)
+        };
+        self.became_ready.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Queues a task unless the pool was closed, and returns its handle, which reports a task
+    /// that was not queued as never run.
+    fn submit<T, F>(&self, priority: i32, tag: Option<String>, task: F) -> (TaskHandle<T>, Result<(), SubmitError>) where
+        T: Send + 'a,
+        F: FnOnce(&mut WorkerState, InterruptToken) -> T, F: Send + 'a
+    {
+        let (sender, receiver) = mpsc::channel();
+        let token = InterruptToken::new();
+        let task_token = token.clone();
+        let job: Job<'a> = Box::new(move |worker: &InterruptToken, state: &mut WorkerState| {
+            if worker.is_interrupted() {
+                task_token.interrupt();
+            }
+            // Code the task calls finds its token rather than the worker's, to see it cancelled.
+            let _current = current::enter(&task_token);
+            let result = panic::catch_unwind(AssertUnwindSafe(move || task(state, task_token)));
+            let panicked = result.is_err();
+            let _ = sender.send(result);
+            panicked
+        });
+        let mut queue = self.queue.lock().unwrap();
+        let sequence = queue.next_sequence;
+        queue.next_sequence += 1;
+        let queued = if queue.quiescing {
+            Err(SubmitError::Quiescing)
+        } else if queue.closed {
+            Err(SubmitError::ShutDown)
+        } else {
+            queue.tasks.push(QueuedTask {
+                priority,
+                rank: i64::from(priority),
+                sequence,
+                enqueued: Instant::now(),
+                tag,
+                job,
+            });
+            self.available.notify_one();
+            Ok(())
+        };
+        let task = TaskHandle {
+            id: sequence,
+            pool: None,
+            token,
+            result: Mutex::new(Some(receiver)),
+            outcome: Mutex::new(None),
+        };
+        (task, queued)
+    }
+
+    /// Drops the queued tasks matching `predicate`, whose handles then report them as never run;
+    /// returns how many.
+    fn purge<P>(&self, mut predicate: P) -> usize where P: FnMut(&TaskMeta) -> bool {
+        let now = Instant::now();
+        let mut queue = self.queue.lock().unwrap();
+        let queued = queue.tasks.len();
+        queue.tasks.retain(|task| !predicate(&task.meta(now)));
+        queued - queue.tasks.len()
+    }
+
+    /// Closes the queue as `close` does, rejecting later tasks with `Quiescing`; returns whether
+    /// the pool was already quiescing.
+    fn quiesce(&self) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let quiescing = queue.quiescing;
+        queue.quiescing = true;
+        queue.closed = true;
+        self.available.notify_all();
+        quiescing
+    }
+
+    /// Lets the workers drain the queue and exit; tasks submitted afterwards never run.
+    fn close(&self) {
+        self.queue.lock().unwrap().closed = true;
+        self.available.notify_all();
+    }
+
+    /// Priority used for dispatch; with aging, waiting tasks gain one level per aging interval.
+    fn effective_priority(&self, task: &QueuedTask<'a>, now: Instant) -> i64 {
+        let boost = match self.aging {
+            Some(aging) if aging > Duration::from_secs(0) => {
+                (now.duration_since(task.enqueued).as_nanos() / aging.as_nanos()) as i64
+            }
+            _ => 0,
+        };
+        i64::from(task.priority) + boost
+    }
+
+    /// With aging, recomputes the ranks of the queued tasks, at most once per aging interval so
+    /// that taking a task stays logarithmic in the length of the queue; ranks thus lag behind
+    /// by one level at most.
+    fn age(&self, queue: &mut Queue<'a>) {
+        let aging = match self.aging {
+            Some(aging) if aging > Duration::from_secs(0) => aging,
+            _ => return,
+        };
+        let now = Instant::now();
+        if now < queue.aged_at + aging {
+            return;
+        }
+        let mut tasks = mem::take(&mut queue.tasks).into_vec();
+        for task in tasks.iter_mut() {
+            task.rank = self.effective_priority(task, now);
+        }
+        queue.tasks = BinaryHeap::from(tasks);
+        queue.aged_at = now;
+    }
+
+    /// Takes the next task for a worker, waiting for one; `None` once the pool is closed and
+    /// drained, or when no task came within `keep_alive`, after which the worker exits.
+    fn next_task(&self, keep_alive: Option<Duration>) -> Option<QueuedTask<'a>> {
+        let mut queue = self.queue.lock().unwrap();
+        let retire_at = keep_alive.map(|keep_alive| Instant::now() + keep_alive);
+        loop {
+            if !queue.tasks.is_empty() {
+                self.age(&mut queue);
+                queue.busy += 1;
+                return queue.tasks.pop();
+            }
+            let now = Instant::now();
+            if queue.closed || retire_at.is_some_and(|retire_at| now >= retire_at) {
+                queue.workers -= 1;
+                return None;
+            }
+            queue = match retire_at {
+                Some(retire_at) => self.available.wait_timeout(queue, retire_at - now).unwrap().0,
+                None => self.available.wait(queue).unwrap(),
+            };
+        }
+    }
+
+    /// Whether queued tasks outnumber the workers free to take them while fewer than
+    /// `max_workers` run, in which case the caller must spawn a worker, counted from now on.
+    fn needs_worker(&self, max_workers: usize) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let planned = queue.workers + queue.unstarted;
+        let needed = !queue.closed && planned < max_workers && queue.tasks.len() > planned - queue.busy;
+        if needed {
+            queue.workers += 1;
+        }
+        needed
+    }
+
+    /// Whether a worker runs or is about to, which will take the queued tasks.
+    fn has_workers(&self) -> bool {
+        let queue = self.queue.lock().unwrap();
+        queue.workers + queue.unstarted > 0
+    }
+
+    fn stats(&self) -> PoolStats {
+        let (queued, workers, busy) = {
+            let queue = self.queue.lock().unwrap();
+            (queue.tasks.len(), queue.workers, queue.busy)
+        };
+        let times = self.times.lock().unwrap();
+        PoolStats {
+            queued,
+            busy,
+            idle: workers - busy,
+            completed: times.completed,
+            failed: times.failed,
+            queue_wait: times.queue_wait.percentiles(),
+            execution: times.execution.percentiles(),
+        }
+    }
+}
+
+/// Worker counted in `Queue::unstarted` until its thread starts, then in `Queue::workers` until
+/// it exits, whichever way that happens: spawning its thread failed and dropped the runnable,
+/// its `init` panicked, or it retired.
+struct WorkerSlot<'a> {
+    shared: Arc<PoolShared<'a>>,
+    /// Whether the worker was set with `PoolBuilder::workers` and reports its initialization.
+    core: bool,
+    started: bool,
+    initialized: bool,
+    /// Cleared once `next_task` let the worker retire, which it uncounted.
+    counted: bool,
+}
+
+impl<'a> WorkerSlot<'a> {
+    /// Worker counted in `Queue::unstarted`, whose thread may start later.
+    fn new(shared: Arc<PoolShared<'a>>, core: bool) -> Self {
+        WorkerSlot {
+            shared,
+            core,
+            started: false,
+            initialized: false,
+            counted: true,
+        }
+    }
+
+    /// Worker spawned on demand, already counted by `needs_worker`.
+    fn on_demand(shared: Arc<PoolShared<'a>>) -> Self {
+        WorkerSlot {
+            shared,
+            core: false,
+            started: true,
+            initialized: false,
+            counted: true,
+        }
+    }
+
+    /// Counts the worker as live once its thread runs.
+    fn start(&mut self) {
+        if !self.started {
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.unstarted -= 1;
+            queue.workers += 1;
+            self.started = true;
+        }
+    }
+}
+
+impl<'a> Drop for WorkerSlot<'a> {
+    fn drop(&mut self) {
+        if !self.started {
+            self.shared.queue.lock().unwrap().unstarted -= 1;
+        } else if self.counted {
+            self.shared.queue.lock().unwrap().workers -= 1;
+        }
+        if self.core && !self.initialized {
+            self.shared.report_ready(false);
+        }
+    }
+}
+
+/// Runs tasks until the pool is closed or, with a `keep_alive`, until the worker stayed idle that
+/// long. Each task is timed, and reported as `ThreadEvent::TaskRan` by managed workers.
+fn run_worker(mut slot: WorkerSlot, index: usize, setup: WorkerSetup, keep_alive: Option<Duration>, interrupted: InterruptToken) {
+    slot.start();
+    if let Some(init) = setup.init {
+        init(index);
+    }
+    let mut state = setup.state.map(|factory| factory(index));
+    slot.initialized = true;
+    if slot.core {
+        slot.shared.report_ready(true);
+    }
+    let shared = slot.shared.clone();
+    while let Some(task) = shared.next_task(keep_alive) {
+        let started = Instant::now();
+        let waited = started.saturating_duration_since(task.enqueued);
+        let panicked = (task.job)(&interrupted, &mut state);
+        let ran = started.elapsed();
+        shared.queue.lock().unwrap().busy -= 1;
+        {
+            let mut times = shared.times.lock().unwrap();
+            times.completed += 1;
+            if panicked {
+                times.failed += 1;
+            }
+            times.queue_wait.record(waited);
+            times.execution.record(ran);
+        }
+        events::publish(&interrupted.shared, || ThreadEvent::TaskRan { waited, ran, panicked });
+    }
+    slot.counted = false;
+}
+
+/// Configures and starts a `ThreadPool`.
+pub struct PoolBuilder {
+    name: String,
+    workers: usize,
+    max_workers: usize,
+    keep_alive: Duration,
+    aging: Option<Duration>,
+    setup: WorkerSetup,
+    settings: SpawnSettings,
+    quota: Quota,
+    on_exceeded: Option<QuotaCallback>,
+}
+
+impl PoolBuilder {
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Lets the pool spawn up to `workers` threads in total while queued tasks outnumber the idle
+    /// workers; threads beyond the count set with `workers` exit once idle for the keep-alive.
+    pub fn max_workers(mut self, workers: usize) -> Self {
+        self.max_workers = workers;
+        self
+    }
+
+    /// How long a worker beyond the count set with `workers` waits for a task before exiting;
+    /// one minute by default.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Raises the priority of queued tasks by one level for every `interval` they wait.
+    pub fn aging(mut self, interval: Duration) -> Self {
+        self.aging = Some(interval);
+        self
+    }
+
+    /// Closure run on each worker, with its index, before it takes its first task.
+    pub fn init<F>(mut self, init: F) -> Self where F: Fn(usize) + Send + Sync + 'static {
+        self.setup.init = Some(Arc::new(init));
+        self
+    }
+
+    /// Closure creating, on each worker and with its index, state such as a database connection
+    /// or a large buffer that the worker keeps across tasks and hands to those submitted with
+    /// `ThreadPool::submit_with_state`. It runs after `init`, and the state is dropped on the
+    /// worker when it exits.
+    pub fn worker_state<S, F>(mut self, factory: F) -> Self where S: 'static, F: Fn(usize) -> S + Send + Sync + 'static {
+        self.setup.state = Some(Arc::new(move |index| Box::new(factory(index))));
+        self
+    }
+
+    /// Limits the worker threads of the pool as `ThreadGroup::with_quota` does. The workers set
+    /// with `workers` are counted from `build`, which fails if they exceed the quota; workers
+    /// spawned on demand beyond the quota are not spawned, and the queued tasks wait for a free one.
+    pub fn quota(mut self, quota: Quota, on_exceeded: Option<QuotaCallback>) -> Self {
+        self.quota = quota;
+        self.on_exceeded = on_exceeded;
+        self
+    }
+
+    /// Closure run first thing on each worker thread, as with `Builder::on_thread_start`.
+    pub fn on_thread_start<F>(mut self, hook: F) -> Self where F: Fn() + Send + Sync + 'static {
+        self.settings.on_start = Some(Arc::new(hook));
+        self
+    }
+
+    /// Closure run last thing on each worker thread, once the pool shuts down.
+    pub fn on_thread_stop<F>(mut self, hook: F) -> Self where F: Fn() + Send + Sync + 'static {
+        self.settings.on_stop = Some(Arc::new(hook));
+        self
+    }
+
+    /// Creates the pool. Worker threads are spawned on the first submission,
+    /// or up front with `ThreadPool::warm_up`.
+    pub fn build(self) -> io::Result<ThreadPool> {
+        let mut settings = self.settings.clone();
+        settings.group = Some(self.name.clone());
+        let shared = Arc::new(PoolShared::new(self.aging, self.workers));
+        let accounting = Accounting::new(self.name.clone(), self.quota.clone(), self.on_exceeded.clone());
+        let workers = (0..self.workers).map(|index| {
+            let slot = WorkerSlot::new(shared.clone(), true);
+            let mut member = accounting.admit()?;
+            let setup = self.setup.clone();
+            let name = format!("{}-{}", self.name, index);
+            Ok(ThreadHandle::lazy_configured(name, &settings, move |interrupted| {
+                member.start();
+                run_worker(slot, index, setup, None, interrupted)
+            }))
+        }).collect::<io::Result<_>>()?;
+        Ok(ThreadPool {
+            name: self.name,
+            shared,
+            workers,
+            max_workers: cmp::max(self.max_workers, self.workers),
+            keep_alive: self.keep_alive,
+            extra: Mutex::new(Vec::new()),
+            spawned: AtomicUsize::new(self.workers),
+            setup: self.setup,
+            settings,
+            accounting,
+        })
+    }
+}
+
+/// Set of managed worker threads executing submitted tasks, highest priority first; it grows up
+/// to `PoolBuilder::max_workers` under load.
+pub struct ThreadPool {
+    name: String,
+    shared: Arc<PoolShared<'static>>,
+    workers: Vec<ThreadHandle<()>>,
+    max_workers: usize,
+    keep_alive: Duration,
+    /// Workers spawned on demand beyond `workers`, until they are joined after retiring.
+    extra: Mutex<Vec<ThreadHandle<()>>>,
+    /// Workers spawned so far, for naming the next one.
+    spawned: AtomicUsize,
+    setup: WorkerSetup,
+    settings: SpawnSettings,
+    accounting: Arc<Accounting>,
+}
+
+impl ThreadPool {
+    pub fn builder(name: String) -> PoolBuilder {
+        PoolBuilder {
+            name,
+            workers: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            max_workers: 0,
+            keep_alive: Duration::from_secs(60),
+            aging: None,
+            setup: WorkerSetup::default(),
+            settings: SpawnSettings::default(),
+            quota: Quota::new(),
+            on_exceeded: None,
+        }
+    }
+
+    /// Spawns every worker that has not been started yet. All of them are attempted; the first
+    /// error is returned.
+    pub fn warm_up(&self) -> io::Result<()> {
+        let mut result = Ok(());
+        for worker in self.workers.iter() {
+            if let Err(error) = worker.ensure_started() {
+                result = result.and(Err(error));
+            }
+        }
+        result
+    }
+
+    /// Number of workers set with `PoolBuilder::workers` that have finished their initialization.
+    pub fn ready_workers(&self) -> usize {
+        self.shared.readiness.lock().unwrap().ready
+    }
+
+    /// Blocks until every worker has finished its initialization, starting them if needed.
+    /// Returns `false` if a worker could not be spawned, if its `init` panicked, or if
+    /// `timeout` elapsed first.
+    pub fn wait_ready(&self, timeout: Option<Duration>) -> bool {
+        // A worker failing to spawn is counted as failed by its slot.
+        self.warm_up().ok();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut readiness = self.shared.readiness.lock().unwrap();
+        while readiness.ready + readiness.failed < self.workers.len() {
+            readiness = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.shared.became_ready.wait_timeout(readiness, deadline - now).unwrap().0
+                }
+                None => self.shared.became_ready.wait(readiness).unwrap(),
+            };
+        }
+        readiness.failed == 0
+    }
+
+    /// Like `wait_ready` without a timeout, for async code: the future resolves once every worker
+    /// has finished its initialization, to `false` if one failed.
+    pub fn wait_ready_async(&self) -> ReadyFuture {
+        self.warm_up().ok();
+        ReadyFuture {
+            shared: self.shared.clone(),
+            workers: self.workers.len(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Workers set with `PoolBuilder::workers`, which stay until the pool shuts down.
+    pub fn workers(&self) -> &[ThreadHandle<()>] {
+        &self.workers
+    }
+
+    /// Number of running workers, including those spawned on demand; workers whose thread has
+    /// not started yet, e.g. before the first submission, are not counted.
+    pub fn live_workers(&self) -> usize {
+        self.shared.queue.lock().unwrap().workers
+    }
+
+    pub fn queued(&self) -> usize {
+        self.shared.queue.lock().unwrap().tasks.len()
+    }
+
+    /// Submits a task with the default priority of zero.
+    pub fn submit<T, F>(&self, task: F) -> TaskHandle<T> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        self.submit_with_priority(0, task)
+    }
+
+    /// Submits a task; tasks with a higher priority are dispatched first.
+    pub fn submit_with_priority<T, F>(&self, priority: i32, task: F) -> TaskHandle<T> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        self.submit_queued(priority, None, task).0
+    }
+
+    /// Like `submit`, failing instead of returning a handle of a task that never runs once the
+    /// pool is quiescing or shut down.
+    pub fn try_submit<T, F>(&self, task: F) -> Result<TaskHandle<T>, SubmitError> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        self.try_submit_with_priority(0, task)
+    }
+
+    pub fn try_submit_with_priority<T, F>(&self, priority: i32, task: F) -> Result<TaskHandle<T>, SubmitError> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let (task, queued) = self.submit_queued(priority, None, task);
+        queued.map(|()| task)
+    }
+
+    /// Submits a task labelled with `tag`, for instance the client it serves, so that `purge`
+    /// can select it.
+    pub fn submit_tagged<T, F>(&self, tag: String, priority: i32, task: F) -> TaskHandle<T> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        self.submit_queued(priority, Some(tag), task).0
+    }
+
+    /// Submits a task run with the state of the worker that takes it, created with
+    /// `PoolBuilder::worker_state`. The task panics if the pool has no state of type `S`.
+    pub fn submit_with_state<S, T, F>(&self, task: F) -> TaskHandle<T> where
+        S: 'static,
+        T: Send + 'static,
+        F: FnOnce(&mut S, InterruptToken) -> T, F: Send + 'static
+    {
+        self.submit_job(0, None, move |state: &mut WorkerState, token| {
+            match state.as_mut().and_then(|state| state.downcast_mut::<S>()) {
+                Some(state) => task(state, token),
+                None => panic!("pool workers have no state of type {}", any::type_name::<S>()),
+            }
+        }).0
+    }
+
+    fn submit_queued<T, F>(&self, priority: i32, tag: Option<String>, task: F) -> (TaskHandle<T>, Result<(), SubmitError>) where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        self.submit_job(priority, tag, move |_: &mut WorkerState, token| task(token))
+    }
+
+    fn submit_job<T, F>(&self, priority: i32, tag: Option<String>, task: F) -> (TaskHandle<T>, Result<(), SubmitError>) where
+        T: Send + 'static,
+        F: FnOnce(&mut WorkerState, InterruptToken) -> T, F: Send + 'static
+    {
+        let (mut task, mut queued) = self.shared.submit(priority, tag, task);
+        task.pool = Some(Arc::downgrade(&self.shared));
+        if queued.is_ok() {
+            let warmed_up = self.warm_up();
+            let grown = self.grow();
+            // Unless no worker is left to run it, a task outlives a worker that failed to spawn.
+            match warmed_up.and(grown) {
+                Err(error) if !self.shared.has_workers() => {
+                    let id = task.id;
+                    if self.shared.purge(|meta| meta.id == id) > 0 {
+                        queued = Err(SubmitError::Spawn(error.kind()));
+                    }
+                }
+                _ => (),
+            }
+        }
+        (task, queued)
+    }
+
+    /// Stops accepting tasks, rejecting them with `SubmitError::Quiescing`, while the workers
+    /// run the queued ones and then exit; the workers report `is_quiescing` until they terminate.
+    /// Unlike `shutdown`, this returns right away. Returns whether the pool was already quiescing.
+    pub fn quiesce(&self) -> bool {
+        let quiescing = self.shared.quiesce();
+        for worker in self.workers.iter().chain(self.extra.lock().unwrap().iter()) {
+            worker.quiesce();
+        }
+        quiescing
+    }
+
+    pub fn is_quiescing(&self) -> bool {
+        self.shared.queue.lock().unwrap().quiescing
+    }
+
+    /// Worker threads counted against the quota set with `PoolBuilder::quota`, and their CPU time.
+    pub fn usage(&self) -> GroupUsage {
+        self.accounting.usage()
+    }
+
+    /// Current load of the pool, with percentile estimates of how long recent tasks waited and ran.
+    pub fn stats(&self) -> PoolStats {
+        self.shared.stats()
+    }
+
+    /// Drops the queued tasks matching `predicate`, such as every task tagged for a client that
+    /// disconnected; returns how many. Running tasks are left alone.
+    pub fn purge<P>(&self, predicate: P) -> usize where P: FnMut(&TaskMeta) -> bool {
+        self.shared.purge(predicate)
+    }
+
+    /// Spawns a worker if the queue outgrew the idle ones, and joins those that retired.
+    fn grow(&self) -> io::Result<()> {
+        let mut extra = self.extra.lock().unwrap();
+        extra.retain(|worker| !worker.shared.is_terminated() || worker.join().is_none());
+        if !self.shared.needs_worker(self.max_workers) {
+            return Ok(());
+        }
+        let index = self.spawned.fetch_add(1, atomic::Ordering::Relaxed);
+        let slot = WorkerSlot::on_demand(self.shared.clone());
+        let mut member = self.accounting.admit()?;
+        let (setup, keep_alive) = (self.setup.clone(), self.keep_alive);
+        let runnable = move |interrupted| {
+            member.start();
+            run_worker(slot, index, setup, Some(keep_alive), interrupted)
+        };
+        extra.push(ThreadHandle::spawn_configured(format!("{}-{}", self.name, index), self.settings.token(), &self.settings, runnable)?);
+        Ok(())
+    }
+
+    /// Lets the workers drain the queue, then joins them. Tasks submitted afterwards never run.
+    pub fn shutdown(&mut self) {
+        self.shared.close();
+        let extra: Vec<_> = self.extra.lock().unwrap().drain(..).collect();
+        for worker in extra {
+            worker.join();
+        }
+        for worker in self.workers.drain(..) {
+            if !worker.discard_pending() {
+                worker.join();
+            }
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Pool of a `scoped_pool` call, whose tasks may borrow data living for `'env`.
+pub struct PoolScope<'scope, 'env: 'scope> {
+    shared: Arc<PoolShared<'scope>>,
+    tokens: Mutex<Vec<InterruptToken>>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, 'env> PoolScope<'scope, 'env> {
+    pub fn submit<T, F>(&self, task: F) -> TaskHandle<T> where
+        T: Send + 'scope,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'scope
+    {
+        self.submit_with_priority(0, task)
+    }
+
+    pub fn submit_with_priority<T, F>(&self, priority: i32, task: F) -> TaskHandle<T> where
+        T: Send + 'scope,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'scope
+    {
+        let (task, _) = self.shared.submit(priority, None, move |_: &mut WorkerState, token| task(token));
+        self.tokens.lock().unwrap().push(task.token.clone());
+        task
+    }
+
+    pub fn queued(&self) -> usize {
+        self.shared.queue.lock().unwrap().tasks.len()
+    }
+}
+
+/// Interrupts every task and lets the workers drain the queue, including while unwinding.
+impl<'scope, 'env> Drop for PoolScope<'scope, 'env> {
+    fn drop(&mut self) {
+        let tokens = match self.tokens.lock() {
+            Ok(tokens) => tokens,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for token in tokens.iter() {
+            token.interrupt();
+        }
+        self.shared.close();
+    }
+}
+
+/// Runs `f` with a pool of `workers` threads whose tasks may borrow from the enclosing stack.
+/// When `f` returns or panics, every task is interrupted and the workers are joined once they
+/// ran the remaining ones, so they see their token interrupted. Fails with `InvalidInput` for
+/// zero workers, which would never run the tasks.
+pub fn scoped_pool<'env, F, R>(workers: usize, f: F) -> io::Result<R> where
+    F: for<'scope> FnOnce(&PoolScope<'scope, 'env>) -> R
+{
+    if workers == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "a scoped pool needs at least one worker"));
+    }
+    thread::scope(|scope| {
+        let pool = PoolScope {
+            shared: Arc::new(PoolShared::new(None, workers)),
+            tokens: Mutex::new(Vec::new()),
+            env: PhantomData,
+        };
+        for index in 0..workers {
+            let slot = WorkerSlot::new(pool.shared.clone(), false);
+            thread::Builder::new()
+                .name(format!("scoped-pool-{}", index))
+                .spawn_scoped(scope, move || run_worker(slot, index, WorkerSetup::default(), None, InterruptToken::new()))?;
+        }
+        Ok(f(&pool))
+    })
+}
+
+/// Why a pool did not accept a task.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SubmitError {
+    /// The pool is finishing its queued tasks before stopping, see `ThreadPool::quiesce`.
+    Quiescing,
+    ShutDown,
+    /// No worker could be spawned to run the task, which was dropped.
+    Spawn(io::ErrorKind),
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SubmitError::Quiescing => write!(f, "pool is quiescing"),
+            SubmitError::ShutDown => write!(f, "pool is shut down"),
+            SubmitError::Spawn(kind) => write!(f, "could not spawn a pool worker: {}", kind),
+        }
+    }
+}
+
+impl Error for SubmitError {}
+
+/// Future of `ThreadPool::wait_ready_async`, resolving to whether every worker initialized.
+pub struct ReadyFuture {
+    shared: Arc<PoolShared<'static>>,
+    workers: usize,
+}
+
+impl Future for ReadyFuture {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<bool> {
+        let mut readiness = self.shared.readiness.lock().unwrap();
+        if readiness.ready + readiness.failed >= self.workers {
+            return Poll::Ready(readiness.failed == 0);
+        }
+        if !readiness.wakers.iter().any(|waker| waker.will_wake(context.waker())) {
+            readiness.wakers.push(context.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Handle to a task submitted to a `ThreadPool`.
+pub struct TaskHandle<T> {
+    id: u64,
+    /// Pool the task was queued in, to take it back out; `None` in a scoped pool.
+    pool: Option<Weak<PoolShared<'static>>>,
+    token: InterruptToken,
+    result: Mutex<Option<Receiver<thread::Result<T>>>>,
+    /// Outcome received by `status` before anybody joined.
+    outcome: Mutex<Option<thread::Result<T>>>,
+}
+
+impl<T> TaskHandle<T> {
+    /// Identifier of the task, unique within its pool.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Requests interruption of the task.
+    pub fn interrupt(&self) -> InterruptOutcome {
+        self.token.interrupt()
+    }
+
+    /// Removes the task from the queue if it has not started yet, in which case it never runs and
+    /// `true` is returned; interrupts it otherwise. Tasks of a scoped pool are only interrupted.
+    pub fn cancel(&self) -> bool {
+        let id = self.id;
+        let removed = self.pool.as_ref().and_then(Weak::upgrade).is_some_and(|pool| pool.purge(|meta| meta.id == id) > 0);
+        if !removed {
+            self.token.interrupt();
+        }
+        removed
+    }
+
+    /// `Terminated` once the task finished, was joined, or was dropped without running.
+    pub fn status(&self) -> ThreadStatus {
+        let mut result = self.result.lock().unwrap();
+        let received = match *result {
+            Some(ref receiver) => receiver.try_recv(),
+            None => return ThreadStatus::Terminated,
+        };
+        match received {
+            Ok(outcome) => *self.outcome.lock().unwrap() = Some(outcome),
+            Err(TryRecvError::Empty) => return ThreadStatus::Running,
+            Err(TryRecvError::Disconnected) => (),
+        }
+        *result = None;
+        ThreadStatus::Terminated
+    }
+
+    /// Waits for the task to finish; returns `None` if it was already joined or never ran.
+    pub fn join(&self) -> Option<thread::Result<T>> {
+        let receiver = self.result.lock().unwrap().take();
+        self.outcome.lock().unwrap().take().or_else(|| receiver.and_then(|receiver| receiver.recv().ok()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use panics::PanicPayload;
+    use super::*;
+
+    fn record_order(aging: Option<Duration>) -> Vec<&'static str> {
+        let mut builder = ThreadPool::builder("Test priority".to_string()).workers(1);
+        if let Some(aging) = aging {
+            builder = builder.aging(aging);
+        }
+        let pool = builder.build().unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let blocker = pool.submit(|_| sleep(Duration::from_millis(300)));
+        sleep(Duration::from_millis(50));
+        let tasks: Vec<_> = vec![(0, "bulk"), (5, "interactive")].into_iter().map(|(priority, label)| {
+            let order = order.clone();
+            let task = pool.submit_with_priority(priority, move |_| order.lock().unwrap().push(label));
+            sleep(Duration::from_millis(150));
+            task
+        }).collect();
+        blocker.join().unwrap().unwrap();
+        for task in tasks.iter() {
+            task.join().unwrap().unwrap();
+        }
+        let order = order.lock().unwrap().clone();
+        order
+    }
+
+    #[test]
+    fn test_pool_priority() {
+        assert_eq!(vec!["interactive", "bulk"], record_order(None));
+    }
+
+    #[test]
+    fn test_pool_aging() {
+        assert_eq!(vec!["bulk", "interactive"], record_order(Some(Duration::from_millis(20))));
+    }
+
+    #[test]
+    fn test_pool_queue_order() {
+        let shared = PoolShared::new(Some(Duration::from_secs(60)), 0);
+        for &priority in [0, 5, 0, 5].iter() {
+            shared.submit(priority, None, |_, _| ()).1.unwrap();
+        }
+        let order: Vec<_> = (0..4).map(|_| shared.next_task(None).unwrap().sequence).collect();
+        assert_eq!(vec![1, 3, 0, 2], order);
+
+        for &priority in [0, 5, 5].iter() {
+            shared.submit(priority, None, |_, _| ()).1.unwrap();
+        }
+        let long_ago = Instant::now() - Duration::from_secs(600);
+        {
+            let mut queue = shared.queue.lock().unwrap();
+            let mut tasks = mem::take(&mut queue.tasks).into_vec();
+            tasks.iter_mut().filter(|task| task.priority == 0).for_each(|task| task.enqueued = long_ago);
+            queue.tasks = BinaryHeap::from(tasks);
+        }
+        // Ranked again once per aging interval only, not whenever a task is taken.
+        assert_eq!(5, shared.next_task(None).unwrap().sequence);
+        shared.queue.lock().unwrap().aged_at = long_ago;
+        assert_eq!(4, shared.next_task(None).unwrap().sequence);
+        assert_eq!(6, shared.next_task(None).unwrap().sequence);
+    }
+
+    #[test]
+    fn test_scoped_pool() {
+        let records = [3, 1, 4, 1, 5];
+        let mut total = 0;
+        let sum = scoped_pool(2, |pool| {
+            let tasks: Vec<_> = records.chunks(2).map(|chunk| pool.submit(move |_| chunk.iter().sum::<i32>())).collect();
+            let blocked = pool.submit(|interrupted| {
+                while !interrupted.is_interrupted() {
+                    sleep(Duration::from_millis(5));
+                }
+                total = 1;
+            });
+            let sum = tasks.iter().map(|task| task.join().unwrap().unwrap()).sum::<i32>();
+            assert_eq!(ThreadStatus::Running, blocked.status());
+            sum
+        }).unwrap();
+        assert_eq!(14, sum);
+        assert_eq!(1, total);
+        let error = scoped_pool(0, |pool| pool.submit(|_| ()).join()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+    }
+
+    #[test]
+    fn test_pool_adaptive_size() {
+        let mut pool = ThreadPool::builder("Test adaptive".to_string())
+            .workers(1)
+            .max_workers(3)
+            .keep_alive(Duration::from_millis(50))
+            .build().unwrap();
+        pool.warm_up().unwrap();
+        assert!(pool.wait_ready(None));
+        assert_eq!(1, pool.live_workers());
+        let tasks: Vec<_> = (0..6).map(|i| pool.submit(move |_| {
+            sleep(Duration::from_millis(50));
+            i
+        })).collect();
+        assert_eq!(3, pool.live_workers());
+        let results: Vec<_> = tasks.iter().map(|task| task.join().unwrap().unwrap()).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], results);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.live_workers() > 1 && Instant::now() < deadline {
+            sleep(Duration::from_millis(5));
+        }
+        assert_eq!(1, pool.live_workers());
+        assert_eq!(1, pool.submit(|_| 1).join().unwrap().unwrap());
+        pool.shutdown();
+        assert_eq!(0, pool.live_workers());
+    }
+
+    #[test]
+    fn test_pool_cancel_and_purge() {
+        let pool = ThreadPool::builder("Test cancel".to_string()).workers(1).build().unwrap();
+        let running = pool.submit(|interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+            "interrupted"
+        });
+        sleep(Duration::from_millis(50));
+        let queued = pool.submit(|_| "ran");
+        let tasks: Vec<_> = ["alice", "bob", "alice"].iter().map(|&client| {
+            pool.submit_tagged(client.to_string(), 0, move |_| client)
+        }).collect();
+        assert_eq!(4, pool.queued());
+        assert!(queued.cancel());
+        assert_eq!(ThreadStatus::Terminated, queued.status());
+        assert!(queued.join().is_none());
+        assert_eq!(2, pool.purge(|meta| meta.tag.as_deref() == Some("alice")));
+        assert_eq!(1, pool.queued());
+        assert!(!running.cancel());
+        assert_eq!("interrupted", running.join().unwrap().unwrap());
+        assert!(tasks[0].join().is_none());
+        assert_eq!("bob", tasks[1].join().unwrap().unwrap());
+        assert_ne!(tasks[1].id(), tasks[2].id());
+    }
+
+    #[test]
+    fn test_pool_stats() {
+        let pool = ThreadPool::builder("Test pool stats".to_string()).workers(2).build().unwrap();
+        assert_eq!(None, pool.stats().execution);
+        let tasks: Vec<_> = (0..4).map(|i| pool.submit(move |_| {
+            sleep(Duration::from_millis(30));
+            if i == 3 {
+                panic!("failed task");
+            }
+        })).collect();
+        for task in tasks.iter() {
+            task.join().unwrap().ok();
+        }
+        // Joining returns as soon as a task finished, before its worker recorded it.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut stats = pool.stats();
+        while (stats.busy, stats.completed) != (0, 4) && Instant::now() < deadline {
+            sleep(Duration::from_millis(1));
+            stats = pool.stats();
+        }
+        assert_eq!((0, 0, 2), (stats.queued, stats.busy, stats.idle));
+        assert_eq!((4, 1), (stats.completed, stats.failed));
+        assert!(stats.execution.unwrap().p50 >= Duration::from_millis(30));
+        assert!(stats.queue_wait.unwrap().max >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_pool_quiesce() {
+        let pool = ThreadPool::builder("Test pool quiesce".to_string()).workers(1).build().unwrap();
+        let tasks: Vec<_> = (0..3).map(|i| pool.try_submit(move |_| {
+            sleep(Duration::from_millis(20));
+            i
+        }).unwrap()).collect();
+        assert!(!pool.quiesce());
+        assert!(pool.is_quiescing());
+        assert_eq!(SubmitError::Quiescing, pool.try_submit(|_| 3).err().unwrap());
+        assert!(pool.workers()[0].is_quiescing());
+        let results: Vec<_> = tasks.iter().map(|task| task.join().unwrap().unwrap()).collect();
+        assert_eq!(vec![0, 1, 2], results);
+        pool.workers()[0].wait_finished(Some(Duration::from_secs(5)));
+        assert_eq!(ThreadStatus::Terminated, pool.workers()[0].status());
+    }
+
+    #[test]
+    fn test_pool_task_panic_keeps_worker() {
+        let pool = ThreadPool::builder("Test pool panic".to_string()).workers(1).build().unwrap();
+        let failing = pool.submit(|_| -> u32 { panic!("task failed") });
+        let next = pool.submit(|_| 7);
+        assert!(failing.join().unwrap().is_err());
+        assert_eq!(7, next.join().unwrap().unwrap());
+        assert!(next.join().is_none());
+    }
+
+    #[test]
+    fn test_pool_worker_state() {
+        let pool = ThreadPool::builder("Test worker state".to_string())
+            .workers(2)
+            .worker_state(|index| (index, Vec::<u32>::new()))
+            .build().unwrap();
+        let tasks: Vec<_> = (0..6).map(|i| pool.submit_with_state(move |state: &mut (usize, Vec<u32>), _| {
+            state.1.push(i);
+            (state.0, state.1.len())
+        })).collect();
+        let (mut runs, mut kept) = ([0, 0], [0, 0]);
+        for task in tasks {
+            let (worker, seen) = task.join().unwrap().unwrap();
+            runs[worker] += 1;
+            kept[worker] = cmp::max(kept[worker], seen);
+        }
+        assert_eq!(6, runs[0] + runs[1]);
+        assert_eq!(runs, kept);
+        let missing = pool.submit_with_state(|_: &mut String, _| ());
+        assert_eq!(Some("pool workers have no state of type alloc::string::String"), missing.join().unwrap().unwrap_err().message());
+    }
+
+    #[test]
+    fn test_pool_shutdown_drains() {
+        let mut pool = ThreadPool::builder("Test pool drain".to_string()).workers(2).build().unwrap();
+        let tasks: Vec<_> = (0..6).map(|i| pool.submit(move |interrupted: InterruptToken| {
+            sleep(Duration::from_millis(50));
+            (i, interrupted.is_interrupted())
+        })).collect();
+        pool.shutdown();
+        let results: Vec<_> = tasks.iter().map(|task| task.join().unwrap().unwrap()).collect();
+        assert_eq!((0..6).map(|i| (i, false)).collect::<Vec<_>>(), results);
+        assert!(pool.submit(|_| ()).join().is_none());
+    }
+
+    #[test]
+    fn test_pool_warm_up() {
+        let initialized = Arc::new(Mutex::new(Vec::new()));
+        let record = initialized.clone();
+        let pool = ThreadPool::builder("Test warm up".to_string())
+            .workers(3)
+            .init(move |index| {
+                sleep(Duration::from_millis(100));
+                record.lock().unwrap().push(index);
+            })
+            .build().unwrap();
+        assert_eq!(0, pool.ready_workers());
+        assert!(!pool.wait_ready(Some(Duration::from_millis(10))));
+        assert!(pool.wait_ready(Some(Duration::from_secs(5))));
+        assert_eq!(3, pool.ready_workers());
+        let mut initialized = initialized.lock().unwrap().clone();
+        initialized.sort();
+        assert_eq!(vec![0, 1, 2], initialized);
+    }
+
+    #[test]
+    fn test_pool_init_panic_not_ready() {
+        let pool = ThreadPool::builder("Test init panic".to_string())
+            .workers(3)
+            .init(|index| if index == 1 {
+                panic!("cache unavailable");
+            })
+            .build().unwrap();
+        assert!(!pool.wait_ready(None));
+        assert_eq!(2, pool.ready_workers());
+        assert_eq!(2, pool.live_workers());
+        assert_eq!(1, pool.submit(|_| 1).join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_pool_extra_worker_init_panic() {
+        let pool = ThreadPool::builder("Test extra init panic".to_string())
+            .workers(1)
+            .max_workers(2)
+            .init(|index| if index == 1 {
+                panic!("cache unavailable");
+            })
+            .build().unwrap();
+        let (release, blocked) = ::std::sync::mpsc::channel::<()>();
+        let blocking = pool.submit(move |_| blocked.recv().ok());
+        while pool.stats().busy == 0 {
+            sleep(Duration::from_millis(1));
+        }
+        // The first extra worker dies in `init`, leaving its task queued; it must give back its
+        // place, or no other worker could be spawned while the core one is busy.
+        let first = pool.submit(|_| "first");
+        while pool.live_workers() > 1 {
+            sleep(Duration::from_millis(1));
+        }
+        let second = pool.submit(|_| "second");
+        assert_eq!("first", first.join().unwrap().unwrap());
+        assert_eq!("second", second.join().unwrap().unwrap());
+        release.send(()).unwrap();
+        assert_eq!(Some(()), blocking.join().unwrap().unwrap());
+    }
+
+    struct Unpark(thread::Thread);
+
+    impl std::task::Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    #[test]
+    fn test_pool_wait_ready_async() {
+        let pool = ThreadPool::builder("Test ready async".to_string())
+            .workers(2)
+            .init(|_| sleep(Duration::from_millis(50)))
+            .build().unwrap();
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut ready = pool.wait_ready_async();
+        let mut polls = 0;
+        let initialized = loop {
+            polls += 1;
+            if let Poll::Ready(initialized) = Pin::new(&mut ready).poll(&mut context) {
+                break initialized;
+            }
+            thread::park();
+        };
+        assert!(initialized);
+        assert!(polls >= 2);
+        assert_eq!(2, pool.ready_workers());
+    }
+
+    #[test]
+    fn test_pool_quota() {
+        let quota = Quota::new().max_live(2);
+        assert!(ThreadPool::builder("Test pool quota".to_string()).workers(3).quota(quota.clone(), None).build().is_err());
+        let mut pool = ThreadPool::builder("Test pool quota".to_string())
+            .workers(1)
+            .max_workers(4)
+            .quota(quota, None)
+            .build().unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+        let (sender, started) = mpsc::channel();
+        let tasks: Vec<_> = (0..4).map(|i| {
+            let (released, sender) = (released.clone(), sender.clone());
+            pool.submit(move |_| {
+                sender.send(()).unwrap();
+                released.lock().unwrap().recv().ok();
+                i
+            })
+        }).collect();
+        // Both workers the quota allows run a task.
+        started.recv().unwrap();
+        started.recv().unwrap();
+        assert_eq!(2, pool.live_workers());
+        assert_eq!(2, pool.usage().live);
+        for _ in 0..4 {
+            release.send(()).unwrap();
+        }
+        let results: Vec<_> = tasks.iter().map(|task| task.join().unwrap().unwrap()).collect();
+        assert_eq!(vec![0, 1, 2, 3], results);
+        pool.shutdown();
+        assert_eq!(GroupUsage { live: 0, spawned: 2, cpu_time: pool.usage().cpu_time }, pool.usage());
+    }
+
+    #[test]
+    fn test_pool_live_workers_lazy() {
+        let pool = ThreadPool::builder("Test lazy workers".to_string()).workers(2).build().unwrap();
+        assert_eq!(0, pool.live_workers());
+        assert_eq!(0, pool.stats().idle);
+        pool.warm_up().unwrap();
+        assert!(pool.wait_ready(None));
+        assert_eq!(2, pool.live_workers());
+        assert_eq!(2, pool.stats().idle);
+    }
+
+    #[test]
+    fn test_pool_shutdown_without_start() {
+        let mut pool = ThreadPool::builder("Test unstarted".to_string())
+            .workers(2)
+            .init(|_| panic!("workers must not start"))
+            .build().unwrap();
+        pool.shutdown();
+        assert_eq!(0, pool.ready_workers());
+    }
+
+    #[test]
+    fn test_pool_thread_hooks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let on_start = events.clone();
+        let on_stop = events.clone();
+        let mut pool = ThreadPool::builder("Test pool hooks".to_string())
+            .workers(2)
+            .on_thread_start(move || on_start.lock().unwrap().push("start"))
+            .on_thread_stop(move || on_stop.lock().unwrap().push("stop"))
+            .build().unwrap();
+        assert!(pool.wait_ready(Some(Duration::from_secs(5))));
+        assert_eq!(vec!["start", "start"], *events.lock().unwrap());
+        pool.shutdown();
+        assert_eq!(vec!["start", "start", "stop", "stop"], *events.lock().unwrap());
+    }
+}
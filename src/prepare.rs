@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use {Builder, InterruptToken, ThreadHandle};
+
+
+/// Error of a two-stage spawn.
+#[derive(Debug)]
+pub enum PrepareError<E> {
+    /// The `prepare` closure failed on the calling thread; no thread was spawned.
+    Prepare(E),
+    /// The thread could not be spawned; the prepared state was dropped.
+    Spawn(io::Error),
+}
+
+impl<E> fmt::Display for PrepareError<E> where E: fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PrepareError::Prepare(ref error) => write!(f, "preparing the thread failed: {}", error),
+            PrepareError::Spawn(ref error) => write!(f, "spawning the thread failed: {}", error),
+        }
+    }
+}
+
+impl<E> Error for PrepareError<E> where E: fmt::Debug + fmt::Display {}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Runs `prepare` on the calling thread, for setup that may fail such as opening files or
+    /// binding sockets, then spawns a thread running `run` with the state it produced. A failed
+    /// setup is returned right away instead of surfacing when joining.
+    #[track_caller]
+    pub fn spawn_prepared<S, E, P, F>(name: String, prepare: P, run: F) -> Result<Self, PrepareError<E>> where
+        S: Send + 'static,
+        P: FnOnce() -> Result<S, E>,
+        F: FnOnce(S, InterruptToken) -> T, F: Send + 'static
+    {
+        Builder::new(name).spawn_prepared(prepare, run)
+    }
+}
+
+impl Builder {
+    /// Like `ThreadHandle::spawn_prepared`, with this configuration.
+    #[track_caller]
+    pub fn spawn_prepared<T, S, E, P, F>(&self, prepare: P, run: F) -> Result<ThreadHandle<T>, PrepareError<E>> where
+        T: Send + 'static,
+        S: Send + 'static,
+        P: FnOnce() -> Result<S, E>,
+        F: FnOnce(S, InterruptToken) -> T, F: Send + 'static
+    {
+        let state = prepare().map_err(PrepareError::Prepare)?;
+        self.spawn(move |interrupted| run(state, interrupted)).map_err(PrepareError::Spawn)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::num::ParseIntError;
+    use super::*;
+
+    #[test]
+    fn test_spawn_prepared() {
+        let handle = ThreadHandle::spawn_prepared("Test prepared".to_string(), || "21".parse::<u32>(), |value, _| value * 2).unwrap();
+        assert_eq!(42, handle.join().unwrap().unwrap());
+        let failed: Result<ThreadHandle<u32>, PrepareError<ParseIntError>> = ThreadHandle::spawn_prepared("Test prepare failed".to_string(), || "x".parse::<u32>(), |_, _| panic!("must not run"));
+        match failed {
+            Err(PrepareError::Prepare(error)) => assert_eq!("invalid digit found in string", error.to_string()),
+            _ => panic!("expected a preparation error"),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::io;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+use panics::PanicPayload;
+use sys;
+use {AlreadyTerminated, InterruptOutcome, InterruptToken, ThreadGroup, ThreadHandle, ThreadStatus};
+
+
+/// Real time between two looks at whether the child process exited.
+const POLL: Duration = Duration::from_millis(10);
+
+/// Runnable of the thread watching a child process: it asks the process to terminate when
+/// interrupted, and returns its exit status.
+fn watch(child: Arc<Mutex<Child>>) -> impl FnOnce(InterruptToken) -> io::Result<ExitStatus> {
+    move |interrupted| {
+        let mut termination_requested = false;
+        loop {
+            if let Some(status) = child.lock().unwrap().try_wait()? {
+                return Ok(status);
+            }
+            if !termination_requested && interrupted.is_interrupted() {
+                termination_requested = true;
+                sys::request_termination(&mut child.lock().unwrap())?;
+            }
+            sleep(POLL);
+        }
+    }
+}
+
+/// Kills and waits for a child process left without a watcher, so that it neither keeps running
+/// nor lingers as a zombie.
+fn reap(child: &Mutex<Child>) {
+    let mut child = child.lock().unwrap();
+    child.kill().ok();
+    child.wait().ok();
+}
+
+/// Handle of a child process, watched by a managed thread of the same name, so that it reports
+/// events, can be registered and monitored, and is interrupted like a thread.
+pub struct ProcessHandle {
+    child: Arc<Mutex<Child>>,
+    pid: u32,
+    watcher: ThreadHandle<io::Result<ExitStatus>>,
+}
+
+impl ProcessHandle {
+    #[track_caller]
+    pub fn spawn(name: String, command: &mut Command) -> io::Result<Self> {
+        let child = command.spawn()?;
+        let pid = child.id();
+        let child = Arc::new(Mutex::new(child));
+        match ThreadHandle::spawn(name, watch(child.clone())) {
+            Ok(watcher) => Ok(ProcessHandle { child, pid, watcher }),
+            Err(error) => {
+                reap(&child);
+                Err(error)
+            }
+        }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn name(&self) -> &str {
+        self.watcher.name()
+    }
+
+    /// Handle of the watching thread, for the registry, monitors and everything else handles offer.
+    pub fn watcher(&self) -> &ThreadHandle<io::Result<ExitStatus>> {
+        &self.watcher
+    }
+
+    /// `Terminated` once the process exited.
+    pub fn status(&self) -> ThreadStatus {
+        self.watcher.status()
+    }
+
+    /// Asks the process to terminate (SIGTERM, or CTRL_BREAK on Windows); fails once it exited.
+    pub fn interrupt(&self) -> Result<InterruptOutcome, AlreadyTerminated> {
+        self.watcher.interrupt()
+    }
+
+    /// Kills the process right away.
+    pub fn kill(&self) -> io::Result<()> {
+        self.child.lock().unwrap().kill()
+    }
+
+    /// Waits for the process to exit and returns its exit status; `None` if it was already taken.
+    pub fn join(&self) -> Option<io::Result<ExitStatus>> {
+        self.watcher.join().map(|outcome| outcome.unwrap_or_else(|payload| Err(io::Error::other(payload.describe()))))
+    }
+
+    /// Interrupts the process, kills it if it has not exited within `grace`, and joins it.
+    pub fn shutdown(&self, grace: Duration) -> Option<io::Result<ExitStatus>> {
+        self.interrupt().ok();
+        if !self.watcher.wait_finished(Some(grace)) {
+            // The process may exit between the wait and the kill.
+            self.kill().ok();
+        }
+        self.join()
+    }
+}
+
+impl ThreadGroup<io::Result<ExitStatus>> {
+    /// Starts a child process watched by a thread of the group and returns the index of that
+    /// thread; interrupting it asks the process to terminate.
+    pub fn spawn_process(&mut self, name: String, command: &mut Command) -> io::Result<usize> {
+        let child = Arc::new(Mutex::new(command.spawn()?));
+        let spawned = self.spawn(name, watch(child.clone()));
+        if spawned.is_err() {
+            reap(&child);
+        }
+        spawned
+    }
+}
+
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+    use super::*;
+
+    #[test]
+    fn test_process_exit_status() {
+        let process = ProcessHandle::spawn("Test process exit".to_string(), Command::new("sh").args(["-c", "exit 3"])).unwrap();
+        assert_eq!(Some(3), process.join().unwrap().unwrap().code());
+        assert_eq!(ThreadStatus::Terminated, process.status());
+        assert!(process.interrupt().is_err());
+    }
+
+    #[test]
+    fn test_process_interrupt() {
+        let process = ProcessHandle::spawn("Test process interrupt".to_string(), Command::new("sleep").arg("10")).unwrap();
+        assert_eq!(ThreadStatus::Running, process.status());
+        assert_eq!(Ok(InterruptOutcome::Requested), process.interrupt());
+        assert_eq!(Some(15), process.join().unwrap().unwrap().signal());
+    }
+
+    #[test]
+    fn test_process_shutdown_escalates() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "trap '' TERM; sleep 10"]);
+        let process = ProcessHandle::spawn("Test process shutdown".to_string(), &mut command).unwrap();
+        sleep(Duration::from_millis(100));
+        assert_eq!(Some(9), process.shutdown(Duration::from_millis(100)).unwrap().unwrap().signal());
+    }
+
+    #[test]
+    fn test_group_process() {
+        let mut group = ThreadGroup::new("Test process group".to_string());
+        group.spawn_process("Test process member".to_string(), &mut Command::new("true")).unwrap();
+        assert!(group.join_all().unwrap()[0].as_ref().unwrap().success());
+    }
+}
@@ -0,0 +1,211 @@
+use std::any::Any;
+use std::time::Instant;
+
+use counters::AtomicCounters;
+use {InterruptToken, ThreadGroup, ThreadHandle};
+
+
+/// Latest progress reported by a runnable.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Progress {
+    /// Units of work completed; 0 if the runnable only reported fractions.
+    pub done: u64,
+    /// `None` if the runnable only reported fractions.
+    pub total: Option<u64>,
+    /// Completed share between 0.0 and 1.0, when known.
+    pub fraction: Option<f32>,
+    pub first_reported: Instant,
+    pub updated: Instant,
+}
+
+impl InterruptToken {
+    /// Reports that `done` out of `total` units of work are complete.
+    pub fn report_progress(&self, done: u64, total: u64) {
+        let fraction = if total == 0 { 1.0 } else { done as f32 / total as f32 };
+        self.update_progress(done, Some(total), Some(fraction.min(1.0)));
+    }
+
+    /// Reports progress as a fraction between 0.0 and 1.0, for work not counted in units.
+    pub fn report_fraction(&self, fraction: f32) {
+        self.update_progress(0, None, Some(fraction.clamp(0.0, 1.0)));
+    }
+
+    /// Publishes a snapshot of user-defined state, replacing the previous one.
+    pub fn publish<S>(&self, state: S) where S: Send + Clone + 'static {
+        *self.shared.state.lock().unwrap() = Some(Box::new(state));
+    }
+
+    fn update_progress(&self, done: u64, total: Option<u64>, fraction: Option<f32>) {
+        let now = self.shared.clock.now();
+        AtomicCounters::increment(&self.shared.counters.progress_updates);
+        let mut progress = self.shared.progress.lock().unwrap();
+        let first_reported = progress.map_or(now, |previous| previous.first_reported);
+        *progress = Some(Progress {
+            done,
+            total,
+            fraction,
+            first_reported,
+            updated: now,
+        });
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Latest progress reported through the token, if any.
+    pub fn progress(&self) -> Option<Progress> {
+        *self.shared.progress.lock().unwrap()
+    }
+
+    /// Latest state published through the token, if any was published with type `S`.
+    pub fn latest_state<S>(&self) -> Option<S> where S: Clone + 'static {
+        let state = self.shared.state.lock().unwrap();
+        state.as_ref().and_then(|state| (**state).downcast_ref::<S>()).cloned()
+    }
+}
+
+/// Progress of a whole group, aggregated from what its threads reported.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GroupProgress {
+    /// Units of work completed by all threads.
+    pub done: u64,
+    /// Units of work of all threads, if every thread reported a total.
+    pub total: Option<u64>,
+    /// Completed share of the group: `done` over `total` when every total is known, otherwise
+    /// the mean of the fractions of the threads, counting silent running threads as 0.0 and
+    /// silent terminated ones as 1.0.
+    pub fraction: f32,
+    /// Latest progress of each thread, in index order.
+    pub members: Vec<Option<Progress>>,
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// Aggregated progress of the threads of the group, for driving a single progress bar;
+    /// `None` for an empty group.
+    pub fn progress(&self) -> Option<GroupProgress> {
+        let handles = self.handles();
+        if handles.is_empty() {
+            return None;
+        }
+        let members: Vec<Option<Progress>> = handles.iter().map(|handle| handle.progress()).collect();
+        let done = members.iter().flatten().map(|progress| progress.done).sum();
+        let total = members.iter().map(|progress| progress.and_then(|progress| progress.total)).sum::<Option<u64>>();
+        let fraction = match total {
+            Some(0) => 1.0,
+            Some(total) => done as f32 / total as f32,
+            None => {
+                let fractions = members.iter().zip(handles).map(|(progress, handle)| match progress.and_then(|progress| progress.fraction) {
+                    Some(fraction) => fraction,
+                    None if handle.shared.is_terminated() => 1.0,
+                    None => 0.0,
+                });
+                fractions.sum::<f32>() / handles.len() as f32
+            }
+        };
+        Some(GroupProgress {
+            done,
+            total,
+            fraction: fraction.min(1.0),
+            members,
+        })
+    }
+}
+
+pub(crate) type PublishedState = Option<Box<dyn Any + Send>>;
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let (step, steps) = channel();
+        let (reported, reports) = channel();
+        let handle = ThreadHandle::spawn("Test progress".to_string(), move |interrupted| {
+            for done in 1..4 {
+                steps.recv().unwrap();
+                interrupted.report_progress(done, 4);
+                reported.send(()).unwrap();
+            }
+        }).unwrap();
+        assert_eq!(None, handle.progress());
+        step.send(()).unwrap();
+        reports.recv().unwrap();
+        let first = handle.progress().unwrap();
+        assert_eq!(1, first.done);
+        assert_eq!(Some(0.25), first.fraction);
+        step.send(()).unwrap();
+        step.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+        let last = handle.progress().unwrap();
+        assert_eq!(3, last.done);
+        assert_eq!(Some(4), last.total);
+        assert_eq!(first.first_reported, last.first_reported);
+        assert!(last.updated >= first.updated);
+    }
+
+    #[test]
+    fn test_group_progress() {
+        let mut group = ThreadGroup::new("Test group progress".to_string());
+        assert_eq!(None, group.progress());
+        let (reported, reports) = channel();
+        for (shard, total) in [(0, 10), (1, 30)] {
+            let reported = reported.clone();
+            group.spawn(format!("Test group progress {}", shard), move |interrupted| {
+                interrupted.report_progress(total / 2, total);
+                reported.send(()).unwrap();
+                while !interrupted.is_interrupted() {
+                    ::std::thread::sleep(::std::time::Duration::from_millis(5));
+                }
+            }).unwrap();
+        }
+        reports.recv().unwrap();
+        reports.recv().unwrap();
+        let progress = group.progress().unwrap();
+        assert_eq!((20, Some(40)), (progress.done, progress.total));
+        assert_eq!(0.5, progress.fraction);
+        assert_eq!(Some(15), progress.members[1].map(|member| member.done));
+        group.spawn("Test group progress silent".to_string(), |_| ()).unwrap();
+        group.handles()[2].wait_finished(None);
+        let progress = group.progress().unwrap();
+        assert_eq!(None, progress.total);
+        assert!((progress.fraction - 2.0 / 3.0).abs() < 1e-6);
+        for handle in group.handles() {
+            handle.interrupt().ok();
+        }
+        group.join_all().unwrap();
+    }
+
+    #[test]
+    fn test_progress_fraction() {
+        let token = InterruptToken::new();
+        token.report_fraction(1.5);
+        let progress = token.shared.progress.lock().unwrap().unwrap();
+        assert_eq!((0, None, Some(1.0)), (progress.done, progress.total, progress.fraction));
+    }
+
+    #[derive(Clone, PartialEq, Debug)]
+    struct Connections {
+        open: usize,
+        peer: String,
+    }
+
+    #[test]
+    fn test_latest_state() {
+        let (published, wait) = channel();
+        let handle = ThreadHandle::spawn("Test state".to_string(), move |interrupted| {
+            interrupted.publish(Connections { open: 3, peer: "db".to_string() });
+            published.send(()).unwrap();
+            while !interrupted.is_interrupted() {
+                ::std::thread::sleep(::std::time::Duration::from_millis(20));
+            }
+        }).unwrap();
+        assert_eq!(None, handle.latest_state::<Connections>());
+        wait.recv().unwrap();
+        assert_eq!(Some(Connections { open: 3, peer: "db".to_string() }), handle.latest_state());
+        assert_eq!(None, handle.latest_state::<u32>());
+        handle.interrupt().unwrap();
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::atomic;
+
+use {InterruptToken, Shared, ThreadHandle};
+
+
+impl Shared {
+    // Sequentially consistent, to order with the count of command senders, see `CommandSender`.
+    pub(crate) fn is_quiescing(&self) -> bool {
+        self.quiescing.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Returns whether quiescing had already been requested.
+    pub(crate) fn quiesce(&self) -> bool {
+        self.quiescing.swap(true, atomic::Ordering::SeqCst)
+    }
+}
+
+impl InterruptToken {
+    /// Whether the owner asked the runnable to stop taking new work and exit once it finished
+    /// what it already accepted. Unlike interruption, in-flight work is not abandoned.
+    pub fn is_quiescing(&self) -> bool {
+        self.shared.is_quiescing()
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Asks the runnable to finish the work it already accepted and then exit, without taking
+    /// more; `is_quiescing` reports it until it terminates. The runnable sees the request
+    /// through `InterruptToken::is_quiescing`, and command workers through `Commands::recv`.
+    /// Returns whether it had already been requested.
+    pub fn quiesce(&self) -> bool {
+        self.shared.quiesce()
+    }
+
+    /// Whether the thread is still running but was asked to quiesce. Its status stays `Running`
+    /// until it terminates.
+    pub fn is_quiescing(&self) -> bool {
+        self.shared.is_quiescing() && !self.shared.is_terminated()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use ThreadStatus;
+    use super::*;
+
+    #[test]
+    fn test_quiesce() {
+        let (jobs, queued) = channel();
+        for job in 1..=3 {
+            jobs.send(job).unwrap();
+        }
+        let handle = ThreadHandle::spawn("Test quiesce".to_string(), move |interrupted| {
+            let mut done = Vec::new();
+            while !interrupted.is_quiescing() {
+                ::std::thread::sleep(::std::time::Duration::from_millis(5));
+            }
+            done.extend(queued.try_iter());
+            done
+        }).unwrap();
+        assert_eq!(ThreadStatus::Running, handle.status());
+        assert!(!handle.quiesce());
+        assert!(handle.quiesce());
+        assert_eq!((ThreadStatus::Running, true), (handle.status(), handle.is_quiescing()));
+        assert_eq!(vec![1, 2, 3], handle.join().unwrap().unwrap());
+        assert_eq!((ThreadStatus::Terminated, false), (handle.status(), handle.is_quiescing()));
+    }
+}
@@ -0,0 +1,244 @@
+use std::cell::Cell;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use backend::Job;
+#[cfg(feature = "backtrace")]
+use backtrace;
+use {context, sys};
+
+
+/// OS-level name of the threads parked in the cache, which must not keep showing the name of
+/// the runnable they last ran.
+const IDLE_NAME: &str = "recycled idle";
+
+#[derive(Clone, Copy, Debug)]
+struct Config {
+    max_idle: usize,
+    keep_alive: Duration,
+}
+
+struct IdleWorker {
+    id: u64,
+    jobs: Sender<Job>,
+}
+
+struct Cache {
+    config: Option<Config>,
+    idle: Vec<IdleWorker>,
+    next_id: u64,
+}
+
+/// Cache of parked OS threads waiting for their next job.
+struct Recycler {
+    cache: Mutex<Cache>,
+}
+
+impl Recycler {
+    fn new(config: Option<Config>) -> Self {
+        Recycler {
+            cache: Mutex::new(Cache {
+                config,
+                idle: Vec::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    /// Runs `job` on an idle cached thread if there is one, otherwise on a new OS thread.
+    /// Only threads that are not going to be cached are returned for joining.
+    fn spawn(self: &Arc<Self>, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        let mut job = job;
+        let id = {
+            let mut cache = self.cache.lock().unwrap();
+            if cache.config.is_none() {
+                drop(cache);
+                return thread::Builder::new().name(name).spawn(job).map(Some);
+            }
+            while let Some(worker) = cache.idle.pop() {
+                let renamed = name.clone();
+                let named: Job = Box::new(move || {
+                    sys::set_os_thread_name(&renamed);
+                    reset_thread_locals();
+                    job()
+                });
+                match worker.jobs.send(named) {
+                    Ok(()) => return Ok(None),
+                    Err(mpsc::SendError(returned)) => job = returned,
+                }
+            }
+            cache.next_id += 1;
+            cache.next_id
+        };
+        let recycler = self.clone();
+        thread::Builder::new().name(name).spawn(move || recycler.run_worker(id, job))?;
+        Ok(None)
+    }
+
+    fn run_worker(&self, id: u64, first: Job) {
+        let mut job = first;
+        loop {
+            job();
+            if RETIRED.with(|retired| retired.replace(false)) {
+                return;
+            }
+            let (sender, receiver) = mpsc::channel();
+            let keep_alive = {
+                let mut cache = self.cache.lock().unwrap();
+                match cache.config {
+                    Some(config) if cache.idle.len() < config.max_idle => {
+                        cache.idle.push(IdleWorker { id, jobs: sender });
+                        config.keep_alive
+                    }
+                    _ => return,
+                }
+            };
+            sys::set_os_thread_name(IDLE_NAME);
+            job = match receiver.recv_timeout(keep_alive) {
+                Ok(job) => job,
+                Err(RecvTimeoutError::Timeout) => {
+                    let mut cache = self.cache.lock().unwrap();
+                    if let Some(position) = cache.idle.iter().position(|worker| worker.id == id) {
+                        cache.idle.remove(position);
+                        return;
+                    }
+                    drop(cache);
+                    // Someone took this worker out of the cache just now and is handing it a job.
+                    match receiver.recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+        }
+    }
+
+    fn configure(&self, config: Option<Config>) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.config = config;
+        match config {
+            Some(config) => {
+                let excess = cache.idle.len().saturating_sub(config.max_idle);
+                cache.idle.drain(..excess);
+            }
+            None => cache.idle.clear(),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.cache.lock().unwrap().config.is_some()
+    }
+
+    fn idle(&self) -> usize {
+        self.cache.lock().unwrap().idle.len()
+    }
+}
+
+thread_local! {
+    /// Set when the job running on the thread changed it in a way the next one must not
+    /// inherit, see `retire_current`.
+    static RETIRED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keeps the calling thread out of the cache once its current job is done.
+#[cfg(all(feature = "linux", target_os = "linux"))]
+pub(crate) fn retire_current() {
+    RETIRED.with(|retired| retired.set(true));
+}
+
+/// Clears what the crate keeps in thread-locals, so that a reused thread starts its next
+/// runnable as a new one would.
+fn reset_thread_locals() {
+    #[cfg(feature = "backtrace")]
+    backtrace::disarm();
+    context::clear();
+}
+
+fn global() -> &'static Arc<Recycler> {
+    static GLOBAL: OnceLock<Arc<Recycler>> = OnceLock::new();
+    GLOBAL.get_or_init(|| Arc::new(Recycler::new(None)))
+}
+
+pub(crate) fn spawn(name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+    global().spawn(name, job)
+}
+
+/// Whether finished threads are kept for reuse.
+pub(crate) fn is_enabled() -> bool {
+    global().is_enabled()
+}
+
+/// Makes `ThreadHandle::spawn` reuse finished threads: up to `max_idle` of them are parked for
+/// `keep_alive` waiting for the next runnable. Handles behave the same, except that
+/// `thread::current().name()` inside a reused thread reports the name it was created with
+/// (the OS-level name is updated where supported, and reset while parked) and thread-locals
+/// other than the crate's own persist between runnables.
+pub fn enable_recycling(max_idle: usize, keep_alive: Duration) {
+    global().configure(Some(Config { max_idle, keep_alive }));
+}
+
+/// Stops reusing threads and lets the parked ones exit.
+pub fn disable_recycling() {
+    global().configure(None);
+}
+
+/// Number of threads currently parked in the recycling cache.
+pub fn idle_threads() -> usize {
+    global().idle()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use test_util::assert_eventually;
+    use super::*;
+
+    fn run(recycler: &Arc<Recycler>, name: &str) -> thread::ThreadId {
+        let (sender, receiver) = channel();
+        recycler.spawn(name.to_string(), Box::new(move || {
+            sender.send(thread::current().id()).unwrap();
+        })).unwrap();
+        let id = receiver.recv().unwrap();
+        // `spawn` took any idle worker out of the cache, so one is back once the job is done.
+        assert_eventually(Duration::from_secs(5), "worker parked", || recycler.idle() >= 1);
+        id
+    }
+
+    #[test]
+    fn test_recycler_reuses_threads() {
+        let recycler = Arc::new(Recycler::new(Some(Config {
+            max_idle: 2,
+            keep_alive: Duration::from_secs(5),
+        })));
+        let first = run(&recycler, "Test recycle first");
+        assert_eq!(1, recycler.idle());
+        let second = run(&recycler, "Test recycle second");
+        assert_eq!(first, second);
+        recycler.configure(None);
+        assert_eq!(0, recycler.idle());
+    }
+
+    #[test]
+    fn test_recycler_keep_alive() {
+        let recycler = Arc::new(Recycler::new(Some(Config {
+            max_idle: 2,
+            keep_alive: Duration::from_millis(100),
+        })));
+        run(&recycler, "Test recycle keep alive");
+        assert_eq!(1, recycler.idle());
+        assert_eventually(Duration::from_secs(5), "idle worker exited", || recycler.idle() == 0);
+    }
+
+    #[test]
+    fn test_recycler_disabled_spawns_joinable_threads() {
+        let recycler = Arc::new(Recycler::new(None));
+        let join_handle = recycler.spawn("Test recycle disabled".to_string(), Box::new(|| ())).unwrap();
+        assert!(join_handle.unwrap().join().is_ok());
+        assert_eq!(0, recycler.idle());
+    }
+}
@@ -0,0 +1,450 @@
+//! Process-wide registry of handles that opted in with `ThreadHandle::register`.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic;
+use std::time::{Duration, Instant};
+
+use clock::WaitBudget;
+use namespace;
+use shutdown;
+use {HandleId, Shared, ShutdownFuture, Snapshot, SpawnOrigin, ThreadHandle, ThreadStatus};
+
+
+/// What `register_with_policy` does when a live entry already uses the thread's name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NamePolicy {
+    AllowDuplicates,
+    /// Fail with `AlreadyExists`.
+    Reject,
+    /// Register under the name followed by `-2`, `-3`, ... until one is free.
+    Suffix,
+    /// Replace the entry if its thread has terminated, fail with `AlreadyExists` otherwise.
+    ReplaceTerminated,
+}
+
+#[derive(Clone)]
+pub(crate) struct Entry {
+    pub(crate) name: String,
+    pub(crate) shared: Arc<Shared>,
+    /// Left out of `wait_for_all_terminated`.
+    detached: bool,
+}
+
+struct Registry {
+    entries: Vec<Entry>,
+}
+
+static REGISTERED: Mutex<Registry> = Mutex::new(Registry { entries: Vec::new() });
+
+pub(crate) fn registered() -> Vec<Entry> {
+    REGISTERED.lock().unwrap().entries.clone()
+}
+
+fn collision(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::AlreadyExists, format!("a thread named {} is already registered", name))
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Adds the handle to the process-wide registry. Registered threads stay listed after they
+    /// terminate until they are joined, so that unjoined threads can be reported.
+    pub fn register(&self) {
+        self.register_entry(NamePolicy::AllowDuplicates, false).ok();
+    }
+
+    /// Like `register`, but `wait_for_all_terminated` does not wait for the thread.
+    pub fn register_detached(&self) {
+        self.register_entry(NamePolicy::AllowDuplicates, true).ok();
+    }
+
+    /// Like `register`, resolving a collision with the name of a live entry as `policy` says;
+    /// returns the name the handle is registered under.
+    pub fn register_with_policy(&self, policy: NamePolicy) -> io::Result<String> {
+        self.register_entry(policy, false)
+    }
+
+    fn register_entry(&self, policy: NamePolicy, detached: bool) -> io::Result<String> {
+        prune();
+        let mut registry = REGISTERED.lock().unwrap();
+        if let Some(entry) = registry.entries.iter().find(|entry| Arc::ptr_eq(&entry.shared, &self.shared)) {
+            return Ok(entry.name.clone());
+        }
+        let taken = |entries: &[Entry], name: &str| entries.iter().position(|entry| entry.name == name);
+        let mut name = self.name().to_string();
+        match (policy, taken(&registry.entries, &name)) {
+            (_, None) | (NamePolicy::AllowDuplicates, _) => (),
+            (NamePolicy::Reject, Some(_)) => return Err(collision(&name)),
+            (NamePolicy::Suffix, Some(_)) => {
+                let mut counter = 2;
+                while taken(&registry.entries, &format!("{}-{}", self.name(), counter)).is_some() {
+                    counter += 1;
+                }
+                name = format!("{}-{}", self.name(), counter);
+            }
+            (NamePolicy::ReplaceTerminated, Some(index)) => {
+                if !registry.entries[index].shared.is_terminated() {
+                    return Err(collision(&name));
+                }
+                registry.entries.remove(index);
+            }
+        }
+        registry.entries.push(Entry {
+            name: name.clone(),
+            shared: self.shared.clone(),
+            detached,
+        });
+        Ok(name)
+    }
+
+    pub fn unregister(&self) {
+        REGISTERED.lock().unwrap().entries.retain(|entry| !Arc::ptr_eq(&entry.shared, &self.shared));
+    }
+}
+
+/// Names of the registered threads, dropping those that terminated and were joined.
+pub fn names() -> Vec<String> {
+    prune();
+    registered().into_iter().map(|entry| entry.name).collect()
+}
+
+/// Current state of every registered thread, dropping those that terminated and were joined;
+/// compare two of them with `Snapshot::diff`.
+pub fn snapshot() -> Vec<Snapshot> {
+    prune();
+    registered().into_iter().map(|entry| entry.shared.snapshot()).collect()
+}
+
+/// Names of the registered threads in `namespace` or below it, see `ThreadGroup::namespaced`.
+pub fn names_in(namespace: &str) -> Vec<String> {
+    names().into_iter().filter(|name| namespace::contains(namespace, name)).collect()
+}
+
+/// Current state of the registered threads in `namespace` or below it.
+pub fn snapshot_in(namespace: &str) -> Vec<Snapshot> {
+    snapshot().into_iter().filter(|snapshot| namespace::contains(namespace, &snapshot.name)).collect()
+}
+
+/// The registered threads as a tree of their namespaces, see `namespace::render_tree`.
+pub fn dump_tree() -> String {
+    namespace::render_tree(&snapshot())
+}
+
+/// Requests interruption of the running threads registered under `name`; returns how many.
+pub fn interrupt(name: &str) -> usize {
+    interrupt_where(|registered| registered == name)
+}
+
+/// Requests interruption of the running threads whose registered name starts with `prefix`;
+/// returns how many.
+pub fn interrupt_matching(prefix: &str) -> usize {
+    interrupt_where(|registered| registered.starts_with(prefix))
+}
+
+/// Requests interruption of the running threads in `namespace` or below it; returns how many.
+pub fn interrupt_namespace(namespace: &str) -> usize {
+    interrupt_where(|registered| namespace::contains(namespace, registered))
+}
+
+fn interrupt_where<F>(matches: F) -> usize where F: Fn(&str) -> bool {
+    let mut interrupted = 0;
+    for entry in registered().into_iter().filter(|entry| matches(&entry.name) && !entry.shared.is_terminated()) {
+        entry.shared.request_interrupt(Some("interrupted through the registry"));
+        interrupted += 1;
+    }
+    interrupted
+}
+
+/// Status of the thread registered under `name`; with duplicate names, of the first registered.
+pub fn status_of(name: &str) -> Option<ThreadStatus> {
+    prune();
+    registered().into_iter().find(|entry| entry.name == name).map(|entry| entry.shared.status())
+}
+
+/// Blocks until every registered thread that is not detached has terminated, including threads
+/// registered while waiting. Returns `false` if `timeout` elapsed first, the time spent waiting
+/// for each thread counted on its clock.
+pub fn wait_for_all_terminated(timeout: Option<Duration>) -> bool {
+    let mut budget = WaitBudget::new(timeout);
+    loop {
+        let running: Vec<_> = registered().into_iter().filter(|entry| !entry.detached && !entry.shared.is_terminated()).collect();
+        if running.is_empty() {
+            return true;
+        }
+        for entry in running {
+            if !budget.spend(&*entry.shared.clock, |remaining| entry.shared.wait_terminated(remaining)) {
+                return false;
+            }
+        }
+    }
+}
+
+/// Interrupts the registered threads that are not detached one shutdown class at a time, lowest
+/// first, waiting up to `timeout` for each class; returns the names of the threads still running
+/// when their class timed out.
+pub fn shutdown(timeout: Option<Duration>) -> Vec<String> {
+    let entries = registered();
+    shutdown::shutdown_by_class(entries.iter().filter(|entry| !entry.detached).map(|entry| &entry.shared).collect(), timeout)
+}
+
+/// Like `shutdown`, for async main functions: the threads are interrupted and waited for by a
+/// helper thread, so no worker of the runtime blocks on them.
+pub fn shutdown_async(timeout: Option<Duration>) -> io::Result<ShutdownFuture> {
+    ShutdownFuture::spawn(move || shutdown(timeout))
+}
+
+/// Number of registered handles, dropping those that terminated and were joined.
+pub fn len() -> usize {
+    prune();
+    REGISTERED.lock().unwrap().entries.len()
+}
+
+fn prune() {
+    REGISTERED.lock().unwrap().entries.retain(|entry| !(entry.shared.is_terminated() && entry.shared.joined.load(atomic::Ordering::Acquire)));
+}
+
+/// Forgets every registered thread that has terminated, joined or not.
+pub fn clear_terminated() {
+    REGISTERED.lock().unwrap().entries.retain(|entry| !entry.shared.is_terminated());
+}
+
+/// Limits beyond which `diagnose` reports a thread.
+#[derive(Clone, Copy, Debug)]
+pub struct Thresholds {
+    /// Running without a heartbeat for longer than this.
+    pub stale_after: Duration,
+    /// Still running this long after interruption was requested.
+    pub interrupt_grace: Duration,
+    /// Terminated this long ago without anybody joining it.
+    pub unjoined_after: Duration,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            stale_after: Duration::from_secs(30),
+            interrupt_grace: Duration::from_secs(10),
+            unjoined_after: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Finding {
+    Stale { silence: Duration },
+    IgnoringInterrupt { requested: Duration },
+    Unjoined { terminated: Duration },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    pub id: HandleId,
+    pub name: String,
+    pub finding: Finding,
+    pub origin: Option<SpawnOrigin>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub generated: Instant,
+    pub inspected: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn is_healthy(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+fn inspect(shared: &Shared, thresholds: &Thresholds) -> Vec<Finding> {
+    let now = shared.clock.now();
+    let mut findings = Vec::new();
+    if shared.is_terminated() {
+        let terminated = shared.finished.get(shared.created).map_or(Duration::from_secs(0), |at| now.saturating_duration_since(at));
+        if !shared.joined.load(atomic::Ordering::Acquire) && terminated > thresholds.unjoined_after {
+            findings.push(Finding::Unjoined { terminated });
+        }
+        return findings;
+    }
+    if let Some(silence) = shared.silence(now) {
+        if silence > thresholds.stale_after {
+            findings.push(Finding::Stale { silence });
+        }
+    }
+    if let Some(at) = shared.interrupt_requested.get(shared.created) {
+        let requested = now.saturating_duration_since(at);
+        if requested > thresholds.interrupt_grace {
+            findings.push(Finding::IgnoringInterrupt { requested });
+        }
+    }
+    findings
+}
+
+/// Inspects every registered thread and reports the stale, the ones not reacting to
+/// interruption, and the terminated ones nobody joined.
+pub fn diagnose(thresholds: Thresholds) -> Report {
+    prune();
+    let registered = registered();
+    let now = Instant::now();
+    let diagnostics = registered.iter().flat_map(|entry| {
+        inspect(&entry.shared, &thresholds).into_iter().map(move |finding| Diagnostic {
+            id: entry.shared.id,
+            name: entry.name.clone(),
+            finding,
+            origin: entry.shared.spawn_origin(),
+        })
+    }).collect();
+    Report {
+        generated: now,
+        inspected: registered.len(),
+        diagnostics,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread::sleep;
+    use test_util::assert_eventually;
+    use {Builder, Clock, MockClock};
+    use super::*;
+
+    fn findings_for(report: &Report, name: &str) -> Vec<Finding> {
+        report.diagnostics.iter().filter(|diagnostic| diagnostic.name == name).map(|diagnostic| diagnostic.finding.clone()).collect()
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let thresholds = Thresholds {
+            stale_after: Duration::from_millis(100),
+            interrupt_grace: Duration::from_millis(100),
+            unjoined_after: Duration::from_millis(100),
+        };
+        let clock = Arc::new(MockClock::new());
+        let (release, released) = mpsc::channel::<()>();
+        let (sender, started) = mpsc::channel();
+        let stubborn = Builder::new("Test diagnose stubborn".to_string()).clock(clock.clone()).spawn(move |_| {
+            sender.send(()).unwrap();
+            released.recv().ok();
+        }).unwrap();
+        let unjoined = Builder::new("Test diagnose unjoined".to_string()).clock(clock.clone()).spawn(|_| ()).unwrap();
+        let healthy = Builder::new("Test diagnose healthy".to_string()).clock(clock.clone()).spawn(|interrupted| {
+            while !interrupted.is_interrupted() {
+                interrupted.heartbeat();
+                sleep(Duration::from_millis(1));
+            }
+        }).unwrap();
+        stubborn.register();
+        unjoined.register();
+        healthy.register();
+        started.recv().unwrap();
+        assert!(unjoined.wait_finished(Some(Duration::from_secs(5))));
+        stubborn.interrupt().unwrap();
+        clock.advance(Duration::from_millis(300));
+        assert_eventually(Duration::from_secs(5), "heartbeat after advancing", || healthy.last_heartbeat() == Some(clock.now()));
+
+        let report = diagnose(thresholds);
+        let stubborn_findings = findings_for(&report, "Test diagnose stubborn");
+        assert_eq!(2, stubborn_findings.len());
+        match findings_for(&report, "Test diagnose unjoined")[..] {
+            [Finding::Unjoined { .. }] => (),
+            ref other => panic!("unexpected findings {:?}", other),
+        }
+        assert!(findings_for(&report, "Test diagnose healthy").is_empty());
+
+        healthy.interrupt().unwrap();
+        drop(release);
+        for handle in [&stubborn, &unjoined, &healthy].iter() {
+            handle.join().unwrap().unwrap();
+        }
+        let report = diagnose(thresholds);
+        assert!(findings_for(&report, "Test diagnose unjoined").is_empty());
+        assert!(!registered().iter().any(|entry| Arc::ptr_eq(&entry.shared, &unjoined.shared)));
+    }
+
+    #[test]
+    fn test_unregister() {
+        let handle = ThreadHandle::spawn("Test unregister".to_string(), |_| ()).unwrap();
+        handle.register();
+        handle.register();
+        assert_eq!(1, registered().iter().filter(|entry| Arc::ptr_eq(&entry.shared, &handle.shared)).count());
+        handle.unregister();
+        assert!(!registered().iter().any(|entry| Arc::ptr_eq(&entry.shared, &handle.shared)));
+    }
+
+    #[test]
+    fn test_name_policy() {
+        let spawn = || ThreadHandle::spawn("Test name policy".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+        }).unwrap();
+        let (first, second, third) = (spawn(), spawn(), spawn());
+        assert_eq!("Test name policy", first.register_with_policy(NamePolicy::Suffix).unwrap());
+        assert_eq!("Test name policy-2", second.register_with_policy(NamePolicy::Suffix).unwrap());
+        assert_eq!("Test name policy-2", second.register_with_policy(NamePolicy::Reject).unwrap());
+        assert_eq!(io::ErrorKind::AlreadyExists, third.register_with_policy(NamePolicy::Reject).unwrap_err().kind());
+        assert!(third.register_with_policy(NamePolicy::ReplaceTerminated).is_err());
+        first.interrupt().unwrap();
+        first.shared.wait_terminated(None);
+        assert_eq!("Test name policy", third.register_with_policy(NamePolicy::ReplaceTerminated).unwrap());
+        assert!(!registered().iter().any(|entry| Arc::ptr_eq(&entry.shared, &first.shared)));
+        for handle in [&first, &second, &third].iter() {
+            handle.interrupt().ok();
+            handle.join().unwrap().unwrap();
+            handle.unregister();
+        }
+    }
+
+    #[test]
+    fn test_operations_by_name() {
+        let spawn = |name: &str| {
+            let handle = ThreadHandle::spawn(name.to_string(), |interrupted| {
+                while !interrupted.is_interrupted() {
+                    sleep(Duration::from_millis(10));
+                }
+            }).unwrap();
+            handle.register();
+            handle
+        };
+        let indexer = spawn("Test by name indexer");
+        let crawlers = [spawn("Test by name crawler 1"), spawn("Test by name crawler 2")];
+        assert_eq!(Some(ThreadStatus::Running), status_of("Test by name indexer"));
+        assert_eq!(None, status_of("Test by name missing"));
+        assert_eq!(2, interrupt_matching("Test by name crawler"));
+        for crawler in crawlers.iter() {
+            crawler.shared.wait_terminated(None);
+        }
+        assert_eq!(0, interrupt_matching("Test by name crawler"));
+        assert_eq!(Some(ThreadStatus::Terminated), status_of("Test by name crawler 1"));
+        assert_eq!(1, interrupt("Test by name indexer"));
+        indexer.join().unwrap().unwrap();
+        assert_eq!(Some("interrupted through the registry"), indexer.interrupt_reason());
+        assert_eq!(None, status_of("Test by name indexer"));
+        for crawler in crawlers.iter() {
+            crawler.join().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let mut ingest = ::ThreadGroup::new("Test namespaces ingest".to_string()).namespaced();
+        for name in ["parser-1", "parser-2"].iter() {
+            ingest.spawn(name.to_string(), |interrupted: ::InterruptToken| {
+                while !interrupted.is_interrupted() {
+                    sleep(Duration::from_millis(10));
+                }
+            }).unwrap();
+        }
+        for handle in ingest.handles() {
+            handle.register();
+        }
+        assert_eq!(vec!["Test namespaces ingest/parser-1", "Test namespaces ingest/parser-2"], names_in("Test namespaces ingest"));
+        assert_eq!(2, snapshot_in("Test namespaces ingest/").len());
+        assert!(names_in("Test namespaces").is_empty());
+        assert!(dump_tree().contains("Test namespaces ingest/\n  parser-1 (Running)\n  parser-2 (Running)\n"));
+        assert_eq!(2, interrupt_namespace("Test namespaces ingest"));
+        assert!(ingest.join_all().is_ok());
+    }
+}
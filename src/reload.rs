@@ -0,0 +1,192 @@
+use std::any::Any;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::sync::atomic;
+
+use events::{self, ThreadEvent};
+use {InterruptToken, Shared, ThreadHandle};
+
+
+/// Produces the runnables of a supervised thread after `ThreadHandle::reload`.
+pub(crate) type Factory<T, E> = Box<dyn FnMut() -> Box<dyn FnOnce(InterruptToken) -> Result<T, E>> + Send>;
+
+/// Reloads requested for a supervised thread.
+#[derive(Default)]
+pub(crate) struct Reloads {
+    /// Whether the thread runs `spawn_supervised`, which picks up reloads until it exits.
+    supervised: bool,
+    exited: bool,
+    /// `Factory` of the next incarnation, until the current one exits.
+    pending: Option<Box<dyn Any + Send>>,
+    /// Whether the current incarnation is asked to exit and be started again from the same
+    /// factory, see `Shared::request_restart`.
+    restart: bool,
+    done: u64,
+}
+
+impl Reloads {
+    fn is_open(&self) -> bool {
+        self.supervised && !self.exited
+    }
+}
+
+/// Stops accepting reloads when the supervised loop exits, including by panicking.
+pub(crate) struct ReloadScope<'a> {
+    shared: &'a Shared,
+}
+
+impl<'a> Drop for ReloadScope<'a> {
+    fn drop(&mut self) {
+        let (ref lock, ref condvar) = self.shared.reloads;
+        let mut reloads = lock.lock().unwrap();
+        reloads.exited = true;
+        reloads.pending = None;
+        reloads.restart = false;
+        self.shared.reloading.store(false, atomic::Ordering::Release);
+        condvar.notify_all();
+    }
+}
+
+impl Shared {
+    /// Marks the thread as supervised as soon as it is spawned, so that it can be reloaded
+    /// before its loop starts.
+    pub(crate) fn mark_supervised(&self) {
+        self.reloads.0.lock().unwrap().supervised = true;
+    }
+
+    pub(crate) fn accept_reloads(&self) -> ReloadScope<'_> {
+        self.mark_supervised();
+        ReloadScope { shared: self }
+    }
+
+    /// Whether the current incarnation is asked to exit for a reload, which its token reports
+    /// as interruption.
+    pub(crate) fn is_reloading(&self) -> bool {
+        self.reloading.load(atomic::Ordering::Acquire)
+    }
+
+    /// Takes the factory passed to `reload` while the incarnation that just exited ran.
+    pub(crate) fn take_reload<T, E>(&self) -> Option<Factory<T, E>> where T: 'static, E: 'static {
+        let (ref lock, ref condvar) = self.reloads;
+        let mut reloads = lock.lock().unwrap();
+        let pending = reloads.pending.take()?;
+        reloads.restart = false;
+        reloads.done += 1;
+        let done = reloads.done;
+        self.reloading.store(false, atomic::Ordering::Release);
+        condvar.notify_all();
+        drop(reloads);
+        events::publish(self, || ThreadEvent::Reloaded { reloads: done });
+        pending.downcast::<Factory<T, E>>().ok().map(|factory| *factory)
+    }
+
+    /// Asks the current incarnation of a supervised thread to exit, as for a reload, so that a
+    /// new one is started from the same factory. Returns `false` if the thread is not
+    /// supervised or has terminated.
+    pub(crate) fn request_restart(&self) -> bool {
+        let mut reloads = self.reloads.0.lock().unwrap();
+        if !reloads.is_open() {
+            return false;
+        }
+        reloads.restart = true;
+        self.reloading.store(true, atomic::Ordering::Release);
+        true
+    }
+
+    /// Takes a restart requested while the incarnation that just exited ran.
+    pub(crate) fn take_restart(&self) -> bool {
+        let mut reloads = self.reloads.0.lock().unwrap();
+        let restart = mem::replace(&mut reloads.restart, false);
+        if restart && reloads.pending.is_none() {
+            self.reloading.store(false, atomic::Ordering::Release);
+        }
+        restart
+    }
+}
+
+impl<T, E> ThreadHandle<Result<T, E>> where T: Send + 'static, E: fmt::Debug + Send + 'static {
+    /// Replaces the runnables of a thread spawned with `spawn_supervised`, e.g. to apply a new
+    /// configuration without a restart: the current runnable sees interruption, and once it
+    /// returned, runnables from `factory` run under the same handle. Blocks until then; fails
+    /// with `Unsupported` if the thread is not supervised or has terminated.
+    pub fn reload<F, R>(&self, mut factory: F) -> io::Result<()> where
+        F: FnMut() -> R, F: Send + 'static,
+        R: FnOnce(InterruptToken) -> Result<T, E>, R: 'static
+    {
+        let factory: Factory<T, E> = Box::new(move || Box::new(factory()));
+        let (ref lock, ref condvar) = self.shared.reloads;
+        let mut reloads = lock.lock().unwrap();
+        while reloads.is_open() && reloads.pending.is_some() {
+            reloads = condvar.wait(reloads).unwrap();
+        }
+        if !reloads.is_open() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "only running supervised threads can be reloaded"));
+        }
+        let target = reloads.done + 1;
+        reloads.pending = Some(Box::new(factory));
+        self.shared.reloading.store(true, atomic::Ordering::Release);
+        while reloads.is_open() && reloads.done < target {
+            reloads = condvar.wait(reloads).unwrap();
+        }
+        if reloads.done < target {
+            return Err(io::Error::other("supervised thread terminated before reloading"));
+        }
+        Ok(())
+    }
+
+    /// Number of times the runnables were replaced with `reload`.
+    pub fn reload_count(&self) -> u64 {
+        self.shared.reloads.0.lock().unwrap().done
+    }
+}
+
+
+#[cfg(all(test, not(feature = "minimal")))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use events::{self, Event};
+    use RestartPolicy;
+    use super::*;
+
+    fn serve(config: &'static str) -> impl FnOnce(InterruptToken) -> Result<&'static str, ()> {
+        move |interrupted: InterruptToken| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+            Ok(config)
+        }
+    }
+
+    #[test]
+    fn test_reload() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let record = events.clone();
+        let hook = events::subscribe(Arc::new(move |event: &Event| {
+            if event.thread == "Test reload" {
+                if let ThreadEvent::Reloaded { reloads } = event.event {
+                    record.lock().unwrap().push(reloads);
+                }
+            }
+        }));
+        let handle = ThreadHandle::spawn_supervised("Test reload".to_string(), RestartPolicy::new(), || serve("v1")).unwrap();
+        let id = handle.id();
+        handle.reload(|| serve("v2")).unwrap();
+        assert_eq!(1, handle.reload_count());
+        assert_eq!(0, handle.restart_count());
+        assert!(!handle.is_interrupt_requested());
+        handle.interrupt().unwrap();
+        assert_eq!(Ok("v2"), handle.join().unwrap().unwrap());
+        assert_eq!(id, handle.id());
+        events::unsubscribe(hook);
+        assert_eq!(vec![1], *events.lock().unwrap());
+        assert_eq!(io::ErrorKind::Unsupported, handle.reload(|| serve("v3")).unwrap_err().kind());
+
+        let plain = ThreadHandle::spawn_fallible("Test reload unsupervised".to_string(), serve("v1")).unwrap();
+        assert_eq!(io::ErrorKind::Unsupported, plain.reload(|| serve("v2")).unwrap_err().kind());
+        plain.interrupt().unwrap();
+        assert!(plain.join().unwrap().is_ok());
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use events::{Event, Subscriber};
+use HandleId;
+
+
+/// Event as recorded by an `EventRecorder`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RecordedEvent {
+    /// Time since the recorder was created, from a monotonic clock.
+    pub offset: Duration,
+    pub id: HandleId,
+    pub thread: String,
+    pub group: Option<String>,
+    /// Debug rendering of the `ThreadEvent`.
+    pub event: String,
+}
+
+impl RecordedEvent {
+    /// Tab-separated line of the log file, without the newline.
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}", self.offset.as_nanos(), self.id.0, escape(&self.thread),
+            self.group.as_deref().map(escape).unwrap_or_default(), escape(&self.event))
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(5, '\t');
+        let nanos: u64 = fields.next()?.parse().ok()?;
+        let id = fields.next()?.parse().ok()?;
+        let thread = fields.next()?.to_string();
+        let group = Some(fields.next()?).filter(|group| !group.is_empty()).map(str::to_string);
+        let event = fields.next()?.to_string();
+        Some(RecordedEvent {
+            offset: Duration::from_nanos(nanos),
+            id: HandleId(id),
+            thread,
+            group,
+            event,
+        })
+    }
+}
+
+/// Keeps the fields of a line apart.
+fn escape(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+impl fmt::Display for RecordedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "+{:.3}ms {} {}", self.offset.as_secs_f64() * 1e3, self.id, self.thread)?;
+        if let Some(ref group) = self.group {
+            write!(f, " [{}]", group)?;
+        }
+        write!(f, " {}", self.event)
+    }
+}
+
+/// Subscriber keeping the last events of every thread, and optionally appending all of them to
+/// a file, to replay which thread did what and when after a shutdown hangs. Subscribe it with
+/// `events::subscribe`.
+pub struct EventRecorder {
+    created: Instant,
+    capacity: usize,
+    recent: Mutex<VecDeque<RecordedEvent>>,
+    file: Option<Mutex<File>>,
+}
+
+impl EventRecorder {
+    /// Recorder keeping the last `capacity` events in memory.
+    pub fn new(capacity: usize) -> Self {
+        EventRecorder {
+            created: Instant::now(),
+            capacity,
+            recent: Mutex::new(VecDeque::with_capacity(capacity)),
+            file: None,
+        }
+    }
+
+    /// Recorder also appending every event to the file at `path`, a line each, for
+    /// `Timeline::read`. Lines are written as events happen, so a killed process keeps them.
+    pub fn with_file<P: AsRef<Path>>(capacity: usize, path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(EventRecorder {
+            file: Some(Mutex::new(file)),
+            ..EventRecorder::new(capacity)
+        })
+    }
+
+    /// Events kept in memory, oldest first.
+    pub fn timeline(&self) -> Timeline {
+        Timeline {
+            events: self.recent.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+impl Subscriber for EventRecorder {
+    fn on_event(&self, event: &Event) {
+        let recorded = RecordedEvent {
+            offset: self.created.elapsed(),
+            id: event.id,
+            thread: event.thread.clone(),
+            group: event.group.clone(),
+            event: format!("{:?}", event.event),
+        };
+        if let Some(ref file) = self.file {
+            // Losing the log must not break the threads observed.
+            writeln!(file.lock().unwrap(), "{}", recorded.to_line()).ok();
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(recorded);
+    }
+}
+
+impl fmt::Debug for EventRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventRecorder")
+            .field("capacity", &self.capacity)
+            .field("recorded", &self.recent.lock().unwrap().len())
+            .field("file", &self.file.is_some())
+            .finish()
+    }
+}
+
+/// Recorded events in the order they happened, rendered a line each.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Timeline {
+    pub events: Vec<RecordedEvent>,
+}
+
+impl Timeline {
+    /// Reads a log written by `EventRecorder::with_file`.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut events = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            match RecordedEvent::from_line(&line) {
+                Some(event) => events.push(event),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("malformed event: {}", line))),
+            }
+        }
+        Ok(Timeline { events })
+    }
+
+    /// Events of the thread with handle `id`.
+    pub fn of(&self, id: HandleId) -> impl Iterator<Item = &RecordedEvent> {
+        self.events.iter().filter(move |event| event.id == id)
+    }
+}
+
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(all(test, not(feature = "minimal")))]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::sync::Arc;
+    use events;
+    use ThreadHandle;
+    use super::*;
+
+    #[test]
+    fn test_event_recorder() {
+        let path = env::temp_dir().join(format!("thread-handle-replay-{}.log", ::std::process::id()));
+        let recorder = Arc::new(EventRecorder::with_file(64, &path).unwrap());
+        let subscription = events::subscribe(recorder.clone());
+        let handle = ThreadHandle::spawn("Test replay\tlog".to_string(), |_| ()).unwrap();
+        handle.join().unwrap().unwrap();
+        events::unsubscribe(subscription);
+
+        let timeline = recorder.timeline();
+        let recorded: Vec<_> = timeline.of(handle.id()).map(|event| event.event.as_str()).collect();
+        assert_eq!("Spawned", recorded[0]);
+        assert!(recorded.last().unwrap().starts_with("Terminated"));
+        assert!(timeline.to_string().contains(&format!("{} Test replay\tlog Spawned", handle.id())));
+
+        let replayed = Timeline::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let thread: Vec<_> = replayed.of(handle.id()).collect();
+        assert_eq!(recorded.len(), thread.len());
+        assert_eq!("Test replay log", thread[0].thread);
+        assert!(thread.windows(2).all(|pair| pair[0].offset <= pair[1].offset));
+    }
+}
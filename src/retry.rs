@@ -0,0 +1,343 @@
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use backend::{Job, SpawnBackend};
+use clock::{self, Clock};
+use counters::AtomicCounters;
+use lifecycle::ServiceState;
+use panics::PanicPayload;
+use {InterruptToken, Shared, ThreadHandle};
+
+
+/// How many times a job is attempted and how long to wait between attempts.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    multiplier: u32,
+    max_backoff: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: cmp::max(max_attempts, 1),
+            initial_backoff: Duration::from_millis(100),
+            multiplier: 2,
+            max_backoff: Duration::from_secs(30),
+            clock: clock::system(),
+        }
+    }
+
+    pub fn backoff(mut self, initial: Duration) -> Self {
+        self.initial_backoff = initial;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn max_backoff(mut self, max: Duration) -> Self {
+        self.max_backoff = max;
+        self
+    }
+
+    /// Clock measuring attempts and backoff delays.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Delay to wait after the given (1-based) failed attempt.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let mut delay = self.initial_backoff;
+        for _ in 1..attempt {
+            delay = match delay.checked_mul(self.multiplier) {
+                Some(next) if next < self.max_backoff => next,
+                _ => return self.max_backoff,
+            };
+        }
+        cmp::min(delay, self.max_backoff)
+    }
+}
+
+type SpawnFailureHook = Arc<dyn Fn(&io::Error, u32) + Send + Sync>;
+
+/// Retrying of thread creation configured with `Builder::retry_spawn`.
+#[derive(Clone)]
+pub(crate) struct SpawnRetry {
+    pub(crate) policy: RetryPolicy,
+    pub(crate) on_failure: SpawnFailureHook,
+}
+
+/// Spawns `job` on `backend`, retrying as long as the policy allows when the OS is temporarily
+/// out of resources (`EAGAIN`); other errors are returned right away.
+pub(crate) fn spawn(backend: &dyn SpawnBackend, name: String, job: Job, retry: Option<&SpawnRetry>) -> io::Result<Option<JoinHandle<()>>> {
+    let retry = match retry {
+        Some(retry) => retry,
+        None => return backend.spawn(name, job),
+    };
+    // A failed spawn drops the job it was given, so each attempt gets a closure borrowing it.
+    let job = Arc::new(Mutex::new(Some(job)));
+    let mut number = 0;
+    loop {
+        number += 1;
+        let attempt = job.clone();
+        let spawned = backend.spawn(name.clone(), Box::new(move || {
+            if let Some(job) = attempt.lock().unwrap().take() {
+                job();
+            }
+        }));
+        match spawned {
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock && number < retry.policy.max_attempts => {
+                (retry.on_failure)(error, number);
+                let deadline = retry.policy.clock.now() + retry.policy.delay(number);
+                clock::sleep_until(&*retry.policy.clock, deadline, || false);
+            }
+            spawned => return spawned,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AttemptOutcome {
+    Succeeded,
+    Failed(String),
+    Panicked(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Attempt {
+    pub number: u32,
+    pub started: Instant,
+    pub duration: Duration,
+    pub outcome: AttemptOutcome,
+}
+
+impl Shared {
+    /// Forgets what the previous runnable of a retried or supervised thread reported, so that the
+    /// next one starts out like a new thread: starting, without progress, and with its silence
+    /// measured from now.
+    fn begin_incarnation(&self) {
+        self.set_service_state(ServiceState::Starting);
+        *self.progress.lock().unwrap() = None;
+        *self.state.lock().unwrap() = None;
+        self.last_heartbeat.clear();
+        self.started.set(self.created, self.clock.now());
+    }
+}
+
+/// Attempts kept by `ThreadHandle::attempts`; older ones are forgotten, so that a thread
+/// restarting forever does not grow its history forever.
+const ATTEMPT_HISTORY: usize = 64;
+
+/// Runs attempt `number` of a retried or supervised runnable and records it; attempts after the
+/// first start from a clean state, see `Shared::begin_incarnation`.
+pub(crate) fn run_attempt<T, E, R>(interrupted: &InterruptToken, clock: &dyn Clock, number: u32, runnable: R) -> thread::Result<Result<T, E>> where
+    E: fmt::Debug,
+    R: FnOnce(InterruptToken) -> Result<T, E>
+{
+    if number > 1 {
+        interrupted.shared.begin_incarnation();
+    }
+    let token = interrupted.clone();
+    let started = clock.now();
+    let result = panic::catch_unwind(AssertUnwindSafe(move || runnable(token)));
+    let outcome = match result {
+        Ok(Ok(_)) => AttemptOutcome::Succeeded,
+        Ok(Err(ref error)) => AttemptOutcome::Failed(format!("{:?}", error)),
+        Err(ref payload) => AttemptOutcome::Panicked(payload.describe()),
+    };
+    let mut attempts = interrupted.shared.attempts.lock().unwrap();
+    if attempts.len() >= ATTEMPT_HISTORY {
+        attempts.remove(0);
+    }
+    attempts.push(Attempt {
+        number,
+        started,
+        duration: clock.now().saturating_duration_since(started),
+        outcome,
+    });
+    result
+}
+
+/// Result of the thread once its last attempt ended this way.
+pub(crate) fn conclude<T>(result: thread::Result<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+impl<T, E> ThreadHandle<Result<T, E>> where T: Send + 'static, E: fmt::Debug + Send + 'static {
+    /// Spawns a thread that runs closures produced by `factory` until one of them returns `Ok`,
+    /// the policy runs out of attempts or the thread is interrupted. Both `Err` and panics count
+    /// as failures; the outcome of the last attempt becomes the result of the thread.
+    pub fn spawn_with_retry<F, R>(name: String, policy: RetryPolicy, mut factory: F) -> io::Result<Self> where
+        F: FnMut() -> R, F: Send + 'static,
+        R: FnOnce(InterruptToken) -> Result<T, E>
+    {
+        ThreadHandle::spawn(name, move |interrupted| {
+            let mut number = 0;
+            loop {
+                number += 1;
+                if number > 1 {
+                    AtomicCounters::increment(&interrupted.shared.counters.restarts);
+                }
+                let result = run_attempt(&interrupted, &*policy.clock, number, factory());
+                let succeeded = matches!(result, Ok(Ok(_)));
+                if !succeeded && number < policy.max_attempts {
+                    let deadline = policy.clock.now() + policy.delay(number);
+                    clock::sleep_until(&*policy.clock, deadline, || interrupted.is_interrupted());
+                }
+                if succeeded || number >= policy.max_attempts || interrupted.is_interrupted() {
+                    return conclude(result);
+                }
+            }
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{self, AtomicUsize};
+    use std::thread::sleep;
+    use MockClock;
+    use test_util::assert_eventually;
+    use super::*;
+
+    #[test]
+    fn test_policy_delay() {
+        let policy = RetryPolicy::new(5)
+            .backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300));
+        assert_eq!(Duration::from_millis(100), policy.delay(1));
+        assert_eq!(Duration::from_millis(200), policy.delay(2));
+        assert_eq!(Duration::from_millis(300), policy.delay(3));
+        assert_eq!(Duration::from_millis(300), policy.delay(10));
+    }
+
+    #[test]
+    fn test_retry_until_success() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let policy = RetryPolicy::new(5).backoff(Duration::from_millis(10));
+        let handle = ThreadHandle::spawn_with_retry("Test retry success".to_string(), policy, move || {
+            let counter = counter.clone();
+            move |_| {
+                let call = counter.fetch_add(1, atomic::Ordering::SeqCst);
+                if call < 2 {
+                    panic!("attempt {}", call);
+                }
+                Ok::<_, ()>(call)
+            }
+        }).unwrap();
+        assert_eq!(2, handle.join().unwrap().unwrap().unwrap());
+        let attempts = handle.attempts();
+        assert_eq!(3, attempts.len());
+        assert_eq!(AttemptOutcome::Panicked("attempt 0".to_string()), attempts[0].outcome);
+        assert_eq!(AttemptOutcome::Succeeded, attempts[2].outcome);
+    }
+
+    #[test]
+    fn test_retry_exhausted() {
+        let policy = RetryPolicy::new(3).backoff(Duration::from_millis(10));
+        let handle = ThreadHandle::spawn_with_retry("Test retry exhausted".to_string(), policy, || {
+            |_| Err::<(), _>("unavailable")
+        }).unwrap();
+        assert_eq!(Err("unavailable"), handle.join().unwrap().unwrap());
+        let attempts = handle.attempts();
+        assert_eq!(3, attempts.len());
+        assert_eq!(AttemptOutcome::Failed("\"unavailable\"".to_string()), attempts[2].outcome);
+    }
+
+    #[test]
+    fn test_retry_panic_propagates() {
+        let policy = RetryPolicy::new(2).backoff(Duration::from_millis(10));
+        let handle = ThreadHandle::spawn_with_retry("Test retry panic".to_string(), policy, || {
+            |_| -> Result<(), ()> { panic!("always") }
+        }).unwrap();
+        assert!(handle.join().unwrap().is_err());
+        assert_eq!(2, handle.attempts().len());
+    }
+
+    #[test]
+    fn test_retry_stops_on_interrupt() {
+        let policy = RetryPolicy::new(100).backoff(Duration::from_millis(200));
+        let handle = ThreadHandle::spawn_with_retry("Test retry interrupt".to_string(), policy, || {
+            |_| Err::<(), _>(())
+        }).unwrap();
+        while handle.attempts().is_empty() {
+            sleep(Duration::from_millis(5));
+        }
+        handle.interrupt().unwrap();
+        assert_eq!(Err(()), handle.join().unwrap().unwrap());
+        assert!(handle.attempts().len() < 5);
+    }
+
+    #[test]
+    fn test_retry_backoff_with_mock_clock() {
+        let clock = Arc::new(MockClock::new());
+        let policy = RetryPolicy::new(2).backoff(Duration::from_secs(3600)).clock(clock.clone());
+        let handle = ThreadHandle::spawn_with_retry("Test retry mock clock".to_string(), policy, || {
+            |_| Err::<(), _>(())
+        }).unwrap();
+        assert_eventually(Duration::from_secs(5), "first attempt", || !handle.attempts().is_empty());
+        let reads = clock.reads();
+        assert_eventually(Duration::from_secs(5), "backoff waiting", || clock.reads() >= reads + 2);
+        assert_eq!(1, handle.attempts().len());
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(Err(()), handle.join().unwrap().unwrap());
+        let attempts = handle.attempts();
+        assert_eq!(Duration::from_secs(3600), attempts[1].started - attempts[0].started);
+    }
+
+    #[test]
+    fn test_retry_spawn() {
+        struct Exhausted {
+            failures: AtomicUsize,
+        }
+
+        impl SpawnBackend for Exhausted {
+            fn spawn(&self, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+                let failing = self.failures.fetch_update(atomic::Ordering::SeqCst, atomic::Ordering::SeqCst, |failures| failures.checked_sub(1));
+                if failing.is_ok() {
+                    return Err(io::Error::from(io::ErrorKind::WouldBlock));
+                }
+                ::StdBackend.spawn(name, job)
+            }
+        }
+
+        let shed = Arc::new(Mutex::new(Vec::new()));
+        let record = shed.clone();
+        let backend = Arc::new(Exhausted { failures: AtomicUsize::new(2) });
+        let handle = ::Builder::new("Test retry spawn".to_string())
+            .backend(backend.clone())
+            .retry_spawn(RetryPolicy::new(3).backoff(Duration::from_millis(1)), move |error: &io::Error, attempt| {
+                record.lock().unwrap().push((error.kind(), attempt));
+            })
+            .spawn(|_| 42)
+            .unwrap();
+        assert_eq!(42, handle.join().unwrap().unwrap());
+        assert_eq!(vec![(io::ErrorKind::WouldBlock, 1), (io::ErrorKind::WouldBlock, 2)], *shed.lock().unwrap());
+        backend.failures.store(3, atomic::Ordering::SeqCst);
+        let failed = ::Builder::new("Test retry spawn exhausted".to_string())
+            .backend(backend)
+            .retry_spawn(RetryPolicy::new(3).backoff(Duration::from_millis(1)), |_: &io::Error, _| ())
+            .lazy(|_| ());
+        assert_eq!(io::ErrorKind::WouldBlock, failed.ensure_started().unwrap_err().kind());
+    }
+}
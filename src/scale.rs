@@ -0,0 +1,198 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+
+use builder::SpawnSettings;
+use {HandleId, InterruptToken, ThreadHandle, ThreadStatus};
+
+
+type Factory<T> = Arc<dyn Fn(usize, InterruptToken) -> T + Send + Sync>;
+type ScaleCallback = Box<dyn Fn(&ScaleEvent) + Send + Sync>;
+
+/// Change of size of a `ScalableGroup`, handed to the callback set with `on_scale`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ScaleEvent {
+    pub group: String,
+    /// Number of workers before and after the change.
+    pub from: usize,
+    pub to: usize,
+    /// Workers spawned, or interrupted and joined, by the change.
+    pub workers: Vec<HandleId>,
+}
+
+/// Group of identical workers, spawned with `spawn_n`, that can grow and shrink while it runs,
+/// e.g. for a service adapting to its load. Worker `i` is named "group-i" and its runnable is
+/// the factory called with `i`; numbers are not reused after scaling down.
+pub struct ScalableGroup<T> {
+    name: String,
+    factory: Factory<T>,
+    workers: Vec<ThreadHandle<T>>,
+    spawned: usize,
+    on_scale: Option<ScaleCallback>,
+}
+
+impl<T> ScalableGroup<T> where T: Send + 'static {
+    /// Spawns `count` workers running `factory`; on failure the workers already spawned are
+    /// interrupted.
+    pub fn spawn_n<F>(name: String, count: usize, factory: F) -> io::Result<Self> where
+        F: Fn(usize, InterruptToken) -> T + Send + Sync + 'static
+    {
+        let mut group = ScalableGroup {
+            name,
+            factory: Arc::new(factory),
+            workers: Vec::with_capacity(count),
+            spawned: 0,
+            on_scale: None,
+        };
+        if let Err(error) = group.spawn_workers(count) {
+            for worker in &group.workers {
+                worker.interrupt().ok();
+            }
+            return Err(error);
+        }
+        Ok(group)
+    }
+
+    /// Callback invoked after every change of size.
+    pub fn on_scale<F>(&mut self, callback: F) where F: Fn(&ScaleEvent) + Send + Sync + 'static {
+        self.on_scale = Some(Box::new(callback));
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of workers in the group, including the ones that already exited on their own.
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn handles(&self) -> &[ThreadHandle<T>] {
+        &self.workers
+    }
+
+    /// Spawns `count` more workers. If one fails to spawn, the ones spawned before it stay.
+    pub fn scale_up(&mut self, count: usize) -> io::Result<()> {
+        let from = self.workers.len();
+        let result = self.spawn_workers(count);
+        let workers = self.workers[from..].iter().map(ThreadHandle::id).collect();
+        self.notify(from, workers);
+        result
+    }
+
+    /// Interrupts and joins `count` workers, preferring the ones that already exited, then the
+    /// most recently spawned, and returns their outcomes.
+    pub fn scale_down(&mut self, count: usize) -> Vec<Option<thread::Result<T>>> {
+        let from = self.workers.len();
+        let mut removed = Vec::with_capacity(count);
+        while removed.len() < count && !self.workers.is_empty() {
+            let index = self.workers.iter().rposition(|worker| worker.status() == ThreadStatus::Terminated).unwrap_or(self.workers.len() - 1);
+            removed.push(self.workers.remove(index));
+        }
+        for worker in &removed {
+            worker.interrupt().ok();
+        }
+        let workers = removed.iter().map(ThreadHandle::id).collect();
+        let outcomes = removed.iter().map(ThreadHandle::join).collect();
+        self.notify(from, workers);
+        outcomes
+    }
+
+    /// Grows or shrinks the group to `size` workers; returns the outcomes of the workers
+    /// removed, if it shrank.
+    pub fn scale_to(&mut self, size: usize) -> io::Result<Vec<Option<thread::Result<T>>>> {
+        if size > self.workers.len() {
+            let count = size - self.workers.len();
+            self.scale_up(count).map(|_| Vec::new())
+        } else {
+            let count = self.workers.len() - size;
+            Ok(self.scale_down(count))
+        }
+    }
+
+    /// Interrupts every worker and joins them, in the order they were spawned.
+    pub fn shutdown(self) -> Vec<Option<thread::Result<T>>> {
+        for worker in &self.workers {
+            worker.interrupt().ok();
+        }
+        self.workers.iter().map(ThreadHandle::join).collect()
+    }
+
+    fn spawn_workers(&mut self, count: usize) -> io::Result<()> {
+        for _ in 0..count {
+            let number = self.spawned;
+            let factory = self.factory.clone();
+            let settings = SpawnSettings {
+                group: Some(self.name.clone()),
+                ..SpawnSettings::default()
+            };
+            let worker = ThreadHandle::spawn_configured(format!("{}-{}", self.name, number), settings.token(), &settings, move |token| factory(number, token))?;
+            self.spawned += 1;
+            self.workers.push(worker);
+        }
+        Ok(())
+    }
+
+    fn notify(&self, from: usize, workers: Vec<HandleId>) {
+        if workers.is_empty() {
+            return;
+        }
+        if let Some(ref on_scale) = self.on_scale {
+            on_scale(&ScaleEvent {
+                group: self.name.clone(),
+                from,
+                to: self.workers.len(),
+                workers,
+            });
+        }
+    }
+}
+
+impl<T> fmt::Debug for ScalableGroup<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScalableGroup")
+            .field("name", &self.name)
+            .field("size", &self.workers.len())
+            .field("spawned", &self.spawned)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_scalable_group() {
+        let mut group = ScalableGroup::spawn_n("Test scale".to_string(), 2, |number, interrupted: InterruptToken| {
+            if number == 1 {
+                return number;
+            }
+            while !interrupted.is_interrupted() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            number
+        }).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        group.on_scale(move |event| recorded.lock().unwrap().push((event.from, event.to, event.workers.len())));
+        assert_eq!(2, group.size());
+        group.scale_up(2).unwrap();
+        assert_eq!(4, group.size());
+        assert_eq!("Test scale-3", group.handles()[3].name());
+        assert!(group.handles()[1].wait_finished(Some(Duration::from_secs(5))));
+
+        // The worker that already exited goes first, then the youngest.
+        let removed: Vec<_> = group.scale_down(2).into_iter().map(|outcome| outcome.unwrap().unwrap()).collect();
+        assert_eq!(vec![1, 3], removed);
+        assert_eq!(vec![(2, 4, 2), (4, 2, 2)], *events.lock().unwrap());
+        assert!(group.scale_to(3).unwrap().is_empty());
+        assert_eq!("Test scale-4", group.handles()[2].name());
+        let outcomes: Vec<_> = group.shutdown().into_iter().map(|outcome| outcome.unwrap().unwrap()).collect();
+        assert_eq!(vec![0, 2, 4], outcomes);
+    }
+}
@@ -0,0 +1,420 @@
+use std::cmp;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use clock::Clock;
+#[cfg(any(test, feature = "test-util"))]
+use {MockClock, Stall, Watchdog};
+use {Builder, InterruptToken, ThreadHandle};
+
+
+/// Longest real time the scheduler thread waits between two looks at its clock, so that it
+/// follows a clock that is advanced from another thread.
+const IDLE_CHECK: Duration = Duration::from_millis(10);
+
+type Runnable = Box<dyn FnOnce(InterruptToken) + Send>;
+type Factory = Box<dyn FnMut() -> Runnable + Send>;
+
+/// Identifies a job of a `Scheduler`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct JobId(u64);
+
+struct Job {
+    id: JobId,
+    name: String,
+    next: Instant,
+    period: Option<Duration>,
+    /// Locked on its own, so that it runs without holding the lock of the jobs.
+    factory: Arc<Mutex<Factory>>,
+    runs: u32,
+}
+
+struct Jobs {
+    jobs: Vec<Job>,
+    next_id: u64,
+}
+
+struct Queue {
+    jobs: Mutex<Jobs>,
+    /// Signalled when jobs are added or cancelled, so that the scheduler thread recomputes
+    /// its next deadline.
+    changed: Condvar,
+    /// Spawns that failed without anybody to return the error to, see `take_errors`.
+    errors: Mutex<Vec<(JobId, io::Error)>>,
+}
+
+/// Spawns threads after a delay or periodically, according to its clock.
+pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+    queue: Arc<Queue>,
+    thread: Option<ThreadHandle<()>>,
+}
+
+impl Scheduler {
+    /// Starts a scheduler thread running jobs as they become due.
+    pub fn spawn(name: String, clock: Arc<dyn Clock>) -> io::Result<Self> {
+        let mut scheduler = Scheduler::manual(clock);
+        let (clock, queue) = (scheduler.clock.clone(), scheduler.queue.clone());
+        scheduler.thread = Some(ThreadHandle::spawn(name, move |interrupted| {
+            while !interrupted.is_interrupted() {
+                spawn_due(&clock, &queue);
+                let jobs = queue.jobs.lock().unwrap();
+                let wait = earliest(&jobs).map_or(IDLE_CHECK, |next| {
+                    cmp::min(next.saturating_duration_since(clock.now()), IDLE_CHECK)
+                });
+                if wait > Duration::from_secs(0) && !interrupted.is_interrupted() {
+                    drop(queue.changed.wait_timeout(jobs, wait).unwrap());
+                }
+            }
+        })?);
+        Ok(scheduler)
+    }
+
+    /// Scheduler without a thread of its own; jobs only run through `run_due`.
+    pub fn manual(clock: Arc<dyn Clock>) -> Self {
+        Scheduler {
+            clock,
+            queue: Arc::new(Queue {
+                jobs: Mutex::new(Jobs {
+                    jobs: Vec::new(),
+                    next_id: 0,
+                }),
+                changed: Condvar::new(),
+                errors: Mutex::new(Vec::new()),
+            }),
+            thread: None,
+        }
+    }
+
+    /// Spawns `runnable` once, `delay` from now.
+    pub fn after<F>(&self, delay: Duration, name: String, runnable: F) -> JobId where
+        F: FnOnce(InterruptToken), F: Send + 'static
+    {
+        let mut runnable = Some(runnable);
+        self.add(name, delay, None, Box::new(move || {
+            let runnable = runnable.take().expect("delayed job runs only once");
+            Box::new(runnable)
+        }))
+    }
+
+    /// Spawns a closure produced by `factory` every `period`, the first one `period` from now.
+    pub fn every<F, R>(&self, period: Duration, name: String, mut factory: F) -> JobId where
+        F: FnMut() -> R, F: Send + 'static,
+        R: FnOnce(InterruptToken), R: Send + 'static
+    {
+        self.add(name, period, Some(period), Box::new(move || Box::new(factory())))
+    }
+
+    fn add(&self, name: String, delay: Duration, period: Option<Duration>, factory: Factory) -> JobId {
+        let mut jobs = self.queue.jobs.lock().unwrap();
+        jobs.next_id += 1;
+        let id = JobId(jobs.next_id);
+        jobs.jobs.push(Job {
+            id,
+            name,
+            next: self.clock.now() + delay,
+            period,
+            factory: Arc::new(Mutex::new(factory)),
+            runs: 0,
+        });
+        self.queue.changed.notify_all();
+        id
+    }
+
+    /// Removes a job; returns `false` if it already ran its last time or was cancelled.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut jobs = self.queue.jobs.lock().unwrap();
+        let before = jobs.jobs.len();
+        jobs.jobs.retain(|job| job.id != id);
+        self.queue.changed.notify_all();
+        jobs.jobs.len() != before
+    }
+
+    /// Number of times the job was spawned; zero for unknown or finished one-shot jobs.
+    pub fn runs(&self, id: JobId) -> u32 {
+        self.queue.jobs.lock().unwrap().jobs.iter().find(|job| job.id == id).map_or(0, |job| job.runs)
+    }
+
+    /// Earliest time a job is due.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        earliest(&self.queue.jobs.lock().unwrap())
+    }
+
+    /// Spawns every job that is due, catching up on missed periods, and returns the handles of
+    /// the threads or why they could not be spawned. A run that failed to spawn is not counted
+    /// by `runs`, and is not tried again.
+    pub fn run_due(&self) -> Vec<io::Result<ThreadHandle<()>>> {
+        run_due(&self.clock, &self.queue.jobs).into_iter().map(|(_, spawned)| spawned).collect()
+    }
+
+    /// Takes the errors of the runs that failed to spawn on the scheduler thread, or while
+    /// advancing `VirtualTime`, oldest first.
+    pub fn take_errors(&self) -> Vec<(JobId, io::Error)> {
+        mem::take(&mut *self.queue.errors.lock().unwrap())
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            thread.interrupt().ok();
+            self.queue.changed.notify_all();
+            thread.join();
+        }
+    }
+}
+
+fn earliest(jobs: &Jobs) -> Option<Instant> {
+    jobs.jobs.iter().map(|job| job.next).min()
+}
+
+fn run_due(clock: &Arc<dyn Clock>, jobs: &Mutex<Jobs>) -> Vec<(JobId, io::Result<ThreadHandle<()>>)> {
+    let now = clock.now();
+    let mut due = Vec::new();
+    {
+        let mut jobs = jobs.lock().unwrap();
+        for job in jobs.jobs.iter_mut() {
+            while job.next <= now {
+                due.push((job.id, job.name.clone(), job.factory.clone()));
+                match job.period {
+                    Some(period) => job.next += period,
+                    None => break,
+                }
+            }
+        }
+        // One-shot jobs that are due leave the queue; periodic ones are due again later.
+        jobs.jobs.retain(|job| job.next > now);
+    }
+    // Produced and spawned outside the lock so that a job can schedule others.
+    let spawned: Vec<_> = due.into_iter().map(|(id, name, factory)| {
+        let runnable = (*factory.lock().unwrap())();
+        (id, Builder::new(name).clock(clock.clone()).spawn(runnable))
+    }).collect();
+    let mut jobs = jobs.lock().unwrap();
+    for &(id, _) in spawned.iter().filter(|(_, spawned)| spawned.is_ok()) {
+        if let Some(job) = jobs.jobs.iter_mut().find(|job| job.id == id) {
+            job.runs += 1;
+        }
+    }
+    spawned
+}
+
+/// Like `run_due`, keeping the errors for `Scheduler::take_errors`.
+fn spawn_due(clock: &Arc<dyn Clock>, queue: &Queue) -> Vec<ThreadHandle<()>> {
+    let mut handles = Vec::new();
+    for (id, spawned) in run_due(clock, &queue.jobs) {
+        match spawned {
+            Ok(handle) => handles.push(handle),
+            Err(error) => queue.errors.lock().unwrap().push((id, error)),
+        }
+    }
+    handles
+}
+
+/// Virtual time for tests: advancing it fires due scheduler jobs and watchdog checks in order,
+/// without real sleeps. Jobs are expected to finish without waiting on virtual time; each step
+/// waits at most `settle` of real time for the threads it spawned.
+#[cfg(any(test, feature = "test-util"))]
+pub struct VirtualTime {
+    clock: Arc<MockClock>,
+    scheduler: Scheduler,
+    watchdog: Watchdog,
+    check_every: Option<(Duration, Instant)>,
+    settle: Duration,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl VirtualTime {
+    pub fn new() -> Self {
+        let clock = Arc::new(MockClock::new());
+        VirtualTime {
+            scheduler: Scheduler::manual(clock.clone()),
+            watchdog: Watchdog::manual(),
+            check_every: None,
+            settle: Duration::from_secs(5),
+            clock,
+        }
+    }
+
+    /// Clock to hand to threads and components driven by this virtual time.
+    pub fn clock(&self) -> Arc<MockClock> {
+        self.clock.clone()
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Watchdog checked at every step, and every `interval` if set with `check_watchdog_every`.
+    pub fn watchdog(&self) -> &Watchdog {
+        &self.watchdog
+    }
+
+    pub fn check_watchdog_every(&mut self, interval: Duration) {
+        self.check_every = Some((interval, self.clock.now() + interval));
+    }
+
+    /// Longest real time a step waits for the threads it spawned.
+    pub fn settle(&mut self, timeout: Duration) {
+        self.settle = timeout;
+    }
+
+    /// Moves virtual time forward by `by`, stopping at every job deadline and watchdog check
+    /// on the way; returns the stalls reported meanwhile.
+    pub fn advance(&mut self, by: Duration) -> Vec<Stall> {
+        let target = self.clock.now() + by;
+        let mut stalls = Vec::new();
+        loop {
+            let check = self.check_every.map(|(_, at)| at);
+            let next = match (self.scheduler.next_deadline(), check) {
+                (Some(job), Some(check)) => cmp::min(job, check),
+                (job, check) => match job.or(check) {
+                    Some(next) => next,
+                    None => break,
+                },
+            };
+            if next > target {
+                break;
+            }
+            self.move_to(next);
+            for handle in spawn_due(&self.scheduler.clock, &self.scheduler.queue) {
+                handle.wait_finished(Some(self.settle));
+            }
+            if let Some((interval, ref mut at)) = self.check_every {
+                while *at <= next {
+                    *at += interval;
+                }
+            }
+            stalls.extend(self.watchdog.check_now());
+        }
+        self.move_to(target);
+        stalls.extend(self.watchdog.check_now());
+        stalls
+    }
+
+    fn move_to(&self, at: Instant) {
+        self.clock.advance(at.saturating_duration_since(self.clock.now()));
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for VirtualTime {
+    fn default() -> Self {
+        VirtualTime::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{self, AtomicUsize};
+    use std::sync::mpsc;
+    use std::thread::sleep;
+    use {clock, StallAction};
+    use super::*;
+
+    #[test]
+    fn test_periodic_job_in_virtual_time() {
+        let mut time = VirtualTime::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let job = time.scheduler().every(Duration::from_secs(300), "Test schedule periodic".to_string(), move || {
+            let counter = counter.clone();
+            move |_| {
+                counter.fetch_add(1, atomic::Ordering::SeqCst);
+            }
+        });
+        time.advance(Duration::from_secs(3 * 3600));
+        assert_eq!(36, runs.load(atomic::Ordering::SeqCst));
+        assert_eq!(36, time.scheduler().runs(job));
+        assert!(time.scheduler().cancel(job));
+        time.advance(Duration::from_secs(3600));
+        assert_eq!(36, runs.load(atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_delayed_job_in_virtual_time() {
+        let mut time = VirtualTime::new();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let counter = ran.clone();
+        let job = time.scheduler().after(Duration::from_secs(60), "Test schedule delayed".to_string(), move |_| {
+            counter.fetch_add(1, atomic::Ordering::SeqCst);
+        });
+        time.advance(Duration::from_secs(59));
+        assert_eq!(0, ran.load(atomic::Ordering::SeqCst));
+        time.advance(Duration::from_secs(3600));
+        assert_eq!(1, ran.load(atomic::Ordering::SeqCst));
+        assert!(!time.scheduler().cancel(job));
+    }
+
+    #[test]
+    fn test_watchdog_in_virtual_time() {
+        let mut time = VirtualTime::new();
+        time.check_watchdog_every(Duration::from_secs(10));
+        let handle = Builder::new("Test schedule stalled".to_string()).clock(time.clock()).spawn(|interrupted| {
+            interrupted.heartbeat();
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        while handle.last_heartbeat().is_none() {
+            sleep(Duration::from_millis(1));
+        }
+        time.watchdog().watch(&handle, Duration::from_secs(60), StallAction::Interrupt, |_: &Stall| ());
+        let stalls = time.advance(Duration::from_secs(3600));
+        assert_eq!(1, stalls.len());
+        assert_eq!(Duration::from_secs(70), stalls[0].silence);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_factory_schedules_job() {
+        let clock = Arc::new(MockClock::new());
+        let scheduler = Arc::new(Scheduler::manual(clock.clone()));
+        let (sender, receiver) = mpsc::channel();
+        let chained = Arc::downgrade(&scheduler);
+        scheduler.every(Duration::from_secs(60), "Test schedule factory".to_string(), move || {
+            let sender = sender.clone();
+            chained.upgrade().unwrap().after(Duration::from_secs(1), "Test schedule chained".to_string(), move |_| {
+                sender.send(()).unwrap();
+            });
+            |_| ()
+        });
+        clock.advance(Duration::from_secs(60));
+        for spawned in scheduler.run_due() {
+            spawned.unwrap().join().unwrap().unwrap();
+        }
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(1, scheduler.run_due().len());
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_scheduler_thread() {
+        let scheduler = Scheduler::spawn("Test scheduler".to_string(), clock::system()).unwrap();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let counter = ran.clone();
+        scheduler.after(Duration::from_millis(20), "Test scheduler delayed".to_string(), move |_| {
+            counter.fetch_add(1, atomic::Ordering::SeqCst);
+        });
+        while ran.load(atomic::Ordering::SeqCst) == 0 {
+            sleep(Duration::from_millis(5));
+        }
+        assert_eq!(None, scheduler.next_deadline());
+    }
+
+    #[test]
+    fn test_scheduler_thread_wakes_for_earlier_job() {
+        let scheduler = Scheduler::spawn("Test scheduler earlier".to_string(), clock::system()).unwrap();
+        let late = scheduler.after(Duration::from_secs(3600), "Test scheduler late".to_string(), |_| ());
+        sleep(Duration::from_millis(20));
+        let (sender, receiver) = mpsc::channel();
+        scheduler.after(Duration::from_millis(10), "Test scheduler early".to_string(), move |_| {
+            sender.send(()).unwrap();
+        });
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert!(scheduler.cancel(late));
+    }
+}
@@ -0,0 +1,129 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use {InterruptToken, Shared, ThreadHandle};
+
+
+/// Guard that interrupts every thread spawned through it when dropped,
+/// including on early return or while unwinding from a panic.
+pub struct InterruptScope {
+    token: InterruptToken,
+    join_on_drop: bool,
+    spawned: Mutex<Vec<Arc<Shared>>>,
+}
+
+impl InterruptScope {
+    pub fn new() -> Self {
+        InterruptScope::child_of(&InterruptToken::new())
+    }
+
+    /// Creates a scope that is also interrupted when `parent` is.
+    pub fn child_of(parent: &InterruptToken) -> Self {
+        InterruptScope {
+            token: parent.child(),
+            join_on_drop: false,
+            spawned: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Wait for spawned threads to terminate after interrupting them on drop.
+    pub fn join_on_drop(mut self, join: bool) -> Self {
+        self.join_on_drop = join;
+        self
+    }
+
+    pub fn token(&self) -> &InterruptToken {
+        &self.token
+    }
+
+    /// Spawns a thread with a child token of the scope.
+    pub fn spawn<T, F>(&self, name: String, runnable: F) -> io::Result<ThreadHandle<T>> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let handle = ThreadHandle::spawn_with_token(name, self.token.child(), runnable)?;
+        self.spawned.lock().unwrap().push(handle.shared.clone());
+        Ok(handle)
+    }
+
+    pub fn interrupt(&self) {
+        self.token.interrupt();
+    }
+}
+
+impl Default for InterruptScope {
+    fn default() -> Self {
+        InterruptScope::new()
+    }
+}
+
+impl Drop for InterruptScope {
+    fn drop(&mut self) {
+        self.token.interrupt();
+        if self.join_on_drop {
+            let spawned = match self.spawned.lock() {
+                Ok(spawned) => spawned,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            for shared in spawned.iter() {
+                shared.wait_terminated(None);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::sync::mpsc;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use ThreadStatus;
+    use super::*;
+
+    fn wait_for_interrupt(interrupted: InterruptToken) {
+        while !interrupted.is_interrupted() {
+            sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_scope_drop_joins() {
+        let handle;
+        {
+            let scope = InterruptScope::new().join_on_drop(true);
+            let (sender, started) = mpsc::channel();
+            handle = scope.spawn("Test scope".to_string(), move |interrupted| {
+                sender.send(()).unwrap();
+                wait_for_interrupt(interrupted)
+            }).unwrap();
+            started.recv().unwrap();
+            assert_eq!(ThreadStatus::Running, handle.status());
+        }
+        assert_eq!(ThreadStatus::Terminated, handle.status());
+    }
+
+    #[test]
+    fn test_scope_interrupts_on_panic() {
+        let (tx, rx) = ::std::sync::mpsc::channel();
+        let result = panic::catch_unwind(move || {
+            let scope = InterruptScope::new().join_on_drop(true);
+            tx.send(scope.spawn("Test scope panic".to_string(), wait_for_interrupt).unwrap()).unwrap();
+            panic!("owner failed");
+        });
+        assert!(result.is_err());
+        let handle = rx.recv().unwrap();
+        assert_eq!(ThreadStatus::Terminated, handle.status());
+        assert!(handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_child_scope() {
+        let parent = InterruptScope::new();
+        let child = InterruptScope::child_of(parent.token());
+        let handle = child.spawn("Test child scope".to_string(), wait_for_interrupt).unwrap();
+        parent.interrupt();
+        assert!(handle.join().unwrap().is_ok());
+    }
+}
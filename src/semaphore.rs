@@ -0,0 +1,135 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use InterruptToken;
+
+
+/// Longest time a waiter sleeps before checking for interruption again.
+const INTERRUPT_CHECK: Duration = Duration::from_millis(10);
+
+/// Why `Semaphore::acquire` did not get a permit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AcquireError {
+    /// Interruption was requested while waiting.
+    Interrupted,
+    TimedOut,
+}
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AcquireError::Interrupted => write!(f, "interrupted while waiting for a permit"),
+            AcquireError::TimedOut => write!(f, "timed out waiting for a permit"),
+        }
+    }
+}
+
+impl Error for AcquireError {}
+
+/// Counting semaphore whose waiters give up once interrupted, e.g. to limit the outbound
+/// connections a group of workers opens at once without holding up their shutdown.
+#[derive(Debug)]
+pub struct Semaphore {
+    permits: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Permit of a `Semaphore`, given back when dropped.
+#[derive(Debug)]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Number of permits currently free.
+    pub fn available(&self) -> usize {
+        *self.permits.lock().unwrap()
+    }
+
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut permits = self.permits.lock().unwrap();
+        if *permits == 0 {
+            return None;
+        }
+        *permits -= 1;
+        Some(SemaphorePermit { semaphore: self })
+    }
+
+    /// Waits for a permit, giving up once interruption is requested through `token` or
+    /// `timeout` elapsed.
+    pub fn acquire(&self, token: &InterruptToken, timeout: Option<Duration>) -> Result<SemaphorePermit<'_>, AcquireError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut permits = self.permits.lock().unwrap();
+        loop {
+            if token.is_interrupted() {
+                return Err(AcquireError::Interrupted);
+            }
+            if *permits > 0 {
+                *permits -= 1;
+                return Ok(SemaphorePermit { semaphore: self });
+            }
+            let wait = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(AcquireError::TimedOut);
+                    }
+                    INTERRUPT_CHECK.min(deadline - now)
+                }
+                None => INTERRUPT_CHECK,
+            };
+            permits = self.released.wait_timeout(permits, wait).unwrap().0;
+        }
+    }
+}
+
+impl<'a> Drop for SemaphorePermit<'a> {
+    fn drop(&mut self) {
+        *self.semaphore.permits.lock().unwrap() += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use ThreadHandle;
+    use super::*;
+
+    #[test]
+    fn test_semaphore() {
+        let semaphore = Arc::new(Semaphore::new(2));
+        let token = InterruptToken::new();
+        let first = semaphore.acquire(&token, None).unwrap();
+        let second = semaphore.try_acquire().unwrap();
+        assert_eq!(0, semaphore.available());
+        assert!(semaphore.try_acquire().is_none());
+        assert_eq!(AcquireError::TimedOut, semaphore.acquire(&token, Some(Duration::from_millis(20))).unwrap_err());
+        let waiting = semaphore.clone();
+        let handle = ThreadHandle::spawn("Test semaphore".to_string(), move |interrupted| {
+            waiting.acquire(&interrupted, None).map(drop)
+        }).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        handle.interrupt().unwrap();
+        assert_eq!(Err(AcquireError::Interrupted), handle.join().unwrap().unwrap());
+        drop(first);
+        let waiting = semaphore.clone();
+        let handle = ThreadHandle::spawn("Test semaphore released".to_string(), move |interrupted| {
+            waiting.acquire(&interrupted, Some(Duration::from_secs(5))).map(drop)
+        }).unwrap();
+        assert_eq!(Ok(()), handle.join().unwrap().unwrap());
+        drop(second);
+        assert_eq!(2, semaphore.available());
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+use command::{self, CommandError, CommandSender, Commands};
+use {InterruptToken, ThreadGroup};
+
+
+/// Group of workers each fed by its own command channel, messages being routed by key so that
+/// messages with the same key are handled in order by the same worker; see
+/// `ThreadGroup::spawn_sharded`.
+pub struct ShardedGroup<M, T> {
+    group: ThreadGroup<T>,
+    senders: Vec<CommandSender<M>>,
+}
+
+impl<M, T> ShardedGroup<M, T> where T: Send + 'static {
+    pub fn group(&self) -> &ThreadGroup<T> {
+        &self.group
+    }
+
+    /// Index of the worker handling messages with `key`.
+    pub fn shard_of<K>(&self, key: &K) -> usize where K: Hash + ?Sized {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.senders.len() as u64) as usize
+    }
+
+    /// Sends `message` to the worker handling `key`, blocking while its buffer is full.
+    pub fn dispatch<K>(&self, key: &K, message: M) -> Result<(), CommandError<M>> where K: Hash + ?Sized {
+        let shard = self.shard_of(key);
+        self.senders[shard].send(&self.group.handles()[shard].shared, message)
+    }
+
+    /// Stops taking messages: each worker's `Commands::recv` returns `None` once it received
+    /// what was already sent, and the group is returned for joining.
+    pub fn close(self) -> ThreadGroup<T> {
+        self.group
+    }
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// Spawns `shards` workers named after the group, each receiving the messages dispatched
+    /// to it through a channel buffering up to `capacity`. The worker at index `i` runs the
+    /// closure `factory(i)` returns.
+    pub fn spawn_sharded<M, F, R>(name: String, shards: usize, capacity: usize, mut factory: F) -> io::Result<ShardedGroup<M, T>> where
+        M: Send + 'static,
+        F: FnMut(usize) -> R,
+        R: FnOnce(InterruptToken, Commands<M>) -> T, R: Send + 'static
+    {
+        if shards == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a sharded group needs at least one worker"));
+        }
+        let mut group = ThreadGroup::new(name);
+        let mut senders = Vec::with_capacity(shards);
+        for index in 0..shards {
+            let (sender, receiver) = command::channel(capacity);
+            let runnable = factory(index);
+            let name = format!("{}-{}", group.name(), index);
+            group.spawn(name, move |interrupted: InterruptToken| {
+                let commands = Commands::new(receiver, interrupted.clone());
+                runnable(interrupted, commands)
+            })?;
+            senders.push(sender);
+        }
+        Ok(ShardedGroup { group, senders })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispatch_by_key() {
+        let sharded = ThreadGroup::spawn_sharded("Test sharded".to_string(), 3, 4, |index| {
+            move |_, commands: Commands<(String, u32)>| {
+                let mut received = Vec::new();
+                while let Some(message) = commands.recv() {
+                    received.push(message);
+                }
+                (index, received)
+            }
+        }).unwrap();
+        let accounts = ["alice", "bob", "carol", "dave"];
+        for sequence in 0..10 {
+            for account in &accounts {
+                sharded.dispatch(*account, (account.to_string(), sequence)).unwrap();
+            }
+        }
+        let shards: Vec<_> = accounts.iter().map(|account| sharded.shard_of(*account)).collect();
+        for (index, received) in sharded.close().join_all().unwrap() {
+            for (account, &shard) in accounts.iter().zip(&shards) {
+                let sequences: Vec<_> = received.iter().filter(|&(key, _)| key == account).map(|&(_, sequence)| sequence).collect();
+                if shard == index {
+                    assert_eq!((0..10).collect::<Vec<_>>(), sequences);
+                } else {
+                    assert!(sequences.is_empty());
+                }
+            }
+        }
+        assert!(ThreadGroup::<()>::spawn_sharded("Test sharded empty".to_string(), 0, 1, |_| |_, _: Commands<()>| ()).is_err());
+    }
+}
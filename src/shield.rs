@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+use std::sync::atomic;
+
+use {HandleId, InterruptToken, Shared, ThreadHandle};
+
+
+thread_local! {
+    /// Handles whose token is in a shielded section on this thread, once per nesting level.
+    static SHIELDED: RefCell<Vec<HandleId>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Leaves a shielded section, including by panicking.
+struct Unshield<'a>(&'a Shared);
+
+impl<'a> Drop for Unshield<'a> {
+    fn drop(&mut self) {
+        SHIELDED.with(|shielded| {
+            let mut shielded = shielded.borrow_mut();
+            if let Some(index) = shielded.iter().rposition(|&id| id == self.0.id) {
+                shielded.remove(index);
+            }
+        });
+        self.0.shielded.fetch_sub(1, atomic::Ordering::AcqRel);
+        // Futures and flags registered meanwhile missed the notification of a request.
+        if !self.0.is_shielded_here() && self.0.is_interrupt_requested() {
+            self.0.notify_interrupt_listeners();
+        }
+    }
+}
+
+impl Shared {
+    /// Whether a shielded section is running, on whichever thread; for reporting.
+    pub(crate) fn is_shielded(&self) -> bool {
+        self.shielded.load(atomic::Ordering::Acquire) > 0
+    }
+
+    /// Whether the calling thread is in a shielded section of this token.
+    pub(crate) fn is_shielded_here(&self) -> bool {
+        SHIELDED.with(|shielded| shielded.borrow().contains(&self.id))
+    }
+}
+
+impl InterruptToken {
+    /// Runs `critical`, a short section that must not be abandoned halfway such as applying a
+    /// set of related changes, with interruption deferred: on the calling thread,
+    /// `is_interrupted` returns `false` on every clone of the token until it returns, so that
+    /// helpers it calls stop no earlier. Other threads sharing the token still see requests.
+    /// A request made meanwhile is kept and seen at the first check after the section.
+    pub fn shield<F, R>(&self, critical: F) -> R where F: FnOnce() -> R {
+        SHIELDED.with(|shielded| shielded.borrow_mut().push(self.shared.id));
+        self.shared.shielded.fetch_add(1, atomic::Ordering::AcqRel);
+        let _unshield = Unshield(&self.shared);
+        critical()
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Whether the runnable is inside `InterruptToken::shield`, where an interruption request
+    /// is not acted upon until the section ends.
+    pub fn is_shielded(&self) -> bool {
+        self.shared.is_shielded()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use super::*;
+
+    #[test]
+    fn test_shield() {
+        let (entered, has_entered) = channel();
+        let (release, released) = channel::<()>();
+        let handle = ThreadHandle::spawn("Test shield".to_string(), move |interrupted| {
+            let seen = interrupted.shield(|| {
+                entered.send(()).unwrap();
+                released.recv().unwrap();
+                let elsewhere = interrupted.clone();
+                let helper = ::std::thread::spawn(move || elsewhere.is_interrupted()).join().unwrap();
+                let seen = interrupted.shield(|| interrupted.is_interrupted()) || interrupted.is_interrupted();
+                (seen, helper)
+            });
+            (seen, interrupted.is_interrupted())
+        }).unwrap();
+        has_entered.recv().unwrap();
+        assert!(handle.is_shielded());
+        handle.interrupt().unwrap();
+        assert!(handle.snapshot().interrupt_requested);
+        release.send(()).unwrap();
+        assert_eq!(((false, true), true), handle.join().unwrap().unwrap());
+        assert!(!handle.is_shielded());
+    }
+}
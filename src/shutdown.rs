@@ -0,0 +1,234 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic;
+use std::time::Duration;
+
+use abort::Wait;
+use clock::WaitBudget;
+use {Shared, ThreadGroup, ThreadHandle};
+
+
+/// Node of a `ShutdownGraph`: a handle or a whole group.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(usize);
+
+struct Node {
+    members: Vec<Arc<Shared>>,
+    /// Nodes this one depends on, which must outlive it.
+    dependencies: Vec<usize>,
+}
+
+/// Shuts threads down in dependency order: a node is only interrupted once every node depending
+/// on it has terminated, so consumers stop before the producers feeding them.
+#[derive(Default)]
+pub struct ShutdownGraph {
+    nodes: Vec<Node>,
+}
+
+impl ShutdownGraph {
+    pub fn new() -> Self {
+        ShutdownGraph::default()
+    }
+
+    pub fn add<T>(&mut self, handle: &ThreadHandle<T>) -> NodeId where T: Send + 'static {
+        self.add_members(vec![handle.shared.clone()])
+    }
+
+    pub fn add_group<T>(&mut self, group: &ThreadGroup<T>) -> NodeId where T: Send + 'static {
+        self.add_members(group.handles().iter().map(|handle| handle.shared.clone()).collect())
+    }
+
+    fn add_members(&mut self, members: Vec<Arc<Shared>>) -> NodeId {
+        self.nodes.push(Node {
+            members,
+            dependencies: Vec::new(),
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Declares that `dependent` needs `dependency` running until it has stopped itself.
+    /// Fails with `InvalidInput` if that would create a cycle.
+    pub fn depends_on(&mut self, dependent: NodeId, dependency: NodeId) -> io::Result<()> {
+        if self.reaches(dependency.0, dependent.0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "shutdown dependency would create a cycle"));
+        }
+        let dependencies = &mut self.nodes[dependent.0].dependencies;
+        if !dependencies.contains(&dependency.0) {
+            dependencies.push(dependency.0);
+        }
+        Ok(())
+    }
+
+    /// Whether `to` is `from` or one of its transitive dependencies.
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        let mut pending = vec![from];
+        let mut visited = vec![false; self.nodes.len()];
+        while let Some(node) = pending.pop() {
+            if node == to {
+                return true;
+            }
+            if !visited[node] {
+                visited[node] = true;
+                pending.extend(self.nodes[node].dependencies.iter().cloned());
+            }
+        }
+        false
+    }
+
+    /// Layers of nodes in shutdown order; nodes of a layer stop together.
+    fn layers(&self) -> Vec<Vec<usize>> {
+        let mut dependents = vec![0; self.nodes.len()];
+        for node in self.nodes.iter() {
+            for &dependency in node.dependencies.iter() {
+                dependents[dependency] += 1;
+            }
+        }
+        let mut layers = Vec::new();
+        let mut layer: Vec<usize> = (0..self.nodes.len()).filter(|&node| dependents[node] == 0).collect();
+        while !layer.is_empty() {
+            let mut next = Vec::new();
+            for &node in layer.iter() {
+                for &dependency in self.nodes[node].dependencies.iter() {
+                    dependents[dependency] -= 1;
+                    if dependents[dependency] == 0 {
+                        next.push(dependency);
+                    }
+                }
+            }
+            layers.push(layer);
+            layer = next;
+        }
+        layers
+    }
+
+    /// Interrupts the threads layer by layer, waiting up to `timeout` for each layer to terminate
+    /// before moving on to the next. Returns the names of the threads that were still running
+    /// when their layer timed out.
+    pub fn shutdown(&self, timeout: Option<Duration>) -> Vec<String> {
+        let mut stragglers = Vec::new();
+        for layer in self.layers() {
+            let members: Vec<&Arc<Shared>> = layer.iter().flat_map(|&node| self.nodes[node].members.iter()).collect();
+            stop_layer(&members, timeout, &mut stragglers);
+        }
+        stragglers
+    }
+}
+
+/// Interrupts the threads of a layer, then waits up to `timeout` for all of them to terminate,
+/// adding the names of those still running to `stragglers`.
+fn stop_layer(members: &[&Arc<Shared>], timeout: Option<Duration>, stragglers: &mut Vec<String>) {
+    for shared in members.iter() {
+        shared.request_interrupt(Some("shutdown"));
+    }
+    let mut budget = WaitBudget::new(timeout);
+    for shared in members {
+        if budget.spend(&*shared.clock, |remaining| shared.wait_terminated_reporting(remaining)) != Wait::Terminated {
+            stragglers.push(shared.name().to_string());
+        }
+    }
+}
+
+/// Stops `members` one shutdown class at a time, lowest first, waiting up to `timeout` for each
+/// class; returns the names of the threads still running when their class timed out.
+pub(crate) fn shutdown_by_class(mut members: Vec<&Arc<Shared>>, timeout: Option<Duration>) -> Vec<String> {
+    members.sort_by_key(|shared| shared.shutdown_class());
+    let mut stragglers = Vec::new();
+    for layer in members.chunk_by(|first, second| first.shutdown_class() == second.shutdown_class()) {
+        stop_layer(layer, timeout, &mut stragglers);
+    }
+    stragglers
+}
+
+impl Shared {
+    fn shutdown_class(&self) -> i32 {
+        self.shutdown_class.load(atomic::Ordering::Relaxed)
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Class set with `Builder::shutdown_class`.
+    pub fn shutdown_class(&self) -> i32 {
+        self.shared.shutdown_class()
+    }
+
+    /// Changes the position of the thread in the teardown of its group or of the registry.
+    pub fn set_shutdown_class(&self, class: i32) {
+        self.shared.shutdown_class.store(class, atomic::Ordering::Relaxed);
+    }
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// Interrupts the threads of the group one shutdown class at a time, lowest first, waiting up
+    /// to `timeout` for each class to terminate. Returns the names of the threads still running
+    /// when their class timed out; results are not taken, so the threads can still be joined.
+    pub fn shutdown(&self, timeout: Option<Duration>) -> Vec<String> {
+        shutdown_by_class(self.handles().iter().map(|handle| &handle.shared).collect(), timeout)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::thread::sleep;
+    use InterruptToken;
+    use super::*;
+
+    fn stage(name: &str, log: &Arc<Mutex<Vec<String>>>) -> ThreadHandle<()> {
+        let log = log.clone();
+        let label = name.to_string();
+        ThreadHandle::spawn(format!("Test shutdown {}", name), move |interrupted: InterruptToken| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+            sleep(Duration::from_millis(30));
+            log.lock().unwrap().push(label);
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_shutdown_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let source = stage("source", &log);
+        let transform = stage("transform", &log);
+        let sink = stage("sink", &log);
+        let mut graph = ShutdownGraph::new();
+        let source_node = graph.add(&source);
+        let transform_node = graph.add(&transform);
+        let sink_node = graph.add(&sink);
+        graph.depends_on(sink_node, transform_node).unwrap();
+        graph.depends_on(transform_node, source_node).unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, graph.depends_on(source_node, sink_node).unwrap_err().kind());
+        assert!(graph.shutdown(Some(Duration::from_secs(5))).is_empty());
+        assert_eq!(vec!["sink", "transform", "source"], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn test_group_shutdown_by_class() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut group = ThreadGroup::new("Test shutdown classes".to_string());
+        for (name, class) in [("flusher", 1), ("worker", 0), ("ingress", -1)] {
+            let log = log.clone();
+            let index = group.spawn(format!("Test shutdown class {}", name), move |interrupted| {
+                while !interrupted.is_interrupted() {
+                    sleep(Duration::from_millis(10));
+                }
+                sleep(Duration::from_millis(30));
+                log.lock().unwrap().push(name);
+            }).unwrap();
+            group.handles()[index].set_shutdown_class(class);
+        }
+        assert_eq!(-1, group.handles()[2].shutdown_class());
+        assert!(group.shutdown(Some(Duration::from_secs(5))).is_empty());
+        assert_eq!(vec!["ingress", "worker", "flusher"], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn test_shutdown_reports_stragglers() {
+        let stuck = ThreadHandle::spawn("Test shutdown stuck".to_string(), |_| sleep(Duration::from_millis(300))).unwrap();
+        let mut graph = ShutdownGraph::new();
+        graph.add(&stuck);
+        assert_eq!(vec!["Test shutdown stuck"], graph.shutdown(Some(Duration::from_millis(20))));
+        stuck.join().unwrap().unwrap();
+    }
+}
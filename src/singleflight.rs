@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
+use std::sync::Mutex;
+use std::sync::mpsc::Receiver;
+
+use {HandleId, InterruptToken, ThreadHandle, ThreadStatus};
+
+
+/// Waiter for the outcome of a job started through `Singleflight::run`.
+#[derive(Debug)]
+pub struct Flight<T> {
+    id: HandleId,
+    joined: bool,
+    outcome: Receiver<Result<T, String>>,
+}
+
+impl<T> Flight<T> {
+    /// Id of the handle running the job.
+    pub fn id(&self) -> HandleId {
+        self.id
+    }
+
+    /// Whether the job was already running for the key, so this waiter shares its outcome
+    /// instead of starting it.
+    pub fn is_shared(&self) -> bool {
+        self.joined
+    }
+
+    /// Blocks until the job finishes and returns a copy of its value, or the message of its
+    /// panic.
+    pub fn wait(self) -> Result<T, String> {
+        self.outcome.recv().unwrap_or_else(|_| Err("thread terminated without a result".to_string()))
+    }
+}
+
+/// Runs at most one job per key at a time: while a job for a key runs on its thread, further
+/// requests for the key wait for its outcome instead of spawning duplicates, e.g. to keep a
+/// cache miss from loading the same entry many times over. A request after the job finished
+/// starts it again.
+pub struct Singleflight<K, T> {
+    name: String,
+    flights: Mutex<Flights<K, T>>,
+}
+
+struct Flights<K, T> {
+    running: HashMap<K, ThreadHandle<T>>,
+    started: u64,
+}
+
+impl<K, T> Singleflight<K, T> where K: Hash + Eq, T: Clone + Send + 'static {
+    /// Jobs run on threads named after `name` and a sequence number.
+    pub fn new(name: String) -> Self {
+        Singleflight {
+            name,
+            flights: Mutex::new(Flights {
+                running: HashMap::new(),
+                started: 0,
+            }),
+        }
+    }
+
+    /// Waiter for the job running for `key`, spawning `runnable` for it if there is none.
+    #[track_caller]
+    pub fn run<F>(&self, key: K, runnable: F) -> io::Result<Flight<T>> where
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let mut flights = self.flights.lock().unwrap();
+        flights.running.retain(|_, handle| !is_done(handle));
+        if let Some(handle) = flights.running.get(&key) {
+            return Ok(Flight {
+                id: handle.id(),
+                joined: true,
+                outcome: handle.subscribe_result(),
+            });
+        }
+        let handle = ThreadHandle::spawn(format!("{}-{}", self.name, flights.started), runnable)?;
+        flights.started += 1;
+        let flight = Flight {
+            id: handle.id(),
+            joined: false,
+            outcome: handle.subscribe_result(),
+        };
+        flights.running.insert(key, handle);
+        Ok(flight)
+    }
+
+    /// Number of jobs running.
+    pub fn in_flight(&self) -> usize {
+        let flights = self.flights.lock().unwrap();
+        flights.running.values().filter(|handle| !is_done(handle)).count()
+    }
+
+    /// Interrupts the job running for `key`, if any; its waiters get whatever it returns.
+    pub fn interrupt(&self, key: &K) -> bool {
+        match self.flights.lock().unwrap().running.get(key) {
+            Some(handle) => handle.interrupt().is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Whether the job produced its outcome; its thread may still be finishing.
+fn is_done<T>(handle: &ThreadHandle<T>) -> bool where T: Send + 'static {
+    handle.status() == ThreadStatus::Terminated || {
+        let slot = handle.result.lock().unwrap();
+        slot.result.is_some() || slot.deferred.is_some()
+    }
+}
+
+impl<K, T> fmt::Debug for Singleflight<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Singleflight")
+            .field("name", &self.name)
+            .field("tracked", &self.flights.lock().unwrap().running.len())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{self, AtomicUsize};
+    use std::sync::mpsc;
+    use super::*;
+
+    #[test]
+    fn test_singleflight() {
+        let singleflight = Singleflight::new("Test singleflight".to_string());
+        let loads = Arc::new(AtomicUsize::new(0));
+        let (release, released) = mpsc::channel::<()>();
+        let counted = loads.clone();
+        let first = singleflight.run("key", move |_| {
+            counted.fetch_add(1, atomic::Ordering::SeqCst);
+            released.recv().unwrap();
+            "value".to_string()
+        }).unwrap();
+        let second = singleflight.run("key", |_| unreachable!()).unwrap();
+        let other = singleflight.run("other", |_| "other".to_string()).unwrap();
+        assert!(!first.is_shared());
+        assert!(second.is_shared());
+        assert_eq!(first.id(), second.id());
+        assert_ne!(first.id(), other.id());
+        release.send(()).unwrap();
+        assert_eq!(Ok("value".to_string()), first.wait());
+        assert_eq!(Ok("value".to_string()), second.wait());
+        assert_eq!(Ok("other".to_string()), other.wait());
+        assert_eq!(1, loads.load(atomic::Ordering::SeqCst));
+
+        let again = singleflight.run("key", |_| "reloaded".to_string()).unwrap();
+        assert!(!again.is_shared());
+        assert_eq!(Ok("reloaded".to_string()), again.wait());
+        assert_eq!(0, singleflight.in_flight());
+    }
+}
@@ -0,0 +1,116 @@
+use std::io;
+use std::sync::atomic;
+use std::sync::mpsc::{Sender, SyncSender};
+use std::thread;
+
+use {Builder, HandleId, InterruptToken, ThreadHandle};
+
+
+/// Outcome of a thread spawned with a result sink, along with which thread it was.
+pub struct Delivery<T> {
+    pub id: HandleId,
+    pub thread: String,
+    pub outcome: thread::Result<T>,
+}
+
+/// Receives the outcome of a thread as soon as it terminates, on that thread; see
+/// `Builder::spawn_with_sink`.
+pub trait ResultSink<T>: Send + 'static {
+    fn deliver(self: Box<Self>, delivery: Delivery<T>);
+}
+
+impl<T> ResultSink<T> for Sender<Delivery<T>> where T: Send + 'static {
+    /// Drops the outcome if the receiver is gone.
+    fn deliver(self: Box<Self>, delivery: Delivery<T>) {
+        self.send(delivery).ok();
+    }
+}
+
+impl<T> ResultSink<T> for SyncSender<Delivery<T>> where T: Send + 'static {
+    /// Blocks the terminating thread while the channel is full; drops the outcome if the
+    /// receiver is gone.
+    fn deliver(self: Box<Self>, delivery: Delivery<T>) {
+        self.send(delivery).ok();
+    }
+}
+
+impl<T> ResultSink<T> for Box<dyn ResultSink<T>> where T: 'static {
+    fn deliver(self: Box<Self>, delivery: Delivery<T>) {
+        (*self).deliver(delivery)
+    }
+}
+
+/// Sink calling a closure, made with `sink_fn`.
+pub struct FnSink<F>(F);
+
+impl<T, F> ResultSink<T> for FnSink<F> where F: FnOnce(Delivery<T>) + Send + 'static {
+    fn deliver(self: Box<Self>, delivery: Delivery<T>) {
+        (self.0)(delivery)
+    }
+}
+
+/// Sink calling `f` with the outcome.
+pub fn sink_fn<T, F>(f: F) -> FnSink<F> where F: FnOnce(Delivery<T>) + Send + 'static {
+    FnSink(f)
+}
+
+impl Builder {
+    /// Spawns a thread whose outcome goes to `sink` as soon as it terminates, so that a
+    /// fire-and-forget thread cannot lose its error or panic; `join` then returns `None`.
+    #[track_caller]
+    pub fn spawn_with_sink<T, S, F>(&self, sink: S, runnable: F) -> io::Result<ThreadHandle<T>> where
+        T: Send + 'static,
+        S: ResultSink<T>,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        let handle = self.lazy(runnable);
+        let (id, thread, shared) = (handle.id(), handle.name().to_string(), handle.shared.clone());
+        let sink = Box::new(sink);
+        handle.result.lock().unwrap().forward = Some(Box::new(move |outcome| {
+            shared.joined.store(true, atomic::Ordering::Release);
+            sink.deliver(Delivery { id, thread, outcome });
+        }));
+        handle.ensure_started()?;
+        Ok(handle)
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Like `Builder::spawn_with_sink`, with the default configuration.
+    #[track_caller]
+    pub fn spawn_with_sink<S, F>(name: String, sink: S, runnable: F) -> io::Result<Self> where
+        S: ResultSink<T>,
+        F: FnOnce(InterruptToken) -> T, F: Send + 'static
+    {
+        Builder::new(name).spawn_with_sink(sink, runnable)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use super::*;
+
+    #[test]
+    fn test_result_sink() {
+        let (sender, receiver) = channel();
+        for i in 0..3 {
+            ThreadHandle::spawn_with_sink(format!("Test sink {}", i), sender.clone(), move |_| -> u32 {
+                if i == 1 {
+                    panic!("failed");
+                }
+                i
+            }).unwrap();
+        }
+        let mut deliveries: Vec<_> = receiver.iter().take(3).map(|delivery| (delivery.thread, delivery.outcome.ok())).collect();
+        deliveries.sort();
+        assert_eq!(vec![("Test sink 0".to_string(), Some(0)), ("Test sink 1".to_string(), None), ("Test sink 2".to_string(), Some(2))], deliveries);
+
+        let (sender, receiver) = channel();
+        let sink: Box<dyn ResultSink<&str>> = Box::new(sink_fn(move |delivery: Delivery<&str>| sender.send((delivery.id, delivery.outcome.unwrap())).unwrap()));
+        let handle = ThreadHandle::spawn_with_sink("Test sink callback".to_string(), sink, |_| "done").unwrap();
+        assert_eq!((handle.id(), "done"), receiver.recv().unwrap());
+        assert!(handle.join().is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use std::cmp;
+use std::sync::atomic::{self, AtomicU64};
+use std::time::Duration;
+
+use abort::Wait;
+use events::{self, ThreadEvent};
+use Shared;
+
+
+/// Threshold in nanoseconds; zero disables the reports.
+static SLOW_JOIN_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+
+/// Publishes `ThreadEvent::SlowJoin`, naming the thread, every time `join` or a shutdown has
+/// been waiting for it another `threshold`, so a hang during teardown identifies its culprit.
+/// Disabled with `None`, the default.
+pub fn set_slow_join_threshold(threshold: Option<Duration>) {
+    let nanos = threshold.map_or(0, |threshold| cmp::max(threshold.as_nanos() as u64, 1));
+    SLOW_JOIN_THRESHOLD.store(nanos, atomic::Ordering::Relaxed);
+}
+
+fn threshold() -> Option<Duration> {
+    match SLOW_JOIN_THRESHOLD.load(atomic::Ordering::Relaxed) {
+        0 => None,
+        nanos => Some(Duration::from_nanos(nanos)),
+    }
+}
+
+impl Shared {
+    /// Like `wait_cancellable`, reporting each time the wait exceeds another threshold.
+    pub(crate) fn wait_terminated_reporting(&self, timeout: Option<Duration>) -> Wait {
+        let generation = self.wait_generation();
+        let threshold = match threshold() {
+            Some(threshold) => threshold,
+            None => return self.wait_cancellable(generation, timeout),
+        };
+        let started = self.clock.now();
+        let deadline = timeout.map(|timeout| started + timeout);
+        let mut report_at = started + threshold;
+        loop {
+            let until = deadline.map_or(report_at, |deadline| cmp::min(deadline, report_at));
+            match self.wait_cancellable(generation, Some(until.saturating_duration_since(self.clock.now()))) {
+                Wait::TimedOut => (),
+                wait => return wait,
+            }
+            let now = self.clock.now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Wait::TimedOut;
+            }
+            events::publish(self, || ThreadEvent::SlowJoin { waited: now.saturating_duration_since(started) });
+            report_at += threshold;
+        }
+    }
+}
+
@@ -0,0 +1,203 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use {HandleId, Progress, ServiceState, Shared, SpawnOrigin, ThreadGroup, ThreadHandle, ThreadStatus};
+
+
+/// Real time between two evaluations of the predicate of `wait_until`.
+const POLL: Duration = Duration::from_millis(10);
+
+/// State of a handle at one point in time.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Snapshot {
+    pub id: HandleId,
+    pub name: String,
+    pub status: ThreadStatus,
+    /// Whether the runnable panicked, or failed by returning an error; only once terminated.
+    pub panicked: bool,
+    /// `Debug` form of the error returned by a runnable spawned with `spawn_fallible`.
+    pub failure: Option<String>,
+    pub interrupt_requested: bool,
+    /// Whether the runnable is in a section where it does not observe interruption.
+    pub shielded: bool,
+    pub uptime: Option<Duration>,
+    /// Time since the last heartbeat, or since the start if there was none; `None` until it starts.
+    pub heartbeat_age: Option<Duration>,
+    pub progress: Option<Progress>,
+    pub service_state: ServiceState,
+    pub origin: Option<SpawnOrigin>,
+}
+
+/// Change of a thread between two sets of snapshots, see `Snapshot::diff`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Change {
+    Started { id: HandleId, name: String },
+    Finished { id: HandleId, name: String },
+    Panicked { id: HandleId, name: String },
+    /// The thread has been silent for longer than the threshold given to `diff`.
+    WentStale { id: HandleId, name: String, heartbeat_age: Duration },
+    /// A stale thread sent a heartbeat again.
+    Recovered { id: HandleId, name: String },
+}
+
+impl Snapshot {
+    /// Changes between `old` and `new`, two sets of snapshots taken of a group or of the
+    /// registry at different times, in the order of `new`; a thread counts as stale once its
+    /// heartbeat is older than `stale_after`. Threads only found in `old` are left out, as
+    /// joined threads leave the registry.
+    pub fn diff(old: &[Snapshot], new: &[Snapshot], stale_after: Duration) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for snapshot in new {
+            let (id, name) = (snapshot.id, snapshot.name.clone());
+            let before = old.iter().find(|old| old.id == id);
+            if before.is_none_or(|before| before.uptime.is_none()) && snapshot.uptime.is_some() {
+                changes.push(Change::Started { id, name: name.clone() });
+            }
+            if snapshot.status == ThreadStatus::Terminated && before.is_none_or(|before| before.status != ThreadStatus::Terminated) {
+                if snapshot.panicked {
+                    changes.push(Change::Panicked { id, name });
+                } else {
+                    changes.push(Change::Finished { id, name });
+                }
+                continue;
+            }
+            let was_stale = before.is_some_and(|before| before.is_stale(stale_after));
+            match snapshot.heartbeat_age {
+                Some(heartbeat_age) if snapshot.is_stale(stale_after) && !was_stale => {
+                    changes.push(Change::WentStale { id, name, heartbeat_age });
+                }
+                _ if was_stale && !snapshot.is_stale(stale_after) => changes.push(Change::Recovered { id, name }),
+                _ => (),
+            }
+        }
+        changes
+    }
+
+    fn is_stale(&self, threshold: Duration) -> bool {
+        self.status != ThreadStatus::Terminated && self.heartbeat_age.is_some_and(|age| age > threshold)
+    }
+}
+
+impl Shared {
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            id: self.id,
+            name: self.name().to_string(),
+            status: self.status(),
+            panicked: self.is_terminated() && self.has_panicked(),
+            failure: self.returned_error.get().cloned(),
+            interrupt_requested: self.is_interrupt_requested(),
+            shielded: self.is_shielded(),
+            uptime: self.uptime(),
+            heartbeat_age: self.silence(self.clock.now()),
+            progress: *self.progress.lock().unwrap(),
+            service_state: self.service_state(),
+            origin: self.spawn_origin(),
+        }
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Current state of the handle; never starts a lazy handle.
+    pub fn snapshot(&self) -> Snapshot {
+        self.shared.snapshot()
+    }
+
+    /// Waits until `predicate` holds for a snapshot of the handle, evaluating it every few
+    /// milliseconds, and returns that snapshot; `None` if `timeout` elapsed first.
+    pub fn wait_until<P>(&self, timeout: Option<Duration>, mut predicate: P) -> Option<Snapshot> where P: FnMut(&Snapshot) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let snapshot = self.snapshot();
+            if predicate(&snapshot) {
+                return Some(snapshot);
+            }
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => return None,
+                Some(deadline) => sleep(POLL.min(deadline - now)),
+                None => sleep(POLL),
+            }
+        }
+    }
+}
+
+impl<T> ThreadGroup<T> where T: Send + 'static {
+    /// Current state of every thread of the group, in index order.
+    pub fn snapshot(&self) -> Vec<Snapshot> {
+        self.handles().iter().map(ThreadHandle::snapshot).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_until() {
+        let handle = ThreadHandle::spawn("Test wait until".to_string(), |interrupted| {
+            for done in 1..=10 {
+                interrupted.report_progress(done, 10);
+                sleep(Duration::from_millis(10));
+            }
+            interrupted.set_ready();
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+        }).unwrap();
+        let halfway = handle.wait_until(Some(Duration::from_secs(5)), |snapshot| {
+            snapshot.progress.and_then(|progress| progress.fraction).is_some_and(|fraction| fraction >= 0.5)
+        }).unwrap();
+        assert_eq!("Test wait until", halfway.name);
+        assert_eq!(ThreadStatus::Running, halfway.status);
+        assert!(handle.wait_until(None, |snapshot| snapshot.service_state == ServiceState::Ready).is_some());
+        assert!(handle.wait_until(Some(Duration::from_millis(30)), |snapshot| snapshot.interrupt_requested).is_none());
+        handle.interrupt().unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(ThreadStatus::Terminated, handle.snapshot().status);
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let mut group = ThreadGroup::new("Test diff".to_string());
+        let (release, released) = ::std::sync::mpsc::channel::<()>();
+        group.spawn("Test diff silent".to_string(), move |interrupted| {
+            released.recv().ok();
+            interrupted.heartbeat();
+            while !interrupted.is_interrupted() {
+                interrupted.heartbeat();
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        group.spawn("Test diff failing".to_string(), |_| {
+            sleep(Duration::from_millis(20));
+            panic!("failed")
+        }).unwrap();
+        for handle in group.handles() {
+            handle.wait_until(None, |snapshot| snapshot.uptime.is_some());
+        }
+        let started = group.snapshot();
+        let changes = Snapshot::diff(&[], &started, Duration::from_secs(60));
+        assert_eq!(vec!["Test diff silent", "Test diff failing"], changes.iter().map(|change| match *change {
+            Change::Started { ref name, .. } => name.as_str(),
+            ref other => panic!("unexpected {:?}", other),
+        }).collect::<Vec<_>>());
+        group.handles()[1].wait_finished(None);
+        sleep(Duration::from_millis(30));
+        let later = group.snapshot();
+        let (silent, failing) = (later[0].id, later[1].id);
+        let heartbeat_age = later[0].heartbeat_age.unwrap();
+        assert_eq!(vec![
+            Change::WentStale { id: silent, name: "Test diff silent".to_string(), heartbeat_age },
+            Change::Panicked { id: failing, name: "Test diff failing".to_string() },
+        ], Snapshot::diff(&started, &later, Duration::from_millis(20)));
+        assert!(Snapshot::diff(&later, &later, Duration::from_millis(20)).is_empty());
+        release.send(()).unwrap();
+        let recovered = group.handles()[0].wait_until(Some(Duration::from_secs(5)), |snapshot| snapshot.heartbeat_age.is_some_and(|age| age < Duration::from_millis(20))).unwrap();
+        assert_eq!(vec![Change::Recovered { id: silent, name: "Test diff silent".to_string() }], Snapshot::diff(&later[..1], &[recovered], Duration::from_millis(20)));
+        group.cancel_with_deadline(Duration::from_secs(5));
+        let stopped = group.snapshot();
+        assert_eq!(Change::Finished { id: silent, name: "Test diff silent".to_string() }, Snapshot::diff(&later, &stopped, Duration::from_millis(20))[0]);
+    }
+}
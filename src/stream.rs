@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+use std::io;
+#[cfg(feature = "stream")]
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+#[cfg(feature = "stream")]
+use std::task::{Context, Poll};
+use std::task::Waker;
+use std::time::Duration;
+
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+
+use {InterruptToken, ThreadHandle};
+
+
+/// How often a producer blocked on a full sink looks for interruption.
+const INTERRUPT_CHECK: Duration = Duration::from_millis(10);
+
+struct Buffer<T> {
+    items: VecDeque<T>,
+    producer_done: bool,
+    consumer_gone: bool,
+    /// Tasks polling the stream for the next item.
+    wakers: Vec<Waker>,
+}
+
+impl<T> Buffer<T> {
+    fn wake(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+struct Channel<T> {
+    buffer: Mutex<Buffer<T>>,
+    capacity: usize,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+/// Producer side of a stream, given to the runnable of `spawn_stream`.
+pub struct Sink<T> {
+    channel: Arc<Channel<T>>,
+    token: InterruptToken,
+}
+
+impl<T> Sink<T> {
+    /// Pushes an item, blocking while the buffer is full. Returns `false`, dropping the item,
+    /// if the consumer went away or interruption was requested.
+    pub fn send(&self, item: T) -> bool {
+        let mut buffer = self.channel.buffer.lock().unwrap();
+        while buffer.items.len() >= self.channel.capacity && !buffer.consumer_gone {
+            if self.token.is_interrupted() {
+                return false;
+            }
+            buffer = self.channel.not_full.wait_timeout(buffer, INTERRUPT_CHECK).unwrap().0;
+        }
+        if buffer.consumer_gone || self.token.is_interrupted() {
+            return false;
+        }
+        buffer.items.push_back(item);
+        buffer.wake();
+        self.channel.not_empty.notify_one();
+        true
+    }
+}
+
+/// Marks the stream finished when the runnable exits, including by panicking.
+struct Close<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Drop for Close<T> {
+    fn drop(&mut self) {
+        let mut buffer = match self.channel.buffer.lock() {
+            Ok(buffer) => buffer,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        buffer.producer_done = true;
+        buffer.wake();
+        self.channel.not_empty.notify_all();
+    }
+}
+
+/// Thread producing a stream of items; iterating blocks until the next item is available and
+/// ends once the thread has exited and every item was consumed. With the `stream` feature it is
+/// also a `futures_core::Stream` of the same items.
+pub struct StreamHandle<T> {
+    channel: Arc<Channel<T>>,
+    handle: ThreadHandle<()>,
+}
+
+impl<T> StreamHandle<T> where T: Send + 'static {
+    pub fn handle(&self) -> &ThreadHandle<()> {
+        &self.handle
+    }
+}
+
+impl<T> Channel<T> {
+    fn recv(&self) -> Option<T> {
+        let mut buffer = self.buffer.lock().unwrap();
+        loop {
+            if let Some(item) = buffer.items.pop_front() {
+                self.not_full.notify_one();
+                return Some(item);
+            }
+            if buffer.producer_done {
+                return None;
+            }
+            buffer = self.not_empty.wait(buffer).unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<T> Stream for StreamHandle<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<T>> {
+        let mut buffer = self.channel.buffer.lock().unwrap();
+        if let Some(item) = buffer.items.pop_front() {
+            self.channel.not_full.notify_one();
+            return Poll::Ready(Some(item));
+        }
+        if buffer.producer_done {
+            return Poll::Ready(None);
+        }
+        if !buffer.wakers.iter().any(|waker| waker.will_wake(context.waker())) {
+            buffer.wakers.push(context.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> StreamHandle<T> {
+    /// Iterates through a shared reference, so that several consumers can share the items.
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter { channel: &self.channel }
+    }
+}
+
+impl<T> Iterator for StreamHandle<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.channel.recv()
+    }
+}
+
+pub struct Iter<'a, T: 'a> {
+    channel: &'a Channel<T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.channel.recv()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a StreamHandle<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> Drop for StreamHandle<T> {
+    fn drop(&mut self) {
+        let mut buffer = match self.channel.buffer.lock() {
+            Ok(buffer) => buffer,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        buffer.consumer_gone = true;
+        buffer.items.clear();
+        self.channel.not_full.notify_all();
+    }
+}
+
+impl ThreadHandle<()> {
+    /// Spawns a producer thread pushing items into a sink buffering up to `capacity` of them.
+    pub fn spawn_stream<T, F>(name: String, capacity: usize, runnable: F) -> io::Result<StreamHandle<T>> where
+        T: Send + 'static,
+        F: FnOnce(InterruptToken, Sink<T>), F: Send + 'static
+    {
+        let channel = Arc::new(Channel {
+            buffer: Mutex::new(Buffer {
+                items: VecDeque::new(),
+                producer_done: false,
+                consumer_gone: false,
+                wakers: Vec::new(),
+            }),
+            capacity: capacity.max(1),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        });
+        let producer = channel.clone();
+        let handle = ThreadHandle::spawn(name, move |interrupted: InterruptToken| {
+            let _close = Close { channel: producer.clone() };
+            let sink = Sink {
+                channel: producer,
+                token: interrupted.clone(),
+            };
+            runnable(interrupted, sink)
+        })?;
+        Ok(StreamHandle { channel, handle })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use super::*;
+
+    #[test]
+    fn test_stream() {
+        let stream = ThreadHandle::spawn_stream("Test stream".to_string(), 2, |_, sink| {
+            for i in 0..5 {
+                assert!(sink.send(i));
+            }
+        }).unwrap();
+        assert_eq!(vec![0, 1, 2, 3, 4], stream.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_stream_producer_stops() {
+        let mut stream = ThreadHandle::spawn_stream("Test stream interrupt".to_string(), 1, |_, sink| {
+            let mut next = 0;
+            while sink.send(next) {
+                next += 1;
+            }
+        }).unwrap();
+        assert_eq!(Some(0), stream.next());
+        sleep(Duration::from_millis(50));
+        stream.handle().interrupt().unwrap();
+        assert!(stream.handle().join().unwrap().is_ok());
+        assert_eq!(vec![1], stream.collect::<Vec<_>>());
+
+        let stream = ThreadHandle::spawn_stream("Test stream consumer gone".to_string(), 1, |_, sink| {
+            while sink.send(()) {}
+        }).unwrap();
+        let producer = stream.handle().shared.clone();
+        drop(stream);
+        assert!(producer.wait_terminated(Some(Duration::from_secs(5))));
+    }
+
+    #[test]
+    fn test_stream_ends_on_panic() {
+        let stream = ThreadHandle::spawn_stream("Test stream panic".to_string(), 4, |_, sink| {
+            sink.send("first");
+            panic!("producer failed");
+        }).unwrap();
+        assert_eq!(vec!["first"], stream.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_stream_by_reference() {
+        let stream = ThreadHandle::spawn_stream("Test stream shared".to_string(), 8, |_, sink| {
+            for i in 0..4 {
+                sink.send(i);
+            }
+        }).unwrap();
+        let mut received = Vec::new();
+        for item in &stream {
+            received.push(item);
+        }
+        assert_eq!(vec![0, 1, 2, 3], received);
+        assert!(stream.handle().join().unwrap().is_ok());
+        assert_eq!(None, stream.iter().next());
+    }
+
+    #[cfg(feature = "stream")]
+    struct Unpark(::std::thread::Thread);
+
+    #[cfg(feature = "stream")]
+    impl ::std::task::Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_stream_async() {
+        let mut stream = ThreadHandle::spawn_stream("Test stream async".to_string(), 1, |_, sink| {
+            for i in 0..3 {
+                sleep(Duration::from_millis(10));
+                sink.send(i);
+            }
+        }).unwrap();
+        let waker = Waker::from(Arc::new(Unpark(::std::thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut received = Vec::new();
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut context) {
+                Poll::Ready(Some(item)) => received.push(item),
+                Poll::Ready(None) => break,
+                Poll::Pending => ::std::thread::park(),
+            }
+        }
+        assert_eq!(vec![0, 1, 2], received);
+    }
+}
@@ -0,0 +1,343 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::{atomic, Arc};
+use std::time::{Duration, Instant};
+
+use clock::{self, Clock};
+use counters::AtomicCounters;
+use reload::Factory;
+use retry::{self, AttemptOutcome};
+use {InterruptToken, ThreadHandle};
+
+
+/// Failure of a supervised runnable, as given to the classifier of a `RestartPolicy`.
+#[derive(Debug)]
+pub enum ChildFailure<'a, E: 'a> {
+    /// The runnable panicked with this payload, see `PanicPayload`.
+    Panicked(&'a (dyn Any + Send)),
+    Returned(&'a E),
+}
+
+/// What a supervised thread does after a failure, see `RestartPolicy::classify`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FailureAction {
+    /// Restarts after the delay, as long as the restart limit allows.
+    Restart,
+    /// Restarts after the delay without counting towards the restart limit, but towards a
+    /// limit of its own: at most `max_restarts` ignored failures within the same window.
+    Ignore,
+    /// Terminates with the failure, reported like a panic even for a returned error, so that it
+    /// reaches panic handlers, linked threads and monitors.
+    Escalate,
+    /// Terminates with the failure as its outcome.
+    GiveUp,
+}
+
+type Classify<E> = dyn Fn(ChildFailure<E>) -> FailureAction + Send + Sync;
+
+/// When a supervised runnable returning errors of type `E` is restarted after failing.
+pub struct RestartPolicy<E> {
+    max_restarts: u32,
+    /// Window `max_restarts` applies to; `None` counts every restart.
+    within: Option<Duration>,
+    delay: Duration,
+    clock: Arc<dyn Clock>,
+    classifier: Option<Arc<Classify<E>>>,
+}
+
+impl<E> Clone for RestartPolicy<E> {
+    fn clone(&self) -> Self {
+        RestartPolicy {
+            max_restarts: self.max_restarts,
+            within: self.within,
+            delay: self.delay,
+            clock: self.clock.clone(),
+            classifier: self.classifier.clone(),
+        }
+    }
+}
+
+impl<E> fmt::Debug for RestartPolicy<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RestartPolicy")
+            .field("max_restarts", &self.max_restarts)
+            .field("within", &self.within)
+            .field("delay", &self.delay)
+            .field("classified", &self.classifier.is_some())
+            .finish()
+    }
+}
+
+impl<E> RestartPolicy<E> {
+    /// Restarts without limit, 100 ms after each failure.
+    pub fn new() -> Self {
+        RestartPolicy {
+            max_restarts: u32::MAX,
+            within: None,
+            delay: Duration::from_millis(100),
+            clock: clock::system(),
+            classifier: None,
+        }
+    }
+
+    /// Gives up once `restarts` restarts happened within `within`, keeping the last failure as
+    /// the outcome of the thread.
+    pub fn max_restarts(mut self, restarts: u32, within: Option<Duration>) -> Self {
+        self.max_restarts = restarts;
+        self.within = within;
+        self
+    }
+
+    /// Time to wait after a failure before restarting.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Clock measuring the restart window and delays.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Decides per failure what the thread does, e.g. to restart after a transient IO error but
+    /// escalate a logic error. Without a classifier every failure is restarted.
+    pub fn classify<C>(mut self, classifier: C) -> Self where
+        C: Fn(ChildFailure<E>) -> FailureAction + Send + Sync + 'static
+    {
+        self.classifier = Some(Arc::new(classifier));
+        self
+    }
+
+    fn action(&self, failure: ChildFailure<E>) -> FailureAction {
+        self.classifier.as_ref().map_or(FailureAction::Restart, |classifier| classifier(failure))
+    }
+}
+
+/// Forgets the restarts that happened before the window of the policy.
+fn forget_expired(restarts: &mut VecDeque<Instant>, now: Instant, within: Option<Duration>) {
+    if let Some(within) = within {
+        while restarts.front().is_some_and(|&restarted| now.saturating_duration_since(restarted) > within) {
+            restarts.pop_front();
+        }
+    }
+}
+
+impl<E> Default for RestartPolicy<E> {
+    fn default() -> Self {
+        RestartPolicy::new()
+    }
+}
+
+impl<T, E> ThreadHandle<Result<T, E>> where T: Send + 'static, E: Clone + fmt::Debug + Send + Sync + 'static {
+    /// Spawns a thread running closures produced by `factory`, restarting it in place whenever
+    /// one panics or returns `Err`, as long as `policy` allows, without a supervisor tree. The
+    /// handle keeps its identity across restarts; it terminates once a runnable returns `Ok`,
+    /// the thread is interrupted, or the policy gives up, with the outcome of the last run. Each
+    /// new runnable starts out like a new thread, without the service state, progress or
+    /// heartbeat of the previous one. The runnables can be replaced with `reload`.
+    #[track_caller]
+    pub fn spawn_supervised<F, R>(name: String, policy: RestartPolicy<E>, mut factory: F) -> io::Result<Self> where
+        F: FnMut() -> R, F: Send + 'static,
+        R: FnOnce(InterruptToken) -> Result<T, E>
+    {
+        let handle = ThreadHandle::spawn(name, move |interrupted: InterruptToken| {
+            let _reloads = interrupted.shared.accept_reloads();
+            let mut reloaded: Option<Factory<T, E>> = None;
+            let mut restarts = VecDeque::new();
+            let mut ignored = VecDeque::new();
+            let mut number = 0;
+            loop {
+                number += 1;
+                let result = match reloaded {
+                    Some(ref mut factory) => retry::run_attempt(&interrupted, &*policy.clock, number, factory()),
+                    None => retry::run_attempt(&interrupted, &*policy.clock, number, factory()),
+                };
+                let action = match result {
+                    Ok(Ok(_)) => None,
+                    Ok(Err(ref error)) => Some(policy.action(ChildFailure::Returned(error))),
+                    Err(ref payload) => Some(policy.action(ChildFailure::Panicked(&**payload))),
+                };
+                let now = policy.clock.now();
+                if !interrupted.shared.is_interrupt_requested() {
+                    if let Some(factory) = interrupted.shared.take_reload() {
+                        reloaded = Some(factory);
+                        continue;
+                    }
+                    if interrupted.shared.take_restart() {
+                        AtomicCounters::increment(&interrupted.shared.counters.restarts);
+                        continue;
+                    }
+                }
+                forget_expired(&mut restarts, now, policy.within);
+                forget_expired(&mut ignored, now, policy.within);
+                let stop = match action {
+                    Some(FailureAction::Restart) => restarts.len() >= policy.max_restarts as usize,
+                    Some(FailureAction::Ignore) => ignored.len() >= policy.max_restarts as usize,
+                    Some(FailureAction::Escalate) | Some(FailureAction::GiveUp) | None => true,
+                };
+                if !stop && !interrupted.is_interrupted() {
+                    let deadline = now + policy.delay;
+                    clock::sleep_until(&*policy.clock, deadline, || interrupted.is_interrupted());
+                }
+                if stop || interrupted.is_interrupted() {
+                    if let (Some(FailureAction::Escalate), &Ok(Err(ref error))) = (action, &result) {
+                        interrupted.shared.record_error(error);
+                    }
+                    return retry::conclude(result);
+                }
+                match action {
+                    Some(FailureAction::Restart) => restarts.push_back(policy.clock.now()),
+                    Some(FailureAction::Ignore) => ignored.push_back(policy.clock.now()),
+                    _ => (),
+                }
+                AtomicCounters::increment(&interrupted.shared.counters.restarts);
+            }
+        })?;
+        handle.shared.mark_supervised();
+        Ok(handle)
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Number of times the runnable was restarted by `spawn_supervised` or `spawn_with_retry`.
+    pub fn restart_count(&self) -> u64 {
+        self.shared.counters.restarts.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Outcome of the latest failed run of a supervised or retried runnable.
+    pub fn last_failure(&self) -> Option<AttemptOutcome> {
+        let attempts = self.shared.attempts.lock().unwrap();
+        attempts.iter().rev().map(|attempt| &attempt.outcome).find(|&outcome| *outcome != AttemptOutcome::Succeeded).cloned()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread::sleep;
+    use panics::PanicPayload;
+    use ServiceState;
+    use ThreadStatus;
+    use super::*;
+
+    #[test]
+    fn test_supervised_restarts_in_place() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let policy = RestartPolicy::new().delay(Duration::from_millis(5));
+        let handle = ThreadHandle::spawn_supervised("Test supervised".to_string(), policy, move || {
+            let run = counter.fetch_add(1, atomic::Ordering::SeqCst);
+            move |interrupted: InterruptToken| {
+                match run {
+                    0 => panic!("crashed"),
+                    1 => Err("connection lost"),
+                    _ => {
+                        while !interrupted.is_interrupted() {
+                            sleep(Duration::from_millis(5));
+                        }
+                        Ok(run)
+                    }
+                }
+            }
+        }).unwrap();
+        let id = handle.id();
+        while runs.load(atomic::Ordering::SeqCst) < 3 {
+            sleep(Duration::from_millis(5));
+        }
+        assert_eq!(2, handle.restart_count());
+        assert_eq!(Some(AttemptOutcome::Failed("\"connection lost\"".to_string())), handle.last_failure());
+        handle.interrupt().unwrap();
+        assert_eq!(Ok(2), handle.join().unwrap().unwrap());
+        assert_eq!(id, handle.id());
+    }
+
+    #[test]
+    fn test_supervised_restart_starts_afresh() {
+        let (restarted, on_restarted) = ::std::sync::mpsc::channel();
+        let mut run = 0;
+        let policy = RestartPolicy::new().delay(Duration::from_millis(1));
+        let handle = ThreadHandle::spawn_supervised("Test supervised afresh".to_string(), policy, move || {
+            run += 1;
+            let restarted = restarted.clone();
+            move |interrupted: InterruptToken| {
+                if run == 1 {
+                    interrupted.set_ready();
+                    interrupted.report_progress(5, 10);
+                    interrupted.heartbeat();
+                    return Err("crashed");
+                }
+                restarted.send(()).unwrap();
+                while !interrupted.is_interrupted() {
+                    sleep(Duration::from_millis(5));
+                }
+                Ok(())
+            }
+        }).unwrap();
+        on_restarted.recv().unwrap();
+        assert_eq!(ServiceState::Starting, handle.service_state());
+        assert!(handle.progress().is_none());
+        assert!(handle.last_heartbeat().is_none());
+        handle.interrupt().unwrap();
+        assert_eq!(Ok(()), handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_supervised_gives_up() {
+        let policy = RestartPolicy::new().delay(Duration::from_millis(1)).max_restarts(3, Some(Duration::from_secs(60)));
+        let handle = ThreadHandle::spawn_supervised("Test supervised gives up".to_string(), policy, || {
+            |_| Err::<(), _>("unavailable")
+        }).unwrap();
+        assert_eq!(Err("unavailable"), handle.join().unwrap().unwrap());
+        assert_eq!(3, handle.restart_count());
+        assert_eq!(4, handle.attempts().len());
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum SyncError {
+        Timeout,
+        Corrupt,
+    }
+
+    #[test]
+    fn test_supervised_classifier() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let policy = RestartPolicy::new().delay(Duration::from_millis(1)).max_restarts(3, None).classify(|failure: ChildFailure<SyncError>| {
+            match failure {
+                ChildFailure::Returned(&SyncError::Timeout) => FailureAction::Ignore,
+                ChildFailure::Returned(&SyncError::Corrupt) => FailureAction::Escalate,
+                ChildFailure::Panicked(payload) if payload.message() == Some("retry me") => FailureAction::Ignore,
+                ChildFailure::Panicked(_) => FailureAction::GiveUp,
+            }
+        });
+        let handle = ThreadHandle::spawn_supervised("Test supervised classifier".to_string(), policy, move || {
+            let run = counter.fetch_add(1, atomic::Ordering::SeqCst);
+            move |_: InterruptToken| match run {
+                0 | 1 => Err::<(), _>(SyncError::Timeout),
+                2 => panic!("retry me"),
+                _ => Err(SyncError::Corrupt),
+            }
+        }).unwrap();
+        let monitor = handle.monitor();
+        assert_eq!(Err(SyncError::Corrupt), handle.join().unwrap().unwrap());
+        assert_eq!(ThreadStatus::Failed(SyncError::Corrupt), handle.fallible_status());
+        assert_eq!(4, runs.load(atomic::Ordering::SeqCst));
+        assert_eq!(3, handle.restart_count());
+        assert_eq!(::DownReason::Failed("Corrupt".to_string()), monitor.recv().unwrap().reason);
+    }
+
+    #[test]
+    fn test_supervised_ignore_limit() {
+        let policy = RestartPolicy::new().delay(Duration::from_millis(1)).max_restarts(2, None).classify(|_| FailureAction::Ignore);
+        let handle = ThreadHandle::spawn_supervised("Test supervised ignore limit".to_string(), policy, || {
+            |_: InterruptToken| Err::<(), _>(SyncError::Timeout)
+        }).unwrap();
+        assert_eq!(Err(SyncError::Timeout), handle.join().unwrap().unwrap());
+        assert_eq!(2, handle.restart_count());
+        assert_eq!(3, handle.attempts().len());
+    }
+}
@@ -0,0 +1,400 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs;
+    use std::mem;
+    use std::os::raw::{c_int, c_long};
+    use std::time::Duration;
+
+    #[repr(C)]
+    struct Timespec {
+        tv_sec: c_long,
+        tv_nsec: c_long,
+    }
+
+    const CLOCK_THREAD_CPUTIME_ID: c_int = 3;
+
+    extern "C" {
+        fn clock_gettime(clock: c_int, time: *mut Timespec) -> c_int;
+        fn syscall(number: c_long, ...) -> c_long;
+    }
+
+    // The `gettid` wrapper is missing from glibc before 2.30 and from older musl.
+    #[cfg(target_arch = "x86_64")]
+    const SYS_GETTID: Option<c_long> = Some(186);
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64", target_arch = "loongarch64"))]
+    const SYS_GETTID: Option<c_long> = Some(178);
+    #[cfg(any(target_arch = "x86", target_arch = "arm"))]
+    const SYS_GETTID: Option<c_long> = Some(224);
+    #[cfg(any(target_arch = "powerpc", target_arch = "powerpc64"))]
+    const SYS_GETTID: Option<c_long> = Some(207);
+    #[cfg(target_arch = "s390x")]
+    const SYS_GETTID: Option<c_long> = Some(236);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64", target_arch = "loongarch64",
+                  target_arch = "x86", target_arch = "arm", target_arch = "powerpc", target_arch = "powerpc64",
+                  target_arch = "s390x")))]
+    const SYS_GETTID: Option<c_long> = None;
+
+    /// Kernel id of the calling thread, where the number of the system call is known.
+    pub fn gettid() -> Option<c_int> {
+        SYS_GETTID.map(|number| unsafe { syscall(number) as c_int })
+    }
+
+    const CPU_SET_WORDS: usize = 16;
+
+    #[repr(C)]
+    struct CpuSet {
+        bits: [u64; CPU_SET_WORDS],
+    }
+
+    extern "C" {
+        fn sched_getaffinity(pid: c_int, size: usize, mask: *mut CpuSet) -> c_int;
+        fn sched_setaffinity(pid: c_int, size: usize, mask: *const CpuSet) -> c_int;
+    }
+
+    pub fn allowed_cpus() -> Option<Vec<usize>> {
+        let mut set = CpuSet { bits: [0; CPU_SET_WORDS] };
+        if unsafe { sched_getaffinity(0, mem::size_of::<CpuSet>(), &mut set) } != 0 {
+            return None;
+        }
+        Some((0..CPU_SET_WORDS * 64).filter(|&cpu| set.bits[cpu / 64] & (1 << (cpu % 64)) != 0).collect())
+    }
+
+    pub fn pin_to_cpus(cpus: &[usize]) -> bool {
+        if cpus.is_empty() || cpus.iter().any(|&cpu| cpu >= CPU_SET_WORDS * 64) {
+            return false;
+        }
+        let mut set = CpuSet { bits: [0; CPU_SET_WORDS] };
+        for &cpu in cpus {
+            set.bits[cpu / 64] |= 1 << (cpu % 64);
+        }
+        unsafe { sched_setaffinity(0, mem::size_of::<CpuSet>(), &set) == 0 }
+    }
+
+    pub fn thread_cpu_time() -> Option<Duration> {
+        let mut time = Timespec { tv_sec: 0, tv_nsec: 0 };
+        if unsafe { clock_gettime(CLOCK_THREAD_CPUTIME_ID, &mut time) } == 0 {
+            Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+        } else {
+            None
+        }
+    }
+
+    pub fn thread_id() -> Option<u64> {
+        gettid().map(|tid| tid as u64)
+    }
+
+    /// Kernel stack of a thread of this process, which reading usually requires privileges,
+    /// falling back to the kernel function it waits in.
+    pub fn thread_stack(tid: u64) -> Option<String> {
+        let task = format!("/proc/self/task/{}", tid);
+        match fs::read_to_string(format!("{}/stack", task)) {
+            Ok(ref stack) if !stack.is_empty() => Some(stack.clone()),
+            _ => match fs::read_to_string(format!("{}/wchan", task)) {
+                Ok(ref wchan) if !wchan.is_empty() && wchan != "0" => Some(format!("waiting in {}", wchan)),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::time::Duration;
+
+    pub fn allowed_cpus() -> Option<Vec<usize>> {
+        None
+    }
+
+    pub fn pin_to_cpus(_cpus: &[usize]) -> bool {
+        false
+    }
+
+    pub fn thread_cpu_time() -> Option<Duration> {
+        None
+    }
+
+    pub fn thread_id() -> Option<u64> {
+        None
+    }
+
+    pub fn thread_stack(_tid: u64) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod naming {
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char, c_int};
+
+    #[cfg(target_os = "linux")]
+    type Pthread = ::std::os::raw::c_ulong;
+    #[cfg(target_os = "macos")]
+    type Pthread = *mut ::std::os::raw::c_void;
+
+    extern "C" {
+        fn pthread_self() -> Pthread;
+        #[cfg(target_os = "linux")]
+        fn pthread_setname_np(thread: Pthread, name: *const c_char) -> c_int;
+        #[cfg(target_os = "macos")]
+        fn pthread_setname_np(name: *const c_char) -> c_int;
+        fn pthread_getname_np(thread: Pthread, name: *mut c_char, len: usize) -> c_int;
+    }
+
+    /// Linux limits thread names to 15 bytes, macOS to 63.
+    #[cfg(target_os = "linux")]
+    pub const MAX_OS_NAME: usize = 15;
+    #[cfg(target_os = "macos")]
+    pub const MAX_OS_NAME: usize = 63;
+
+    pub fn set(name: &str) -> bool {
+        let mut end = name.len().min(MAX_OS_NAME);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        let name = match CString::new(&name[..end]) {
+            Ok(name) => name,
+            Err(_) => return false,
+        };
+        #[cfg(target_os = "linux")]
+        let result = unsafe { pthread_setname_np(pthread_self(), name.as_ptr()) };
+        #[cfg(target_os = "macos")]
+        let result = unsafe { pthread_setname_np(name.as_ptr()) };
+        result == 0
+    }
+
+    pub fn get() -> Option<String> {
+        let mut buffer = [0 as c_char; MAX_OS_NAME + 1];
+        if unsafe { pthread_getname_np(pthread_self(), buffer.as_mut_ptr(), buffer.len()) } != 0 {
+            return None;
+        }
+        let name = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+        Some(name.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(windows)]
+mod naming {
+    use std::os::raw::c_void;
+    use std::ptr;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadDescription(thread: *mut c_void, description: *const u16) -> i32;
+        fn GetThreadDescription(thread: *mut c_void, description: *mut *mut u16) -> i32;
+        fn LocalFree(memory: *mut c_void) -> *mut c_void;
+    }
+
+    pub fn set(name: &str) -> bool {
+        if name.contains('\0') {
+            return false;
+        }
+        let wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        // Failure HRESULTs are negative.
+        unsafe { SetThreadDescription(GetCurrentThread(), wide.as_ptr()) >= 0 }
+    }
+
+    pub fn get() -> Option<String> {
+        let mut description = ptr::null_mut();
+        if unsafe { GetThreadDescription(GetCurrentThread(), &mut description) } < 0 || description.is_null() {
+            return None;
+        }
+        let mut len = 0;
+        while unsafe { *description.add(len) } != 0 {
+            len += 1;
+        }
+        let name = String::from_utf16_lossy(unsafe { ::std::slice::from_raw_parts(description, len) });
+        unsafe { LocalFree(description as *mut c_void) };
+        Some(name)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod naming {
+    pub fn set(_name: &str) -> bool {
+        false
+    }
+
+    pub fn get() -> Option<String> {
+        None
+    }
+}
+
+#[cfg(unix)]
+mod termination {
+    use std::io;
+    use std::os::raw::c_int;
+    use std::process::Child;
+
+    const SIGTERM: c_int = 15;
+
+    extern "C" {
+        fn kill(pid: c_int, signal: c_int) -> c_int;
+    }
+
+    pub fn request(child: &mut Child) -> io::Result<()> {
+        if unsafe { kill(child.id() as c_int, SIGTERM) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod termination {
+    use std::io;
+    use std::process::Child;
+
+    const CTRL_BREAK_EVENT: u32 = 1;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GenerateConsoleCtrlEvent(event: u32, process_group: u32) -> i32;
+    }
+
+    /// Only reaches children started in their own process group (`CREATE_NEW_PROCESS_GROUP`).
+    pub fn request(child: &mut Child) -> io::Result<()> {
+        if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, child.id()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod termination {
+    use std::io;
+    use std::process::Child;
+
+    pub fn request(child: &mut Child) -> io::Result<()> {
+        child.kill()
+    }
+}
+
+/// Asks a child process to terminate: SIGTERM on Unix, CTRL_BREAK on Windows, a kill elsewhere.
+pub(crate) fn request_termination(child: &mut ::std::process::Child) -> io::Result<()> {
+    termination::request(child)
+}
+
+/// Renames the calling thread at the OS level, truncating the name where the platform limits
+/// its length; `thread::current().name()` is unaffected.
+pub(crate) fn set_os_thread_name(name: &str) -> bool {
+    naming::set(name)
+}
+
+/// Name the OS stores for the calling thread.
+pub(crate) fn os_thread_name() -> Option<String> {
+    naming::get()
+}
+
+/// CPUs the process may run on, limited to the parallelism std reports (which honours cgroup quotas).
+pub(crate) fn available_cpus() -> Vec<usize> {
+    let parallelism = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut cpus = imp::allowed_cpus().unwrap_or_else(|| (0..parallelism).collect());
+    cpus.truncate(parallelism);
+    cpus
+}
+
+/// Pins the calling thread to a single CPU; returns `false` where unsupported or refused.
+pub(crate) fn pin_to_cpu(cpu: usize) -> bool {
+    imp::pin_to_cpus(&[cpu])
+}
+
+/// Restricts the calling thread to a set of CPUs; returns `false` where unsupported or refused.
+#[cfg(all(feature = "linux", target_os = "linux"))]
+pub(crate) fn pin_to_cpus(cpus: &[usize]) -> bool {
+    imp::pin_to_cpus(cpus)
+}
+
+/// CPU time consumed by the calling thread, where the platform exposes it.
+pub(crate) fn thread_cpu_time() -> Option<Duration> {
+    imp::thread_cpu_time()
+}
+
+/// OS identifier of the calling thread, where the platform exposes one.
+pub(crate) fn os_thread_id() -> Option<u64> {
+    imp::thread_id()
+}
+
+/// Stack of the thread with OS identifier `tid` as the OS reports it, where it does.
+pub(crate) fn os_thread_stack(tid: u64) -> Option<String> {
+    imp::thread_stack(tid)
+}
+
+/// Scheduling priority and cgroup a thread moves itself into when it starts.
+#[cfg(all(feature = "linux", target_os = "linux"))]
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Placement {
+    pub(crate) nice: Option<i32>,
+    /// Nice value taken once interrupted.
+    pub(crate) interrupt_nice: Option<i32>,
+    pub(crate) cgroup: Option<::std::path::PathBuf>,
+    pub(crate) numa_node: Option<usize>,
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+pub(crate) use self::placement::{set_thread_nice, thread_nice};
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+mod placement {
+    use std::fs::OpenOptions;
+    use std::io::{self, Write};
+    use std::os::raw::{c_int, c_uint};
+
+    const PRIO_PROCESS: c_int = 0;
+
+    extern "C" {
+        fn getpriority(which: c_int, who: c_uint) -> c_int;
+        fn setpriority(which: c_int, who: c_uint, priority: c_int) -> c_int;
+        fn __errno_location() -> *mut c_int;
+    }
+
+    pub(crate) fn thread_nice(tid: u64) -> io::Result<i32> {
+        // -1 is a valid nice value, so only errno tells a failure apart.
+        unsafe { *__errno_location() = 0 };
+        let nice = unsafe { getpriority(PRIO_PROCESS, tid as c_uint) };
+        if nice == -1 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(0) {
+                return Err(error);
+            }
+        }
+        Ok(nice)
+    }
+
+    pub(crate) fn set_thread_nice(tid: u64, nice: i32) -> io::Result<()> {
+        match unsafe { setpriority(PRIO_PROCESS, tid as c_uint, nice) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    impl super::Placement {
+        /// Applies the placement to the calling thread.
+        pub(crate) fn apply(&self) -> io::Result<()> {
+            // Without its id, 0 stands for the calling thread.
+            let tid = super::imp::gettid().unwrap_or(0);
+            if let Some(nice) = self.nice {
+                // On Linux the nice value is a per-thread attribute when given a thread id.
+                if unsafe { setpriority(PRIO_PROCESS, tid as c_uint, nice) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(node) = self.numa_node {
+                ::numa::bind_to_node(node)?;
+            }
+            if let Some(ref cgroup) = self.cgroup {
+                // Threaded cgroups (v2) accept thread ids in `cgroup.threads`.
+                let mut threads = OpenOptions::new().append(true).open(cgroup.join("cgroup.threads"))?;
+                write!(threads, "{}", tid)?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{self, AtomicU64};
+
+use InterruptToken;
+
+
+/// Distinguishes the temporary paths created by this process.
+static NEXT: AtomicU64 = AtomicU64::new(0);
+/// Attempts at finding a free name before giving up.
+const ATTEMPTS: u32 = 16;
+
+/// Creates a fresh path in the temporary directory with `create`, retrying with another name if
+/// the path exists.
+fn create_unique<R, F>(prefix: &str, create: F) -> io::Result<(PathBuf, R)> where F: Fn(&Path) -> io::Result<R> {
+    let mut last = None;
+    for _ in 0..ATTEMPTS {
+        let path = env::temp_dir().join(format!("{}-{}-{}", prefix, process::id(), NEXT.fetch_add(1, atomic::Ordering::Relaxed)));
+        match create(&path) {
+            Ok(created) => return Ok((path, created)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => last = Some(error),
+            Err(error) => return Err(error),
+        }
+    }
+    Err(last.unwrap_or_else(|| io::Error::from(io::ErrorKind::AlreadyExists)))
+}
+
+/// Temporary file or directory created with `InterruptToken::temp_dir` or `temp_file`, removed
+/// with its content when dropped.
+#[derive(Debug)]
+pub struct TempPath {
+    path: PathBuf,
+    dir: bool,
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.dir {
+            fs::remove_dir_all(&self.path).ok();
+        } else {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+impl InterruptToken {
+    /// Creates a directory in the temporary directory of the system, removed with its content
+    /// when the returned guard is dropped; kept by the runnable, that is when it exits by
+    /// returning, panicking or after interruption, whichever token it was created with.
+    pub fn temp_dir(&self, prefix: &str) -> io::Result<TempPath> {
+        let (path, ()) = create_unique(prefix, |path| fs::create_dir(path))?;
+        Ok(TempPath { path, dir: true })
+    }
+
+    /// Creates an empty file in the temporary directory of the system, opened for reading and
+    /// writing, and removed when the returned guard is dropped, as with `temp_dir`.
+    pub fn temp_file(&self, prefix: &str) -> io::Result<(TempPath, File)> {
+        let (path, file) = create_unique(prefix, |path| OpenOptions::new().read(true).write(true).create_new(true).open(path))?;
+        Ok((TempPath { path, dir: false }, file))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::mpsc;
+    use ThreadHandle;
+    use super::*;
+
+    #[test]
+    fn test_temp_paths() {
+        let (paths, created) = mpsc::channel();
+        let handle = ThreadHandle::spawn("Test temp paths".to_string(), move |interrupted: InterruptToken| {
+            let dir = interrupted.temp_dir("thread-handle-test").unwrap();
+            fs::write(dir.join("partial"), b"chunk").unwrap();
+            let (path, mut file) = interrupted.temp_file("thread-handle-test").unwrap();
+            file.write_all(b"chunk").unwrap();
+            assert!(*dir != *path);
+            paths.send((dir.to_path_buf(), path.to_path_buf())).unwrap();
+            panic!("interrupted mid-write");
+        }).unwrap();
+        let (dir, path) = created.recv().unwrap();
+        assert!(handle.join().unwrap().is_err());
+        assert!(!dir.exists());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_temp_paths_without_runnable() {
+        let token = InterruptToken::new();
+        let dir = token.temp_dir("thread-handle-test").unwrap();
+        let (path, _) = token.temp_file("thread-handle-test").unwrap();
+        let (kept, removed) = (dir.to_path_buf(), path.to_path_buf());
+        assert!(kept.is_dir() && removed.is_file());
+        drop((dir, path));
+        assert!(!kept.exists());
+        assert!(!removed.exists());
+    }
+}
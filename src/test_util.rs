@@ -0,0 +1,81 @@
+//! Assertions for tests of code running managed threads.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ThreadHandle;
+
+
+/// Panics unless the thread terminates within `timeout`.
+#[track_caller]
+pub fn assert_terminates_within<T>(handle: &ThreadHandle<T>, timeout: Duration) where T: Send + 'static {
+    if !handle.wait_finished(Some(timeout)) {
+        panic!("thread {:?} still running after {:?}: {}", handle.name(), timeout, handle);
+    }
+}
+
+/// Interrupts the thread and panics unless it terminates within `timeout` of the request.
+#[track_caller]
+pub fn assert_stops_after_interrupt<T>(handle: &ThreadHandle<T>, timeout: Duration) where T: Send + 'static {
+    let requested = Instant::now();
+    handle.interrupt().ok();
+    if !handle.wait_finished(Some(timeout)) {
+        panic!("thread {:?} ignored interruption for {:?}: {}", handle.name(), requested.elapsed(), handle);
+    }
+}
+
+/// Panics unless `condition` holds within `timeout`, checking it every millisecond. For waiting
+/// on state that no channel or handle reports, in place of sleeping and hoping it was enough.
+#[track_caller]
+pub fn assert_eventually<F>(timeout: Duration, what: &str, mut condition: F) where F: FnMut() -> bool {
+    let started = Instant::now();
+    while !condition() {
+        if started.elapsed() >= timeout {
+            panic!("{} not reached within {:?}", what, timeout);
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::thread::sleep;
+    use PanicPayload;
+    use super::*;
+
+    #[test]
+    fn test_assert_terminates_within() {
+        let quick = ThreadHandle::spawn("Test assert quick".to_string(), |_| ()).unwrap();
+        assert_terminates_within(&quick, Duration::from_secs(5));
+        let slow = ThreadHandle::spawn("Test assert slow".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        let failure = panic::catch_unwind(AssertUnwindSafe(|| {
+            assert_terminates_within(&slow, Duration::from_millis(20));
+        })).unwrap_err();
+        assert!(failure.describe().starts_with("thread \"Test assert slow\" still running after 20ms"));
+        assert_stops_after_interrupt(&slow, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_assert_stops_after_interrupt() {
+        let stubborn = ThreadHandle::spawn("Test assert stubborn".to_string(), |_| sleep(Duration::from_millis(300))).unwrap();
+        let failure = panic::catch_unwind(AssertUnwindSafe(|| {
+            assert_stops_after_interrupt(&stubborn, Duration::from_millis(20));
+        })).unwrap_err();
+        assert!(failure.describe().starts_with("thread \"Test assert stubborn\" ignored interruption"));
+        assert_terminates_within(&stubborn, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_assert_eventually() {
+        let started = Instant::now();
+        assert_eventually(Duration::from_secs(5), "10ms elapsed", || started.elapsed() >= Duration::from_millis(10));
+        let failure = panic::catch_unwind(|| assert_eventually(Duration::from_millis(20), "never", || false)).unwrap_err();
+        assert_eq!("never not reached within 20ms", failure.describe());
+    }
+}
@@ -0,0 +1,101 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use {InterruptToken, Shared};
+
+
+/// Longest time the runnable sleeps at once before checking for interruption.
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+/// Longest pause added at one checkpoint, so that a long stretch of work is not followed by an
+/// equally long stall.
+const MAX_PAUSE: Duration = Duration::from_secs(1);
+
+/// Duty cycle set with `InterruptToken::throttle`.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    percent: u8,
+    /// End of the previous checkpoint, where the current stretch of work started.
+    resumed: Instant,
+}
+
+impl Throttle {
+    fn new(percent: u8) -> Option<Throttle> {
+        match percent {
+            100.. => None,
+            percent => Some(Throttle {
+                percent: percent.max(1),
+                resumed: Instant::now(),
+            }),
+        }
+    }
+
+    /// Pause keeping the work done since the previous checkpoint within the duty cycle.
+    fn pause(&self, now: Instant) -> Duration {
+        let busy = now.saturating_duration_since(self.resumed);
+        let percent = u32::from(self.percent);
+        (busy * (100 - percent) / percent).min(MAX_PAUSE)
+    }
+}
+
+impl Shared {
+    /// Sleeps as the throttle of the handle requires, returning early if interruption is requested.
+    pub(crate) fn throttle_pause(&self) {
+        let pause = match *self.throttle.lock().unwrap() {
+            Some(ref throttle) => throttle.pause(Instant::now()),
+            None => return,
+        };
+        let deadline = Instant::now() + pause;
+        loop {
+            let now = Instant::now();
+            if now >= deadline || self.is_interrupt_requested() {
+                break;
+            }
+            thread::sleep(SLEEP_SLICE.min(deadline - now));
+        }
+        if let Some(ref mut throttle) = *self.throttle.lock().unwrap() {
+            throttle.resumed = Instant::now();
+        }
+    }
+}
+
+impl InterruptToken {
+    /// Caps the share of time the runnable works to `percent`, from 1 to 100: each `yield_now`
+    /// sleeps in proportion to the work done since the previous one, so that maintenance jobs do
+    /// not starve latency-critical threads on small machines. 100 removes the throttle.
+    pub fn throttle(&self, percent: u8) {
+        *self.shared.throttle.lock().unwrap() = Throttle::new(percent);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use Builder;
+    use super::*;
+
+    #[test]
+    fn test_throttle_pause() {
+        let start = Instant::now();
+        let throttle = Throttle::new(25).unwrap();
+        let pause = throttle.pause(start + Duration::from_millis(20));
+        assert!(pause >= Duration::from_millis(59) && pause <= Duration::from_millis(61));
+        assert_eq!(MAX_PAUSE, throttle.pause(start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_throttle() {
+        let handle = Builder::new("Test throttle".to_string()).throttle(20).spawn(|interrupted: InterruptToken| {
+            let start = Instant::now();
+            let mut busy = Duration::from_secs(0);
+            while start.elapsed() < Duration::from_millis(300) {
+                let stretch = Instant::now();
+                while stretch.elapsed() < Duration::from_millis(5) {}
+                busy += stretch.elapsed();
+                interrupted.yield_now().unwrap();
+            }
+            busy.as_secs_f64() / start.elapsed().as_secs_f64()
+        }).unwrap();
+        let duty = handle.join().unwrap().unwrap();
+        assert!(duty < 0.35, "duty cycle {}", duty);
+    }
+}
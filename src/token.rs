@@ -0,0 +1,338 @@
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicBool};
+use std::time::Instant;
+
+#[cfg(feature = "backtrace")]
+use backtrace;
+use clock::{self, Clock};
+use ordering;
+use {HandleId, InterruptOutcome, Shared};
+
+
+/// Source of truth for whether a thread has been asked to stop.
+///
+/// `AtomicBool` is the built-in implementation; implement this for your own token types
+/// to drive a handle from an external cancellation mechanism.
+pub trait CancelSignal: Send + Sync {
+    fn is_cancelled(&self) -> bool;
+
+    /// Requests cancellation and returns whether it had already been requested.
+    fn cancel(&self) -> bool;
+}
+
+impl CancelSignal for AtomicBool {
+    fn is_cancelled(&self) -> bool {
+        self.load(ordering::load())
+    }
+
+    fn cancel(&self) -> bool {
+        self.swap(true, ordering::swap())
+    }
+}
+
+/// Signal of a token: the flag of `InterruptToken::new`, kept inline so that spawning does not
+/// allocate it, the one of a child token, or one given with `with_signal`.
+pub(crate) enum Signal {
+    Flag(AtomicBool),
+    Child(ChildSignal),
+    Custom(Box<dyn CancelSignal>),
+}
+
+impl Signal {
+    /// Whether the signal can be raised without a request to its token, which is then only
+    /// noticed by checking it.
+    pub(crate) fn is_external(&self) -> bool {
+        match *self {
+            Signal::Flag(_) => false,
+            Signal::Child(ref child) => child.parent.shared.signal.is_external(),
+            Signal::Custom(_) => true,
+        }
+    }
+}
+
+impl CancelSignal for Signal {
+    fn is_cancelled(&self) -> bool {
+        match *self {
+            Signal::Flag(ref flag) => flag.is_cancelled(),
+            Signal::Child(ref child) => child.is_cancelled(),
+            Signal::Custom(ref signal) => signal.is_cancelled(),
+        }
+    }
+
+    fn cancel(&self) -> bool {
+        match *self {
+            Signal::Flag(ref flag) => flag.cancel(),
+            Signal::Child(ref child) => child.cancel(),
+            Signal::Custom(ref signal) => signal.cancel(),
+        }
+    }
+}
+
+impl<S> CancelSignal for Arc<S> where S: CancelSignal + ?Sized {
+    fn is_cancelled(&self) -> bool {
+        (**self).is_cancelled()
+    }
+
+    fn cancel(&self) -> bool {
+        (**self).cancel()
+    }
+}
+
+/// Token handed to the runnable of a managed thread.
+#[derive(Clone)]
+pub struct InterruptToken {
+    pub(crate) shared: Arc<Shared>,
+}
+
+impl InterruptToken {
+    pub fn new() -> Self {
+        InterruptToken::configured(Signal::Flag(AtomicBool::new(false)), clock::system())
+    }
+
+    pub fn with_signal<S>(signal: S) -> Self where S: CancelSignal + 'static {
+        InterruptToken::configured(Signal::Custom(Box::new(signal)), clock::system())
+    }
+
+    /// Token whose thread takes its timestamps, heartbeats and staleness from `clock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        InterruptToken::configured(Signal::Flag(AtomicBool::new(false)), clock)
+    }
+
+    /// Token carrying `slot` in its shared state, see `SlotRef`.
+    pub(crate) fn with_slot<S>(slot: S) -> Self where S: Any + Send + Sync {
+        InterruptToken::configured_with(Signal::Flag(AtomicBool::new(false)), clock::system(), slot)
+    }
+
+    pub(crate) fn configured(signal: Signal, clock: Arc<dyn Clock>) -> Self {
+        InterruptToken::configured_with(signal, clock, ())
+    }
+
+    pub(crate) fn configured_with<S>(signal: Signal, clock: Arc<dyn Clock>, slot: S) -> Self where S: Any + Send + Sync {
+        InterruptToken {
+            shared: Arc::new(Shared::new(signal, clock, slot)),
+        }
+    }
+
+    /// Id of the handle of the thread this token was given to.
+    pub fn id(&self) -> HandleId {
+        self.shared.id
+    }
+
+    /// Current time according to the clock of the thread.
+    pub fn now(&self) -> Instant {
+        self.shared.clock.now()
+    }
+
+    /// Whether interruption was requested; always `false` inside `shield`.
+    pub fn is_interrupted(&self) -> bool {
+        self.shared.is_interrupted()
+    }
+
+    /// Requests interruption; repeated requests are counted but otherwise have no effect. A token
+    /// can always be interrupted, so unlike `ThreadHandle::interrupt` this cannot fail.
+    pub fn interrupt(&self) -> InterruptOutcome {
+        InterruptOutcome::after(self.shared.request_interrupt(None))
+    }
+
+    /// Number of interruption requests so far, repeated ones included, e.g. to drain gracefully
+    /// on the first and drop everything on the second. Requests to a parent token are not counted.
+    pub fn interrupt_count(&self) -> u64 {
+        self.shared.counters.interrupts.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Like `interrupt`, recording why.
+    pub fn interrupt_with_reason(&self, reason: &str) -> InterruptOutcome {
+        InterruptOutcome::after(self.shared.request_interrupt(Some(reason)))
+    }
+
+    /// Creates a token that is interrupted whenever this one is, but can also be interrupted on its own.
+    pub fn child(&self) -> InterruptToken {
+        let signal = ChildSignal {
+            parent: self.clone(),
+            interrupted: AtomicBool::new(false),
+        };
+        let child = InterruptToken::configured(Signal::Child(signal), self.shared.clock.clone());
+        self.shared.add_child_listener(&child.shared);
+        if let Some(values) = self.shared.values.get() {
+            child.shared.values.set(values.clone()).ok();
+        }
+        if let Some(&deadline) = self.shared.deadline.get() {
+            child.shared.deadline.set(deadline).ok();
+        }
+        child
+    }
+}
+
+pub(crate) struct ChildSignal {
+    parent: InterruptToken,
+    interrupted: AtomicBool,
+}
+
+impl CancelSignal for ChildSignal {
+    fn is_cancelled(&self) -> bool {
+        self.interrupted.is_cancelled() || self.parent.shared.is_interrupt_requested()
+    }
+
+    fn cancel(&self) -> bool {
+        let inherited = self.parent.shared.is_interrupt_requested();
+        self.interrupted.cancel() || inherited
+    }
+}
+
+impl InterruptToken {
+    /// Registers `cleanup` to run on the thread of the runnable given this token once it exits,
+    /// by returning or panicking. Cleanups run in reverse order of registration.
+    pub fn defer<F>(&self, cleanup: F) where F: FnOnce() + Send + 'static {
+        self.shared.deferred.lock().unwrap().push(Box::new(cleanup));
+    }
+}
+
+impl Shared {
+    /// Runs the deferred cleanups, newest first, returning their panics; one panicking does
+    /// not keep the others from running.
+    pub(crate) fn run_deferred(&self) -> Vec<Box<dyn Any + Send>> {
+        let mut panics = Vec::new();
+        loop {
+            let cleanup = match self.deferred.lock().unwrap().pop() {
+                Some(cleanup) => cleanup,
+                None => return panics,
+            };
+            let outcome = panic::catch_unwind(AssertUnwindSafe(cleanup));
+            #[cfg(feature = "backtrace")]
+            backtrace::returned(outcome.is_err());
+            if let Err(payload) = outcome {
+                panics.push(payload);
+            }
+        }
+    }
+}
+
+impl Default for InterruptToken {
+    fn default() -> Self {
+        InterruptToken::new()
+    }
+}
+
+impl CancelSignal for InterruptToken {
+    fn is_cancelled(&self) -> bool {
+        self.is_interrupted()
+    }
+
+    fn cancel(&self) -> bool {
+        self.shared.request_interrupt(None)
+    }
+}
+
+impl fmt::Debug for InterruptToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InterruptToken")
+            .field("interrupted", &self.is_interrupted())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use ThreadHandle;
+    use super::*;
+
+    #[test]
+    fn test_token_interrupt() {
+        let token = InterruptToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_interrupted());
+        assert_eq!(InterruptOutcome::Requested, token.interrupt());
+        assert!(clone.is_interrupted());
+        assert_eq!(InterruptOutcome::AlreadyRequested, clone.interrupt());
+    }
+
+    #[test]
+    fn test_child_token() {
+        let parent = InterruptToken::new();
+        let child = parent.child();
+        assert_eq!(InterruptOutcome::Requested, child.interrupt());
+        assert!(!parent.is_interrupted());
+        let other = parent.child();
+        parent.interrupt();
+        assert!(other.is_interrupted());
+        assert_eq!(InterruptOutcome::AlreadyRequested, other.interrupt());
+        // Shielding the parent defers its own interruption, not that of its children.
+        let late = parent.child();
+        assert!(parent.shield(|| late.is_interrupted() && !parent.is_interrupted()));
+    }
+
+    #[test]
+    fn test_interrupt_count() {
+        let (drained, on_drained) = ::std::sync::mpsc::channel();
+        let handle = ThreadHandle::spawn("Test interrupt count".to_string(), move |interrupted| {
+            while interrupted.interrupt_count() == 0 {
+                ::std::thread::yield_now();
+            }
+            drained.send(()).unwrap();
+            while interrupted.interrupt_count() < 2 {
+                ::std::thread::yield_now();
+            }
+            interrupted.interrupt_count()
+        }).unwrap();
+        handle.interrupt().unwrap();
+        on_drained.recv().unwrap();
+        handle.interrupt().unwrap();
+        assert_eq!(2, handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_token_custom_signal() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let token = InterruptToken::with_signal(flag.clone());
+        flag.store(true, atomic::Ordering::Relaxed);
+        assert!(token.is_interrupted());
+    }
+
+    #[test]
+    fn test_defer() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let record = order.clone();
+        let handle = ThreadHandle::spawn("Test defer".to_string(), move |interrupted| -> () {
+            for i in 0..3 {
+                let record = record.clone();
+                interrupted.defer(move || record.lock().unwrap().push(i));
+            }
+            panic!("mid-job");
+        }).unwrap();
+        assert!(handle.join().unwrap().is_err());
+        assert_eq!(vec![2, 1, 0], *order.lock().unwrap());
+        let failing = ThreadHandle::spawn("Test defer panic".to_string(), |interrupted| {
+            interrupted.defer(|| panic!("cleanup"));
+            1
+        }).unwrap();
+        assert!(failing.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_defer_nested_panics() {
+        let ran = Arc::new(Mutex::new(false));
+        let record = ran.clone();
+        let handle = ThreadHandle::spawn("Test defer nested panics".to_string(), move |interrupted| -> () {
+            interrupted.defer(move || *record.lock().unwrap() = true);
+            interrupted.defer(|| panic!("teardown"));
+            interrupted.defer(|| panic!("flush"));
+            ::std::panic::panic_any(42u32);
+        }).unwrap();
+        let payload = handle.join().unwrap().unwrap_err();
+        assert!(*ran.lock().unwrap());
+        assert_eq!("Box<Any>; then cleanup panicked: flush; then cleanup panicked: teardown", ::PanicPayload::describe(&*payload));
+        assert_eq!(Some(&42), ::PanicPayload::payload::<u32>(&*payload));
+        let combined = payload.downcast_ref::<::CombinedPanic>().unwrap();
+        assert_eq!(vec!["flush", "teardown"], combined.cleanups);
+
+        let handle = ThreadHandle::spawn("Test defer single panic".to_string(), |interrupted| {
+            interrupted.defer(|| panic!("cleanup"));
+        }).unwrap();
+        assert_eq!(Some("cleanup"), ::PanicPayload::message(&*handle.join().unwrap().unwrap_err()));
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+use std::sync::{Arc, Weak};
+
+use {AlreadyTerminated, InterruptOutcome, Shared, ThreadHandle};
+
+
+/// Handle that can only request interruption of a thread: it gives no access to its status or
+/// result, and does not keep the state of the thread alive. Suited to plugins or FFI callbacks
+/// that must be able to stop a worker but nothing else.
+#[derive(Clone)]
+pub struct InterruptTrigger {
+    shared: Weak<Shared>,
+}
+
+impl InterruptTrigger {
+    /// Requests interruption; fails once the thread terminated or every handle of it was dropped.
+    pub fn interrupt(&self) -> Result<InterruptOutcome, AlreadyTerminated> {
+        self.request(None)
+    }
+
+    pub fn interrupt_with_reason(&self, reason: &str) -> Result<InterruptOutcome, AlreadyTerminated> {
+        self.request(Some(reason))
+    }
+
+    fn request(&self, reason: Option<&str>) -> Result<InterruptOutcome, AlreadyTerminated> {
+        match self.shared.upgrade() {
+            Some(shared) => shared.interrupt_running(reason),
+            None => Err(AlreadyTerminated),
+        }
+    }
+}
+
+impl fmt::Debug for InterruptTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InterruptTrigger").finish_non_exhaustive()
+    }
+}
+
+impl<T> ThreadHandle<T> where T: Send + 'static {
+    /// Trigger requesting interruption of this thread, and nothing else.
+    pub fn trigger(&self) -> InterruptTrigger {
+        InterruptTrigger {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread::{self, sleep};
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_interrupt_trigger() {
+        let handle = ThreadHandle::spawn("Test trigger".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(5));
+            }
+        }).unwrap();
+        let trigger = handle.trigger();
+        let plugin = trigger.clone();
+        let outcome = thread::spawn(move || plugin.interrupt_with_reason("plugin request")).join().unwrap();
+        assert_eq!(Ok(InterruptOutcome::Requested), outcome);
+        assert_eq!(Ok(InterruptOutcome::AlreadyRequested), trigger.interrupt());
+        handle.join().unwrap().unwrap();
+        assert_eq!(Some("plugin request"), handle.interrupt_reason());
+        assert_eq!(Err(AlreadyTerminated), trigger.interrupt());
+        drop(handle);
+        assert_eq!(Err(AlreadyTerminated), trigger.interrupt());
+    }
+}
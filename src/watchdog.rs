@@ -0,0 +1,306 @@
+use std::io;
+use std::ptr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use clock::{self, Clock};
+use {Shared, ThreadHandle};
+
+
+/// What the watchdog does, besides invoking the callback, when a thread stalls.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StallAction {
+    Notify,
+    Interrupt,
+    /// Restarts the runnable of a thread spawned with `spawn_supervised`: the stalled incarnation
+    /// sees interruption, and once it returned a new one starts under the same handle. Other
+    /// threads are interrupted.
+    Restart,
+}
+
+/// A running thread that has not sent a heartbeat within its threshold.
+#[derive(Clone, Debug)]
+pub struct Stall {
+    pub name: String,
+    pub silence: Duration,
+    pub threshold: Duration,
+    pub action: StallAction,
+    /// Name of the blocking section the thread was in, whose threshold then applied.
+    pub blocking_section: Option<String>,
+}
+
+type StallCallback = Arc<dyn Fn(&Stall) + Send + Sync>;
+
+struct Watched {
+    shared: Weak<Shared>,
+    threshold: Duration,
+    /// Threshold while the thread is in a blocking section; `threshold` if unset.
+    blocking_threshold: Option<Duration>,
+    action: StallAction,
+    on_stall: StallCallback,
+    stalled: bool,
+}
+
+struct Watchlist {
+    watched: Mutex<Vec<Watched>>,
+}
+
+impl Watchlist {
+    /// Fires callbacks for threads that became stale since the previous check; a thread has to
+    /// send a heartbeat again before it can be reported a second time.
+    fn check(&self) -> Vec<Stall> {
+        let mut stalls = Vec::new();
+        let mut fired = Vec::new();
+        {
+            let mut watched = self.watched.lock().unwrap();
+            watched.retain(|entry| entry.shared.upgrade().is_some_and(|shared| !shared.is_terminated()));
+            for entry in watched.iter_mut() {
+                let shared = match entry.shared.upgrade() {
+                    Some(shared) => shared,
+                    None => continue,
+                };
+                let silence = match shared.silence(shared.clock.now()) {
+                    Some(silence) => silence,
+                    None => continue,
+                };
+                let section = shared.blocking_section().map(|section| section.name);
+                let threshold = match section {
+                    Some(_) => entry.blocking_threshold.unwrap_or(entry.threshold),
+                    None => entry.threshold,
+                };
+                if silence <= threshold {
+                    entry.stalled = false;
+                } else if !entry.stalled {
+                    entry.stalled = true;
+                    let stall = Stall {
+                        name: shared.name().to_string(),
+                        silence,
+                        threshold,
+                        action: entry.action,
+                        blocking_section: section,
+                    };
+                    fired.push((shared, entry.on_stall.clone(), stall.clone()));
+                    stalls.push(stall);
+                }
+            }
+        }
+        for (shared, on_stall, stall) in fired {
+            let interrupt = match stall.action {
+                StallAction::Notify => false,
+                StallAction::Interrupt => true,
+                StallAction::Restart => !shared.request_restart(),
+            };
+            if interrupt {
+                shared.request_interrupt(Some(&format!("watchdog: no heartbeat for {:?}", stall.silence)));
+            }
+            on_stall(&stall);
+        }
+        stalls
+    }
+}
+
+/// Background thread detecting watched threads whose heartbeats stopped.
+pub struct Watchdog {
+    watchlist: Arc<Watchlist>,
+    thread: Option<ThreadHandle<()>>,
+}
+
+impl Watchdog {
+    /// Starts a watchdog checking its threads every `interval`.
+    pub fn spawn(name: String, interval: Duration) -> io::Result<Self> {
+        Watchdog::with_clock(name, interval, clock::system())
+    }
+
+    /// Like `spawn`, measuring the check interval with `clock`; silence is always measured
+    /// with the clock of each watched thread.
+    pub fn with_clock(name: String, interval: Duration, clock: Arc<dyn Clock>) -> io::Result<Self> {
+        let watchlist = Arc::new(Watchlist { watched: Mutex::new(Vec::new()) });
+        let checked = watchlist.clone();
+        let thread = ThreadHandle::spawn(name, move |interrupted| {
+            while !interrupted.is_interrupted() {
+                clock::sleep_until(&*clock, clock.now() + interval, || interrupted.is_interrupted());
+                checked.check();
+            }
+        })?;
+        Ok(Watchdog {
+            watchlist,
+            thread: Some(thread),
+        })
+    }
+
+    /// Watchdog without a thread of its own; checks only happen through `check_now`.
+    pub fn manual() -> Self {
+        Watchdog {
+            watchlist: Arc::new(Watchlist { watched: Mutex::new(Vec::new()) }),
+            thread: None,
+        }
+    }
+
+    /// Starts watching `handle`: when it has been silent for longer than `threshold`,
+    /// `action` is applied and `on_stall` invoked from the watchdog thread.
+    pub fn watch<T, F>(&self, handle: &ThreadHandle<T>, threshold: Duration, action: StallAction, on_stall: F) where
+        T: Send + 'static,
+        F: Fn(&Stall) + Send + Sync + 'static
+    {
+        self.watchlist.watched.lock().unwrap().push(Watched {
+            shared: Arc::downgrade(&handle.shared),
+            threshold,
+            action,
+            blocking_threshold: None,
+            on_stall: Arc::new(on_stall),
+            stalled: false,
+        });
+    }
+
+    /// Uses `threshold` instead of the usual one while `handle` is inside a blocking section,
+    /// so that a long but expected blocking call is not taken for a stall.
+    pub fn set_blocking_threshold<T>(&self, handle: &ThreadHandle<T>, threshold: Duration) where T: Send + 'static {
+        for entry in self.watchlist.watched.lock().unwrap().iter_mut() {
+            if ptr::addr_eq(entry.shared.as_ptr(), Arc::as_ptr(&handle.shared)) {
+                entry.blocking_threshold = Some(threshold);
+            }
+        }
+    }
+
+    /// Number of watched threads that are still running.
+    pub fn watched(&self) -> usize {
+        self.watchlist.watched.lock().unwrap().len()
+    }
+
+    /// Runs a check immediately and returns the stalls it reported.
+    pub fn check_now(&self) -> Vec<Stall> {
+        self.watchlist.check()
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            thread.interrupt().ok();
+            thread.join();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use std::thread::sleep;
+    use {Builder, InterruptToken, MockClock, RestartPolicy};
+    use super::*;
+
+    #[test]
+    fn test_watchdog_interrupts_stalled() {
+        let watchdog = Watchdog::spawn("Test watchdog".to_string(), Duration::from_millis(20)).unwrap();
+        let handle = ThreadHandle::spawn("Test stalled".to_string(), |interrupted| {
+            interrupted.heartbeat();
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+        }).unwrap();
+        let (sender, receiver) = channel();
+        let sender = Mutex::new(sender);
+        watchdog.watch(&handle, Duration::from_millis(100), StallAction::Interrupt, move |stall: &Stall| {
+            sender.lock().unwrap().send(stall.name.clone()).unwrap();
+        });
+        assert_eq!("Test stalled", receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+        handle.join().unwrap().unwrap();
+        sleep(Duration::from_millis(60));
+        assert_eq!(0, watchdog.watched());
+    }
+
+    #[test]
+    fn test_watchdog_reports_once_per_stall() {
+        let watchdog = Watchdog::manual();
+        let handle = ThreadHandle::spawn("Test stall notify".to_string(), |interrupted| {
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+        }).unwrap();
+        watchdog.watch(&handle, Duration::from_millis(50), StallAction::Notify, |_: &Stall| ());
+        assert!(watchdog.check_now().is_empty());
+        sleep(Duration::from_millis(100));
+        assert_eq!(1, watchdog.check_now().len());
+        assert!(watchdog.check_now().is_empty());
+        handle.interrupt().unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_watchdog_blocking_threshold() {
+        let clock = Arc::new(MockClock::new());
+        let watchdog = Watchdog::manual();
+        let (release, released) = channel::<()>();
+        let handle = Builder::new("Test stall blocking".to_string()).clock(clock.clone()).spawn(move |interrupted| {
+            interrupted.heartbeat();
+            interrupted.blocking_section("flush", || released.recv().ok());
+        }).unwrap();
+        while handle.blocking_section().is_none() {
+            sleep(Duration::from_millis(1));
+        }
+        watchdog.watch(&handle, Duration::from_secs(10), StallAction::Notify, |_: &Stall| ());
+        watchdog.set_blocking_threshold(&handle, Duration::from_secs(60));
+        clock.advance(Duration::from_secs(30));
+        assert!(watchdog.check_now().is_empty());
+        clock.advance(Duration::from_secs(31));
+        let stalls = watchdog.check_now();
+        assert_eq!(Some("flush".to_string()), stalls[0].blocking_section);
+        assert_eq!(Duration::from_secs(60), stalls[0].threshold);
+        release.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_watchdog_restarts_supervised() {
+        let watchdog = Watchdog::manual();
+        let (started, incarnations) = channel();
+        let started = Mutex::new(started);
+        let mut incarnation = 0;
+        let handle = ThreadHandle::spawn_supervised("Test stall restart".to_string(), RestartPolicy::new(), move || {
+            incarnation += 1;
+            let number = incarnation;
+            let started = started.lock().unwrap().clone();
+            move |interrupted: InterruptToken| -> Result<u32, ()> {
+                interrupted.heartbeat();
+                started.send(number).unwrap();
+                if number == 1 {
+                    while !interrupted.is_interrupted() {
+                        sleep(Duration::from_millis(5));
+                    }
+                }
+                Ok(number)
+            }
+        }).unwrap();
+        assert_eq!(1, incarnations.recv().unwrap());
+        watchdog.watch(&handle, Duration::from_millis(20), StallAction::Restart, |_: &Stall| ());
+        while watchdog.check_now().is_empty() {
+            sleep(Duration::from_millis(5));
+        }
+        assert_eq!(2, incarnations.recv().unwrap());
+        assert_eq!(Ok(2), handle.join().unwrap().unwrap());
+        assert_eq!(1, handle.restart_count());
+        assert!(!handle.is_interrupt_requested());
+    }
+
+    #[test]
+    fn test_watchdog_with_mock_clock() {
+        let clock = Arc::new(MockClock::new());
+        let watchdog = Watchdog::manual();
+        let handle = Builder::new("Test stall mock clock".to_string()).clock(clock.clone()).spawn(|interrupted| {
+            interrupted.heartbeat();
+            while !interrupted.is_interrupted() {
+                sleep(Duration::from_millis(10));
+            }
+        }).unwrap();
+        while handle.last_heartbeat().is_none() {
+            sleep(Duration::from_millis(1));
+        }
+        watchdog.watch(&handle, Duration::from_secs(60), StallAction::Interrupt, |_: &Stall| ());
+        assert!(watchdog.check_now().is_empty());
+        clock.advance(Duration::from_secs(61));
+        assert!(handle.is_stale(Duration::from_secs(60)));
+        assert_eq!(Duration::from_secs(61), watchdog.check_now()[0].silence);
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+//! Counts the allocations of spawning and joining a thread, with a global allocator, so in a
+//! binary of its own.
+
+extern crate thread_handle;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use thread_handle::ThreadHandle;
+
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+const SPAWNS: usize = 100;
+
+/// Average number of allocations made by `spawn_and_join`.
+fn allocations<F>(spawn_and_join: F) -> usize where F: Fn() {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..SPAWNS {
+        spawn_and_join();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) / SPAWNS
+}
+
+#[test]
+fn test_spawn_allocations() {
+    // Warms up the statics allocated once per process.
+    for _ in 0..10 {
+        ThreadHandle::spawn("Test allocations".to_string(), |_| 1).unwrap().join();
+    }
+    let plain = allocations(|| {
+        thread::Builder::new().name("Test allocations".to_string()).spawn(|| 1).unwrap().join().unwrap();
+    });
+    let managed = allocations(|| {
+        ThreadHandle::spawn("Test allocations".to_string(), |_| 1).unwrap().join();
+    });
+    // The shared state, which holds the result slot; without the `minimal` feature, also the
+    // names of the spawning and the spawned thread.
+    let extra = if cfg!(feature = "minimal") { 1 } else { 3 };
+    assert!(managed <= plain + extra, "{} allocations per spawn, {} for a plain thread", managed, plain);
+}
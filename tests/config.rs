@@ -0,0 +1,27 @@
+//! The configuration is installed once per process, so it is tested in a binary of its own.
+
+extern crate thread_handle;
+
+use std::sync::mpsc;
+use std::thread::sleep;
+use std::time::Duration;
+
+use thread_handle::{DropPolicy, ThreadHandle, ThreadHandleConfig};
+
+
+#[test]
+fn test_installed_config() {
+    let config = ThreadHandleConfig::new().name_prefix("billing-").drop_policy(DropPolicy::Interrupt);
+    assert!(config.install().is_ok());
+    assert!(ThreadHandleConfig::new().name_prefix("late-").install().is_err());
+    let (stopped, on_stopped) = mpsc::channel();
+    let handle = ThreadHandle::spawn("worker".to_string(), move |interrupted| {
+        while !interrupted.is_interrupted() {
+            sleep(Duration::from_millis(5));
+        }
+        stopped.send(std::thread::current().name().map(str::to_string)).unwrap();
+    }).unwrap();
+    assert_eq!("billing-worker", handle.name());
+    drop(handle);
+    assert_eq!(Ok(Some("billing-worker".to_string())), on_stopped.recv_timeout(Duration::from_secs(5)));
+}
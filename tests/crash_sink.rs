@@ -0,0 +1,35 @@
+//! The default crash sink is process-wide, so it is tested in a binary of its own.
+
+extern crate thread_handle;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use thread_handle::{set_crash_sink, CrashReport, CrashSink, RetryPolicy, ThreadHandle};
+
+
+struct Collect(Mutex<Vec<CrashReport>>);
+
+impl CrashSink for Collect {
+    fn record(&self, report: &CrashReport) -> io::Result<()> {
+        self.0.lock().unwrap().push(report.clone());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_crash_sink_counts_restarts() {
+    let sink = Arc::new(Collect(Mutex::new(Vec::new())));
+    set_crash_sink(Some(sink.clone()));
+    let policy = RetryPolicy::new(3).backoff(Duration::from_millis(10));
+    let handle = ThreadHandle::spawn_with_retry("Test crash restarts".to_string(), policy, || {
+        |_| -> Result<(), ()> { panic!("again") }
+    }).unwrap();
+    assert!(handle.join().unwrap().is_err());
+    set_crash_sink(None);
+    let reports = sink.0.lock().unwrap();
+    assert_eq!(1, reports.len());
+    assert_eq!("Test crash restarts", reports[0].thread);
+    assert_eq!(2, reports[0].restarts);
+}
@@ -0,0 +1,36 @@
+//! The default backend is replaced for the whole process, so it is tested in a binary of its
+//! own.
+
+extern crate thread_handle;
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicUsize};
+use std::thread::JoinHandle;
+
+use thread_handle::{set_spawn_backend, Job, SpawnBackend, StdBackend, ThreadHandle};
+
+
+struct Counting {
+    spawned: AtomicUsize,
+}
+
+impl SpawnBackend for Counting {
+    fn spawn(&self, name: String, job: Job) -> io::Result<Option<JoinHandle<()>>> {
+        self.spawned.fetch_add(1, atomic::Ordering::SeqCst);
+        StdBackend.spawn(name, job)
+    }
+}
+
+#[test]
+fn test_default_backend() {
+    let backend = Arc::new(Counting { spawned: AtomicUsize::new(0) });
+    set_spawn_backend(Some(backend.clone()));
+    let handle = ThreadHandle::spawn("Test backend default".to_string(), |_| 1);
+    set_spawn_backend(None);
+    assert_eq!(1, handle.unwrap().join().unwrap().unwrap());
+    assert_eq!(1, backend.spawned.load(atomic::Ordering::SeqCst));
+    let after = ThreadHandle::spawn("Test backend reset".to_string(), |_| 2).unwrap();
+    assert_eq!(2, after.join().unwrap().unwrap());
+    assert_eq!(1, backend.spawned.load(atomic::Ordering::SeqCst));
+}
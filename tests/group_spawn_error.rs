@@ -0,0 +1,24 @@
+//! Failing spawns are provoked with a spawn hook, which sees every spawn in the process, so they
+//! are tested in a binary of their own.
+
+extern crate thread_handle;
+
+use std::io;
+use std::time::Duration;
+
+use thread_handle::hooks::{register_spawn_hook, unregister_spawn_hook};
+use thread_handle::{GroupUsage, ThreadGroup};
+
+
+#[test]
+fn test_group_spawn_error_not_charged() {
+    let hook = register_spawn_hook(|request| match request.group() {
+        Some("Test group spawn error") => Err(io::Error::new(io::ErrorKind::PermissionDenied, "no threads")),
+        _ => Ok(()),
+    });
+    let mut group = ThreadGroup::<()>::new("Test group spawn error".to_string());
+    let error = group.spawn("Test group spawn error rejected".to_string(), |_| ()).unwrap_err();
+    unregister_spawn_hook(hook);
+    assert_eq!(io::ErrorKind::PermissionDenied, error.kind());
+    assert_eq!(GroupUsage { live: 0, spawned: 0, cpu_time: Duration::from_secs(0) }, group.usage());
+}
@@ -0,0 +1,37 @@
+//! Failing spawns are provoked with a spawn hook, which sees every spawn in the process, so they
+//! are tested in a binary of their own.
+
+extern crate thread_handle;
+
+use std::io;
+use std::thread::sleep;
+use std::time::Duration;
+
+use thread_handle::hooks::{register_spawn_hook, unregister_spawn_hook};
+use thread_handle::{InterruptToken, LinkedSet, ThreadStatus};
+
+
+fn wait_for_interrupt(interrupted: InterruptToken) {
+    while !interrupted.is_interrupted() {
+        sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_linked_set_restart_rolls_back() {
+    let mut set = LinkedSet::new();
+    for name in ["Test linked set rollback first", "Test linked set rollback second", "Test linked set rollback third"] {
+        set.spawn(name.to_string(), || wait_for_interrupt).unwrap();
+    }
+    let hook = register_spawn_hook(|request| match request.name() {
+        "Test linked set rollback second" => Err(io::Error::new(io::ErrorKind::PermissionDenied, "no threads")),
+        _ => Ok(()),
+    });
+    let error = set.restart().unwrap_err();
+    unregister_spawn_hook(hook);
+    assert_eq!(io::ErrorKind::PermissionDenied, error.kind());
+    for handle in set.handles() {
+        assert_eq!(ThreadStatus::Terminated, handle.status());
+    }
+    assert!(set.handles()[0].is_interrupt_requested());
+}
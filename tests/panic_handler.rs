@@ -0,0 +1,49 @@
+//! The panic handler is set for the whole process, so it is tested in a binary of its own.
+
+extern crate thread_handle;
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+use thread_handle::{set_panic_handler, unwinding_supported, DownReason, PanicReport, ThreadHandle};
+
+
+#[test]
+fn test_default_panic_handler() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let record = reports.clone();
+    set_panic_handler(Some(Arc::new(move |report: &PanicReport| {
+        record.lock().unwrap().push(report.thread.clone());
+    })));
+    let handle = ThreadHandle::spawn("Test default panic handler".to_string(), |_| -> u32 { panic!("") }).unwrap();
+    assert!(handle.join().unwrap().is_err());
+    set_panic_handler(None);
+    assert!(reports.lock().unwrap().contains(&"Test default panic handler".to_string()));
+
+    assert!(unwinding_supported());
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let record = reports.clone();
+    let survivor = ThreadHandle::spawn("Test fallible survivor".to_string(), |interrupted| {
+        while !interrupted.is_interrupted() {
+            sleep(Duration::from_millis(10));
+        }
+    }).unwrap();
+    let (start, started) = mpsc::channel::<()>();
+    let failing = ThreadHandle::spawn_fallible("Test fallible".to_string(), move |_| {
+        started.recv().ok();
+        Err::<(), _>("disk full")
+    }).unwrap();
+    failing.link(&survivor);
+    let monitor = failing.monitor();
+    set_panic_handler(Some(Arc::new(move |report: &PanicReport| {
+        record.lock().unwrap().push(report.message.clone());
+    })));
+    start.send(()).unwrap();
+    assert_eq!(Err("disk full"), failing.join().unwrap().unwrap());
+    set_panic_handler(None);
+    assert!(survivor.join().unwrap().is_ok());
+    assert_eq!("\"disk full\"", survivor.linked_failure().unwrap().message);
+    assert_eq!(vec!["\"disk full\"".to_string()], *reports.lock().unwrap());
+    assert_eq!(DownReason::Failed("\"disk full\"".to_string()), monitor.recv().unwrap().reason);
+}
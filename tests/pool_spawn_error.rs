@@ -0,0 +1,24 @@
+//! Failing spawns are provoked with a spawn hook, which sees every spawn in the process, so they
+//! are tested in a binary of their own.
+
+extern crate thread_handle;
+
+use std::io;
+
+use thread_handle::hooks::{register_spawn_hook, unregister_spawn_hook};
+use thread_handle::{SubmitError, ThreadPool};
+
+
+#[test]
+fn test_pool_submit_spawn_error() {
+    let hook = register_spawn_hook(|request| match request.group() {
+        Some("Test pool spawn error") => Err(io::Error::new(io::ErrorKind::PermissionDenied, "no threads")),
+        _ => Ok(()),
+    });
+    let pool = ThreadPool::builder("Test pool spawn error".to_string()).workers(1).build().unwrap();
+    let error = pool.try_submit(|_| ()).err().unwrap();
+    unregister_spawn_hook(hook);
+    assert_eq!(SubmitError::Spawn(io::ErrorKind::PermissionDenied), error);
+    assert_eq!((0, 0), (pool.queued(), pool.live_workers()));
+    assert!(!pool.wait_ready(None));
+}
@@ -0,0 +1,32 @@
+//! Recycling is switched on for the whole process, so it is tested in a binary of its own.
+
+extern crate thread_handle;
+
+use std::thread;
+use std::time::Duration;
+
+use thread_handle::{context, disable_recycling, enable_recycling, idle_threads, ThreadHandle, ThreadStatus};
+
+
+#[test]
+fn test_recycled_handles() {
+    enable_recycling(4, Duration::from_secs(5));
+    let first = ThreadHandle::spawn("Test recycled first".to_string(), |_| {
+        context::insert("request", "first");
+        thread::current().id()
+    }).unwrap();
+    let first_thread = first.join().unwrap().unwrap();
+    while idle_threads() == 0 {
+        thread::yield_now();
+    }
+    let second = ThreadHandle::spawn("Test recycled second".to_string(), |_| {
+        (thread::current().id(), context::get("request"))
+    }).unwrap();
+    assert_eq!((first_thread, None), second.join().unwrap().unwrap());
+    let failing = ThreadHandle::spawn("Test recycled failing".to_string(), |_| -> u32 { panic!("") }).unwrap();
+    assert!(failing.join().unwrap().is_err());
+    assert_eq!(ThreadStatus::Terminated, failing.status());
+    assert!(failing.join().is_none());
+    disable_recycling();
+    assert_eq!(0, idle_threads());
+}
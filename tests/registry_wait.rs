@@ -0,0 +1,31 @@
+//! `wait_for_all_terminated` waits for every thread registered in the process, so it is tested in
+//! a binary of its own.
+
+extern crate thread_handle;
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use thread_handle::registry::wait_for_all_terminated;
+use thread_handle::{ThreadHandle, ThreadStatus};
+
+
+#[test]
+fn test_wait_for_all_terminated() {
+    let daemon = ThreadHandle::spawn("Test wait all daemon".to_string(), |interrupted| {
+        while !interrupted.is_interrupted() {
+            sleep(Duration::from_millis(10));
+        }
+    }).unwrap();
+    daemon.register_detached();
+    let worker = ThreadHandle::spawn("Test wait all worker".to_string(), |_| sleep(Duration::from_millis(200))).unwrap();
+    worker.register();
+    assert!(!wait_for_all_terminated(Some(Duration::from_millis(20))));
+    assert!(wait_for_all_terminated(Some(Duration::from_secs(5))));
+    assert_eq!(ThreadStatus::Terminated, worker.status());
+    assert_ne!(ThreadStatus::Terminated, daemon.status());
+    daemon.interrupt().unwrap();
+    for handle in [&daemon, &worker].iter() {
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+//! Failing spawns are provoked with a spawn hook, which sees every spawn in the process, so they
+//! are tested in a binary of their own.
+
+extern crate thread_handle;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use thread_handle::hooks::{register_spawn_hook, unregister_spawn_hook};
+use thread_handle::{Clock, Scheduler, SystemClock};
+
+
+/// Clock moved by hand, so that periodic jobs are due exactly once.
+#[derive(Debug)]
+struct Manual(Mutex<Instant>);
+
+impl Clock for Manual {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[test]
+fn test_scheduler_reports_spawn_errors() {
+    let hook = register_spawn_hook(|request| match request.name() {
+        "Test schedule rejected" => Err(io::Error::other("rejected")),
+        _ => Ok(()),
+    });
+
+    let clock = Arc::new(Manual(Mutex::new(Instant::now())));
+    let manual = Scheduler::manual(clock.clone());
+    let periodic = manual.every(Duration::from_secs(60), "Test schedule rejected".to_string(), || |_| ());
+    let accepted = manual.every(Duration::from_secs(60), "Test schedule accepted".to_string(), || |_| ());
+    *clock.0.lock().unwrap() += Duration::from_secs(60);
+    let spawned = manual.run_due();
+    assert_eq!(1, spawned.iter().filter(|spawned| spawned.as_ref().err().is_some_and(|error| error.to_string() == "rejected")).count());
+    for handle in spawned.iter().filter_map(|spawned| spawned.as_ref().ok()) {
+        handle.join().unwrap().unwrap();
+    }
+    assert_eq!(0, manual.runs(periodic));
+    assert_eq!(1, manual.runs(accepted));
+    assert!(manual.take_errors().is_empty());
+
+    let threaded = Scheduler::spawn("Test schedule thread".to_string(), Arc::new(SystemClock)).unwrap();
+    let delayed = threaded.after(Duration::from_secs(0), "Test schedule rejected".to_string(), |_| ());
+    let give_up = Instant::now() + Duration::from_secs(5);
+    let mut errors = threaded.take_errors();
+    while errors.is_empty() && Instant::now() < give_up {
+        thread::sleep(Duration::from_millis(1));
+        errors = threaded.take_errors();
+    }
+    assert_eq!(1, errors.len());
+    assert_eq!(delayed, errors[0].0);
+    assert_eq!("rejected", errors[0].1.to_string());
+
+    unregister_spawn_hook(hook);
+}
@@ -0,0 +1,36 @@
+//! The slow-join threshold is process-wide, so it is tested in a binary of its own. Events are
+//! not published with the `minimal` feature.
+#![cfg(not(feature = "minimal"))]
+
+extern crate thread_handle;
+
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use thread_handle::events::{self, Event, ThreadEvent};
+use thread_handle::{set_slow_join_threshold, ThreadHandle};
+
+
+#[test]
+fn test_slow_join_reported() {
+    let (report, reports) = mpsc::channel();
+    let report = Mutex::new(report);
+    let id = events::subscribe(Arc::new(move |event: &Event| {
+        if let ThreadEvent::SlowJoin { waited } = event.event {
+            if event.thread == "Test slow join" {
+                report.lock().unwrap().send(waited).ok();
+            }
+        }
+    }));
+    set_slow_join_threshold(Some(Duration::from_millis(20)));
+    // The thread only finishes once its join has been reported slow twice.
+    let handle = ThreadHandle::spawn("Test slow join".to_string(), move |_| {
+        (reports.recv().unwrap(), reports.recv().unwrap())
+    }).unwrap();
+    let (first, second) = handle.join().unwrap().unwrap();
+    set_slow_join_threshold(None);
+    events::unsubscribe(id);
+    assert!(first >= Duration::from_millis(20), "{:?}", first);
+    assert!(second >= Duration::from_millis(40), "{:?}", second);
+}